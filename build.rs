@@ -1,6 +1,7 @@
 fn main() {
     println!("cargo:rerun-if-changed=templates/layout.html");
     println!("cargo:rerun-if-changed=templates/sidebar.html");
+    println!("cargo:rerun-if-changed=templates/book.html");
     println!("cargo:rerun-if-changed=style/main.scss");
 
     // Compile Sass if available
@@ -0,0 +1,71 @@
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+
+use crate::site::{escape_html, SiteIndex};
+use crate::{bode, gmid, logic, noise, plot, quiz, spice, wavedrom};
+
+/// Shown in place of a block that could not be rendered
+pub fn error(lang: &str, message: &str) -> String {
+    format!(
+        r#"<div class="block-error"><p><strong>Error in {} block:</strong> {}</p></div>"#,
+        escape_html(lang),
+        escape_html(message)
+    )
+}
+
+/// Replace fenced code blocks whose language names a block type (```quiz, ```bode, ...) with their rendered HTML.
+/// Any other code block is passed through untouched.
+pub fn render<'a>(events: Vec<Event<'a>>, index: &SiteIndex, page_path: &str) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut iter = events.into_iter();
+    let mut quizzes = 0;
+
+    while let Some(event) = iter.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) = &event else {
+            out.push(event);
+            continue;
+        };
+
+        let lang = lang.split_whitespace().next().unwrap_or_default().to_string();
+        let mut body = Vec::new();
+        for inner in iter.by_ref() {
+            if matches!(inner, Event::End(TagEnd::CodeBlock)) {
+                break;
+            }
+            body.push(inner);
+        }
+        let source: String = body
+            .iter()
+            .filter_map(|e| match e {
+                Event::Text(t) => Some(t.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let rendered = match lang.as_str() {
+            "quiz" => {
+                quizzes += 1;
+                Some(quiz::render(&source, index, &format!("{}#{}", page_path, quizzes)).unwrap_or_else(|e| error(&lang, &e)))
+            }
+            "bode" => Some(bode::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "gmid" => Some(gmid::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "logic" => Some(logic::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "noise" => Some(noise::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "plot" => Some(plot::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "spice-ac" => Some(spice::render_ac(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "spice-op" => Some(spice::render_op(&source).unwrap_or_else(|e| error(&lang, &e))),
+            "wavedrom" => Some(wavedrom::render(&source).unwrap_or_else(|e| error(&lang, &e))),
+            _ => None,
+        };
+
+        match rendered {
+            Some(html) => out.push(Event::Html(CowStr::from(html))),
+            None => {
+                out.push(event);
+                out.extend(body);
+                out.push(Event::End(TagEnd::CodeBlock));
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,313 @@
+use std::f64::consts::PI;
+
+use crate::chart::{self, Axis, Panel};
+use crate::expr::{self, Complex, Env, Expr};
+use crate::units::{self, format_si};
+
+/// A ```bode block. Either a transfer function with its constants:
+///
+/// ```text
+/// L(s) = A0 / ((1 + s/wp1)(1 + s/wp2))
+/// A0 = 1e4
+/// wp1 = 2*pi*1e3
+/// wp2 = 2*pi*5e6
+/// f: 10 .. 1e9
+/// caption: Two-pole loop gain
+/// ```
+///
+/// or gain, poles and zeros in Hz (`gain: 1e4`, `poles: 1e3, 5e6`, `zeros: 1e8`).
+/// Several `name(s) = …` lines plot several curves; margins are marked on the first.
+struct Bode {
+    curves: Vec<(String, Expr)>,
+    env: Env,
+    gain: Option<f64>,
+    poles: Vec<f64>,
+    zeros: Vec<f64>,
+    range: Option<(f64, f64)>,
+    caption: String,
+    margins: bool,
+}
+
+const POINTS: usize = 600;
+
+fn parse_list(value: &str, env: &Env) -> Result<Vec<f64>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| units::parse_value(v, env, Some("Hz")))
+        .collect()
+}
+
+/// `10 .. 1e9` into (10, 1e9)
+pub fn parse_range(value: &str, env: &Env) -> Result<(f64, f64), String> {
+    let (lo, hi) = value.split_once("..").ok_or_else(|| format!("expected a range like `1 .. 1e9`, got `{}`", value.trim()))?;
+    Ok((units::parse_value(lo, env, Some("Hz"))?, units::parse_value(hi, env, Some("Hz"))?))
+}
+
+fn parse(source: &str) -> Result<Bode, String> {
+    let mut bode = Bode {
+        curves: Vec::new(),
+        env: Env::new(),
+        gain: None,
+        poles: Vec::new(),
+        zeros: Vec::new(),
+        range: None,
+        caption: String::new(),
+        margins: true,
+    };
+
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        if let Some((key, value)) = line.split_once(':').filter(|(k, _)| k.trim().chars().all(|c| c.is_ascii_alphabetic())) {
+            let value = value.trim();
+            match key.trim() {
+                "gain" => bode.gain = Some(units::parse_value(value, &bode.env, Some("V/V"))?),
+                "poles" => bode.poles = parse_list(value, &bode.env)?,
+                "zeros" => bode.zeros = parse_list(value, &bode.env)?,
+                "f" | "range" => bode.range = Some(parse_range(value, &bode.env)?),
+                "caption" => bode.caption = value.to_string(),
+                "margins" => bode.margins = !matches!(value, "off" | "no" | "false"),
+                other => return Err(format!("unknown option `{}`", other)),
+            }
+            continue;
+        }
+
+        let (lhs, rhs) = line.split_once('=').ok_or_else(|| format!("expected `name = value` in `{}`", line))?;
+        let lhs = lhs.trim();
+        if let Some(name) = lhs.strip_suffix("(s)") {
+            bode.curves.push((name.trim().to_string(), expr::parse(rhs)?));
+        } else {
+            let value = expr::parse(rhs)?.eval(&bode.env)?;
+            bode.env.insert(lhs.to_string(), value);
+        }
+    }
+
+    if bode.curves.is_empty() {
+        if bode.gain.is_none() && bode.poles.is_empty() && bode.zeros.is_empty() {
+            return Err("no transfer function: add `H(s) = …` or `gain:`/`poles:`/`zeros:`".to_string());
+        }
+        bode.curves.push(("H".to_string(), factored(bode.gain.unwrap_or(1.0), &bode.poles, &bode.zeros)));
+    }
+    Ok(bode)
+}
+
+/// K · Π(1 + s/ωz) / Π(1 + s/ωp) with the corners given in Hz; a corner at 0 is a pure s
+fn factored(gain: f64, poles: &[f64], zeros: &[f64]) -> Expr {
+    let factor = |f: f64| -> Expr {
+        let s = Expr::Var("s".to_string());
+        if f == 0.0 {
+            return s;
+        }
+        let corner = Expr::Num(2.0 * PI * f);
+        Expr::Binary('+', Box::new(Expr::Num(1.0)), Box::new(Expr::Binary('/', Box::new(s), Box::new(corner))))
+    };
+    let mut expr = Expr::Num(gain);
+    for &z in zeros {
+        expr = Expr::Binary('*', Box::new(expr), Box::new(factor(z)));
+    }
+    for &p in poles {
+        expr = Expr::Binary('/', Box::new(expr), Box::new(factor(p)));
+    }
+    expr
+}
+
+/// Magnitude in dB and unwrapped phase in degrees of one curve over the frequency grid
+pub struct Response {
+    pub mag: Vec<f64>,
+    pub phase: Vec<f64>,
+}
+
+impl Response {
+    pub fn new(values: impl IntoIterator<Item = Complex>) -> Response {
+        let mut mag = Vec::new();
+        let mut phase: Vec<f64> = Vec::new();
+        for h in values {
+            mag.push(20.0 * h.abs().log10());
+            let mut p = h.arg().to_degrees();
+            if let Some(&prev) = phase.last() {
+                // Keep the phase continuous so it can roll past -180°
+                p += 360.0 * ((prev - p) / 360.0).round();
+            }
+            phase.push(p);
+        }
+        Response { mag, phase }
+    }
+}
+
+fn evaluate(curve: &Expr, env: &Env, freqs: &[f64]) -> Result<Response, String> {
+    let mut env = env.clone();
+    let values = freqs
+        .iter()
+        .map(|&f| curve.eval_at(&mut env, "s", Complex::new(0.0, 2.0 * PI * f)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Response::new(values))
+}
+
+/// Frequency where `values` crosses `level` going down, interpolated on the log-frequency grid
+pub fn crossing(freqs: &[f64], values: &[f64], level: f64) -> Option<(usize, f64)> {
+    (1..freqs.len()).find_map(|i| {
+        let (a, b) = (values[i - 1] - level, values[i] - level);
+        (a >= 0.0 && b < 0.0).then(|| {
+            let t = a / (a - b);
+            let f = 10f64.powf(freqs[i - 1].log10() + t * (freqs[i].log10() - freqs[i - 1].log10()));
+            (i, f)
+        })
+    })
+}
+
+pub fn interpolate(freqs: &[f64], values: &[f64], i: usize, f: f64) -> f64 {
+    let t = (f.log10() - freqs[i - 1].log10()) / (freqs[i].log10() - freqs[i - 1].log10());
+    values[i - 1] + t * (values[i] - values[i - 1])
+}
+
+/// Default frequency span: a decade either side of the corners, or 1 Hz – 1 GHz
+fn default_range(bode: &Bode) -> (f64, f64) {
+    let corners: Vec<f64> = bode.poles.iter().chain(&bode.zeros).copied().filter(|&f| f > 0.0).collect();
+    if corners.is_empty() {
+        return (1.0, 1e9);
+    }
+    let lo = corners.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = corners.iter().copied().fold(0.0, f64::max);
+    (lo / 10.0, hi * 10.0)
+}
+
+/// `points` frequencies spaced evenly per decade from `lo` to `hi`
+pub fn log_sweep(lo: f64, hi: f64, points: usize) -> Vec<f64> {
+    (0..points)
+        .map(|i| 10f64.powf(lo.log10() + (hi.log10() - lo.log10()) * i as f64 / (points - 1) as f64))
+        .collect()
+}
+
+pub fn render(source: &str) -> Result<String, String> {
+    let bode = parse(source)?;
+    let (lo, hi) = bode.range.unwrap_or_else(|| default_range(&bode));
+    if !(lo > 0.0 && hi > lo) {
+        return Err(format!("frequency range must be positive and increasing, got {} .. {}", lo, hi));
+    }
+
+    let freqs = log_sweep(lo, hi, POINTS);
+    let responses = bode
+        .curves
+        .iter()
+        .map(|(_, curve)| evaluate(curve, &bode.env, &freqs))
+        .collect::<Result<Vec<_>, _>>()?;
+    let names: Vec<String> = bode.curves.iter().map(|(name, _)| format!("{}(s)", name)).collect();
+
+    let caption = if bode.caption.is_empty() {
+        format!("Bode plot of {}", names.join(", "))
+    } else {
+        bode.caption.clone()
+    };
+    chart(&freqs, &names, &responses, bode.margins, &caption)
+}
+
+/// Phase margin from the phase at crossover, wrapped into (-180°, 180°] as the phase is unwrapped
+/// from wherever it starts, which is near +180° for an inverting H
+fn phase_margin(ph: f64) -> f64 {
+    let pm = (180.0 + ph).rem_euclid(360.0);
+    if pm > 180.0 { pm - 360.0 } else { pm }
+}
+
+/// Stacked magnitude and phase panels for `responses` sampled at `freqs`, with gain and phase
+/// margins marked on the first when `margins` is set
+pub fn chart(freqs: &[f64], names: &[String], responses: &[Response], margins: bool, caption: &str) -> Result<String, String> {
+    let (lo, hi) = (freqs[0], freqs[freqs.len() - 1]);
+
+    let finite = |v: &&f64| v.is_finite();
+    let mag_max = responses.iter().flat_map(|r| r.mag.iter()).filter(finite).copied().fold(f64::NEG_INFINITY, f64::max);
+    let mag_min = responses.iter().flat_map(|r| r.mag.iter()).filter(finite).copied().fold(f64::INFINITY, f64::min);
+    let phase_max = responses.iter().flat_map(|r| r.phase.iter()).filter(finite).copied().fold(f64::NEG_INFINITY, f64::max);
+    let phase_min = responses.iter().flat_map(|r| r.phase.iter()).filter(finite).copied().fold(f64::INFINITY, f64::min);
+    if !mag_max.is_finite() {
+        return Err("the transfer function is not finite anywhere in the frequency range".to_string());
+    }
+
+    // Always show 0 dB so the crossover is visible, but don't let a deep notch flatten everything
+    let mag_min = mag_min.max(mag_max - 160.0).min(0.0);
+    let mag_max = mag_max.max(0.0) + 5.0;
+    let phase_lo = (phase_min / 45.0).floor() * 45.0;
+    let phase_hi = ((phase_max.max(0.0)) / 45.0).ceil() * 45.0;
+
+    let (width, left, panel_width, panel_height) = (640.0, 64.0, 546.0, 170.0);
+    let x_axis = Axis::log(lo, hi, "Frequency").unit("Hz");
+    let magnitude = Panel {
+        x: x_axis.clone(),
+        y: Axis::linear(mag_min, mag_max, "Magnitude (dB)").nice(),
+        left,
+        top: 14.0,
+        width: panel_width,
+        height: panel_height,
+    };
+    let phase = Panel {
+        x: x_axis,
+        y: Axis::linear(phase_lo, phase_hi, "Phase (°)").step(if phase_hi - phase_lo > 360.0 { 90.0 } else { 45.0 }),
+        left,
+        top: 14.0 + panel_height + 22.0,
+        width: panel_width,
+        height: panel_height,
+    };
+    let height = phase.top + panel_height + 44.0;
+
+    let mut svg = String::new();
+    magnitude.frame(&mut svg, false);
+    phase.frame(&mut svg, true);
+    magnitude.guide(&mut svg, (lo, 0.0), (hi, 0.0));
+    if phase_lo <= -180.0 {
+        phase.guide(&mut svg, (lo, -180.0), (hi, -180.0));
+    }
+
+    for (series, response) in responses.iter().enumerate() {
+        let mag: Vec<(f64, f64)> = freqs.iter().copied().zip(response.mag.iter().copied()).collect();
+        let ph: Vec<(f64, f64)> = freqs.iter().copied().zip(response.phase.iter().copied()).collect();
+        magnitude.curve(&mut svg, &mag, series, false);
+        phase.curve(&mut svg, &ph, series, false);
+    }
+
+    if margins {
+        let response = &responses[0];
+        if let Some((i, fc)) = crossing(freqs, &response.mag, 0.0) {
+            let ph = interpolate(freqs, &response.phase, i, fc);
+            let pm = phase_margin(ph);
+            magnitude.guide(&mut svg, (fc, magnitude.y.max), (fc, magnitude.y.min));
+            magnitude.dot(&mut svg, (fc, 0.0), 0);
+            magnitude.note(&mut svg, (fc, 0.0), -4.0, &format!("fc = {}", format_si(fc, "Hz")));
+            phase.guide(&mut svg, (fc, phase.y.max), (fc, ph.max(phase.y.min)));
+            phase.dot(&mut svg, (fc, ph), 0);
+            phase.note(&mut svg, (fc, ph), -4.0, &format!("PM = {:.1}°", pm));
+        }
+        if let Some((i, f180)) = crossing(freqs, &response.phase, -180.0) {
+            let gain = interpolate(freqs, &response.mag, i, f180);
+            magnitude.guide(&mut svg, (f180, 0.0), (f180, gain.max(magnitude.y.min)));
+            if magnitude.contains(f180, gain) {
+                magnitude.dot(&mut svg, (f180, gain), 1);
+            }
+            magnitude.note(&mut svg, (f180, gain.max(magnitude.y.min)), 14.0, &format!("GM = {:.1} dB", -gain));
+            phase.dot(&mut svg, (f180, -180.0), 1);
+        }
+    }
+
+    magnitude.legend(&mut svg, names);
+    Ok(chart::figure("bode", width, height, &svg, caption))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_margin_wraps_into_one_turn() {
+        assert_eq!(phase_margin(-90.0), 90.0);
+        assert_eq!(phase_margin(-180.0), 0.0);
+        assert_eq!(phase_margin(22.5), -157.5);
+        assert_eq!(phase_margin(-540.0), 0.0);
+        assert_eq!(phase_margin(-200.0), -20.0);
+    }
+
+    #[test]
+    fn inverting_transfer_function_has_a_wrapped_margin() {
+        let inverting = render("H(s) = -A/(1 + s/wp)\nA = 100\nwp = 2*pi*1e3").unwrap();
+        assert!(inverting.contains("PM = -89.4°"), "{}", inverting);
+        let plain = render("H(s) = A/(1 + s/wp)\nA = 100\nwp = 2*pi*1e3").unwrap();
+        assert!(plain.contains("PM = 90.6°"), "{}", plain);
+    }
+}
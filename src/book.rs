@@ -0,0 +1,230 @@
+use std::sync::LazyLock;
+
+use askama::Template;
+use chrono::Local;
+use regex::Regex;
+
+use crate::render::{self, Numbering};
+use crate::site::{self, content_dir, escape_html, percent_decode, SiteIndex};
+use crate::{build_link, config, get_site_url, parse_numbered_name, SidebarItem};
+
+static HREF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r##"href="([^"#]*)(#[^"]*)?""##).unwrap());
+
+/// One page (or directory) of an exported subtree
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub title: String,
+    /// URL path without the site_url prefix, e.g. "/analog/8) Op-Amp Design/1) Parameters"
+    pub path: String,
+    /// "8.2.1" from the numeric prefixes below the category, empty when a level has none
+    pub number: String,
+    /// 0 for the exported item itself, 1 for its children and so on
+    pub depth: usize,
+    /// Markdown source; empty for a directory without a page of its own
+    pub source: String,
+}
+
+impl Section {
+    pub fn id(&self) -> String {
+        format!("sec-{}", render::slugify(&self.path))
+    }
+
+    /// "8.2 Topologies", or the bare title when the section has no number
+    pub fn heading(&self) -> String {
+        if self.number.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} {}", self.number, self.title)
+        }
+    }
+}
+
+/// A sidebar subtree flattened in sidebar order, ready to be rendered as one document
+#[derive(Clone, Debug)]
+pub struct Book {
+    pub title: String,
+    /// Title of the category the subtree belongs to, or empty when it is a whole category
+    pub category: String,
+    pub sections: Vec<Section>,
+    /// Pages left out because they have no content yet
+    pub skipped: usize,
+}
+
+#[derive(Template)]
+#[template(path = "book.html")]
+struct BookTemplate<'a> {
+    title: &'a str,
+    subtitle: &'a str,
+    site_title: &'a str,
+    date: String,
+    toc: &'a str,
+    body: &'a str,
+}
+
+/// `/analog/8) Op-Amp Design`, `analog/8) Op-Amp Design` or a path under the content directory
+/// (with or without `.md`) into the URL path the sidebar uses
+fn normalize(path: &str) -> String {
+    let path = path.trim().trim_end_matches('/');
+    let path = path.strip_prefix(content_dir()).unwrap_or(path);
+    let path = path.strip_suffix(".md").unwrap_or(path);
+    format!("/{}", path.trim_start_matches('/'))
+}
+
+fn find<'a>(items: &'a [SidebarItem], link: &str) -> Option<&'a SidebarItem> {
+    items.iter().find_map(|item| if item.path == link { Some(item) } else { find(&item.children, link) })
+}
+
+impl Book {
+    /// Collect the sidebar subtree at `path` with each page's source
+    pub fn collect(sidebar: &[SidebarItem], path: &str) -> Result<Book, String> {
+        let wanted = normalize(path);
+        let root = find(sidebar, &build_link(&wanted)).ok_or_else(|| format!("no page or chapter at {}", wanted))?;
+        let mut book = Book {
+            title: root.title.clone(),
+            category: String::new(),
+            sections: Vec::new(),
+            skipped: 0,
+        };
+        let site_url = get_site_url();
+        let segments: Vec<&str> = wanted.split('/').filter(|s| !s.is_empty()).collect();
+        if segments.len() > 1 {
+            book.category = sidebar.iter().find(|c| c.path == build_link(&format!("/{}", segments[0]))).map(|c| c.title.clone()).unwrap_or_default();
+        }
+
+        fn walk(item: &SidebarItem, depth: usize, site_url: &str, book: &mut Book) -> bool {
+            let path = item.path.strip_prefix(site_url).unwrap_or(&item.path).to_string();
+            let source = site::read_page(&path).unwrap_or_default();
+            // Chapter numbers come from the directories below the category, as in "8) Op-Amp Design"
+            let numbers: Vec<i32> = path.split('/').filter(|s| !s.is_empty()).skip(1).map(|s| parse_numbered_name(s).0).collect();
+            let number = if numbers.is_empty() || numbers.contains(&i32::MAX) {
+                String::new()
+            } else {
+                numbers.iter().map(i32::to_string).collect::<Vec<_>>().join(".")
+            };
+
+            let at = book.sections.len();
+            book.sections.push(Section { title: item.title.clone(), path, number, depth, source });
+            let mut has_content = !book.sections[at].source.trim().is_empty();
+            for child in &item.children {
+                has_content |= walk(child, depth + 1, site_url, book);
+            }
+            if !has_content {
+                book.sections.truncate(at);
+                if item.children.is_empty() {
+                    book.skipped += 1;
+                }
+            }
+            has_content
+        }
+        walk(root, 0, &site_url, &mut book);
+
+        if book.sections.is_empty() {
+            return Err(format!("{} has no pages with content", wanted));
+        }
+        Ok(book)
+    }
+
+    /// Each section's page rendered like the site renders it, with figures and equations
+    /// numbered per chapter; empty for a directory without a page of its own
+    pub fn render_sections(&self, index: &SiteIndex) -> Vec<String> {
+        let mut numbering = Numbering::default();
+        let mut chapter = None;
+        let mut pages = Vec::with_capacity(self.sections.len());
+        for (i, section) in self.sections.iter().enumerate() {
+            // Figures and equations count through a chapter and restart at the next one
+            let segments: Vec<&str> = section.path.split('/').filter(|s| !s.is_empty()).collect();
+            let this_chapter = segments.get(1).copied();
+            if i == 0 || this_chapter != chapter {
+                chapter = this_chapter;
+                let prefix = chapter.map(|c| parse_numbered_name(c).0).filter(|&n| n != i32::MAX);
+                numbering = Numbering {
+                    chapter: prefix.map(|n| n.to_string()).unwrap_or_default(),
+                    figures: 0,
+                    equations: Some(0),
+                };
+            }
+            pages.push(if section.source.trim().is_empty() {
+                String::new()
+            } else {
+                render::render_numbered(&section.source, index, &section.path, &mut numbering)
+            });
+        }
+        pages
+    }
+
+    /// The index of the section an href (without its fragment) points to, if it is in the book
+    pub fn find_link(&self, href: &str) -> Option<usize> {
+        let site_url = get_site_url();
+        let path = percent_decode(href);
+        let path = path.strip_prefix(site_url.as_str()).unwrap_or(&path);
+        self.sections.iter().position(|s| s.path == path)
+    }
+
+    /// Point links between pages of the book at their sections, so they stay inside the document
+    pub fn link_sections(&self, html: &str) -> String {
+        HREF_RE
+            .replace_all(html, |caps: &regex::Captures| match self.find_link(&caps[1]) {
+                Some(target) => match caps.get(2).map(|m| m.as_str()) {
+                    Some(fragment) if fragment.len() > 1 => format!("href=\"{}\"", fragment),
+                    _ => format!("href=\"#{}\"", self.sections[target].id()),
+                },
+                None => caps[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    /// The whole subtree as one print-styled HTML document: cover, contents, then every page
+    /// rendered like the site renders it, with figures and equations numbered per chapter
+    pub fn render_html(&self, index: &SiteIndex) -> String {
+        let mut toc = String::new();
+        let mut open = 0;
+        for section in &self.sections {
+            let level = section.depth.max(1);
+            if level > open {
+                toc.push_str(&"<ol>\n<li>".repeat(level - open));
+            } else {
+                toc.push_str("</li>\n");
+                toc.push_str(&"</ol>\n</li>\n".repeat(open - level));
+                toc.push_str("<li>");
+            }
+            open = level;
+            toc.push_str(&format!("<a href=\"#{}\">{}</a>", section.id(), escape_html(&section.heading())));
+        }
+        toc.push_str(&"</li>\n</ol>\n".repeat(open));
+
+        let mut body = String::new();
+        for (i, (section, html)) in self.sections.iter().zip(self.render_sections(index)).enumerate() {
+            let is_part = i == 0 || (section.depth <= 1 && self.sections.get(i + 1).is_some_and(|next| next.depth > section.depth));
+            let number = if section.number.is_empty() {
+                String::new()
+            } else {
+                format!("<span class=\"book-number\">{}</span> ", section.number)
+            };
+            body.push_str(&format!(
+                "<section class=\"book-section book-depth-{}{}\" id=\"{}\">\n<h1 class=\"book-section-title\">{}{}</h1>\n",
+                section.depth,
+                if is_part { " book-part" } else { "" },
+                section.id(),
+                number,
+                escape_html(&section.title)
+            ));
+            body.push_str(&html);
+            body.push_str("\n</section>\n");
+        }
+
+        let body = self.link_sections(&body);
+        // Nothing in print can be clicked open
+        let body = body.replace("<summary>Show answer</summary>", "<summary>Answer</summary>");
+
+        BookTemplate {
+            title: &self.title,
+            subtitle: &self.category,
+            site_title: &config::get().site.title,
+            date: Local::now().format("%B %-d, %Y").to_string(),
+            toc: &toc,
+            body: &body,
+        }
+        .render()
+        .unwrap()
+    }
+}
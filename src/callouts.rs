@@ -0,0 +1,190 @@
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use crate::site::escape_html;
+
+/// How a callout folds: `[!NOTE]` is always open, `[!NOTE]-` starts collapsed and `[!NOTE]+` starts expanded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fold {
+    None,
+    Collapsed,
+    Expanded,
+}
+
+/// The `[!TYPE]± Optional title` line opening a callout
+#[derive(Clone, Debug)]
+pub struct Marker {
+    /// Lowercase type, e.g. "note" or "derivation"
+    pub kind: String,
+    pub fold: Fold,
+    pub title: String,
+}
+
+impl Marker {
+    /// The custom title, or the type name ("Note", "Derivation") when there is none
+    pub fn display_title(&self) -> String {
+        if !self.title.is_empty() {
+            return self.title.clone();
+        }
+        let mut chars = self.kind.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            None => String::new(),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self.kind.as_str() {
+            "note" | "info" => "ℹ️",
+            "tip" | "hint" => "💡",
+            "important" => "❗",
+            "warning" => "⚠️",
+            "caution" | "danger" => "🛑",
+            "derivation" => "✏️",
+            "example" => "🧪",
+            "proof" => "∎",
+            "question" => "❓",
+            "summary" => "📋",
+            _ => "📌",
+        }
+    }
+}
+
+/// Parse the first line of a blockquote as a callout marker
+pub fn parse_marker(text: &str) -> Option<Marker> {
+    let rest = text.strip_prefix("[!")?;
+    let (kind, rest) = rest.split_once(']')?;
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let (fold, title) = match rest.chars().next() {
+        Some('-') => (Fold::Collapsed, &rest[1..]),
+        Some('+') => (Fold::Expanded, &rest[1..]),
+        _ => (Fold::None, rest),
+    };
+
+    Some(Marker {
+        kind: kind.to_ascii_lowercase(),
+        fold,
+        title: title.trim().to_string(),
+    })
+}
+
+/// Turn `> [!TYPE]` blockquotes into `<aside>` callouts, or `<details>` when they fold
+pub fn render(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len());
+    // One entry per open blockquote: the closing tag if it became a callout
+    let mut open: Vec<Option<&'static str>> = Vec::new();
+    let mut i = 0;
+
+    while i < events.len() {
+        let (marker, spaced) = match (&events[i], events.get(i + 1), events.get(i + 2)) {
+            (Event::Start(Tag::BlockQuote(_)), Some(Event::Start(Tag::Paragraph)), Some(Event::Text(t))) => {
+                let line = t.lines().next().unwrap_or_default();
+                (parse_marker(line), line.ends_with(char::is_whitespace) && !t.contains('\n'))
+            }
+            _ => (None, false),
+        };
+
+        match (&events[i], marker) {
+            (Event::Start(Tag::BlockQuote(_)), Some(marker)) => {
+                let (open_tag, title_tag, close) = match marker.fold {
+                    Fold::None => (
+                        format!(r#"<aside class="callout callout-{}">"#, marker.kind),
+                        "p",
+                        "</aside>",
+                    ),
+                    Fold::Collapsed => (
+                        format!(r#"<details class="callout callout-{}">"#, marker.kind),
+                        "summary",
+                        "</details>",
+                    ),
+                    Fold::Expanded => (
+                        format!(r#"<details class="callout callout-{}" open>"#, marker.kind),
+                        "summary",
+                        "</details>",
+                    ),
+                };
+                // The title runs to the end of the marker line, markup included
+                let mut end = i + 3;
+                if spaced || marker.title.is_empty() {
+                    while !matches!(
+                        events.get(end),
+                        None | Some(Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph))
+                    ) {
+                        end += 1;
+                    }
+                }
+                let title = if end > i + 3 {
+                    let space = if spaced && !marker.title.is_empty() { " " } else { "" };
+                    escape_html(&format!("{}{}", marker.title, space))
+                } else {
+                    escape_html(&marker.display_title())
+                };
+                out.push(Event::Html(open_tag.into()));
+                out.push(Event::Html(format!(
+                    r#"<{0} class="callout-title"><span class="callout-icon" aria-hidden="true">{1}</span> {2}"#,
+                    title_tag,
+                    marker.icon(),
+                    title
+                ).into()));
+                out.extend(events[i + 3..end].iter().cloned());
+                out.push(Event::Html(format!("</{}>", title_tag).into()));
+                open.push(Some(close));
+
+                // Whatever followed the marker line in the first paragraph becomes the start of the body
+                i = end;
+                if matches!(events.get(i), Some(Event::SoftBreak)) {
+                    i += 1;
+                }
+                if !matches!(events.get(i), Some(Event::End(TagEnd::Paragraph))) {
+                    out.push(Event::Start(Tag::Paragraph));
+                    continue;
+                }
+                i += 1;
+                continue;
+            }
+            (Event::Start(Tag::BlockQuote(_)), None) => {
+                open.push(None);
+                out.push(events[i].clone());
+            }
+            (Event::End(TagEnd::BlockQuote(_)), _) => match open.pop().flatten() {
+                Some(close) => out.push(Event::Html(CowStr::Borrowed(close))),
+                None => out.push(events[i].clone()),
+            },
+            (event, _) => out.push(event.clone()),
+        }
+        i += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser, TextMergeStream};
+
+    fn render_html(source: &str) -> String {
+        let events: Vec<Event> = TextMergeStream::new(Parser::new(source)).collect();
+        let mut out = String::new();
+        html::push_html(&mut out, render(events).into_iter());
+        out
+    }
+
+    #[test]
+    fn title_keeps_inline_markup() {
+        let out = render_html("> [!NOTE] Custom *title* here\n> Body text.");
+        assert!(out.contains(r#"<p class="callout-title"><span class="callout-icon" aria-hidden="true">ℹ️</span> Custom <em>title</em> here</p>"#), "{}", out);
+        assert!(out.contains("<p>Body text.</p>"), "{}", out);
+    }
+
+    #[test]
+    fn title_can_start_with_markup_or_be_left_out() {
+        let out = render_html("> [!TIP]- `code` first\n>\n> Body.");
+        assert!(out.contains(r#"<summary class="callout-title"><span class="callout-icon" aria-hidden="true">💡</span> <code>code</code> first</summary>"#), "{}", out);
+        let out = render_html("> [!WARNING]\n> Body.");
+        assert!(out.contains("</span> Warning</p>"), "{}", out);
+        assert!(out.contains("<p>Body.</p>"), "{}", out);
+    }
+}
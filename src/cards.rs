@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, CowStr, Event, Parser, Tag, TagEnd};
+use serde_json::{json, Value};
+
+use crate::render::{markdown_options, render_fragment, stable_id};
+use crate::site::{escape_html, SiteIndex};
+use crate::build_link;
+
+/// A flashcard written either as a `Q:: … A:: …` paragraph or as a `> [!CARD]` callout
+/// whose first paragraph is the question and the rest the answer.
+#[derive(Clone, Debug)]
+pub struct Card {
+    pub front: String,
+    pub back: String,
+}
+
+const CARD_MARKER: &str = "[!CARD]";
+
+/// Collect the cards in a page's markdown source
+pub fn extract(source: &str) -> Vec<Card> {
+    let events: Vec<_> = Parser::new_ext(source, markdown_options()).into_offset_iter().collect();
+    let mut cards = Vec::new();
+
+    for (i, (event, range)) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::BlockQuote(_)) => {
+                let first_paragraph = events.get(i + 1).filter(|(e, _)| matches!(e, Event::Start(Tag::Paragraph)));
+                if let Some((_, p)) = first_paragraph {
+                    if source[p.clone()].starts_with(CARD_MARKER) {
+                        cards.extend(split_callout(&source[range.clone()]));
+                    }
+                }
+            }
+            Event::Start(Tag::Paragraph) => {
+                let text = &source[range.clone()];
+                if let Some(rest) = text.strip_prefix("Q::") {
+                    if let Some((front, back)) = rest.split_once("A::") {
+                        cards.push(Card { front: front.trim().to_string(), back: back.trim().to_string() });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    cards
+}
+
+/// Split the raw source of a `> [!CARD]` blockquote into question and answer
+fn split_callout(quote: &str) -> Option<Card> {
+    let body: Vec<&str> = quote
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix('>').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect();
+    let body = body.join("\n");
+    let body = body.trim_start().strip_prefix(CARD_MARKER)?;
+
+    let (front, back) = body.split_once("\n\n").unwrap_or((body, ""));
+    Some(Card { front: front.trim().to_string(), back: back.trim().to_string() })
+}
+
+/// Render cards in place on their page, with the answer folded away
+pub fn render(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    const FRONT: &str = r#"<div class="flashcard"><div class="flashcard-front">"#;
+    const BACK: &str = r#"</div><details class="flashcard-back"><summary>Answer</summary>"#;
+
+    let is_text = |e: Option<&Event<'_>>, f: &dyn Fn(&str) -> bool| matches!(e, Some(Event::Text(t)) if f(t));
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut i = 0;
+
+    while i < events.len() {
+        let event = &events[i];
+
+        // Q:: question A:: answer
+        if matches!(event, Event::Start(Tag::Paragraph)) && is_text(events.get(i + 1), &|t| t.starts_with("Q::")) {
+            out.push(Event::Html(CowStr::Borrowed(FRONT)));
+            let mut flipped = false;
+            i += 1;
+            while i < events.len() {
+                match &events[i] {
+                    Event::End(TagEnd::Paragraph) => break,
+                    Event::Text(t) if !flipped && t.contains("A::") => {
+                        let (before, after) = t.split_once("A::").unwrap();
+                        let before = before.trim_start_matches("Q::").trim();
+                        if !before.is_empty() {
+                            out.push(Event::Text(before.to_string().into()));
+                        }
+                        out.push(Event::Html(CowStr::Borrowed(BACK)));
+                        out.push(Event::Text(after.trim_start().to_string().into()));
+                        flipped = true;
+                    }
+                    Event::Text(t) if t.starts_with("Q::") => {
+                        out.push(Event::Text(t.trim_start_matches("Q::").trim_start().to_string().into()));
+                    }
+                    // The line break right before "A::" would dangle at the end of the question
+                    Event::SoftBreak if !flipped && is_text(events.get(i + 1), &|t| t.starts_with("A::")) => {}
+                    other => out.push(other.clone()),
+                }
+                i += 1;
+            }
+            out.push(Event::Html(CowStr::Borrowed(if flipped { "</details></div>" } else { "</div></div>" })));
+            i += 1;
+            continue;
+        }
+
+        // > [!CARD] callout: the first paragraph is the question, everything after it the answer
+        if matches!(event, Event::Start(Tag::BlockQuote(_)))
+            && matches!(events.get(i + 1), Some(Event::Start(Tag::Paragraph)))
+            && is_text(events.get(i + 2), &|t| t.starts_with(CARD_MARKER))
+        {
+            out.push(Event::Html(CowStr::Borrowed(FRONT)));
+            out.push(Event::Start(Tag::Paragraph));
+            let rest = match &events[i + 2] {
+                Event::Text(t) => t[CARD_MARKER.len()..].trim_start().to_string(),
+                _ => unreachable!(),
+            };
+            if !rest.is_empty() {
+                out.push(Event::Text(rest.into()));
+            }
+            i += 3;
+            if matches!(events.get(i), Some(Event::SoftBreak)) {
+                i += 1;
+            }
+
+            let mut in_front = true;
+            let mut depth = 0;
+            while i < events.len() {
+                match &events[i] {
+                    Event::End(TagEnd::Paragraph) if in_front => {
+                        out.push(events[i].clone());
+                        out.push(Event::Html(CowStr::Borrowed(BACK)));
+                        in_front = false;
+                    }
+                    Event::Start(Tag::BlockQuote(_)) => {
+                        depth += 1;
+                        out.push(events[i].clone());
+                    }
+                    Event::End(TagEnd::BlockQuote(_)) if depth == 0 => break,
+                    Event::End(TagEnd::BlockQuote(_)) => {
+                        depth -= 1;
+                        out.push(events[i].clone());
+                    }
+                    other => out.push(other.clone()),
+                }
+                i += 1;
+            }
+            out.push(Event::Html(CowStr::Borrowed("</details></div>")));
+            i += 1;
+            continue;
+        }
+
+        out.push(event.clone());
+        i += 1;
+    }
+
+    out
+}
+
+/// Card faces for the Anki export: plain HTML with math left for Anki's MathJax (`\(…\)` / `\[…\]`)
+fn anki_html(markdown: &str) -> String {
+    let events = Parser::new_ext(markdown, markdown_options()).map(|event| match event {
+        Event::InlineMath(m) => Event::Text(format!("\\({}\\)", m).into()),
+        Event::DisplayMath(m) => Event::Text(format!("\\[{}\\]", m).into()),
+        other => other,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out.trim().to_string()
+}
+
+/// Every card on the site as a tab-separated file Anki can import directly
+pub fn export_tsv(index: &SiteIndex) -> String {
+    let field = |s: String| s.replace(['\t', '\n'], " ");
+    let mut tsv = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+
+    for page in &index.pages {
+        let mut tags: Vec<String> = Vec::new();
+        if !page.category.is_empty() {
+            tags.push(page.category.clone());
+        }
+        tags.extend(page.tags.iter().cloned());
+        let tags: Vec<String> = tags.iter().map(|t| t.replace(' ', "_")).collect();
+
+        for card in &page.cards {
+            let back = format!(
+                r#"{}<p><small><a href="{}">{}</a></small></p>"#,
+                anki_html(&card.back),
+                build_link(&page.path),
+                escape_html(&page.title)
+            );
+            tsv.push_str(&format!("{}\t{}\t{}\n", field(anki_html(&card.front)), field(back), tags.join(" ")));
+        }
+    }
+
+    tsv
+}
+
+/// The key a card's schedule is stored under. It comes from the question alone so that moving or
+/// renaming a page keeps the schedule; a question asked again elsewhere gets its own count.
+fn card_id(card: &Card, seen: &mut HashMap<String, usize>) -> String {
+    let count = seen.entry(card.front.trim().to_string()).or_insert(0);
+    *count += 1;
+    match *count {
+        1 => stable_id(&card.front),
+        n => stable_id(&format!("{}\n{}", card.front.trim(), n)),
+    }
+}
+
+fn cards_json(index: &SiteIndex) -> Value {
+    let mut seen = HashMap::new();
+    let cards: Vec<Value> = index.pages
+        .iter()
+        .flat_map(|page| page.cards.iter().map(move |card| (page, card)))
+        .map(|(page, card)| {
+            json!({
+                "id": card_id(card, &mut seen),
+                "front": render_fragment(&card.front, index),
+                "back": render_fragment(&card.back, index),
+                "title": page.title,
+                "url": build_link(&page.path),
+                "category": page.category,
+                "tags": page.tags,
+            })
+        })
+        .collect();
+    Value::Array(cards)
+}
+
+/// Content of the `/review` page; scheduling happens entirely in the wasm client
+pub fn render_review_page(index: &SiteIndex) -> String {
+    let mut categories: Vec<&str> = Vec::new();
+    let mut tags: Vec<&str> = Vec::new();
+    for page in index.pages.iter().filter(|p| !p.cards.is_empty()) {
+        if !page.category.is_empty() && !categories.contains(&page.category.as_str()) {
+            categories.push(&page.category);
+        }
+        for tag in &page.tags {
+            if !tags.contains(&tag.as_str()) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags.sort_unstable();
+
+    let options = |values: &[&str]| -> String {
+        values
+            .iter()
+            .map(|v| format!(r#"<option value="{0}">{0}</option>"#, escape_html(v)))
+            .collect()
+    };
+
+    let data = cards_json(index).to_string().replace("</", "<\\/");
+    format!(
+        r#"<h1>Review</h1>
+<div id="review" class="review">
+<div class="review-filters">
+<label>Category <select id="review-category"><option value="">All</option>{}</select></label>
+<label>Tag <select id="review-tag"><option value="">All</option>{}</select></label>
+<span class="review-status"></span>
+</div>
+<div class="flashcard review-card" hidden>
+<div class="flashcard-front"></div>
+<div class="flashcard-back" hidden></div>
+<p class="review-source"></p>
+</div>
+<div class="review-actions">
+<button class="review-show">Show answer</button>
+<span class="review-grades" hidden>
+<button data-grade="0">Again</button>
+<button data-grade="3">Hard</button>
+<button data-grade="4">Good</button>
+<button data-grade="5">Easy</button>
+</span>
+</div>
+<p class="review-done" hidden>Nothing due. Come back later!</p>
+</div>
+<script type="application/json" id="cards-data">{}</script>"#,
+        options(&categories),
+        options(&tags),
+        data
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_come_from_the_question() {
+        let card = |front: &str| Card { front: front.to_string(), back: "answer".to_string() };
+        let mut seen = HashMap::new();
+        let first = card_id(&card("What is gm?"), &mut seen);
+        let again = card_id(&card("What is gm? "), &mut seen);
+        let other = card_id(&card("What is ro?"), &mut seen);
+        assert_eq!(first, stable_id("What is gm?"));
+        assert_ne!(first, again);
+        assert_ne!(first, other);
+        // A fresh build, whatever page the card is on now, gives the same ids
+        assert_eq!(card_id(&card("What is gm?"), &mut HashMap::new()), first);
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt::Write;
+
+use crate::site::escape_html;
+use crate::units::format_si;
+
+/// Fallback colours written as presentation attributes, for renderers that ignore the
+/// stylesheet (feeds, exports). The `.chart` rules in main.scss override them per theme.
+pub const GRID_COLOR: &str = "#e5e7eb";
+pub const AXIS_COLOR: &str = "#6b7280";
+pub const TEXT_COLOR: &str = "#1a1a1a";
+pub const SERIES_COLORS: &[&str] = &["#2563eb", "#dc2626", "#16a34a", "#d97706", "#7c3aed", "#0891b2"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    Linear,
+    Log,
+}
+
+#[derive(Clone, Debug)]
+pub struct Axis {
+    pub min: f64,
+    pub max: f64,
+    pub scale: Scale,
+    pub label: String,
+    /// Appended to tick labels, with an SI prefix when `si` is set ("10 kHz")
+    pub unit: String,
+    pub si: bool,
+    /// Fixed tick spacing instead of the automatic 1/2/5 steps, e.g. 45° for phase
+    pub step: Option<f64>,
+}
+
+impl Axis {
+    pub fn linear(min: f64, max: f64, label: &str) -> Self {
+        Axis { min, max, scale: Scale::Linear, label: label.to_string(), unit: String::new(), si: false, step: None }
+    }
+
+    pub fn log(min: f64, max: f64, label: &str) -> Self {
+        Axis { min, max, scale: Scale::Log, label: label.to_string(), unit: String::new(), si: true, step: None }
+    }
+
+    pub fn unit(mut self, unit: &str) -> Self {
+        self.unit = unit.to_string();
+        self
+    }
+
+    pub fn step(mut self, step: f64) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Widen a linear axis to round tick values, e.g. -37..52 becomes -40..60
+    pub fn nice(mut self) -> Self {
+        if self.scale == Scale::Linear {
+            let step = self.step.unwrap_or_else(|| nice_step(self.max - self.min));
+            self.min = (self.min / step).floor() * step;
+            self.max = (self.max / step).ceil() * step;
+        } else {
+            self.min = 10f64.powf(self.min.log10().floor());
+            self.max = 10f64.powf(self.max.log10().ceil());
+        }
+        self
+    }
+
+    /// Position of a value along the axis, 0 at `min` and 1 at `max`
+    pub fn frac(&self, v: f64) -> f64 {
+        match self.scale {
+            Scale::Linear => (v - self.min) / (self.max - self.min),
+            Scale::Log => (v.log10() - self.min.log10()) / (self.max.log10() - self.min.log10()),
+        }
+    }
+
+    /// Tick positions as (value, is_major); only major ticks get labels
+    pub fn ticks(&self) -> Vec<(f64, bool)> {
+        let mut ticks = Vec::new();
+        match self.scale {
+            Scale::Linear => {
+                let step = self.step.unwrap_or_else(|| nice_step(self.max - self.min));
+                let mut v = (self.min / step).ceil() * step;
+                while v <= self.max + step * 1e-9 {
+                    // Snap so 0.30000000000000004 labels as 0.3
+                    ticks.push(((v / step).round() * step, true));
+                    v += step;
+                }
+            }
+            Scale::Log => {
+                let decades = (self.max.log10() - self.min.log10()).ceil() as i32;
+                let every = if decades > 10 { 2 } else { 1 };
+                for k in self.min.log10().floor() as i32..=self.max.log10().ceil() as i32 {
+                    for m in 1..10 {
+                        let v = m as f64 * 10f64.powi(k);
+                        if v >= self.min * (1.0 - 1e-9) && v <= self.max * (1.0 + 1e-9) {
+                            ticks.push((v, m == 1 && k.rem_euclid(every) == 0));
+                        }
+                    }
+                }
+            }
+        }
+        ticks
+    }
+
+    pub fn format(&self, v: f64) -> String {
+        if self.si {
+            format_si(v, &self.unit)
+        } else if self.unit.is_empty() {
+            format_number(v)
+        } else {
+            format!("{}{}", format_number(v), self.unit)
+        }
+    }
+}
+
+/// A 1, 2 or 5 × 10ⁿ step giving roughly five to ten ticks over `range`
+fn nice_step(range: f64) -> f64 {
+    if range <= 0.0 || !range.is_finite() {
+        return 1.0;
+    }
+    let raw = range / 6.0;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = match raw / magnitude {
+        r if r < 1.5 => 1.0,
+        r if r < 3.5 => 2.0,
+        r if r < 7.5 => 5.0,
+        _ => 10.0,
+    };
+    step * magnitude
+}
+
+/// A number with no more digits than it needs: 0.5, 12, 1.25, 1e-7
+pub fn format_number(v: f64) -> String {
+    if v == 0.0 {
+        return "0".to_string();
+    }
+    if v.abs() >= 1e6 || v.abs() < 1e-3 {
+        return format!("{:e}", v);
+    }
+    let s = format!("{:.4}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+/// One plotting area inside an SVG, in SVG pixel coordinates
+pub struct Panel {
+    pub x: Axis,
+    pub y: Axis,
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Panel {
+    pub fn px(&self, x: f64) -> f64 {
+        self.left + self.x.frac(x) * self.width
+    }
+
+    pub fn py(&self, y: f64) -> f64 {
+        self.top + (1.0 - self.y.frac(y)) * self.height
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (fx, fy) = (self.x.frac(x), self.y.frac(y));
+        (-1e-9..=1.0 + 1e-9).contains(&fx) && (-1e-9..=1.0 + 1e-9).contains(&fy)
+    }
+
+    /// Grid lines, frame, tick labels and axis titles. `x_labels` is off for the upper
+    /// panel of a stacked plot that shares its x axis with the one below.
+    pub fn frame(&self, svg: &mut String, x_labels: bool) {
+        let (right, bottom) = (self.left + self.width, self.top + self.height);
+
+        for (v, major) in self.x.ticks() {
+            let x = self.px(v);
+            let class = if major { "chart-grid" } else { "chart-grid chart-grid-minor" };
+            let _ = write!(svg, r#"<line class="{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, class, x, self.top, x, bottom, GRID_COLOR);
+            if major && x_labels {
+                let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="middle" fill="{}">{}</text>"#, x, bottom + 16.0, TEXT_COLOR, escape_html(&self.x.format(v)));
+            }
+        }
+        for (v, major) in self.y.ticks() {
+            let y = self.py(v);
+            let class = if major { "chart-grid" } else { "chart-grid chart-grid-minor" };
+            let _ = write!(svg, r#"<line class="{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, class, self.left, y, right, y, GRID_COLOR);
+            if major {
+                let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="end" dominant-baseline="middle" fill="{}">{}</text>"#, self.left - 6.0, y, TEXT_COLOR, escape_html(&self.y.format(v)));
+            }
+        }
+
+        let _ = write!(svg, r#"<rect class="chart-axis" x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="none" stroke="{}"/>"#, self.left, self.top, self.width, self.height, AXIS_COLOR);
+
+        if x_labels && !self.x.label.is_empty() {
+            let _ = write!(svg, r#"<text class="chart-label" x="{:.1}" y="{:.1}" text-anchor="middle" fill="{}">{}</text>"#, self.left + self.width / 2.0, bottom + 34.0, TEXT_COLOR, escape_html(&self.x.label));
+        }
+        if !self.y.label.is_empty() {
+            let (x, y) = (self.left - 46.0, self.top + self.height / 2.0);
+            let _ = write!(svg, r#"<text class="chart-label" x="{:.1}" y="{:.1}" text-anchor="middle" transform="rotate(-90 {:.1} {:.1})" fill="{}">{}</text>"#, x, y, x, y, TEXT_COLOR, escape_html(&self.y.label));
+        }
+    }
+
+    /// Draw a curve, breaking it wherever a point is undefined or leaves the panel
+    pub fn curve(&self, svg: &mut String, points: &[(f64, f64)], series: usize, dashed: bool) {
+        let color = SERIES_COLORS[series % SERIES_COLORS.len()];
+        let dash = if dashed { r#" stroke-dasharray="6 4""# } else { "" };
+        let mut segment: Vec<String> = Vec::new();
+        let flush = |segment: &mut Vec<String>, svg: &mut String| {
+            if segment.len() > 1 {
+                let _ = write!(svg, r#"<polyline class="chart-series chart-series-{}" points="{}" fill="none" stroke="{}" stroke-width="2"{}/>"#, series % SERIES_COLORS.len(), segment.join(" "), color, dash);
+            }
+            segment.clear();
+        };
+
+        let mut last_y: Option<f64> = None;
+        for &(x, y) in points {
+            if x.is_finite() && y.is_finite() && self.contains(x, y) {
+                // A jump across most of the panel between samples is an asymptote, not a line
+                if last_y.is_some_and(|last| (self.py(y) - last).abs() > self.height * 0.8) {
+                    flush(&mut segment, svg);
+                }
+                segment.push(format!("{:.1},{:.1}", self.px(x), self.py(y)));
+                last_y = Some(self.py(y));
+            } else {
+                flush(&mut segment, svg);
+                last_y = None;
+            }
+        }
+        flush(&mut segment, svg);
+    }
+
+    /// A dashed guide line between two data points, e.g. a crossover marker
+    pub fn guide(&self, svg: &mut String, from: (f64, f64), to: (f64, f64)) {
+        let _ = write!(svg, r#"<line class="chart-guide" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-dasharray="4 3"/>"#, self.px(from.0), self.py(from.1), self.px(to.0), self.py(to.1), AXIS_COLOR);
+    }
+
+    pub fn dot(&self, svg: &mut String, at: (f64, f64), series: usize) {
+        let _ = write!(svg, r#"<circle class="chart-series-{}" cx="{:.1}" cy="{:.1}" r="3.5" fill="{}"/>"#, series % SERIES_COLORS.len(), self.px(at.0), self.py(at.1), SERIES_COLORS[series % SERIES_COLORS.len()]);
+    }
+
+    /// A note placed next to a data point, kept inside the panel; `dy` shifts it down in pixels
+    pub fn note(&self, svg: &mut String, at: (f64, f64), dy: f64, text: &str) {
+        let x = self.px(at.0);
+        let (x, anchor) = if x > self.left + self.width * 0.7 { (x - 6.0, "end") } else { (x + 6.0, "start") };
+        let y = (self.py(at.1) + dy).clamp(self.top + 12.0, self.top + self.height - 4.0) - dy;
+        let _ = write!(svg, r#"<text class="chart-note" x="{:.1}" y="{:.1}" text-anchor="{}" fill="{}">{}</text>"#, x, y + dy, anchor, TEXT_COLOR, escape_html(text));
+    }
+
+    /// Legend in the top-right corner, one entry per series
+    pub fn legend(&self, svg: &mut String, names: &[String]) {
+        if names.len() < 2 {
+            return;
+        }
+        let width = 24.0 + 7.0 * names.iter().map(|n| n.chars().count()).max().unwrap_or(0) as f64;
+        let x = self.left + self.width - width - 8.0;
+        let _ = write!(svg, r#"<rect class="chart-legend" x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="white" fill-opacity="0.85" stroke="{}"/>"#, x - 6.0, self.top + 6.0, width + 8.0, 18.0 * names.len() as f64 + 6.0, GRID_COLOR);
+        for (i, name) in names.iter().enumerate() {
+            let y = self.top + 18.0 + 18.0 * i as f64;
+            let color = SERIES_COLORS[i % SERIES_COLORS.len()];
+            let _ = write!(svg, r#"<line class="chart-series-{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/>"#, i % SERIES_COLORS.len(), x, y, x + 16.0, y, color);
+            let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" dominant-baseline="middle" fill="{}">{}</text>"#, x + 22.0, y, TEXT_COLOR, escape_html(name));
+        }
+    }
+}
+
+/// Wrap SVG content in a figure; a caption is numbered along with the page's images
+pub fn figure(kind: &str, width: f64, height: f64, body: &str, caption: &str) -> String {
+    if caption.is_empty() {
+        return format!(
+            r#"<figure class="chart chart-{0}">
+<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {1} {2}" width="{1}" height="{2}" font-family="Inter, sans-serif" font-size="11">{3}</svg>
+</figure>"#,
+            kind, width, height, body
+        );
+    }
+    format!(
+        r#"<figure class="chart chart-{0}">
+<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {1} {2}" width="{1}" height="{2}" role="img" aria-label="{3}" font-family="Inter, sans-serif" font-size="11">{4}</svg>
+<figcaption class="numbered">{3}</figcaption>
+</figure>"#,
+        kind,
+        width,
+        height,
+        escape_html(caption),
+        body
+    )
+}
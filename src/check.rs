@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use pulldown_cmark::{Event, Parser, Tag};
+
+use crate::latex::image_file;
+use crate::render::markdown_options;
+use crate::site::{content_dir, SiteIndex};
+use crate::{frontmatter, SidebarItem};
+
+/// What `check` found; any error fails the run
+#[derive(Debug, Default)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// The KaTeX parse error inside the JavaScript failure the katex crate reports, up to the
+/// position it points at
+fn katex_message(e: &katex::Error) -> String {
+    let message = e.to_string();
+    let Some(start) = message.find("KaTeX parse error: ") else { return message };
+    let message = &message[start + "KaTeX parse error: ".len()..];
+    // The rest repeats the source with an escaped underline under the offending part
+    let end = message.find(" at position ").and_then(|i| message[i..].find(':').map(|j| i + j)).unwrap_or(message.len());
+    message[..end].replace("\\\\", "\\")
+}
+
+/// Check every page for links to pages or images that do not exist, math KaTeX cannot typeset
+/// and frontmatter `parse` would misread. Orphan pages are only warnings.
+pub fn run(index: &SiteIndex, sidebar: &[SidebarItem]) -> Report {
+    let mut report = Report { errors: index.problems.clone(), warnings: Vec::new() };
+    let paths: HashSet<&str> = index.pages.iter().map(|p| p.path.as_str()).collect();
+    let inline = katex::Opts::builder().display_mode(false).build().unwrap();
+    let display = katex::Opts::builder().display_mode(true).build().unwrap();
+
+    for page in &index.pages {
+        let Ok(source) = std::fs::read_to_string(&page.file) else {
+            report.errors.push(format!("{}: cannot read {}", page.path, page.file.display()));
+            continue;
+        };
+        for problem in frontmatter::check(&source) {
+            report.errors.push(format!("{}: {}", page.path, problem));
+        }
+
+        let mut reported = HashSet::new();
+        for link in &page.links {
+            // Chapter directories have no page but are still valid targets
+            let is_directory = Path::new(content_dir()).join(link.target.trim_start_matches('/')).is_dir();
+            if !paths.contains(link.target.as_str()) && !is_directory && reported.insert(&link.target) {
+                report.errors.push(format!("{}: link to missing page {}", page.path, link.target));
+            }
+        }
+
+        for event in Parser::new_ext(&source, markdown_options()) {
+            match event {
+                Event::Start(Tag::Image { dest_url, .. }) if !dest_url.contains("://") && !dest_url.starts_with("data:") => {
+                    let file = image_file(&page.path, &dest_url);
+                    if !file.is_file() {
+                        report.errors.push(format!("{}: image {} not found at {}", page.path, dest_url, file.display()));
+                    }
+                }
+                Event::InlineMath(tex) => {
+                    if let Err(e) = katex::render_with_opts(&tex, &inline) {
+                        report.errors.push(format!("{}: ${}$: {}", page.path, tex, katex_message(&e)));
+                    }
+                }
+                Event::DisplayMath(tex) => {
+                    if let Err(e) = katex::render_with_opts(&tex, &display) {
+                        report.errors.push(format!("{}: $${}$$: {}", page.path, tex.trim(), katex_message(&e)));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for orphan in index.orphans(sidebar) {
+        report.warnings.push(format!("orphan page {} ({}) has no inbound links and is not in the sidebar", orphan.path, orphan.file.display()));
+    }
+    report
+}
@@ -0,0 +1,295 @@
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use pulldown_cmark::Options;
+use serde::Deserialize;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Read when no `--config` is given; the defaults below apply when it does not exist
+pub const DEFAULT_FILE: &str = "notes.toml";
+
+/// Markdown extensions by their name in `[markdown] extensions`
+const EXTENSIONS: &[(&str, Options)] = &[
+    ("tables", Options::ENABLE_TABLES),
+    ("footnotes", Options::ENABLE_FOOTNOTES),
+    ("strikethrough", Options::ENABLE_STRIKETHROUGH),
+    ("tasklists", Options::ENABLE_TASKLISTS),
+    ("smart_punctuation", Options::ENABLE_SMART_PUNCTUATION),
+    ("math", Options::ENABLE_MATH),
+    ("wikilinks", Options::ENABLE_WIKILINKS),
+];
+
+const THEMES: &[&str] = &["light", "dark"];
+
+/// Command-line flags that override a `notes.toml` setting, with the setting they override
+pub const FLAGS: &[(&str, &str)] = &[
+    ("--title", "site.title"),
+    ("--author", "site.author"),
+    ("--base-url", "site.base_url"),
+    ("--content-dir", "site.content_dir"),
+    ("--host", "server.address"),
+    ("--port", "server.port"),
+    ("--theme", "theme.default"),
+];
+
+/// Flags that take no value and turn a setting on
+pub const SWITCHES: &[(&str, &str)] = &[("--watch", "server.watch")];
+
+/// Site settings from `notes.toml`:
+///
+/// ```toml
+/// [site]
+/// title = "Sawy's Notes"
+/// base_url = "https://example.github.io/SawysNotes"
+///
+/// [server]
+/// port = 8080
+///
+/// [features]
+/// og_images = false
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub site: SiteConfig,
+    pub server: ServerConfig,
+    pub markdown: MarkdownConfig,
+    pub theme: ThemeConfig,
+    pub features: Features,
+    /// Set for `serve` rather than read from the file: pages are previewed by their author, so
+    /// problems such as broken links are shown instead of hidden
+    #[serde(skip)]
+    pub serving: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub title: String,
+    pub author: String,
+    /// A path prefix such as "/SawysNotes", or a full URL whose path is the prefix and whose
+    /// origin is used for absolute links instead of the request's host
+    pub base_url: String,
+    pub content_dir: String,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            title: "Sawy's Notes".to_string(),
+            author: "Sawy".to_string(),
+            base_url: String::new(),
+            content_dir: "assets/content".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// Reload open pages when anything under the content directory or `style/` changes
+    pub watch: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { address: "127.0.0.1".to_string(), port: 3000, watch: false }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownConfig {
+    /// Names from `EXTENSIONS`; all of them by default
+    pub extensions: Vec<String>,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig { extensions: EXTENSIONS.iter().map(|(name, _)| name.to_string()).collect() }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Theme before the reader picks one, "light" or "dark"
+    pub default: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig { default: "light".to_string() }
+    }
+}
+
+/// Optional parts of the site, all on by default
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub graph: bool,
+    pub review: bool,
+    pub feeds: bool,
+    pub og_images: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features { graph: true, review: true, feeds: true, og_images: true }
+    }
+}
+
+impl Config {
+    /// `path`, or `notes.toml` when it exists. The SITE_URL environment variable still
+    /// overrides `site.base_url`.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let file = path.unwrap_or(DEFAULT_FILE);
+        let mut config = match std::fs::read_to_string(file) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", file, e.message()))?,
+            Err(_) if path.is_none() && !Path::new(file).exists() => Config::default(),
+            Err(e) => return Err(format!("could not read {}: {}", file, e)),
+        };
+        if let Ok(site_url) = std::env::var("SITE_URL") {
+            config.site.base_url = site_url;
+        }
+        Ok(config)
+    }
+
+    /// Override the setting a command-line flag stands for
+    pub fn set(&mut self, flag: &str, value: &str) -> Result<(), String> {
+        match flag {
+            "--title" => self.site.title = value.to_string(),
+            "--author" => self.site.author = value.to_string(),
+            "--base-url" => self.site.base_url = value.to_string(),
+            "--content-dir" => self.site.content_dir = value.to_string(),
+            "--host" => self.server.address = value.to_string(),
+            "--port" => self.server.port = value.parse().map_err(|_| format!("--port: {:?} is not a port number", value))?,
+            "--theme" => self.theme.default = value.to_string(),
+            "--watch" => self.server.watch = true,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+        Ok(())
+    }
+
+    /// Everything wrong with the settings, empty when they are usable
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.site.title.trim().is_empty() {
+            problems.push("site.title must not be empty".to_string());
+        }
+        let base_url = &self.site.base_url;
+        if !base_url.is_empty() && !base_url.starts_with('/') && !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            problems.push(format!("site.base_url {:?} must be a path starting with / or an http(s) URL", base_url));
+        }
+        if !Path::new(&self.site.content_dir).is_dir() {
+            problems.push(format!("site.content_dir {:?} is not a directory", self.site.content_dir));
+        }
+        if self.server.address.parse::<IpAddr>().is_err() {
+            problems.push(format!("server.address {:?} is not an IP address", self.server.address));
+        }
+        if self.server.port == 0 {
+            problems.push("server.port must not be 0".to_string());
+        }
+        for name in &self.markdown.extensions {
+            if !EXTENSIONS.iter().any(|(known, _)| known == name) {
+                let known: Vec<&str> = EXTENSIONS.iter().map(|(known, _)| *known).collect();
+                problems.push(format!("markdown.extensions: unknown extension {:?} (known: {})", name, known.join(", ")));
+            }
+        }
+        if !THEMES.contains(&self.theme.default.as_str()) {
+            problems.push(format!("theme.default {:?} must be one of {}", self.theme.default, THEMES.join(", ")));
+        }
+        problems
+    }
+
+    /// The prefix every link gets, e.g. "/SawysNotes", without a trailing slash
+    pub fn base_path(&self) -> &str {
+        let url = self.site.base_url.as_str();
+        let path = match url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+            None => url,
+        };
+        path.trim_end_matches('/')
+    }
+
+    /// `scheme://host` of `site.base_url`, when it is a full URL
+    pub fn origin(&self) -> Option<&str> {
+        let url = self.site.base_url.as_str();
+        let (scheme, rest) = url.split_once("://")?;
+        Some(&url[..scheme.len() + 3 + rest.find('/').unwrap_or(rest.len())])
+    }
+
+    pub fn bind_address(&self) -> SocketAddr {
+        let ip = self.server.address.parse().unwrap_or(IpAddr::from([127, 0, 0, 1]));
+        SocketAddr::new(ip, self.server.port)
+    }
+
+    /// The pulldown-cmark options for the enabled extensions. Metadata blocks are always on
+    /// since frontmatter depends on them.
+    pub fn markdown_options(&self) -> Options {
+        let mut options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+        for (name, option) in EXTENSIONS {
+            if self.markdown.extensions.iter().any(|n| n == name) {
+                options.insert(*option);
+            }
+        }
+        options
+    }
+}
+
+/// `--flag value` pairs in the order given, with an empty value for switches
+pub type Flags = Vec<(String, String)>;
+
+/// Split `args` into `--flag value` pairs and the remaining arguments. `--flag=value` works too,
+/// and flags and switches may appear anywhere.
+pub fn split_args(args: Vec<String>) -> Result<(Flags, Vec<String>), String> {
+    let mut flags = Flags::new();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            rest.push(arg);
+            continue;
+        }
+        if SWITCHES.iter().any(|(switch, _)| *switch == arg) {
+            flags.push((arg, String::new()));
+            continue;
+        }
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), value.to_string()),
+            None => {
+                let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                (arg, value)
+            }
+        };
+        if flag != "--config" && !FLAGS.iter().any(|(known, _)| *known == flag) {
+            return Err(format!("unknown option {}", flag));
+        }
+        flags.push((flag, value));
+    }
+    Ok((flags, rest))
+}
+
+/// The configuration from `--config` or `notes.toml`, with the other flags applied
+pub fn from_flags(flags: &[(String, String)]) -> Result<Config, String> {
+    let path = flags.iter().rev().find(|(flag, _)| flag == "--config").map(|(_, value)| value.as_str());
+    let mut config = Config::load(path)?;
+    for (flag, value) in flags.iter().filter(|(flag, _)| flag != "--config") {
+        config.set(flag, value)?;
+    }
+    Ok(config)
+}
+
+/// Make `config` the one `get` returns; only the first call has an effect
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// The site configuration, the defaults until `init` is called
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::render::{render_fragment, strip_paragraph};
+use crate::site::{escape_html, Page, SiteIndex};
+use crate::{build_link, parse_numbered_name};
+
+static REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[@([A-Za-z0-9_:.-]+)\]").unwrap());
+
+/// Environment kinds that share one running counter, like amsthm's `\newtheorem{lemma}[theorem]`
+const NUMBERED: &[&str] = &["theorem", "lemma", "proposition", "corollary", "definition", "example", "remark"];
+const UNNUMBERED: &[&str] = &["proof"];
+
+/// A `:::theorem[Title]{#label}` … `:::` block
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pub kind: String,
+    pub title: String,
+    pub label: Option<String>,
+    /// "3" when numbered per page, "4.3" when numbered per chapter; None for proofs
+    pub number: Option<String>,
+}
+
+impl Environment {
+    fn name(&self) -> String {
+        let mut chars = self.kind.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            None => String::new(),
+        }
+    }
+
+    /// How a reference to this environment reads, e.g. "Theorem 4.3"
+    fn reference(&self) -> String {
+        match &self.number {
+            Some(n) => format!("{} {}", self.name(), n),
+            None => self.name(),
+        }
+    }
+}
+
+/// Parse an opening directive line such as `:::theorem[Cauchy's Integral Formula]{#cauchy}`
+pub fn parse_directive(line: &str) -> Option<Environment> {
+    let rest = line.trim().strip_prefix(":::")?;
+    let kind_end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    let kind = rest[..kind_end].to_ascii_lowercase();
+    if !NUMBERED.contains(&kind.as_str()) && !UNNUMBERED.contains(&kind.as_str()) {
+        return None;
+    }
+
+    let mut rest = rest[kind_end..].trim();
+    let mut title = String::new();
+    if let Some(after) = rest.strip_prefix('[') {
+        let end = after.rfind(']')?;
+        title = after[..end].trim().to_string();
+        rest = after[end + 1..].trim();
+    }
+    let label = rest
+        .strip_prefix("{#")
+        .and_then(|l| l.strip_suffix('}'))
+        .map(|l| l.trim().to_string());
+
+    Some(Environment { kind, title, label, number: None })
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Environments on a page in document order, not yet numbered
+pub fn extract(source: &str) -> Vec<Environment> {
+    let mut in_code = false;
+    source
+        .lines()
+        .filter(|line| {
+            if is_fence(line) {
+                in_code = !in_code;
+            }
+            !in_code
+        })
+        .filter_map(parse_directive)
+        .collect()
+}
+
+/// Number every environment on the site. Pages whose frontmatter says `numbering: chapter`
+/// continue the count of earlier pages in the same chapter and prefix the chapter number.
+pub fn number(pages: &mut [Page]) {
+    // Sidebar order of every page within its chapter (the directory right under the category)
+    let order_key = |page: &Page| -> Vec<(i32, String)> {
+        page.path.split('/').filter(|s| !s.is_empty()).map(parse_numbered_name).collect()
+    };
+    let chapter_of = |page: &Page| -> Option<String> {
+        let parts: Vec<&str> = page.path.split('/').filter(|s| !s.is_empty()).collect();
+        (parts.len() > 2).then(|| format!("{}/{}", parts[0], parts[1]))
+    };
+
+    let mut order: Vec<usize> = (0..pages.len()).collect();
+    order.sort_by_key(|&i| order_key(&pages[i]));
+
+    let mut chapter_counts: HashMap<String, usize> = HashMap::new();
+    for i in order {
+        let chapter = chapter_of(&pages[i]);
+        let chapter_number = chapter
+            .as_deref()
+            .and_then(|c| c.split('/').nth(1))
+            .map(|c| parse_numbered_name(c).0)
+            .filter(|&n| n != i32::MAX);
+        let per_chapter = pages[i].numbering == "chapter" && chapter_number.is_some();
+
+        let offset = chapter.as_ref().and_then(|c| chapter_counts.get(c)).copied().unwrap_or(0);
+        let mut count = 0;
+        for env in &mut pages[i].environments {
+            if !NUMBERED.contains(&env.kind.as_str()) {
+                continue;
+            }
+            count += 1;
+            env.number = Some(match (per_chapter, chapter_number) {
+                (true, Some(chapter)) => format!("{}.{}", chapter, offset + count),
+                _ => count.to_string(),
+            });
+        }
+        // Pages numbered on their own leave the chapter's count alone
+        if let (true, Some(c)) = (per_chapter, chapter) {
+            *chapter_counts.entry(c).or_default() += count;
+        }
+    }
+}
+
+/// Find a labelled environment, preferring the current page
+fn find_label<'a>(index: &'a SiteIndex, page: Option<&'a Page>, label: &str) -> Option<(&'a Page, &'a Environment)> {
+    let on = |p: &'a Page| p.environments.iter().find(|e| e.label.as_deref() == Some(label)).map(|e| (p, e));
+    page.and_then(on).or_else(|| index.pages.iter().find_map(on))
+}
+
+/// Replace environment directives with HTML wrappers and `[@label]` references with links,
+/// leaving the markdown between them for the normal pipeline (math included) to render
+pub fn preprocess(source: &str, index: &SiteIndex, page_path: &str) -> String {
+    let page = index.pages.iter().find(|p| p.path == page_path);
+    let mut numbered = page.map(|p| p.environments.iter()).into_iter().flatten();
+    let mut open: Vec<&str> = Vec::new();
+    let mut in_code = false;
+    let mut out = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        if is_fence(line) {
+            in_code = !in_code;
+        }
+        if in_code {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        if let Some(mut env) = parse_directive(line) {
+            // The index saw the same directives in the same order, so it holds the numbers
+            if let Some(known) = numbered.next() {
+                env.number = known.number.clone();
+            }
+            let id = env.label.as_deref().map(|l| format!(r#" id="{}""#, escape_html(l))).unwrap_or_default();
+            let title = if env.title.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", strip_paragraph(&render_fragment(&env.title, index)))
+            };
+
+            if env.kind == "proof" {
+                let summary = if env.title.is_empty() { "Proof.".to_string() } else { format!("Proof{}.", title) };
+                out.push_str(&format!(
+                    "\n<details class=\"env env-proof\"{}>\n<summary class=\"env-title\"><em>{}</em></summary>\n\n",
+                    id, summary
+                ));
+                open.push("\n<p class=\"qed\" aria-label=\"QED\">∎</p>\n</details>\n\n");
+            } else {
+                out.push_str(&format!(
+                    "\n<div class=\"env env-{}\"{}>\n<p class=\"env-title\"><strong>{}</strong>{}.</p>\n\n",
+                    env.kind, id, env.reference(), title
+                ));
+                open.push("\n</div>\n\n");
+            }
+            continue;
+        }
+
+        if line.trim() == ":::" {
+            if let Some(close) = open.pop() {
+                out.push_str(close);
+                continue;
+            }
+        }
+
+        let line = REF_RE.replace_all(line, |caps: &regex::Captures| {
+            match find_label(index, page, &caps[1]) {
+                Some((target, env)) => {
+                    let href = if Some(target.path.as_str()) == page.map(|p| p.path.as_str()) {
+                        format!("#{}", &caps[1])
+                    } else {
+                        format!("{}#{}", build_link(&target.path), &caps[1])
+                    };
+                    format!(r#"<a class="env-ref" href="{}">{}</a>"#, escape_html(&href), env.reference())
+                }
+                None => caps[0].to_string(),
+            }
+        });
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    // Unterminated environments still get closed so the layout isn't broken
+    while let Some(close) = open.pop() {
+        out.push_str(close);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(path: &str, numbering: &str, theorems: usize) -> Page {
+        Page {
+            path: path.to_string(),
+            file: Default::default(),
+            title: String::new(),
+            category: String::new(),
+            tags: Vec::new(),
+            links: Vec::new(),
+            cards: Vec::new(),
+            environments: (0..theorems).map(|_| parse_directive(":::theorem").unwrap()).collect(),
+            numbering: numbering.to_string(),
+            description: String::new(),
+        }
+    }
+
+    fn numbers(page: &Page) -> Vec<&str> {
+        page.environments.iter().filter_map(|e| e.number.as_deref()).collect()
+    }
+
+    #[test]
+    fn only_chapter_numbered_pages_continue_the_count() {
+        let mut pages = vec![
+            page("/math/2) Analysis/1) Limits", "chapter", 2),
+            page("/math/2) Analysis/2) Aside", "", 3),
+            page("/math/2) Analysis/3) Series", "chapter", 1),
+            page("/math/2) Analysis/4) Also Aside", "page", 1),
+        ];
+        number(&mut pages);
+        assert_eq!(numbers(&pages[0]), ["2.1", "2.2"]);
+        assert_eq!(numbers(&pages[1]), ["1", "2", "3"]);
+        assert_eq!(numbers(&pages[2]), ["2.3"]);
+        assert_eq!(numbers(&pages[3]), ["1"]);
+    }
+}
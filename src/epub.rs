@@ -0,0 +1,725 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use svg2pdf::usvg::{TreeWriting, XmlOptions};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::book::Book;
+use crate::config;
+use crate::html::{self, Element, Node};
+use crate::latex::image_file;
+use crate::pdf::Fonts;
+use crate::render::slugify;
+use crate::site::{escape_html, SiteIndex};
+
+const MIMETYPE: &str = "application/epub+zip";
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.45; }
+h1, h2, h3, h4, dt, th, figcaption { font-family: sans-serif; }
+.book-number { margin-right: 0.6em; }
+pre { font-size: 0.85em; white-space: pre-wrap; }
+table { border-collapse: collapse; margin: 0 auto; }
+th, td { border-bottom: 1px solid #d1d5db; padding: 0.2em 0.6em; }
+figure { text-align: center; margin: 1em 0; }
+img { max-width: 100%; height: auto; }
+.katex-display { display: block; text-align: center; margin: 1em 0; }
+.equation { display: flex; align-items: center; }
+.equation .katex-display { flex: 1; }
+.callout, blockquote { border-left: 3px solid #9ca3af; padding: 0.2em 0.8em; margin-left: 0; }
+nav ol { list-style: none; }
+";
+
+/// Interactive markup that has nothing to show on an e-reader
+const DROP: &[&str] = &["script", "style", "button", "input", "select", "textarea", "canvas", "iframe", "form"];
+
+/// A file of the package under `OEBPS/`
+struct Item {
+    href: String,
+    media_type: &'static str,
+    properties: Option<&'static str>,
+    data: Vec<u8>,
+}
+
+fn media_type(extension: &str) -> Option<&'static str> {
+    match extension.to_ascii_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Turns one rendered page into XHTML, collecting the images it uses
+struct Converter<'a> {
+    book: &'a Book,
+    page_path: String,
+    images: &'a mut Vec<Item>,
+    fonts: &'a mut Option<Fonts>,
+    warnings: &'a mut Vec<String>,
+    has_math: bool,
+}
+
+impl Converter<'_> {
+    fn nodes(&mut self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(&escape_html(text)),
+                Node::Element(e) => self.element(e, out),
+            }
+        }
+    }
+
+    fn element(&mut self, e: &Element, out: &mut String) {
+        if DROP.contains(&e.tag.as_str()) || e.attr("hidden").is_some() || e.has_class("quiz-feedback") {
+            return;
+        }
+        // KaTeX already carries MathML beside its HTML rendering; e-readers only need the former
+        if e.has_class("katex") {
+            self.math(e, false, out);
+            return;
+        }
+        // A paragraph holding nothing but a formula is display math
+        if e.tag == "p"
+            && e.elements().count() == 1
+            && e.elements().all(|c| c.has_class("katex"))
+            && e.children.iter().all(|c| !matches!(c, Node::Text(t) if !t.trim().is_empty()))
+        {
+            out.push_str("<p>");
+            e.elements().for_each(|katex| self.math(katex, true, out));
+            out.push_str("</p>");
+            return;
+        }
+        match e.tag.as_str() {
+            "svg" => {
+                let source = e.source.clone().unwrap_or_default();
+                match self.svg(source.as_bytes(), None) {
+                    Ok(href) => out.push_str(&format!("<img src=\"{}\" alt=\"\"/>", escape_html(&href))),
+                    Err(err) => self.warnings.push(format!("{}: figure left out: {}", self.page_path, err)),
+                }
+            }
+            "img" => {
+                let alt = e.attr("alt").unwrap_or_default();
+                match self.image(e.attr("src").unwrap_or_default()) {
+                    Ok(href) => out.push_str(&format!("<img src=\"{}\" alt=\"{}\"/>", escape_html(&href), escape_html(alt))),
+                    Err(err) => {
+                        self.warnings.push(format!("{}: image left out: {}", self.page_path, err));
+                        out.push_str(&escape_html(alt));
+                    }
+                }
+            }
+            "a" => match e.attr("href").map(|href| self.link(href)) {
+                Some(Some(href)) => {
+                    let mut a = e.clone();
+                    a.attrs.retain(|(name, _)| name != "href");
+                    a.attrs.push(("href".to_string(), href));
+                    self.open(&a, out);
+                    self.nodes(&e.children, out);
+                    out.push_str("</a>");
+                }
+                // Pages outside the book have nowhere to go
+                _ => self.nodes(&e.children, out),
+            },
+            "summary" if e.text().trim() == "Show answer" => out.push_str("<summary>Answer</summary>"),
+            _ => {
+                self.open(e, out);
+                if !html::VOID.contains(&e.tag.as_str()) {
+                    self.nodes(&e.children, out);
+                    out.push_str(&format!("</{}>", e.tag));
+                }
+            }
+        }
+    }
+
+    fn math(&mut self, katex: &Element, display: bool, out: &mut String) {
+        let Some(math) = find(katex, "math") else {
+            out.push_str(&escape_html(&katex.text()));
+            return;
+        };
+        self.has_math = true;
+        let mut math = math.clone();
+        if math.attr("xmlns").is_none() {
+            math.attrs.push(("xmlns".to_string(), "http://www.w3.org/1998/Math/MathML".to_string()));
+        }
+        if display && math.attr("display").is_none() {
+            math.attrs.push(("display".to_string(), "block".to_string()));
+        }
+        self.write(&math, out);
+    }
+
+    /// The start tag, self-closed for void elements
+    fn open(&self, e: &Element, out: &mut String) {
+        out.push('<');
+        out.push_str(&e.tag);
+        for (name, value) in &e.attrs {
+            if name.starts_with("on") || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':')) {
+                continue;
+            }
+            out.push_str(&format!(" {}=\"{}\"", name, escape_html(value)));
+        }
+        out.push_str(if html::VOID.contains(&e.tag.as_str()) { "/>" } else { ">" });
+    }
+
+    /// An element written out unchanged, for MathML
+    fn write(&self, e: &Element, out: &mut String) {
+        self.open(e, out);
+        for child in &e.children {
+            match child {
+                Node::Text(text) => out.push_str(&escape_html(text)),
+                Node::Element(c) => self.write(c, out),
+            }
+        }
+        if !html::VOID.contains(&e.tag.as_str()) {
+            out.push_str(&format!("</{}>", e.tag));
+        }
+    }
+
+    /// A link between pages of the book into one between its documents
+    fn link(&self, href: &str) -> Option<String> {
+        if href.starts_with('#') || href.contains("://") || href.starts_with("mailto:") {
+            return Some(href.to_string());
+        }
+        let (path, fragment) = href.split_once('#').map_or((href, None), |(p, f)| (p, Some(f)));
+        let target = &self.book.sections[self.book.find_link(path)?];
+        Some(match fragment {
+            Some(fragment) if !fragment.is_empty() => format!("{}.xhtml#{}", target.id(), fragment),
+            _ => format!("{}.xhtml", target.id()),
+        })
+    }
+
+    /// An SVG with its text turned into outlines, since e-readers cannot be relied on for fonts
+    fn svg(&mut self, data: &[u8], resources: Option<std::path::PathBuf>) -> Result<String, String> {
+        if self.fonts.is_none() {
+            *self.fonts = Some(Fonts::new()?);
+        }
+        let tree = self.fonts.as_ref().map(|f| f.parse_svg(data, resources)).unwrap_or_else(|| Err("no fonts".to_string()))?;
+        Ok(self.save("svg", tree.to_string(&XmlOptions::default()).into_bytes()))
+    }
+
+    fn image(&mut self, src: &str) -> Result<String, String> {
+        if src.contains("://") || src.starts_with("data:") {
+            return Err(format!("{} is not a local file", src));
+        }
+        let file = image_file(&self.page_path, src);
+        let data = std::fs::read(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        let extension = file.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        match media_type(&extension) {
+            Some("image/svg+xml") => self.svg(&data, file.parent().map(Path::to_path_buf)),
+            Some(_) => Ok(self.save(&extension, data)),
+            None => Err(format!("{}: unsupported image type", file.display())),
+        }
+    }
+
+    fn save(&mut self, extension: &str, data: Vec<u8>) -> String {
+        let href = format!("images/fig-{}.{}", self.images.len() + 1, extension);
+        self.images.push(Item {
+            href: href.clone(),
+            media_type: media_type(extension).unwrap_or("application/octet-stream"),
+            properties: None,
+            data,
+        });
+        href
+    }
+}
+
+fn find<'e>(e: &'e Element, tag: &str) -> Option<&'e Element> {
+    e.elements().find_map(|c| if c.tag == tag { Some(c) } else { find(c, tag) })
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"en\" lang=\"en\">\n<head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+/// The book as an EPUB 3 file: one document per section in sidebar order, equations as
+/// MathML and figures as images. Returns the file and warnings for anything left out.
+pub fn export(book: &Book, index: &SiteIndex) -> Result<(Vec<u8>, Vec<String>), String> {
+    let mut items = Vec::new();
+    let mut images = Vec::new();
+    let mut fonts = None;
+    let mut warnings = Vec::new();
+
+    for (section, page) in book.sections.iter().zip(book.render_sections(index)) {
+        let mut converter = Converter {
+            book,
+            page_path: section.path.clone(),
+            images: &mut images,
+            fonts: &mut fonts,
+            warnings: &mut warnings,
+            has_math: false,
+        };
+        let number = if section.number.is_empty() {
+            String::new()
+        } else {
+            format!("<span class=\"book-number\">{}</span> ", section.number)
+        };
+        let mut body = format!(
+            "<section epub:type=\"chapter\" id=\"{}\">\n<h1>{}{}</h1>\n",
+            section.id(),
+            number,
+            escape_html(&section.title)
+        );
+        converter.nodes(&html::parse(&page), &mut body);
+        body.push_str("\n</section>");
+        let has_math = converter.has_math;
+        items.push(Item {
+            href: format!("{}.xhtml", section.id()),
+            media_type: "application/xhtml+xml",
+            properties: has_math.then_some("mathml"),
+            data: xhtml(&section.heading(), &body).into_bytes(),
+        });
+    }
+
+    // The navigation document nests like the sidebar the sections came from
+    let mut toc = String::new();
+    let mut open = 0;
+    for section in &book.sections {
+        let level = section.depth.max(1);
+        if level > open {
+            toc.push_str(&"<ol>\n<li>".repeat(level - open));
+        } else {
+            toc.push_str("</li>\n");
+            toc.push_str(&"</ol>\n</li>\n".repeat(open - level));
+            toc.push_str("<li>");
+        }
+        open = level;
+        toc.push_str(&format!("<a href=\"{}.xhtml\">{}</a>", section.id(), escape_html(&section.heading())));
+    }
+    toc.push_str(&"</li>\n</ol>\n".repeat(open));
+    let nav = xhtml("Contents", &format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n{}</nav>", toc));
+
+    let title = if book.category.is_empty() { book.title.clone() } else { format!("{}: {}", book.category, book.title) };
+    let identifier = format!("urn:notes:{}", slugify(&book.sections[0].path));
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+    for (i, item) in items.iter().chain(&images).enumerate() {
+        let properties = item.properties.map(|p| format!(" properties=\"{}\"", p)).unwrap_or_default();
+        manifest.push_str(&format!("<item id=\"item-{}\" href=\"{}\" media-type=\"{}\"{}/>\n", i + 1, escape_html(&item.href), item.media_type, properties));
+        if i < items.len() {
+            spine.push_str(&format!("<itemref idref=\"item-{}\"/>\n", i + 1));
+        }
+    }
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"en\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"book-id\">{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:creator>{}</dc:creator>\n<dc:language>en</dc:language>\n<meta property=\"dcterms:modified\">{}</meta>\n</metadata>\n<manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+        escape_html(&identifier),
+        escape_html(&title),
+        escape_html(&config::get().site.author),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        manifest,
+        spine
+    );
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let zip_err = |e: zip::result::ZipError| e.to_string();
+    let io_err = |e: std::io::Error| e.to_string();
+    // The mimetype comes first and uncompressed so readers can sniff it at a fixed offset
+    zip.start_file("mimetype", SimpleFileOptions::default().compression_method(CompressionMethod::Stored)).map_err(zip_err)?;
+    zip.write_all(MIMETYPE.as_bytes()).map_err(io_err)?;
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let files = [
+        ("META-INF/container.xml".to_string(), CONTAINER.as_bytes()),
+        ("OEBPS/content.opf".to_string(), opf.as_bytes()),
+        ("OEBPS/nav.xhtml".to_string(), nav.as_bytes()),
+        ("OEBPS/style.css".to_string(), STYLE.as_bytes()),
+    ];
+    for (name, data) in files.iter().map(|(n, d)| (n.clone(), *d)).chain(items.iter().chain(&images).map(|i| (format!("OEBPS/{}", i.href), i.data.as_slice()))) {
+        zip.start_file(name, deflated).map_err(zip_err)?;
+        zip.write_all(data).map_err(io_err)?;
+    }
+    let bytes = zip.finish().map_err(zip_err)?.into_inner();
+    Ok((bytes, warnings))
+}
+
+/// Structural checks on an EPUB, along the lines of epubcheck: the container layout, a
+/// complete package document, well-formed content documents and references that resolve
+pub fn check(bytes: &[u8]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut archive = match ZipArchive::new(Cursor::new(bytes)) {
+        Ok(archive) => archive,
+        Err(e) => return vec![format!("not a zip file: {}", e)],
+    };
+    let mut files = HashMap::new();
+    for i in 0..archive.len() {
+        let Ok(mut file) = archive.by_index(i) else {
+            problems.push(format!("entry {} cannot be read", i));
+            continue;
+        };
+        if i == 0 && (file.name() != "mimetype" || file.compression() != CompressionMethod::Stored) {
+            problems.push("the first entry must be an uncompressed mimetype file".to_string());
+        }
+        let mut data = Vec::new();
+        if file.read_to_end(&mut data).is_err() {
+            problems.push(format!("{} cannot be read", file.name()));
+        }
+        files.insert(file.name().to_string(), data);
+    }
+    let text = |name: &str| files.get(name).map(|d| String::from_utf8_lossy(d).into_owned());
+
+    if text("mimetype").as_deref() != Some(MIMETYPE) {
+        problems.push(format!("mimetype must contain exactly {}", MIMETYPE));
+    }
+    let Some(container) = text("META-INF/container.xml") else {
+        problems.push("META-INF/container.xml is missing".to_string());
+        return problems;
+    };
+    let rootfile = html::parse(&container).iter().find_map(|n| find_node(n, "rootfile")).and_then(|r| r.attr("full-path").map(str::to_string));
+    let Some(opf_path) = rootfile else {
+        problems.push("container.xml names no rootfile".to_string());
+        return problems;
+    };
+    let Some(opf) = text(&opf_path) else {
+        problems.push(format!("package document {} is missing", opf_path));
+        return problems;
+    };
+    if let Err(e) = well_formed(&opf) {
+        problems.push(format!("{}: {}", opf_path, e));
+    }
+    let base = opf_path.rsplit_once('/').map_or(String::new(), |(dir, _)| format!("{}/", dir));
+    let package = html::parse(&opf);
+    let all = |tag: &str| {
+        let mut found = Vec::new();
+        for node in &package {
+            collect(node, tag, &mut found);
+        }
+        found
+    };
+
+    let identifier = all("package").first().and_then(|p| p.attr("unique-identifier")).map(str::to_string);
+    if !all("dc:identifier").iter().any(|e| e.attr("id") == identifier.as_deref() && !e.text().trim().is_empty()) {
+        problems.push("the unique-identifier does not name a dc:identifier".to_string());
+    }
+    for required in ["dc:title", "dc:language"] {
+        if all(required).iter().all(|e| e.text().trim().is_empty()) {
+            problems.push(format!("metadata has no {}", required));
+        }
+    }
+    if !all("meta").iter().any(|m| m.attr("property") == Some("dcterms:modified")) {
+        problems.push("metadata has no dcterms:modified".to_string());
+    }
+
+    let mut manifest = HashMap::new();
+    let mut listed = HashSet::new();
+    let mut navigation = HashSet::new();
+    for item in all("item") {
+        let (Some(id), Some(href), Some(media)) = (item.attr("id"), item.attr("href"), item.attr("media-type")) else {
+            problems.push("manifest item without id, href or media-type".to_string());
+            continue;
+        };
+        let path = format!("{}{}", base, href);
+        if !files.contains_key(&path) {
+            problems.push(format!("manifest item {} is not in the container", href));
+        }
+        let properties: Vec<&str> = item.attr("properties").unwrap_or_default().split_whitespace().collect();
+        if properties.contains(&"nav") {
+            navigation.insert(id.to_string());
+        }
+        if manifest.insert(id.to_string(), (path.clone(), media.to_string(), properties.contains(&"mathml"))).is_some() {
+            problems.push(format!("manifest id {} is used twice", id));
+        }
+        listed.insert(path);
+    }
+    if navigation.len() != 1 {
+        problems.push(format!("expected one navigation document, found {}", navigation.len()));
+    }
+    let spine = all("itemref");
+    if spine.is_empty() {
+        problems.push("the spine is empty".to_string());
+    }
+    let mut read = HashSet::new();
+    for itemref in spine {
+        if !itemref.attr("idref").is_some_and(|id| manifest.contains_key(id)) {
+            problems.push(format!("spine refers to unknown item {}", itemref.attr("idref").unwrap_or_default()));
+        }
+        read.extend(itemref.attr("idref"));
+    }
+    // Content documents outside the spine cannot be reached in reading order
+    let mut unread: Vec<&String> = manifest
+        .iter()
+        .filter(|(id, (_, media, _))| media == "application/xhtml+xml" && !read.contains(id.as_str()) && !navigation.contains(*id))
+        .map(|(_, (path, _, _))| path)
+        .collect();
+    unread.sort();
+    for path in unread {
+        problems.push(format!("{} is in the manifest but not in the spine", path));
+    }
+    for name in files.keys() {
+        if name != "mimetype" && !name.starts_with("META-INF/") && *name != opf_path && !listed.contains(name) {
+            problems.push(format!("{} is not in the manifest", name));
+        }
+    }
+
+    // Every local reference from a content document has to land on a file, and a fragment on an id
+    let documents: Vec<_> = manifest.values().filter(|(_, media, _)| media == "application/xhtml+xml").collect();
+    let mut ids: HashMap<&str, HashSet<String>> = HashMap::new();
+    let mut parsed = Vec::new();
+    for (path, _, mathml) in &documents {
+        let source = text(path).unwrap_or_default();
+        if let Err(e) = well_formed(&source) {
+            problems.push(format!("{}: {}", path, e));
+        }
+        if source.contains("<math") && !mathml {
+            problems.push(format!("{} contains MathML but is not marked with the mathml property", path));
+        }
+        let nodes = html::parse(&source);
+        let mut found = HashSet::new();
+        for node in &nodes {
+            collect_ids(node, &mut found);
+        }
+        ids.insert(path.as_str(), found);
+        parsed.push((path.as_str(), nodes));
+    }
+    for (path, nodes) in &parsed {
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let mut references = Vec::new();
+        for node in nodes {
+            collect_references(node, &mut references);
+        }
+        for reference in references {
+            if reference.contains("://") || reference.starts_with("mailto:") {
+                continue;
+            }
+            let (file, fragment) = reference.split_once('#').map_or((reference.as_str(), None), |(f, frag)| (f, Some(frag)));
+            let target = if file.is_empty() { path.to_string() } else { format!("{}/{}", dir, file) };
+            if !files.contains_key(&target) {
+                problems.push(format!("{}: {} does not exist", path, reference));
+            } else if let Some(fragment) = fragment.filter(|f| !f.is_empty()) {
+                if ids.get(target.as_str()).is_some_and(|found| !found.contains(fragment)) {
+                    problems.push(format!("{}: no id {} in {}", path, fragment, file));
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn find_node<'n>(node: &'n Node, tag: &str) -> Option<&'n Element> {
+    match node {
+        Node::Element(e) if e.tag == tag => Some(e),
+        Node::Element(e) => e.children.iter().find_map(|c| find_node(c, tag)),
+        Node::Text(_) => None,
+    }
+}
+
+fn collect<'n>(node: &'n Node, tag: &str, found: &mut Vec<&'n Element>) {
+    if let Node::Element(e) = node {
+        if e.tag == tag {
+            found.push(e);
+        }
+        for child in &e.children {
+            collect(child, tag, found);
+        }
+    }
+}
+
+fn collect_ids(node: &Node, found: &mut HashSet<String>) {
+    if let Node::Element(e) = node {
+        if let Some(id) = e.attr("id") {
+            found.insert(id.to_string());
+        }
+        for child in &e.children {
+            collect_ids(child, found);
+        }
+    }
+}
+
+fn collect_references(node: &Node, found: &mut Vec<String>) {
+    if let Node::Element(e) = node {
+        let reference = match e.tag.as_str() {
+            "a" | "link" => e.attr("href"),
+            "img" => e.attr("src"),
+            _ => None,
+        };
+        found.extend(reference.map(str::to_string));
+        for child in &e.children {
+            collect_references(child, found);
+        }
+    }
+}
+
+/// Check that `xml` is well-formed: tags nest and close, attributes are quoted, entities are
+/// XML's own or numeric, and there is one root element
+fn well_formed(xml: &str) -> Result<(), String> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut roots = 0;
+    let mut rest = xml;
+    let entity = |s: &str| -> bool {
+        let Some(end) = s.find(';') else { return false };
+        let name = &s[1..end];
+        match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with('x') => hex.len() > 1 && hex[1..].chars().all(|c| c.is_ascii_hexdigit()),
+            Some(dec) => !dec.is_empty() && dec.chars().all(|c| c.is_ascii_digit()),
+            None => matches!(name, "amp" | "lt" | "gt" | "quot" | "apos"),
+        }
+    };
+    let check_text = |text: &str| -> Result<(), String> {
+        match text.match_indices('&').find(|(i, _)| !entity(&text[*i..])) {
+            Some((i, _)) => Err(format!("stray & in \"{}\"", text[i..].chars().take(20).collect::<String>())),
+            None => Ok(()),
+        }
+    };
+
+    while let Some(start) = rest.find('<') {
+        let text = &rest[..start];
+        if stack.is_empty() && !text.trim().is_empty() {
+            return Err("text outside the root element".to_string());
+        }
+        check_text(text)?;
+        rest = &rest[start..];
+        let skip = [("<?", "?>"), ("<!--", "-->"), ("<![CDATA[", "]]>"), ("<!", ">")];
+        if let Some((open, close)) = skip.iter().find(|(open, _)| rest.starts_with(open)) {
+            let end = rest[open.len()..].find(close).ok_or_else(|| format!("unterminated {}", open))?;
+            rest = &rest[open.len() + end + close.len()..];
+            continue;
+        }
+        let end = rest.find('>').ok_or("unterminated tag")?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            match stack.pop() {
+                Some(open) if open == name.trim() => {}
+                open => return Err(format!("</{}> closes <{}>", name.trim(), open.unwrap_or("nothing"))),
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = &tag[..name_end];
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')) {
+            return Err(format!("bad tag name <{}>", name));
+        }
+        // Attributes are name="value" or name='value'
+        let mut attrs = tag[name_end..].trim_start();
+        let mut seen = HashSet::new();
+        while !attrs.is_empty() {
+            let eq = attrs.find('=').ok_or_else(|| format!("attribute without a value in <{}>", name))?;
+            let attr = attrs[..eq].trim();
+            if !seen.insert(attr) {
+                return Err(format!("duplicate attribute {} in <{}>", attr, name));
+            }
+            let value = attrs[eq + 1..].trim_start();
+            let quote = value.chars().next().filter(|&q| q == '"' || q == '\'').ok_or_else(|| format!("unquoted attribute {} in <{}>", attr, name))?;
+            let close = value[1..].find(quote).ok_or_else(|| format!("unterminated attribute {} in <{}>", attr, name))?;
+            let value_text = &value[1..1 + close];
+            if value_text.contains('<') {
+                return Err(format!("< in attribute {} of <{}>", attr, name));
+            }
+            check_text(value_text)?;
+            attrs = value[close + 2..].trim_start();
+        }
+        if stack.is_empty() {
+            roots += 1;
+        }
+        if !self_closing {
+            stack.push(name);
+        }
+    }
+    if !rest.trim().is_empty() {
+        return Err("text outside the root element".to_string());
+    }
+    match (stack.last(), roots) {
+        (Some(open), _) => Err(format!("<{}> is never closed", open)),
+        (None, 1) => Ok(()),
+        (None, n) => Err(format!("expected one root element, found {}", n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Section;
+
+    fn fixture() -> Vec<u8> {
+        let section = |title: &str, path: &str, number: &str, depth: usize, source: &str| Section {
+            title: title.to_string(),
+            path: path.to_string(),
+            number: number.to_string(),
+            depth,
+            source: source.to_string(),
+        };
+        let book = Book {
+            title: "Fixture".to_string(),
+            category: "Analog".to_string(),
+            sections: vec![
+                section("Fixture", "/analog/1) Fixture", "1", 0, "An intro with $x^2$ math.\n"),
+                section(
+                    "Details",
+                    "/analog/1) Fixture/1) Details",
+                    "1.1",
+                    1,
+                    "## Details\n\nBack to [the intro](/analog/1%29%20Fixture) & more.\n\n$$\\frac{a}{b}$$\n",
+                ),
+            ],
+            skipped: 0,
+        };
+        export(&book, &SiteIndex::build()).unwrap().0
+    }
+
+    /// `bytes` with every entry passed through `edit`, which drops it by returning None
+    fn repack(bytes: &[u8], edit: impl Fn(&str, Vec<u8>) -> Option<Vec<u8>>) -> Vec<u8> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let (name, compression) = (file.name().to_string(), file.compression());
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).unwrap();
+            if let Some(data) = edit(&name, data) {
+                zip.start_file(name, SimpleFileOptions::default().compression_method(compression)).unwrap();
+                zip.write_all(&data).unwrap();
+            }
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn replace(data: Vec<u8>, from: &str, to: &str) -> Vec<u8> {
+        String::from_utf8(data).unwrap().replacen(from, to, 1).into_bytes()
+    }
+
+    #[test]
+    fn exported_book_passes_check() {
+        assert_eq!(check(&fixture()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_mimetype_is_reported() {
+        let problems = check(&repack(&fixture(), |name, data| (name != "mimetype").then_some(data)));
+        assert!(problems.contains(&"the first entry must be an uncompressed mimetype file".to_string()), "{:?}", problems);
+        assert!(problems.iter().any(|p| p.starts_with("mimetype must contain")), "{:?}", problems);
+    }
+
+    #[test]
+    fn manifest_item_outside_spine_is_reported() {
+        let bytes = repack(&fixture(), |name, data| {
+            Some(if name == "OEBPS/content.opf" { replace(data, "<itemref idref=\"item-2\"/>\n", "") } else { data })
+        });
+        let problems = check(&bytes);
+        assert!(problems.iter().any(|p| p.ends_with("is in the manifest but not in the spine")), "{:?}", problems);
+    }
+
+    #[test]
+    fn malformed_xhtml_is_reported() {
+        let bytes = repack(&fixture(), |name, data| {
+            Some(if name.ends_with("details.xhtml") { replace(data, "</h1>", "") } else { data })
+        });
+        let problems = check(&bytes);
+        assert!(problems.iter().any(|p| p.contains("details.xhtml: ")), "{:?}", problems);
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::f64::consts::{E, PI};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A complex number; every expression is evaluated over ℂ so the same parser serves
+/// transfer functions in `s`, real plots in `x` and domain colouring in `z`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Self {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn exp(self) -> Self {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    pub fn ln(self) -> Self {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    pub fn sqrt(self) -> Self {
+        Complex::from_polar(self.abs().sqrt(), self.arg() / 2.0)
+    }
+
+    pub fn powc(self, exp: Complex) -> Self {
+        if exp.im == 0.0 && exp.re.fract() == 0.0 && exp.re.abs() <= 64.0 {
+            // Exact repeated multiplication keeps s^2 and x^3 real where they should be
+            let mut result = Complex::real(1.0);
+            for _ in 0..exp.re.abs() as u32 {
+                result = result * self;
+            }
+            return if exp.re < 0.0 { Complex::real(1.0) / result } else { result };
+        }
+        if self == Complex::default() {
+            return Complex::default();
+        }
+        (self.ln() * exp).exp()
+    }
+
+    pub fn sin(self) -> Self {
+        Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    pub fn cos(self) -> Self {
+        Complex::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    pub fn sinh(self) -> Self {
+        (self.exp() - (-self).exp()) / Complex::real(2.0)
+    }
+
+    pub fn cosh(self) -> Self {
+        (self.exp() + (-self).exp()) / Complex::real(2.0)
+    }
+
+    /// The real part, or NaN when the value is meaningfully complex (so real plots show a gap)
+    pub fn as_real(self) -> f64 {
+        if self.im.abs() <= 1e-9 * self.re.abs().max(1.0) {
+            self.re
+        } else {
+            f64::NAN
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        if o.im == 0.0 {
+            return Complex::new(self.re / o.re, self.im / o.re);
+        }
+        let d = o.re * o.re + o.im * o.im;
+        Complex::new((self.re * o.re + self.im * o.im) / d, (self.im * o.re - self.re * o.im) / d)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+/// Variables visible to an expression
+pub type Env = HashMap<String, Complex>;
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "sinh", "cosh", "tanh", "exp", "ln", "log", "log10", "sqrt", "abs", "arg",
+    "re", "im", "conj", "floor", "ceil", "sign", "atan", "asin", "acos", "min", "max", "db",
+];
+
+impl Expr {
+    pub fn eval(&self, env: &Env) -> Result<Complex, String> {
+        Ok(match self {
+            Expr::Num(n) => Complex::real(*n),
+            Expr::Var(name) => match env.get(name) {
+                Some(v) => *v,
+                None => match name.as_str() {
+                    "pi" => Complex::real(PI),
+                    "e" => Complex::real(E),
+                    "i" | "j" => Complex::I,
+                    "inf" => Complex::real(f64::INFINITY),
+                    _ => return Err(format!("unknown variable `{}`", name)),
+                },
+            },
+            Expr::Neg(a) => -a.eval(env)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(env)?, b.eval(env)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powc(b),
+                }
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|a| a.eval(env)).collect::<Result<Vec<_>, _>>()?;
+                call(name, &args)?
+            }
+        })
+    }
+
+    /// Evaluate with a single extra variable bound, the common case when sampling a curve
+    pub fn eval_at(&self, env: &mut Env, var: &str, value: Complex) -> Result<Complex, String> {
+        env.insert(var.to_string(), value);
+        self.eval(env)
+    }
+}
+
+fn call(name: &str, args: &[Complex]) -> Result<Complex, String> {
+    let expected = if matches!(name, "min" | "max") { 2 } else { 1 };
+    if args.len() != expected {
+        return Err(format!("`{}` takes {} argument{}", name, expected, if expected == 1 { "" } else { "s" }));
+    }
+    let a = args[0];
+    let one = Complex::real(1.0);
+    Ok(match name {
+        "sin" => a.sin(),
+        "cos" => a.cos(),
+        "tan" => a.sin() / a.cos(),
+        "sinh" => a.sinh(),
+        "cosh" => a.cosh(),
+        "tanh" => a.sinh() / a.cosh(),
+        "exp" => a.exp(),
+        "ln" | "log" => a.ln(),
+        "log10" => a.ln() / Complex::real(10f64.ln()),
+        "sqrt" => a.sqrt(),
+        "abs" => Complex::real(a.abs()),
+        "arg" => Complex::real(a.arg()),
+        "re" => Complex::real(a.re),
+        "im" => Complex::real(a.im),
+        "conj" => Complex::new(a.re, -a.im),
+        "floor" => Complex::real(a.re.floor()),
+        "ceil" => Complex::real(a.re.ceil()),
+        "sign" => Complex::real(a.re.signum()),
+        "db" => Complex::real(20.0 * a.abs().log10()),
+        "atan" if a.im == 0.0 => Complex::real(a.re.atan()),
+        "asin" if a.im == 0.0 => Complex::real(a.re.asin()),
+        "acos" if a.im == 0.0 => Complex::real(a.re.acos()),
+        // Principal branches: atan z = i/2 ln((i+z)/(i-z)), asin z = -i ln(iz + √(1-z²))
+        "atan" => Complex::new(0.0, 0.5) * ((Complex::I + a) / (Complex::I - a)).ln(),
+        "asin" => -Complex::I * (Complex::I * a + (one - a * a).sqrt()).ln(),
+        "acos" => Complex::real(std::f64::consts::FRAC_PI_2) + Complex::I * (Complex::I * a + (one - a * a).sqrt()).ln(),
+        "min" => Complex::real(a.re.min(args[1].re)),
+        "max" => Complex::real(a.re.max(args[1].re)),
+        _ => return Err(format!("unknown function `{}`", name)),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn si_prefix(suffix: &str) -> Option<f64> {
+    Some(match suffix {
+        "T" => 1e12,
+        "G" => 1e9,
+        "M" | "meg" => 1e6,
+        "k" => 1e3,
+        "m" => 1e-3,
+        "u" | "µ" => 1e-6,
+        "n" => 1e-9,
+        "p" => 1e-12,
+        "f" => 1e-15,
+        _ => return None,
+    })
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent, but only when digits follow so `2e` stays "2·e"
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number: f64 = text.parse().map_err(|_| format!("bad number `{}`", text))?;
+            // An SI prefix right after the digits scales them, so `10k` is 10e3 but `2pi` is still 2·pi
+            let mut end = i;
+            while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            let suffix: String = chars[i..end].iter().collect();
+            match si_prefix(&suffix) {
+                Some(scale) => {
+                    tokens.push(Token::Num(number * scale));
+                    i = end;
+                }
+                None => tokens.push(Token::Num(number)),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '*' && chars.get(i + 1) == Some(&'*') {
+            tokens.push(Token::Op('^'));
+            i += 2;
+        } else if "+-*/^(),·π".contains(c) {
+            tokens.push(match c {
+                '·' => Token::Op('*'),
+                'π' => Token::Ident("pi".to_string()),
+                _ => Token::Op(c),
+            });
+            i += 1;
+        } else {
+            return Err(format!("unexpected `{}`", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.eat('+') {
+                lhs = Expr::Binary('+', Box::new(lhs), Box::new(self.term()?));
+            } else if self.eat('-') {
+                lhs = Expr::Binary('-', Box::new(lhs), Box::new(self.term()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            if self.eat('*') {
+                lhs = Expr::Binary('*', Box::new(lhs), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                lhs = Expr::Binary('/', Box::new(lhs), Box::new(self.unary()?));
+            } else if matches!(self.peek(), Some(Token::Num(_) | Token::Ident(_) | Token::Op('('))) {
+                // Implicit multiplication: 2x, 2pi, (1+s/wp1)(1+s/wp2)
+                lhs = Expr::Binary('*', Box::new(lhs), Box::new(self.power()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            // Right associative, and binds tighter than a leading minus on the exponent's base
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if FUNCTIONS.contains(&name.as_str()) && self.eat('(') {
+                    let mut args = vec![self.expr()?];
+                    while self.eat(',') {
+                        args.push(self.expr()?);
+                    }
+                    if !self.eat(')') {
+                        return Err(format!("missing `)` after arguments of `{}`", name));
+                    }
+                    return Ok(Expr::Call(name, args));
+                }
+                Ok(Expr::Var(name))
+            }
+            Some(Token::Op('(')) => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if !self.eat(')') {
+                    return Err("missing `)`".to_string());
+                }
+                Ok(inner)
+            }
+            Some(Token::Op(c)) => Err(format!("unexpected `{}`", c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Parse an expression such as `A0 / ((1 + s/wp1)(1 + s/wp2))` or `sin(x)/x`
+pub fn parse(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let expr = parser.expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(Token::Op(c)) => Err(format!("unexpected `{}`", c)),
+        Some(Token::Num(n)) => Err(format!("unexpected `{}`", n)),
+        Some(Token::Ident(name)) => Err(format!("unexpected `{}`", name)),
+    }
+}
+
+/// Parse and evaluate a constant expression like `2*pi*10e6`
+pub fn eval_real(source: &str, env: &Env) -> Result<f64, String> {
+    let value = parse(source)?.eval(env)?;
+    match value.as_real() {
+        v if v.is_nan() => Err(format!("`{}` is not a real number", source.trim())),
+        v => Ok(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f64 {
+        eval_real(source, &Env::new()).unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-12 * b.abs().max(1e-300)
+    }
+
+    #[test]
+    fn prefixes_scale_numbers() {
+        assert!(close(eval("10k"), 1e4));
+        assert!(close(eval("2.2M"), 2.2e6));
+        assert!(close(eval("1meg"), 1e6));
+        assert!(close(eval("3m"), 3e-3));
+        assert!(close(eval("4.7u"), 4.7e-6));
+        assert!(close(eval("4.7µ"), 4.7e-6));
+        assert!(close(eval("1/(2*pi*1k*1n)"), 1.0 / (2.0 * std::f64::consts::PI * 1e-6)));
+        // Names that only start like a prefix are multiplied, not scaled
+        assert!(close(eval("2pi"), 2.0 * std::f64::consts::PI));
+        assert!(close(eval("2e"), 2.0 * std::f64::consts::E));
+        assert!(close(eval("1e3"), 1e3));
+    }
+
+    #[test]
+    fn complex_evaluation() {
+        let h = parse("1/(1 + s/wp)").unwrap();
+        let mut env = Env::new();
+        env.insert("wp".to_string(), Complex::real(1e3));
+        let at_pole = h.eval_at(&mut env, "s", Complex::new(0.0, 1e3)).unwrap();
+        assert!(close(at_pole.abs(), std::f64::consts::FRAC_1_SQRT_2));
+        assert!(close(at_pole.arg(), -std::f64::consts::FRAC_PI_4));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(eval_real("sqrt(-1)", &Env::new()).unwrap_err().contains("not a real number"));
+        assert!(eval_real("1 +", &Env::new()).is_err());
+        assert!(eval_real("gm * 2", &Env::new()).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+use regex::Regex;
+use serde_json::{json, Value};
+use walkdir::WalkDir;
+
+use crate::graph::encode_query;
+use crate::render::{self, slugify};
+use crate::site::{content_dir, escape_html, SiteIndex};
+use crate::{build_link, config, format_title};
+
+/// Root-relative `href`/`src` attributes, which feed readers have no page to resolve against
+static ROOT_RELATIVE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(href|src)="/"#).unwrap());
+
+/// Pages in each feed, newest first
+const FEED_LENGTH: usize = 20;
+
+/// A page as feeds and the "Recently Added" table see it
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub title: String,
+    /// URL path without the site_url prefix
+    pub path: String,
+    pub category: String,
+    /// The category's directory name, e.g. "analog"
+    pub category_dir: String,
+    pub file: PathBuf,
+    pub published: DateTime<FixedOffset>,
+    pub updated: DateTime<FixedOffset>,
+    /// URL path the page was first committed under, the same as `path` until it is renamed
+    pub first_path: String,
+}
+
+impl Entry {
+    /// Stays the same across hosts, SITE_URL changes and renames, unlike the page URL
+    pub fn id(&self) -> String {
+        format!("urn:sawys-notes:{}", slugify(&self.first_path))
+    }
+
+    pub fn url(&self, origin: &str) -> String {
+        format!("{}{}", origin, encode_query(&build_link(&self.path)))
+    }
+}
+
+/// A file's history: first and last commit dates, and the name it was first committed under
+struct History {
+    published: DateTime<FixedOffset>,
+    updated: DateTime<FixedOffset>,
+    first_file: PathBuf,
+}
+
+/// `git_history`, kept until `forget_history` so that `git log` runs once per change to the
+/// content rather than once per feed or index page
+static HISTORY: Mutex<Option<Arc<HashMap<PathBuf, History>>>> = Mutex::new(None);
+
+fn history() -> Arc<HashMap<PathBuf, History>> {
+    HISTORY.lock().unwrap().get_or_insert_with(|| Arc::new(git_history())).clone()
+}
+
+/// Drop the cached history, for when the content has changed
+pub fn forget_history() {
+    *HISTORY.lock().unwrap() = None;
+}
+
+/// The history of every file under the content directory, when it is a git checkout. Renames
+/// are followed, so a moved page keeps its dates and first name.
+fn git_history() -> HashMap<PathBuf, History> {
+    let mut history: HashMap<PathBuf, History> = HashMap::new();
+    let Ok(output) = std::process::Command::new("git")
+        .args(["-c", "core.quotepath=off", "log", "-M", "--format=%x00%cI", "--name-status", "--", content_dir()])
+        .output()
+    else {
+        return history;
+    };
+    // Older names of renamed files, mapped to the file's name today
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    // Newest commit first, so the first date seen for a file is its last update
+    for commit in String::from_utf8_lossy(&output.stdout).split('\0').skip(1) {
+        let mut lines = commit.lines();
+        let Some(Ok(date)) = lines.next().map(DateTime::parse_from_rfc3339) else { continue };
+        let mut older = Vec::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            // "M\tpath", or "R100\told\tnew" for a rename
+            let (name, before) = match fields[..] {
+                [status, old, new] if status.starts_with('R') => (new, old),
+                [_, path] => (path, path),
+                _ => continue,
+            };
+            let file = renamed.remove(name).unwrap_or_else(|| name.to_string());
+            history
+                .entry(PathBuf::from(&file))
+                .and_modify(|h| {
+                    h.published = date;
+                    h.first_file = PathBuf::from(before);
+                })
+                .or_insert_with(|| History { published: date, updated: date, first_file: PathBuf::from(before) });
+            older.push((before.to_string(), file));
+        }
+        // Added after every name of this commit is resolved, as renames within it can swap names
+        renamed.extend(older);
+    }
+    history
+}
+
+/// URL path of a markdown file under the content directory
+fn url_path(file: &std::path::Path) -> Option<String> {
+    let relative = file.strip_prefix(content_dir()).ok()?;
+    Some(format!("/{}", relative.with_extension("").to_string_lossy()))
+}
+
+/// Every page, most recently updated first. Dates come from git history so a fresh checkout
+/// keeps them; files git does not know yet fall back to their modification time.
+pub fn entries() -> Vec<Entry> {
+    let history = history();
+    let mut entries = Vec::new();
+
+    for entry in WalkDir::new(content_dir()).min_depth(1).sort_by_file_name() {
+        let Ok(entry) = entry else { continue };
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Ok(relative_path) = path.strip_prefix(content_dir()) else { continue };
+        let components: Vec<String> = relative_path.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        if components.iter().any(|c| c == "images") {
+            continue;
+        }
+
+        let page_path = format!("/{}", relative_path.with_extension("").to_string_lossy());
+        let (published, updated, first_path) = match history.get(path) {
+            Some(h) => (h.published, h.updated, url_path(&h.first_file).unwrap_or_else(|| page_path.clone())),
+            None => {
+                let Ok(modified) = std::fs::metadata(path).and_then(|m| m.modified()) else { continue };
+                let modified: DateTime<Local> = modified.into();
+                (modified.fixed_offset(), modified.fixed_offset(), page_path.clone())
+            }
+        };
+        let category_dir = components[0].trim_end_matches(".md").to_string();
+        entries.push(Entry {
+            title: format_title(&path.file_stem().unwrap().to_string_lossy()),
+            path: page_path,
+            category: format_title(&category_dir),
+            category_dir,
+            file: path.to_path_buf(),
+            published,
+            updated,
+            first_path,
+        });
+    }
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.updated));
+    entries
+}
+
+/// `scheme://host` of `site.base_url` when it is a full URL, regardless of the headers.
+/// Otherwise the one the request came in on, or the address the server is bound to when the
+/// request does not say, for the absolute URLs feeds need.
+pub fn origin(headers: &HeaderMap) -> String {
+    let config = config::get();
+    if let Some(origin) = config.origin() {
+        return origin.to_string();
+    }
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    match header("x-forwarded-host").or_else(|| header("host")) {
+        Some(host) => format!("{}://{}", scheme, host),
+        None => format!("{}://{}", scheme, config.bind_address()),
+    }
+}
+
+/// A feed over all pages, or over one category's when `category` is a category slug
+pub struct Feed {
+    pub title: String,
+    /// Where the feed is served, e.g. "/feeds/analog" without the extension
+    pub base: String,
+    pub entries: Vec<Entry>,
+}
+
+impl Feed {
+    /// None when `category` names no category
+    pub fn new(category: Option<&str>) -> Option<Feed> {
+        let all = entries().into_iter().filter(|e| e.path != "/index");
+        let Some(slug) = category else {
+            return Some(Feed { title: config::get().site.title.clone(), base: "/feed".to_string(), entries: all.take(FEED_LENGTH).collect() });
+        };
+        let entries: Vec<Entry> = all.filter(|e| slugify(&e.category_dir) == slug).take(FEED_LENGTH).collect();
+        let first = entries.first()?;
+        Some(Feed { title: format!("{}: {}", config::get().site.title, first.category), base: format!("/feeds/{}", slug), entries })
+    }
+
+    fn updated(&self) -> String {
+        let updated = self.entries.iter().map(|e| e.updated).max();
+        updated.unwrap_or_else(|| Local::now().fixed_offset()).to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    /// Each entry's page rendered as on the site, with links made absolute
+    fn contents(&self, index: &SiteIndex, origin: &str) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let source = std::fs::read_to_string(&entry.file).unwrap_or_default();
+                let html = render::render_markdown(&source, index, &entry.path);
+                ROOT_RELATIVE_RE.replace_all(&html, format!("$1=\"{}/", origin).as_str()).into_owned()
+            })
+            .collect()
+    }
+
+    pub fn atom(&self, index: &SiteIndex, origin: &str) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<title>{}</title>\n<id>urn:sawys-notes:{}</id>\n<updated>{}</updated>\n<link rel=\"self\" type=\"application/atom+xml\" href=\"{}{}\"/>\n<link rel=\"alternate\" type=\"text/html\" href=\"{}{}/\"/>\n<author><name>{}</name></author>\n",
+            escape_html(&self.title),
+            slugify(&self.base),
+            self.updated(),
+            origin,
+            build_link(&format!("{}.xml", self.base)),
+            origin,
+            build_link(""),
+            escape_html(&config::get().site.author)
+        );
+        for (entry, content) in self.entries.iter().zip(self.contents(index, origin)) {
+            xml.push_str(&format!(
+                "<entry>\n<title>{}</title>\n<id>{}</id>\n<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n<published>{}</published>\n<updated>{}</updated>\n<category term=\"{}\" label=\"{}\"/>\n<content type=\"html\">{}</content>\n</entry>\n",
+                escape_html(&entry.title),
+                entry.id(),
+                escape_html(&entry.url(origin)),
+                entry.published.to_rfc3339_opts(SecondsFormat::Secs, true),
+                entry.updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+                escape_html(&slugify(&entry.category_dir)),
+                escape_html(&entry.category),
+                escape_html(&content)
+            ));
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    /// The feed in JSON Feed 1.1
+    pub fn json(&self, index: &SiteIndex, origin: &str) -> Value {
+        let items: Vec<Value> = self
+            .entries
+            .iter()
+            .zip(self.contents(index, origin))
+            .map(|(entry, content)| {
+                json!({
+                    "id": entry.id(),
+                    "url": entry.url(origin),
+                    "title": entry.title,
+                    "content_html": content,
+                    "date_published": entry.published.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "date_modified": entry.updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "tags": [entry.category],
+                })
+            })
+            .collect();
+        json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "home_page_url": format!("{}{}/", origin, build_link("")),
+            "feed_url": format!("{}{}", origin, build_link(&format!("{}.json", self.base))),
+            "authors": [{ "name": config::get().site.author }],
+            "language": "en",
+            "items": items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_falls_back_to_the_bound_address() {
+        assert_eq!(origin(&HeaderMap::new()), format!("http://{}", config::get().bind_address()));
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "notes.example".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        assert_eq!(origin(&headers), "https://notes.example");
+    }
+}
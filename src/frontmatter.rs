@@ -0,0 +1,92 @@
+/// Metadata from an optional `---` block at the top of a page:
+///
+/// ```text
+/// ---
+/// tags: [opamp, stability]
+/// numbering: chapter
+/// description: How fast the output can move
+/// ---
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Frontmatter {
+    pub tags: Vec<String>,
+    /// "chapter" to number theorem environments across the whole chapter instead of per page
+    pub numbering: String,
+    /// Summary for search results and link previews
+    pub description: String,
+}
+
+/// Parse the leading frontmatter block, if any. Only flat `key: value` lines are understood.
+pub fn parse(source: &str) -> Frontmatter {
+    let mut frontmatter = Frontmatter::default();
+    let Some(rest) = source.strip_prefix("---\n").or_else(|| source.strip_prefix("---\r\n")) else {
+        return frontmatter;
+    };
+
+    for line in rest.lines() {
+        let line = line.trim();
+        if line == "---" || line == "..." {
+            break;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        match key.trim() {
+            "tags" => frontmatter.tags = parse_list(value),
+            "numbering" => frontmatter.numbering = value.trim().to_string(),
+            "description" => frontmatter.description = value.trim().trim_matches(|c| c == '"' || c == '\'').to_string(),
+            _ => {}
+        }
+    }
+
+    frontmatter
+}
+
+/// What is wrong with the leading frontmatter block: a missing closing line, lines that are not
+/// `key: value`, unknown keys and values `parse` would not understand
+pub fn check(source: &str) -> Vec<String> {
+    let Some(rest) = source.strip_prefix("---\n").or_else(|| source.strip_prefix("---\r\n")) else {
+        return Vec::new();
+    };
+
+    let mut problems = Vec::new();
+    let mut closed = false;
+    for (i, line) in rest.lines().enumerate() {
+        let line_number = i + 2;
+        let line = line.trim();
+        if line == "---" || line == "..." {
+            closed = true;
+            break;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            problems.push(format!("frontmatter line {} is not `key: value`", line_number));
+            continue;
+        };
+        match key.trim() {
+            "numbering" => {
+                let value = value.trim();
+                if !value.is_empty() && value != "chapter" && value != "page" {
+                    problems.push(format!("frontmatter line {}: numbering must be \"chapter\" or \"page\", not {:?}", line_number, value));
+                }
+            }
+            "tags" | "description" => {}
+            key => problems.push(format!("frontmatter line {}: unknown key {:?}", line_number, key)),
+        }
+    }
+    if !closed {
+        problems.push("frontmatter block is never closed with ---".to_string());
+    }
+    problems
+}
+
+/// `[a, b]` or `a, b` into a list of trimmed, unquoted items
+fn parse_list(value: &str) -> Vec<String> {
+    let value = value.trim();
+    let value = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).unwrap_or(value);
+    value
+        .split(',')
+        .map(|item| item.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -0,0 +1,440 @@
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::chart::{self, Axis, Panel};
+use crate::expr::Env;
+use crate::site::{content_dir, escape_html};
+use crate::units::{self, format_si};
+
+/// One bias point of a device sweep, with currents and conductances as magnitudes so PMOS tables work too
+#[derive(Clone, Copy)]
+struct Point {
+    w: f64,
+    vgs: f64,
+    id: f64,
+    gm: f64,
+    gds: f64,
+    cgg: f64,
+}
+
+/// A sweep table grouped by channel length, each group in increasing Vgs
+struct Table {
+    lengths: Vec<(f64, Vec<Point>)>,
+}
+
+/// Something that can go on a chart axis or be read off a bias point
+#[derive(Clone, Copy, PartialEq)]
+enum Quantity {
+    GmId,
+    IdW,
+    Gain,
+    Ft,
+    Fom,
+    Vgs,
+    Id,
+    Gm,
+    Gds,
+    Cgg,
+}
+
+impl Quantity {
+    fn parse(name: &str) -> Result<Quantity, String> {
+        Ok(match name.trim().to_lowercase().replace(' ', "").as_str() {
+            "gm/id" | "gmid" => Quantity::GmId,
+            "id/w" | "idw" => Quantity::IdW,
+            "gm/gds" | "gain" => Quantity::Gain,
+            "ft" => Quantity::Ft,
+            "gm*ft/id" | "fom" => Quantity::Fom,
+            "vgs" => Quantity::Vgs,
+            "id" => Quantity::Id,
+            "gm" => Quantity::Gm,
+            "gds" => Quantity::Gds,
+            "cgg" => Quantity::Cgg,
+            other => {
+                return Err(format!(
+                    "unknown quantity `{}`: use gm/id, id/w, gm/gds, ft, gm*ft/id, vgs, id, gm, gds or cgg",
+                    other
+                ))
+            }
+        })
+    }
+
+    fn of(self, p: &Point) -> f64 {
+        match self {
+            Quantity::GmId => p.gm / p.id,
+            // Per micron of width, as the tables are usually read
+            Quantity::IdW => p.id / (p.w * 1e6),
+            Quantity::Gain => p.gm / p.gds,
+            Quantity::Ft => p.gm / (2.0 * PI * p.cgg),
+            Quantity::Fom => p.gm / p.id * p.gm / (2.0 * PI * p.cgg),
+            Quantity::Vgs => p.vgs,
+            Quantity::Id => p.id,
+            Quantity::Gm => p.gm,
+            Quantity::Gds => p.gds,
+            Quantity::Cgg => p.cgg,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Quantity::GmId => "gm/ID (1/V)",
+            Quantity::IdW => "ID/W (A/µm)",
+            Quantity::Gain => "gm/gds (V/V)",
+            Quantity::Ft => "fT",
+            Quantity::Fom => "gm·fT/ID (Hz/V)",
+            Quantity::Vgs => "VGS (V)",
+            Quantity::Id => "ID",
+            Quantity::Gm => "gm",
+            Quantity::Gds => "gds",
+            Quantity::Cgg => "Cgg",
+        }
+    }
+
+    /// Quantities spanning decades get a log axis
+    fn is_log(self) -> bool {
+        matches!(self, Quantity::IdW | Quantity::Fom | Quantity::Ft | Quantity::Id | Quantity::Gm | Quantity::Gds)
+    }
+
+    /// An axis with SI units on the ticks where the quantity has one
+    fn axis(self, min: f64, max: f64) -> Axis {
+        let unit = match self {
+            Quantity::Ft => "Hz",
+            Quantity::Id => "A",
+            Quantity::Gm | Quantity::Gds => "S",
+            _ => "",
+        };
+        match self {
+            Quantity::Cgg => Axis { si: true, ..Axis::linear(min, max, self.label()).unit("F").nice() },
+            _ if self.is_log() => Axis::log(min, max, self.label()).unit(unit),
+            _ => Axis::linear(min, max, self.label()).nice(),
+        }
+    }
+}
+
+/// A ```gmid block: charts and sizing queries over a device sweep committed under `assets/content`.
+///
+/// ```text
+/// table: analog/tables/nch.csv
+/// plot: id/w vs gm/id
+/// plot: ft vs gm/id
+/// query: gm/id = 15, id = 100u, l = 0.36u
+/// ```
+///
+/// The table is CSV or whitespace-separated columns (as exported from a PSF viewer) with a
+/// header naming L, W, Vgs, Id, gm, gds and Cgg; `w:` gives the width when there is no W column.
+struct Gmid {
+    table: Option<String>,
+    width: Option<f64>,
+    plots: Vec<(Quantity, Quantity)>,
+    queries: Vec<Query>,
+}
+
+/// Size a device for a target gm/ID and drain current, at one length or at all of them
+struct Query {
+    gm_id: f64,
+    id: f64,
+    l: Option<f64>,
+}
+
+fn parse(source: &str) -> Result<Gmid, String> {
+    let mut gmid = Gmid { table: None, width: None, plots: Vec::new(), queries: Vec::new() };
+    let env = Env::new();
+
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (key, value) = line.split_once(':').ok_or_else(|| format!("expected `key: value` in `{}`", line))?;
+        let value = value.trim();
+        match key.trim() {
+            "table" | "data" => gmid.table = Some(value.to_string()),
+            "w" | "width" => gmid.width = Some(units::parse_value(value, &env, Some("m"))?),
+            "plot" => {
+                let (y, x) = value.split_once(" vs ").ok_or_else(|| format!("expected `plot: y vs x`, got `{}`", value))?;
+                gmid.plots.push((Quantity::parse(y)?, Quantity::parse(x)?));
+            }
+            "query" => {
+                let mut query = Query { gm_id: f64::NAN, id: f64::NAN, l: None };
+                for part in value.split(',') {
+                    let (name, v) = part.split_once('=').ok_or_else(|| format!("expected `name = value` in `{}`", part.trim()))?;
+                    let value = |unit| units::parse_value(v.trim(), &env, unit);
+                    match name.trim().to_lowercase().replace(' ', "").as_str() {
+                        "gm/id" | "gmid" => query.gm_id = value(None)?,
+                        "id" => query.id = value(Some("A"))?,
+                        "l" => query.l = Some(value(Some("m"))?),
+                        other => return Err(format!("unknown query term `{}`: use gm/id, id and l", other)),
+                    }
+                }
+                if !query.gm_id.is_finite() || !query.id.is_finite() {
+                    return Err(format!("a query needs both gm/id and id, got `{}`", value));
+                }
+                gmid.queries.push(query);
+            }
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+
+    if gmid.plots.is_empty() && gmid.queries.is_empty() {
+        gmid.plots = vec![
+            (Quantity::IdW, Quantity::GmId),
+            (Quantity::Gain, Quantity::GmId),
+            (Quantity::Ft, Quantity::GmId),
+        ];
+    }
+    Ok(gmid)
+}
+
+/// The table file at `path` under the content directory, refusing anything that resolves outside it
+fn read(path: &str) -> Result<String, String> {
+    let root = Path::new(content_dir()).canonicalize().map_err(|e| format!("cannot read `{}`: {}", content_dir(), e))?;
+    let file = root.join(path.trim_start_matches('/')).canonicalize().map_err(|e| format!("cannot read `{}`: {}", path, e))?;
+    if !file.starts_with(&root) {
+        return Err(format!("`{}` is outside the content directory", path));
+    }
+    std::fs::read_to_string(&file).map_err(|e| format!("cannot read `{}`: {}", path, e))
+}
+
+/// Parse the sweep table `text`, read from `path`
+fn load(text: &str, path: &str, width: Option<f64>) -> Result<Table, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('*'));
+    let header = lines.next().ok_or_else(|| format!("`{}` is empty", path))?;
+    let split = |line: &'_ str| -> Vec<String> {
+        if line.contains(',') {
+            line.split(',').map(|f| f.trim().to_string()).collect()
+        } else {
+            line.split_whitespace().map(str::to_string).collect()
+        }
+    };
+    // "Id (A)" and "gm[S]" name the same columns as "id" and "gm"
+    let names: Vec<String> = split(header)
+        .iter()
+        .map(|h| h.split(['(', '[', ' ']).next().unwrap_or_default().to_lowercase())
+        .collect();
+    let column = |name: &str| names.iter().position(|n| n == name);
+    let required = |name: &str| column(name).ok_or_else(|| format!("`{}` has no `{}` column", path, name));
+    let (vgs, id, gm, gds, cgg) = (required("vgs")?, required("id")?, required("gm")?, required("gds")?, required("cgg")?);
+    let (l, w) = (column("l"), column("w"));
+    if w.is_none() && width.is_none() {
+        return Err(format!("`{}` has no W column: add `w:` with the simulated width", path));
+    }
+
+    let mut table = Table { lengths: Vec::new() };
+    for (number, line) in lines.enumerate() {
+        let fields = split(line);
+        let field = |i: usize| -> Result<f64, String> {
+            fields
+                .get(i)
+                .and_then(|f| f.parse::<f64>().ok())
+                .ok_or_else(|| format!("`{}`: bad value in data row {}", path, number + 1))
+        };
+        let length = match l {
+            Some(l) => field(l)?,
+            None => 0.0,
+        };
+        let point = Point {
+            w: match w {
+                Some(w) => field(w)?,
+                None => width.unwrap_or_default(),
+            },
+            vgs: field(vgs)?.abs(),
+            id: field(id)?.abs(),
+            gm: field(gm)?.abs(),
+            gds: field(gds)?.abs(),
+            cgg: field(cgg)?.abs(),
+        };
+        match table.lengths.iter_mut().find(|(l, _)| (l - length).abs() <= 1e-6 * length.abs()) {
+            Some((_, points)) => points.push(point),
+            None => table.lengths.push((length, vec![point])),
+        }
+    }
+    for (_, points) in &mut table.lengths {
+        points.sort_by(|a, b| a.vgs.total_cmp(&b.vgs));
+    }
+    if table.lengths.is_empty() {
+        return Err(format!("`{}` has no data rows", path));
+    }
+    Ok(table)
+}
+
+/// The bias point where gm/ID crosses `target`, interpolated linearly between the two bracketing rows
+fn bias_at(points: &[Point], target: f64) -> Option<Point> {
+    points.windows(2).find_map(|pair| {
+        let (a, b) = (Quantity::GmId.of(&pair[0]), Quantity::GmId.of(&pair[1]));
+        if !((a - target) * (b - target) <= 0.0 && a != b) {
+            return None;
+        }
+        let t = (target - a) / (b - a);
+        let mix = |u: f64, v: f64| u + t * (v - u);
+        let (p, q) = (&pair[0], &pair[1]);
+        // ID spans decades, so interpolate it (and what scales with it) on a log scale
+        let log_mix = |u: f64, v: f64| if u > 0.0 && v > 0.0 { (u.ln() + t * (v.ln() - u.ln())).exp() } else { mix(u, v) };
+        let id = log_mix(p.id, q.id);
+        Some(Point {
+            w: p.w,
+            vgs: mix(p.vgs, q.vgs),
+            id,
+            gm: target * id,
+            gds: log_mix(p.gds, q.gds),
+            cgg: mix(p.cgg, q.cgg),
+        })
+    })
+}
+
+/// One row of a query's result: the channel length and the bias point at the table's width
+struct Sizing {
+    series: usize,
+    l: f64,
+    bias: Option<Point>,
+}
+
+fn answer(table: &Table, query: &Query) -> Result<Vec<Sizing>, String> {
+    let lengths: Vec<usize> = match query.l {
+        Some(l) => {
+            let i = table
+                .lengths
+                .iter()
+                .position(|(length, _)| (length - l).abs() <= 0.01 * l.abs())
+                .ok_or_else(|| {
+                    let have: Vec<String> = table.lengths.iter().map(|(l, _)| format_si(*l, "m")).collect();
+                    format!("no L = {} in the table (it has {})", format_si(l, "m"), have.join(", "))
+                })?;
+            vec![i]
+        }
+        None => (0..table.lengths.len()).collect(),
+    };
+
+    Ok(lengths
+        .into_iter()
+        .map(|i| {
+            let (l, points) = &table.lengths[i];
+            Sizing { series: i, l: *l, bias: bias_at(points, query.gm_id) }
+        })
+        .collect())
+}
+
+fn length_name(l: f64) -> String {
+    if l > 0.0 { format!("L = {}", format_si(l, "m")) } else { "Device".to_string() }
+}
+
+fn chart(table: &Table, y: Quantity, x: Quantity, marks: &[(usize, Point)]) -> Result<String, String> {
+    let curves: Vec<Vec<(f64, f64)>> = table
+        .lengths
+        .iter()
+        .map(|(_, points)| points.iter().map(|p| (x.of(p), y.of(p))).filter(|(x, y)| x.is_finite() && y.is_finite()).collect())
+        .collect();
+    let positive = |q: Quantity, v: f64| v > 0.0 || !q.is_log();
+    let extent = |values: Vec<f64>| values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let (x_min, x_max) = extent(curves.iter().flatten().map(|p| p.0).filter(|&v| positive(x, v)).collect());
+    let (y_min, y_max) = extent(curves.iter().flatten().map(|p| p.1).filter(|&v| positive(y, v)).collect());
+    // Deep subthreshold rows would otherwise stretch a log axis over a dozen decades
+    let y_min = if y.is_log() { y_min.max(y_max * 1e-6) } else { y_min };
+    if !(x_min < x_max && y_min < y_max) {
+        return Err(format!("nothing to plot for {} vs {}", y.label(), x.label()));
+    }
+
+    let panel = Panel { x: x.axis(x_min, x_max), y: y.axis(y_min, y_max), left: 64.0, top: 14.0, width: 546.0, height: 300.0 };
+    let (width, height) = (640.0, panel.top + panel.height + 44.0);
+
+    let mut svg = String::new();
+    panel.frame(&mut svg, true);
+    for (series, points) in curves.iter().enumerate() {
+        panel.curve(&mut svg, points, series, false);
+    }
+    for (series, point) in marks {
+        let at = (x.of(point), y.of(point));
+        if panel.contains(at.0, at.1) {
+            panel.dot(&mut svg, at, *series);
+        }
+    }
+    let names: Vec<String> = table.lengths.iter().map(|(l, _)| length_name(*l)).collect();
+    panel.legend(&mut svg, &names);
+
+    let short = |q: Quantity| q.label().split(" (").next().unwrap_or_default().to_string();
+    let caption = format!("{} versus {}", short(y), short(x));
+    Ok(chart::figure("gmid", width, height, &svg, &caption))
+}
+
+pub fn render(source: &str) -> Result<String, String> {
+    let gmid = parse(source)?;
+    let path = gmid.table.as_deref().ok_or("no device table: add `table: path/under/assets/content.csv`")?;
+    let table = load(&read(path)?, path, gmid.width)?;
+
+    let answers = gmid.queries.iter().map(|q| answer(&table, q)).collect::<Result<Vec<_>, _>>()?;
+    // Query results are marked on the charts, at the bias point they picked
+    let marks: Vec<(usize, Point)> = answers.iter().flatten().filter_map(|s| s.bias.map(|p| (s.series, p))).collect();
+
+    let mut html = String::from("<div class=\"gmid\">\n");
+    for &(y, x) in &gmid.plots {
+        html.push_str(&chart(&table, y, x, &marks)?);
+        html.push('\n');
+    }
+
+    for (query, sizings) in gmid.queries.iter().zip(&answers) {
+        let _ = writeln!(
+            html,
+            "<table class=\"gmid-query\">\n<caption>Sizing for gm/ID = {} V⁻¹, ID = {}</caption>",
+            chart::format_number(query.gm_id),
+            escape_html(&format_si(query.id, "A"))
+        );
+        html.push_str("<thead><tr><th>L</th><th>W</th><th>VGS</th><th>gm</th><th>gm/gds</th><th>fT</th><th>Cgg</th></tr></thead>\n<tbody>\n");
+        for sizing in sizings {
+            let l = escape_html(&format_si(sizing.l, "m"));
+            match &sizing.bias {
+                Some(bias) => {
+                    // Same current density, so everything extensive scales with W
+                    let scale = query.id / bias.id;
+                    let p = &Point { w: bias.w * scale, id: query.id, gm: bias.gm * scale, gds: bias.gds * scale, cgg: bias.cgg * scale, ..*bias };
+                    let cells = [
+                        format_si(p.w, "m"),
+                        format!("{:.3} V", p.vgs),
+                        format_si(p.gm, "S"),
+                        format!("{:.1}", Quantity::Gain.of(p)),
+                        format_si(Quantity::Ft.of(p), "Hz"),
+                        format_si(p.cgg, "F"),
+                    ];
+                    let cells: Vec<String> = cells.iter().map(|c| format!("<td>{}</td>", escape_html(c))).collect();
+                    let _ = writeln!(html, "<tr><td>{}</td>{}</tr>", l, cells.concat());
+                }
+                None => {
+                    let _ = writeln!(html, "<tr><td>{}</td><td colspan=\"6\">gm/ID = {} is outside this length's sweep</td></tr>", l, chart::format_number(query.gm_id));
+                }
+            }
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    html.push_str("</div>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/gmid_synthetic_nmos.csv");
+
+    #[test]
+    fn table_groups_rows_by_length() {
+        let table = load(FIXTURE, "fixture", None).unwrap();
+        let lengths: Vec<f64> = table.lengths.iter().map(|(l, _)| *l).collect();
+        assert_eq!(lengths, vec![1.8e-7, 3.6e-7, 7.2e-7, 1.44e-6]);
+        for (_, points) in &table.lengths {
+            assert!(points.windows(2).all(|p| p[0].vgs <= p[1].vgs));
+        }
+    }
+
+    #[test]
+    fn query_finds_the_bias_point() {
+        let table = load(FIXTURE, "fixture", None).unwrap();
+        let query = Query { gm_id: 15.0, id: 100e-6, l: Some(0.36e-6) };
+        let sizing = answer(&table, &query).unwrap();
+        let bias = sizing[0].bias.expect("gm/ID = 15 is inside the sweep");
+        assert!((Quantity::GmId.of(&bias) - 15.0).abs() < 1e-6);
+        assert!(answer(&table, &Query { l: Some(0.5e-6), ..query }).is_err());
+    }
+
+    #[test]
+    fn tables_outside_the_content_directory_are_refused() {
+        let error = read("../../../../../../etc/passwd").unwrap_err();
+        assert!(error.contains("outside the content directory"), "{}", error);
+        assert!(read("/../../Cargo.toml").unwrap_err().contains("outside"));
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::site::{escape_html, SiteIndex};
+use crate::build_link;
+
+/// The internal-link graph as `{ nodes: [...], edges: [...] }`, with edges indexing into `nodes`
+pub fn graph_json(index: &SiteIndex) -> Value {
+    let positions: HashMap<&str, usize> = index.pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| (page.path.as_str(), i))
+        .collect();
+
+    let nodes: Vec<Value> = index.pages
+        .iter()
+        .map(|page| {
+            json!({
+                "id": page.path,
+                "title": page.title,
+                "category": page.category,
+                "url": build_link(&page.path),
+            })
+        })
+        .collect();
+
+    let mut edges = BTreeSet::new();
+    for (source, page) in index.pages.iter().enumerate() {
+        for link in &page.links {
+            if let Some(&target) = positions.get(link.target.as_str()) {
+                if target != source {
+                    edges.insert((source, target));
+                }
+            }
+        }
+    }
+    let edges: Vec<Value> = edges
+        .into_iter()
+        .map(|(source, target)| json!({ "source": source, "target": target }))
+        .collect();
+
+    json!({ "nodes": nodes, "edges": edges })
+}
+
+/// Content of the `/graph` page: a canvas the wasm client draws into, with the graph inlined as JSON
+pub fn render_graph_page(index: &SiteIndex, focus: &str) -> String {
+    // "</script>" inside the JSON would end the script element early
+    let data = graph_json(index).to_string().replace("</", "<\\/");
+    format!(
+        r#"<h1>Knowledge Graph</h1>
+<p class="graph-help">Scroll to zoom, drag to pan and click a note to open it.</p>
+<div class="graph-container">
+<canvas id="graph-canvas" data-focus="{}"></canvas>
+</div>
+<script type="application/json" id="graph-data">{}</script>"#,
+        escape_html(focus),
+        data
+    )
+}
+
+/// Percent-encode a page path so it can be passed as a query parameter
+pub fn encode_query(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
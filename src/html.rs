@@ -0,0 +1,242 @@
+/// A node of parsed HTML
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Element {
+    pub tag: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+    /// The element's own markup for `<svg>`, which is handed to an SVG renderer whole
+    pub source: Option<String>,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attr("class").is_some_and(|c| c.split_whitespace().any(|c| c == class))
+    }
+
+    /// Concatenated text of every descendant
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        collect_text(&self.children, &mut text);
+        text
+    }
+
+    /// Child elements, skipping text
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|n| match n {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+fn collect_text(nodes: &[Node], text: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(e) => collect_text(&e.children, text),
+        }
+    }
+}
+
+pub const VOID: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+/// Elements whose content is not markup
+const RAW: &[&str] = &["script", "style"];
+
+/// Parse the HTML this site generates (well-formed, closed tags) into a tree. Unknown
+/// closing tags are ignored and unclosed elements are closed at the end of the input.
+pub fn parse(html: &str) -> Vec<Node> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let rest = &html[pos..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            pos += 4 + comment.find("-->").map_or(comment.len(), |end| end + 3);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos += rest.find('>').map_or(rest.len(), |end| end + 1);
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let end = close.find('>').unwrap_or(close.len());
+            let tag = close[..end].trim().to_ascii_lowercase();
+            pos += 2 + (end + 1).min(close.len());
+            if let Some(depth) = stack.iter().rposition(|e| e.tag == tag).filter(|&d| d > 0) {
+                while stack.len() > depth {
+                    close_top(&mut stack);
+                }
+            }
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (mut element, self_closing, len) = parse_tag(rest);
+            pos += len;
+            if element.tag == "svg" {
+                let end = pos + svg_end(&html[pos..]);
+                element.source = Some(html[pos - len..end].to_string());
+                pos = end;
+                push(&mut stack, Node::Element(element));
+            } else if RAW.contains(&element.tag.as_str()) {
+                let closing = format!("</{}", element.tag);
+                let end = html[pos..].find(&closing).map_or(html.len(), |i| pos + i);
+                element.children.push(Node::Text(html[pos..end].to_string()));
+                pos = html[end..].find('>').map_or(html.len(), |i| end + i + 1);
+                push(&mut stack, Node::Element(element));
+            } else if self_closing || VOID.contains(&element.tag.as_str()) {
+                push(&mut stack, Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+        } else {
+            let end = rest.char_indices().skip(1).find(|&(_, c)| c == '<').map_or(rest.len(), |(i, _)| i);
+            push(&mut stack, Node::Text(decode_entities(&rest[..end])));
+            pos += end;
+        }
+    }
+
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+fn push(stack: &mut [Element], node: Node) {
+    if let Some(top) = stack.last_mut() {
+        top.children.push(node);
+    }
+}
+
+fn close_top(stack: &mut Vec<Element>) {
+    if let Some(element) = stack.pop() {
+        push(stack, Node::Element(element));
+    }
+}
+
+/// `<tag a="1" b>` at the start of `text` into an element, whether it ended in `/>`, and its length
+fn parse_tag(text: &str) -> (Element, bool, usize) {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' && bytes[i] != b'/' {
+        i += 1;
+    }
+    let mut element = Element { tag: text[1..i].to_ascii_lowercase(), ..Element::default() };
+    let mut self_closing = false;
+
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => break,
+            Some(b'>') => {
+                i += 1;
+                break;
+            }
+            Some(b'/') => {
+                self_closing = true;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let name = text[start..i].to_ascii_lowercase();
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            match bytes.get(i) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let end = text[i + 1..].find(quote as char).map_or(text.len(), |e| i + 1 + e);
+                    value = decode_entities(&text[i + 1..end]);
+                    i = (end + 1).min(text.len());
+                }
+                _ => {
+                    let start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = decode_entities(&text[start..i]);
+                }
+            }
+        }
+        if name.is_empty() {
+            i += 1;
+        } else {
+            self_closing = false;
+            element.attrs.push((name, value));
+        }
+    }
+    (element, self_closing, i)
+}
+
+/// Offset just past the `</svg>` matching an already-opened `<svg>`
+fn svg_end(text: &str) -> usize {
+    let mut depth = 1;
+    let mut pos = 0;
+    while let Some(i) = text[pos..].find("<") {
+        let at = pos + i;
+        if text[at..].starts_with("</svg") {
+            depth -= 1;
+            pos = text[at..].find('>').map_or(text.len(), |e| at + e + 1);
+            if depth == 0 {
+                return pos;
+            }
+        } else {
+            if text[at..].starts_with("<svg") {
+                depth += 1;
+            }
+            pos = at + 1;
+        }
+    }
+    text.len()
+}
+
+/// Replace character references; unknown named ones are left as written
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
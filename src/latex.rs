@@ -0,0 +1,760 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::Local;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd, TextMergeStream};
+use regex::Regex;
+
+use crate::book::{Book, Section};
+use crate::callouts::parse_marker;
+use crate::environments::parse_directive;
+use crate::html::{self, Element, Node};
+use crate::pdf::Fonts;
+use crate::render::{add_heading_ids, markdown_options, slugify};
+use crate::site::{content_dir, resolve_link, SiteIndex};
+use crate::{blocks, cards, get_site_url, units, wikilinks};
+
+static REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[@([A-Za-z0-9_:.-]+)\]").unwrap());
+
+/// Marks raw LaTeX smuggled through the markdown parser as an HTML comment
+const RAW: &str = "<!--latex:";
+
+const PREAMBLE: &str = r"\documentclass[11pt,a4paper]{article}
+\usepackage[utf8]{inputenc}
+\usepackage[T1]{fontenc}
+\usepackage{lmodern}
+\usepackage{amsmath,amssymb,amsthm}
+\usepackage{graphicx}
+\usepackage{booktabs}
+\usepackage{xcolor}
+\usepackage[most]{tcolorbox}
+\usepackage[normalem]{ulem}
+\usepackage{hyperref}
+\usepackage[capitalise,noabbrev]{cleveref}
+
+\theoremstyle{plain}
+\newtheorem{theorem}{Theorem}
+\newtheorem{lemma}[theorem]{Lemma}
+\newtheorem{proposition}[theorem]{Proposition}
+\newtheorem{corollary}[theorem]{Corollary}
+\theoremstyle{definition}
+\newtheorem{definition}[theorem]{Definition}
+\newtheorem{example}[theorem]{Example}
+\theoremstyle{remark}
+\newtheorem{remark}[theorem]{Remark}
+
+\definecolor{callout-note}{HTML}{3B82F6}
+\definecolor{callout-tip}{HTML}{16A34A}
+\definecolor{callout-important}{HTML}{8B5CF6}
+\definecolor{callout-warning}{HTML}{D97706}
+\definecolor{callout-caution}{HTML}{DC2626}
+\definecolor{callout-derivation}{HTML}{64748B}
+\definecolor{callout-example}{HTML}{0891B2}
+";
+
+/// The colour a callout kind is drawn in, one of the `callout-*` colours of the preamble
+fn callout_color(kind: &str) -> &'static str {
+    match kind {
+        "tip" | "hint" => "callout-tip",
+        "important" => "callout-important",
+        "warning" => "callout-warning",
+        "caution" | "danger" => "callout-caution",
+        "derivation" | "proof" | "summary" => "callout-derivation",
+        "example" | "question" => "callout-example",
+        _ => "callout-note",
+    }
+}
+
+/// Escape text for LaTeX, spelling out the symbols plain pdfLaTeX has no glyph for
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str(r"\textbackslash{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '~' => out.push_str(r"\textasciitilde{}"),
+            '^' => out.push_str(r"\textasciicircum{}"),
+            '<' => out.push_str(r"\textless{}"),
+            '>' => out.push_str(r"\textgreater{}"),
+            '\u{a0}' => out.push('~'),
+            'Ω' => out.push_str(r"\ensuremath{\Omega}"),
+            'µ' | 'μ' => out.push_str(r"\ensuremath{\mu}"),
+            '√' => out.push_str(r"\ensuremath{\surd}"),
+            '°' => out.push_str(r"\ensuremath{^\circ}"),
+            '±' => out.push_str(r"\ensuremath{\pm}"),
+            '×' => out.push_str(r"\ensuremath{\times}"),
+            '·' => out.push_str(r"\ensuremath{\cdot}"),
+            '−' => out.push_str(r"\ensuremath{-}"),
+            '≈' => out.push_str(r"\ensuremath{\approx}"),
+            '≤' => out.push_str(r"\ensuremath{\leq}"),
+            '≥' => out.push_str(r"\ensuremath{\geq}"),
+            '→' => out.push_str(r"\ensuremath{\rightarrow}"),
+            '←' => out.push_str(r"\ensuremath{\leftarrow}"),
+            '∞' => out.push_str(r"\ensuremath{\infty}"),
+            '²' => out.push_str(r"\ensuremath{^2}"),
+            '³' => out.push_str(r"\ensuremath{^3}"),
+            '›' => out.push_str(r"\guilsinglright{}"),
+            'α'..='ω' | 'Α'..='Ω' => match greek(c) {
+                Some(name) => out.push_str(&format!(r"\ensuremath{{\{}}}", name)),
+                None => out.push(c),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn greek(c: char) -> Option<&'static str> {
+    const LOWER: [&str; 25] = [
+        "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu", "nu", "xi",
+        "omicron", "pi", "rho", "varsigma", "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega",
+    ];
+    const UPPER: [&str; 25] = [
+        "", "", "Gamma", "Delta", "", "", "", "Theta", "", "", "Lambda", "", "", "Xi", "", "Pi", "", "", "Sigma", "", "Upsilon",
+        "Phi", "", "Psi", "Omega",
+    ];
+    let name = match c {
+        'α'..='ω' => LOWER[(c as u32 - 'α' as u32) as usize],
+        'Α'..='Ω' => UPPER[(c as u32 - 'Α' as u32) as usize],
+        _ => "",
+    };
+    // There is no \omicron; Latin letters stand in for Greek capitals that look the same
+    (!name.is_empty() && name != "omicron").then_some(name)
+}
+
+/// `\section`, `\subsection`, … for a heading `level` deep in the document
+fn sectioning(level: usize) -> &'static str {
+    match level {
+        0 | 1 => "section",
+        2 => "subsection",
+        3 => "subsubsection",
+        4 => "paragraph",
+        _ => "subparagraph",
+    }
+}
+
+/// Environment directives and `[@label]` references into raw LaTeX, before markdown parsing
+fn directives(source: &str) -> String {
+    let mut open: Vec<String> = Vec::new();
+    let mut in_code = false;
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+        }
+        if in_code {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        if let Some(env) = parse_directive(line) {
+            let title = if env.title.is_empty() { String::new() } else { format!("[{}]", fragment(&env.title)) };
+            let label = env.label.as_deref().map(|l| format!(r"\label{{{}}}", l)).unwrap_or_default();
+            out.push_str(&format!("\n{}\\begin{{{}}}{}{}-->\n\n", RAW, env.kind, title, label));
+            open.push(env.kind);
+            continue;
+        }
+        if line.trim() == ":::" {
+            if let Some(kind) = open.pop() {
+                out.push_str(&format!("\n{}\\end{{{}}}-->\n\n", RAW, kind));
+                continue;
+            }
+        }
+        out.push_str(&REF_RE.replace_all(line, |caps: &regex::Captures| format!(r"{}\cref{{{}}}-->", RAW, &caps[1])));
+        out.push('\n');
+    }
+    while let Some(kind) = open.pop() {
+        out.push_str(&format!("\n{}\\end{{{}}}-->\n\n", RAW, kind));
+    }
+    out
+}
+
+/// Inline markdown (an environment title, say) into LaTeX
+fn fragment(markdown: &str) -> String {
+    let events: Vec<Event> = TextMergeStream::new(Parser::new_ext(markdown, markdown_options())).collect();
+    let mut writer = Writer::new(None, &[], "");
+    writer.events(&events, 0, "");
+    writer.out.trim().to_string()
+}
+
+/// Figures written next to the `.tex` file: SVGs converted to PDF, images copied
+struct Figures {
+    /// Directory for figure files, relative to the `.tex` file's directory
+    dir: PathBuf,
+    base: PathBuf,
+    count: usize,
+    fonts: Option<Fonts>,
+}
+
+impl Figures {
+    /// Save an SVG as a PDF and return the path to include
+    fn svg(&mut self, svg: &[u8], resources: Option<PathBuf>) -> Result<String, String> {
+        if self.fonts.is_none() {
+            self.fonts = Some(Fonts::new()?);
+        }
+        let tree = self.fonts.as_ref().map(|f| f.parse_svg(svg, resources)).unwrap_or_else(|| Err("no fonts".to_string()))?;
+        self.save("pdf", &svg2pdf::convert_tree(&tree, svg2pdf::Options::default()))
+    }
+
+    fn save(&mut self, extension: &str, data: &[u8]) -> Result<String, String> {
+        self.count += 1;
+        let name = self.dir.join(format!("fig-{}.{}", self.count, extension));
+        std::fs::create_dir_all(self.base.join(&self.dir)).map_err(|e| e.to_string())?;
+        std::fs::write(self.base.join(&name), data).map_err(|e| e.to_string())?;
+        Ok(name.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+/// `\includegraphics` for an SVG of `width` CSS pixels, shrunk to the line width when wider
+fn include(file: &str, width: Option<f32>) -> String {
+    // About 345pt of text width in an 11pt article
+    let fraction = width.map_or(1.0, |w| (w * 0.75 / 345.0).min(1.0));
+    format!("\\includegraphics[width={:.2}\\linewidth]{{{}}}", fraction, file)
+}
+
+/// Walks one page's events, writing LaTeX into `out`
+struct Writer<'a> {
+    out: String,
+    figures: Option<&'a mut Figures>,
+    sections: &'a [Section],
+    /// Label prefix of the page being written
+    page: String,
+    /// Heading level of the page's own title; its `#` headings go one deeper
+    depth: usize,
+    footnotes: HashMap<String, String>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(figures: Option<&'a mut Figures>, sections: &'a [Section], page: &str) -> Writer<'a> {
+        Writer {
+            out: String::new(),
+            figures,
+            sections,
+            page: page.to_string(),
+            depth: 0,
+            footnotes: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The label a link to `dest` from `page_path` should point at, when it stays in the document
+    fn link_label(&self, page_path: &str, dest: &str) -> Option<String> {
+        let fragment = dest.split_once('#').map(|(_, f)| slugify(f)).filter(|f| !f.is_empty());
+        let target = if dest.starts_with('#') {
+            Some(self.page.clone())
+        } else {
+            let path = resolve_link(page_path, dest)?;
+            self.sections.iter().find(|s| s.path == path).map(Section::id)
+        }?;
+        Some(match fragment {
+            Some(f) => format!("{}:{}", target, f),
+            None => target,
+        })
+    }
+
+    /// Write `events`, returning the index just past the end of the construct that started at `from`
+    fn events(&mut self, events: &[Event], from: usize, page_path: &str) -> usize {
+        let mut i = from;
+        // Closing text of each open list or blockquote
+        let mut lists: Vec<&'static str> = Vec::new();
+        let mut quotes: Vec<&'static str> = Vec::new();
+        let mut cell = 0;
+        let mut links: Vec<String> = Vec::new();
+        let mut in_code = false;
+        let mut in_metadata = false;
+
+        while i < events.len() {
+            let event = &events[i];
+            i += 1;
+            if in_metadata && !matches!(event, Event::End(TagEnd::MetadataBlock(_))) {
+                continue;
+            }
+            match event {
+                Event::Start(Tag::Paragraph) => {}
+                Event::End(TagEnd::Paragraph) => self.out.push_str("\n\n"),
+                Event::Start(Tag::Heading { level, id, .. }) => {
+                    let level = self.depth + heading_level(*level);
+                    self.out.push_str(&format!("\\{}{{", sectioning(level)));
+                    let label = id.as_ref().map(|id| format!("{}:{}", self.page, id)).unwrap_or_default();
+                    links.push(label);
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    let label = links.pop().unwrap_or_default();
+                    self.out.push('}');
+                    if !label.is_empty() {
+                        self.out.push_str(&format!("\\label{{{}}}", label));
+                    }
+                    self.out.push_str("\n\n");
+                }
+                Event::Start(Tag::BlockQuote(_)) => {
+                    let marker = match (events.get(i), events.get(i + 1)) {
+                        (Some(Event::Start(Tag::Paragraph)), Some(Event::Text(t))) => parse_marker(t.lines().next().unwrap_or_default()),
+                        _ => None,
+                    };
+                    match marker {
+                        Some(marker) => {
+                            let color = callout_color(&marker.kind);
+                            self.out.push_str(&format!(
+                                "\\begin{{tcolorbox}}[colback={0}!5!white,colframe={0}!75!black,fonttitle=\\bfseries,title={{{1}}}]\n",
+                                color,
+                                escape(&marker.display_title())
+                            ));
+                            quotes.push("\\end{tcolorbox}\n\n");
+                            // Drop the marker line, keeping whatever followed it in the paragraph
+                            i += 2;
+                            if let Some(Event::Text(t)) = events.get(i - 1) {
+                                if let Some((_, rest)) = t.split_once('\n') {
+                                    self.text(rest);
+                                }
+                            }
+                            if matches!(events.get(i), Some(Event::SoftBreak)) {
+                                i += 1;
+                            }
+                        }
+                        None => {
+                            self.out.push_str("\\begin{quote}\n");
+                            quotes.push("\\end{quote}\n\n");
+                        }
+                    }
+                }
+                Event::End(TagEnd::BlockQuote(_)) => self.out.push_str(quotes.pop().unwrap_or_default()),
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    if let CodeBlockKind::Fenced(lang) = kind {
+                        if !lang.is_empty() {
+                            self.out.push_str(&format!("% {}\n", lang));
+                        }
+                    }
+                    self.out.push_str("\\begin{verbatim}\n");
+                    in_code = true;
+                }
+                Event::End(TagEnd::CodeBlock) => {
+                    if !self.out.ends_with('\n') {
+                        self.out.push('\n');
+                    }
+                    self.out.push_str("\\end{verbatim}\n\n");
+                    in_code = false;
+                }
+                Event::Start(Tag::List(start)) => {
+                    match start {
+                        Some(n) => {
+                            self.out.push_str("\\begin{enumerate}\n");
+                            if *n != 1 {
+                                self.out.push_str(&format!("\\setcounter{{enumi}}{{{}}}\n", n.saturating_sub(1)));
+                            }
+                            lists.push("\\end{enumerate}\n\n");
+                        }
+                        None => {
+                            self.out.push_str("\\begin{itemize}\n");
+                            lists.push("\\end{itemize}\n\n");
+                        }
+                    }
+                }
+                Event::End(TagEnd::List(_)) => self.out.push_str(lists.pop().unwrap_or_default()),
+                Event::Start(Tag::Item) => {
+                    // A task list item shows its box instead of a bullet
+                    match events.get(i) {
+                        Some(Event::TaskListMarker(done)) => {
+                            self.out.push_str(if *done { "\\item[$\\boxtimes$] " } else { "\\item[$\\square$] " });
+                            i += 1;
+                        }
+                        _ => self.out.push_str("\\item "),
+                    }
+                }
+                Event::End(TagEnd::Item) => {
+                    let trimmed = self.out.trim_end().len();
+                    self.out.truncate(trimmed);
+                    self.out.push('\n');
+                }
+                Event::TaskListMarker(done) => self.out.push_str(if *done { "$\\boxtimes$ " } else { "$\\square$ " }),
+                Event::Start(Tag::Table(alignments)) => {
+                    let spec: String = alignments
+                        .iter()
+                        .map(|a| match a {
+                            Alignment::Center => 'c',
+                            Alignment::Right => 'r',
+                            _ => 'l',
+                        })
+                        .collect();
+                    self.out.push_str(&format!("\\begin{{center}}\n\\begin{{tabular}}{{{}}}\n\\toprule\n", spec));
+                }
+                Event::End(TagEnd::Table) => self.out.push_str("\\bottomrule\n\\end{tabular}\n\\end{center}\n\n"),
+                Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => cell = 0,
+                Event::End(TagEnd::TableHead) => self.out.push_str(" \\\\\n\\midrule\n"),
+                Event::End(TagEnd::TableRow) => self.out.push_str(" \\\\\n"),
+                Event::Start(Tag::TableCell) => {
+                    if cell > 0 {
+                        self.out.push_str(" & ");
+                    }
+                    cell += 1;
+                }
+                Event::End(TagEnd::TableCell) => {}
+                Event::Start(Tag::Emphasis) => self.out.push_str("\\emph{"),
+                Event::Start(Tag::Strong) => self.out.push_str("\\textbf{"),
+                Event::Start(Tag::Strikethrough) => self.out.push_str("\\sout{"),
+                Event::Start(Tag::Superscript) => self.out.push_str("\\textsuperscript{"),
+                Event::Start(Tag::Subscript) => self.out.push_str("\\textsubscript{"),
+                Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Superscript | TagEnd::Subscript) => {
+                    self.out.push('}')
+                }
+                Event::Start(Tag::Link { dest_url, .. }) => match self.link_label(page_path, dest_url) {
+                    Some(label) => {
+                        self.out.push_str(&format!("\\hyperref[{}]{{", label));
+                        links.push("}".to_string());
+                    }
+                    None if dest_url.contains("://") || dest_url.starts_with("mailto:") => {
+                        self.out.push_str(&format!("\\href{{{}}}{{", dest_url.replace('%', "\\%").replace('#', "\\#")));
+                        links.push("}".to_string());
+                    }
+                    // A page that is not part of this document: keep the text only
+                    None => links.push(String::new()),
+                },
+                Event::End(TagEnd::Link) => {
+                    let close = links.pop().unwrap_or_default();
+                    self.out.push_str(&close);
+                }
+                Event::Start(Tag::Image { dest_url, title, .. }) => {
+                    // The alt text, which the site turns into the caption
+                    let mut alt = String::new();
+                    while i < events.len() && !matches!(events[i], Event::End(TagEnd::Image)) {
+                        if let Event::Text(t) | Event::Code(t) = &events[i] {
+                            alt.push_str(t);
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                    self.image(page_path, dest_url, title, &alt);
+                }
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    let start = self.out.len();
+                    i = self.events(events, i, page_path);
+                    let body = self.out.split_off(start);
+                    self.footnotes.insert(label.to_string(), body.trim().to_string());
+                }
+                Event::End(TagEnd::FootnoteDefinition) => return i,
+                Event::FootnoteReference(label) => self.out.push_str(&format!("\u{0}{}\u{0}", label)),
+                Event::Start(Tag::MetadataBlock(_)) => in_metadata = true,
+                Event::End(TagEnd::MetadataBlock(_)) => in_metadata = false,
+                Event::Text(t) if in_code => self.out.push_str(t),
+                Event::Text(t) => self.text(t),
+                Event::Code(t) => self.out.push_str(&format!("\\texttt{{{}}}", escape(t))),
+                // Math is already LaTeX
+                Event::InlineMath(m) => self.out.push_str(&format!("${}$", m.trim())),
+                Event::DisplayMath(m) => self.out.push_str(&format!("\n\\[\n{}\n\\]\n", m.trim())),
+                Event::Html(h) | Event::InlineHtml(h) => match h.trim().strip_prefix(RAW).and_then(|r| r.strip_suffix("-->")) {
+                    Some(raw) => {
+                        self.out.push_str(raw);
+                        if matches!(event, Event::Html(_)) {
+                            self.out.push_str("\n\n");
+                        }
+                    }
+                    None => self.html(h, page_path),
+                },
+                Event::SoftBreak => self.out.push('\n'),
+                Event::HardBreak => self.out.push_str("\\\\\n"),
+                Event::Rule => self.out.push_str("\\bigskip\\hrule\\bigskip\n\n"),
+                _ => {}
+            }
+        }
+        i
+    }
+
+    fn text(&mut self, text: &str) {
+        self.out.push_str(&escape(text));
+    }
+
+    fn image(&mut self, page_path: &str, src: &str, width: &str, alt: &str) {
+        let file = image_file(page_path, src);
+        let saved = match (self.figures.as_deref_mut(), file.extension().and_then(|e| e.to_str())) {
+            (None, _) => return,
+            (Some(figures), Some("svg")) => std::fs::read(&file).map_err(|e| e.to_string()).and_then(|data| figures.svg(&data, file.parent().map(PathBuf::from))),
+            (Some(figures), extension) => std::fs::read(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|data| figures.save(extension.unwrap_or("png"), &data)),
+        };
+        let saved = match saved {
+            Ok(saved) => saved,
+            Err(e) => {
+                self.warnings.push(format!("{}: {}", file.display(), e));
+                return;
+            }
+        };
+        // The site takes the title as a CSS width, e.g. "50%"
+        let fraction = width.strip_suffix('%').and_then(|w| w.trim().parse::<f32>().ok()).map_or(1.0, |w| w / 100.0);
+        let graphic = format!("\\includegraphics[width={:.2}\\linewidth]{{{}}}", fraction.min(1.0), saved);
+        if alt.is_empty() {
+            self.out.push_str(&graphic);
+        } else {
+            self.out.push_str(&format!(
+                "\n\\begin{{figure}}[htbp]\n\\centering\n{}\n\\caption{{{}}}\n\\end{{figure}}\n",
+                graphic,
+                escape(alt)
+            ));
+        }
+    }
+
+    /// HTML from blocks and raw markup: figures, tables, lists and text, with interactive parts left out
+    fn html(&mut self, markup: &str, page_path: &str) {
+        let nodes = html::parse(markup);
+        self.nodes(&nodes, page_path);
+    }
+
+    fn nodes(&mut self, nodes: &[Node], page_path: &str) {
+        for node in nodes {
+            match node {
+                Node::Text(t) => self.text(t),
+                Node::Element(e) => self.element(e, page_path),
+            }
+        }
+    }
+
+    fn element(&mut self, e: &Element, page_path: &str) {
+        let skipped = matches!(e.tag.as_str(), "button" | "script" | "style" | "input")
+            || e.attr("hidden").is_some()
+            || e.attr("aria-hidden") == Some("true")
+            || e.has_class("quiz-feedback");
+        if skipped {
+            return;
+        }
+        let wrap = |writer: &mut Self, open: &str, close: &str| {
+            writer.out.push_str(open);
+            writer.nodes(&e.children, page_path);
+            writer.out.push_str(close);
+        };
+        match e.tag.as_str() {
+            "span" if e.has_class("katex") || e.has_class("katex-display") => {
+                let tex = find(e, &|a| a.tag == "annotation").map(|a| a.text()).unwrap_or_default();
+                let display = e.has_class("katex-display") || find(e, &|m| m.tag == "math" && m.attr("display") == Some("block")).is_some();
+                if display {
+                    self.out.push_str(&format!("\n\\[\n{}\n\\]\n", tex.trim()));
+                } else {
+                    self.out.push_str(&format!("${}$", tex.trim()));
+                }
+            }
+            "figure" => {
+                self.out.push_str("\n\\begin{figure}[htbp]\n\\centering\n");
+                self.nodes(&e.children, page_path);
+                self.out.push_str("\\end{figure}\n\n");
+            }
+            "figcaption" => {
+                // The site's "Fig. 3:" prefix is LaTeX's job here
+                let caption = html::Element {
+                    children: e.children.iter().filter(|c| !matches!(c, Node::Element(s) if s.tag == "strong" && s.text().starts_with("Fig."))).cloned().collect(),
+                    ..e.clone()
+                };
+                self.out.push_str("\\caption{");
+                self.nodes(&caption.children, page_path);
+                let trimmed = self.out.trim_end().len();
+                self.out.truncate(trimmed);
+                self.out.push_str("}\n");
+            }
+            "svg" => {
+                let width = e.attr("width").and_then(|w| w.trim_end_matches("px").parse::<f32>().ok());
+                let source = e.source.clone().unwrap_or_default();
+                let saved = self.figures.as_deref_mut().map(|f| f.svg(source.as_bytes(), None));
+                match saved {
+                    Some(Ok(file)) => self.out.push_str(&format!("{}\n", include(&file, width))),
+                    Some(Err(err)) => self.warnings.push(format!("figure on {}: {}", page_path, err)),
+                    None => {}
+                }
+            }
+            "img" => {
+                let src = e.attr("src").unwrap_or_default().to_string();
+                let alt = e.attr("alt").unwrap_or_default().to_string();
+                self.image(page_path, &src, "", &alt);
+            }
+            "table" => self.table(e, page_path),
+            // A paragraph holding nothing but a formula is display math
+            "p" if e.elements().count() == 1
+                && e.elements().all(|c| c.has_class("katex"))
+                && e.children.iter().all(|c| !matches!(c, Node::Text(t) if !t.trim().is_empty())) =>
+            {
+                let tex = find(e, &|a| a.tag == "annotation").map(|a| a.text()).unwrap_or_default();
+                self.out.push_str(&format!("\n\\[\n{}\n\\]\n", tex.trim()));
+            }
+            "div" if e.has_class("question") => wrap(self, "\\textbf{", "}\n\n"),
+            "p" | "div" | "section" | "aside" => {
+                self.nodes(&e.children, page_path);
+                self.out.push_str("\n\n");
+            }
+            "details" => {
+                self.nodes(&e.children, page_path);
+                self.out.push_str("\n\n");
+            }
+            // Nothing folds on paper
+            "summary" if e.text().trim() == "Show answer" => self.out.push_str("\n\n\\paragraph{Answer}\n"),
+            "summary" => wrap(self, "\n\n\\paragraph{", "}\n"),
+            "ul" => wrap(self, "\\begin{itemize}\n", "\\end{itemize}\n\n"),
+            "ol" => wrap(self, "\\begin{enumerate}\n", "\\end{enumerate}\n\n"),
+            "li" => wrap(self, "\\item ", "\n"),
+            "dl" => wrap(self, "\\begin{description}\n", "\\end{description}\n\n"),
+            "dt" => wrap(self, "\\item[", "] "),
+            "dd" => wrap(self, "", "\n"),
+            "strong" | "b" | "th" => wrap(self, "\\textbf{", "}"),
+            "td" => self.nodes(&e.children, page_path),
+            "em" | "i" => wrap(self, "\\emph{", "}"),
+            "code" => wrap(self, "\\texttt{", "}"),
+            "sub" => wrap(self, "\\textsubscript{", "}"),
+            "sup" => wrap(self, "\\textsuperscript{", "}"),
+            "br" => self.out.push_str("\\\\\n"),
+            "pre" => {
+                self.out.push_str("\\begin{verbatim}\n");
+                self.out.push_str(e.text().trim_end());
+                self.out.push_str("\n\\end{verbatim}\n\n");
+            }
+            "a" => match e.attr("href").and_then(|href| self.link_label(page_path, href)) {
+                Some(label) => wrap(self, &format!("\\hyperref[{}]{{", label), "}"),
+                None => self.nodes(&e.children, page_path),
+            },
+            _ => self.nodes(&e.children, page_path),
+        }
+    }
+
+    /// An HTML table (from a computation block) as a booktabs `tabular`
+    fn table(&mut self, e: &Element, page_path: &str) {
+        // Rows with the group they belong to, so rules can separate head, body and foot
+        let mut rows: Vec<(&Element, &str)> = Vec::new();
+        for child in e.elements() {
+            match child.tag.as_str() {
+                "tr" => rows.push((child, "tbody")),
+                "thead" | "tbody" | "tfoot" => rows.extend(child.elements().filter(|r| r.tag == "tr").map(|r| (r, child.tag.as_str()))),
+                _ => {}
+            }
+        }
+        let cells = |row: &'_ Element| -> Vec<(Element, usize)> {
+            row.elements()
+                .filter(|c| c.tag == "td" || c.tag == "th")
+                .map(|c| (c.clone(), c.attr("colspan").and_then(|s| s.parse().ok()).unwrap_or(1)))
+                .collect()
+        };
+        let columns = rows.iter().map(|(r, _)| cells(r).iter().map(|(_, span)| span).sum::<usize>()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        self.out.push_str(&format!("\\begin{{center}}\n\\begin{{tabular}}{{{}}}\n\\toprule\n", "l".repeat(columns)));
+        let mut group = None;
+        for (row, this_group) in rows {
+            if group.is_some_and(|g| g != this_group) {
+                self.out.push_str("\\midrule\n");
+            }
+            group = Some(this_group);
+            for (n, (cell, span)) in cells(row).into_iter().enumerate() {
+                if n > 0 {
+                    self.out.push_str(" & ");
+                }
+                if span > 1 {
+                    self.out.push_str(&format!("\\multicolumn{{{}}}{{l}}{{", span));
+                }
+                self.element(&cell, page_path);
+                if span > 1 {
+                    self.out.push('}');
+                }
+            }
+            self.out.push_str(" \\\\\n");
+        }
+        self.out.push_str("\\bottomrule\n\\end{tabular}\n\\end{center}\n\n");
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn find<'e>(e: &'e Element, matches: &dyn Fn(&Element) -> bool) -> Option<&'e Element> {
+    e.elements().find_map(|c| if matches(c) { Some(c) } else { find(c, matches) })
+}
+
+/// Where an image `src` lives on disk: `/content/…` under the content directory, other absolute
+/// paths under the repository, relative ones next to the page
+pub fn image_file(page_path: &str, src: &str) -> PathBuf {
+    let site_url = get_site_url();
+    let src = src.strip_prefix(site_url.as_str()).filter(|_| !site_url.is_empty()).unwrap_or(src);
+    let src = html::decode_entities(src);
+    if let Some(rest) = src.strip_prefix("/content/") {
+        Path::new(content_dir()).join(rest)
+    } else if let Some(rest) = src.strip_prefix('/') {
+        PathBuf::from(rest)
+    } else {
+        let page = format!("{}{}", content_dir(), page_path);
+        Path::new(&page).parent().unwrap_or(Path::new(content_dir())).join(src)
+    }
+}
+
+/// The book as a standalone LaTeX document written to `out`, with its figures in a directory
+/// beside it. Returns the warnings for anything that could not be exported.
+pub fn export(book: &Book, index: &SiteIndex, out: &Path) -> Result<Vec<String>, String> {
+    let stem = out.file_stem().and_then(|s| s.to_str()).unwrap_or("notes");
+    let mut figures = Figures {
+        dir: PathBuf::from(format!("{}-figures", stem)),
+        base: out.parent().map(Path::to_path_buf).unwrap_or_default(),
+        count: 0,
+        fonts: None,
+    };
+    let mut tex = String::from(PREAMBLE);
+    tex.push_str(&format!(
+        "\n\\title{{{}}}\n\\date{{{}}}\n\n\\begin{{document}}\n\\maketitle\n",
+        escape(&book.title),
+        Local::now().format("%B %-d, %Y")
+    ));
+    if book.sections.len() > 1 {
+        tex.push_str("\\tableofcontents\n");
+    }
+    let mut warnings = Vec::new();
+
+    for section in &book.sections {
+        let mut writer = Writer::new(Some(&mut figures), &book.sections, &section.id());
+        writer.depth = section.depth;
+        if section.depth > 0 {
+            writer.out.push_str(&format!("\n\\{}{{{}}}\\label{{{}}}\n\n", sectioning(section.depth), escape(&section.title), section.id()));
+        } else {
+            writer.out.push_str(&format!("\\label{{{}}}\n", section.id()));
+        }
+        if !section.source.trim().is_empty() {
+            let source = directives(&section.source);
+            let events = add_heading_ids(TextMergeStream::new(Parser::new_ext(&source, markdown_options())).collect());
+            let events = wikilinks::resolve(events, index);
+            let events = cards::render(events);
+            let events = units::render(events);
+            let events = blocks::render(events, index, &section.path);
+            writer.events(&events, 0, &section.path);
+        }
+        // Footnote definitions come at the end of a page, so references are filled in afterwards
+        let mut body = writer.out;
+        for (label, note) in &writer.footnotes {
+            body = body.replace(&format!("\u{0}{}\u{0}", label), &format!("\\footnote{{{}}}", note));
+        }
+        if body.contains('\u{0}') {
+            warnings.push(format!("{}: footnote without a definition", section.path));
+            body = body.split('\u{0}').step_by(2).collect();
+        }
+        tex.push_str(&body);
+        warnings.append(&mut writer.warnings);
+    }
+    tex.push_str("\n\\end{document}\n");
+
+    // Collapse the blank lines left between blocks, but not inside code
+    let mut tidy = String::with_capacity(tex.len());
+    let mut verbatim = false;
+    for line in tex.lines() {
+        verbatim = (verbatim || line.starts_with("\\begin{verbatim}")) && !line.starts_with("\\end{verbatim}");
+        if !verbatim && line.trim().is_empty() && tidy.ends_with("\n\n") {
+            continue;
+        }
+        tidy.push_str(if verbatim { line } else { line.trim_end() });
+        tidy.push('\n');
+    }
+    std::fs::write(out, tidy).map_err(|e| format!("could not write {}: {}", out.display(), e))?;
+    Ok(warnings)
+}
@@ -2,8 +2,9 @@
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{window, Document, Element, HtmlElement, IntersectionObserver, IntersectionObserverInit, IntersectionObserverEntry, Window};
-use std::cell::RefCell;
+use web_sys::{window, CanvasRenderingContext2d, Document, Element, HtmlCanvasElement, HtmlElement, IntersectionObserver, IntersectionObserverInit, IntersectionObserverEntry, MouseEvent, WheelEvent, Window};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
 #[wasm_bindgen]
 extern "C" {
@@ -26,14 +27,18 @@ pub fn start() -> Result<(), JsValue> {
     init_scroll_spy()?;
     init_theme()?;
     init_sidebar_state()?;
-    format_notes()?;
     highlight_sidebar()?;
+    init_graph()?;
+    init_quizzes()?;
+    init_review()?;
 
     let closure = Closure::wrap(Box::new(move |_event: web_sys::Event| {
         let _ = init_sidebar_state();
         let _ = init_scroll_spy();
-        let _ = format_notes();
         let _ = highlight_sidebar();
+        let _ = init_graph();
+        let _ = init_quizzes();
+        let _ = init_review();
         let _ = attach_dynamic_listeners();
     }) as Box<dyn FnMut(_)>);
 
@@ -123,27 +128,135 @@ fn init_theme() -> Result<(), JsValue> {
     Ok(())
 }
 
-fn format_notes() -> Result<(), JsValue> {
-    let (_, document) = get_window_and_doc();
-    let blockquotes = document.query_selector_all("blockquote")?;
-    for i in 0..blockquotes.length() {
-        if let Some(bq) = blockquotes.get(i) {
-            let bq_el = bq.dyn_into::<Element>()?;
-            if let Some(p) = bq_el.query_selector("p")? {
-                let p_html = p.dyn_into::<HtmlElement>()?;
-                let text = p_html.text_content().unwrap_or_default().trim().to_string();
-                if text.starts_with("[!NOTE]") {
-                    let _ = bq_el.class_list().add_1("note");
-                    let inner_html = p_html.inner_html();
-                    let new_html = inner_html.replacen("[!NOTE]", "<strong>NOTE</strong>", 1);
-                    p_html.set_inner_html(&new_html);
+/// localStorage key holding the result of one quiz on the current page
+fn quiz_key(id: &str) -> String {
+    let path = get_window_and_doc().0.location().pathname().unwrap_or_default();
+    format!("quiz:{}:{}", path, id)
+}
+
+fn init_quizzes() -> Result<(), JsValue> {
+    let (window, document) = get_window_and_doc();
+    let quizzes = document.query_selector_all(".quiz")?;
+    if quizzes.length() == 0 {
+        return Ok(());
+    }
+
+    let storage = window.local_storage().ok().flatten();
+    for i in 0..quizzes.length() {
+        let Some(quiz) = quizzes.get(i) else { continue };
+        let quiz = quiz.dyn_into::<Element>()?;
+        let id = quiz.get_attribute("data-quiz-id").unwrap_or_default();
+
+        if let Some(saved) = storage.as_ref().and_then(|s| s.get_item(&quiz_key(&id)).ok().flatten()) {
+            show_quiz_result(&quiz, saved == "correct");
+        }
+
+        if let Some(btn) = quiz.query_selector(".quiz-check")? {
+            let quiz_el = quiz.clone();
+            let id = id.clone();
+            let closure = Closure::wrap(Box::new(move || {
+                let answer = quiz_el
+                    .query_selector("[data-answer]").ok().flatten()
+                    .and_then(|d| d.get_attribute("data-answer"));
+                let chosen = quiz_el
+                    .query_selector("input:checked").ok().flatten()
+                    .and_then(|input| input.get_attribute("value"));
+                if let Some(chosen) = chosen {
+                    record_quiz_result(&quiz_el, &id, Some(chosen) == answer);
                 }
+            }) as Box<dyn FnMut()>);
+            btn.dyn_into::<HtmlElement>()?.set_onclick(Some(closure.as_ref().unchecked_ref()));
+            closure.forget();
+        }
+
+        // Free-response quizzes are self-graded once the answer has been revealed
+        if let (Some(details), Some(grade)) = (quiz.query_selector("details.quiz-answer")?, quiz.query_selector(".quiz-self-grade")?) {
+            let closure = Closure::wrap(Box::new(move || {
+                let _ = grade.remove_attribute("hidden");
+            }) as Box<dyn FnMut()>);
+            details.add_event_listener_with_callback("toggle", closure.as_ref().unchecked_ref())?;
+            closure.forget();
+        }
+
+        let buttons = quiz.query_selector_all(".quiz-grade")?;
+        for j in 0..buttons.length() {
+            let Some(btn) = buttons.get(j) else { continue };
+            let btn = btn.dyn_into::<HtmlElement>()?;
+            let correct = btn.get_attribute("data-correct").as_deref() == Some("true");
+            let quiz_el = quiz.clone();
+            let id = id.clone();
+            let closure = Closure::wrap(Box::new(move || {
+                record_quiz_result(&quiz_el, &id, correct);
+            }) as Box<dyn FnMut()>);
+            btn.set_onclick(Some(closure.as_ref().unchecked_ref()));
+            closure.forget();
+        }
+    }
+
+    // Score summary sits above the first quiz
+    if document.query_selector(".quiz-score")?.is_none() {
+        if let Some(first) = quizzes.get(0) {
+            let score = document.create_element("div")?;
+            score.set_class_name("quiz-score");
+            score.set_inner_html(r#"<span class="quiz-score-text"></span> <button class="quiz-reset">Reset</button>"#);
+            first.dyn_into::<Element>()?.before_with_node_1(&score)?;
+
+            if let Some(reset) = score.query_selector(".quiz-reset")? {
+                let closure = Closure::wrap(Box::new(reset_quizzes) as Box<dyn FnMut()>);
+                reset.dyn_into::<HtmlElement>()?.set_onclick(Some(closure.as_ref().unchecked_ref()));
+                closure.forget();
             }
         }
     }
+    update_quiz_score()
+}
+
+fn record_quiz_result(quiz: &Element, id: &str, correct: bool) {
+    let (window, _) = get_window_and_doc();
+    if let Ok(Some(storage)) = window.local_storage() {
+        let _ = storage.set_item(&quiz_key(id), if correct { "correct" } else { "incorrect" });
+    }
+    show_quiz_result(quiz, correct);
+    let _ = update_quiz_score();
+}
+
+fn show_quiz_result(quiz: &Element, correct: bool) {
+    let list = quiz.class_list();
+    let _ = list.remove_2("correct", "incorrect");
+    let _ = list.add_1(if correct { "correct" } else { "incorrect" });
+    if let Ok(Some(feedback)) = quiz.query_selector(".quiz-feedback") {
+        feedback.set_text_content(Some(if correct { "✓ Correct" } else { "✗ Not quite" }));
+    }
+}
+
+fn update_quiz_score() -> Result<(), JsValue> {
+    let (_, document) = get_window_and_doc();
+    let total = document.query_selector_all(".quiz")?.length();
+    let correct = document.query_selector_all(".quiz.correct")?.length();
+    let answered = correct + document.query_selector_all(".quiz.incorrect")?.length();
+    if let Some(text) = document.query_selector(".quiz-score-text")? {
+        text.set_text_content(Some(&format!("Concept checks: {} / {} correct ({} answered)", correct, total, answered)));
+    }
     Ok(())
 }
 
+fn reset_quizzes() {
+    let (window, document) = get_window_and_doc();
+    let Ok(quizzes) = document.query_selector_all(".quiz") else { return };
+    let storage = window.local_storage().ok().flatten();
+    for i in 0..quizzes.length() {
+        let Some(quiz) = quizzes.get(i).and_then(|q| q.dyn_into::<Element>().ok()) else { continue };
+        if let (Some(storage), Some(id)) = (storage.as_ref(), quiz.get_attribute("data-quiz-id")) {
+            let _ = storage.remove_item(&quiz_key(&id));
+        }
+        let _ = quiz.class_list().remove_2("correct", "incorrect");
+        if let Ok(Some(feedback)) = quiz.query_selector(".quiz-feedback") {
+            feedback.set_text_content(None);
+        }
+    }
+    let _ = update_quiz_score();
+}
+
 fn highlight_sidebar() -> Result<(), JsValue> {
     let (window, document) = get_window_and_doc();
     let current_path = window.location().pathname()?;
@@ -167,7 +280,7 @@ fn init_scroll_spy() -> Result<(), JsValue> {
     let (_, document) = get_window_and_doc();
 
     thread_local! {
-        static OBSERVER: RefCell<Option<IntersectionObserver>> = RefCell::new(None);
+        static OBSERVER: RefCell<Option<IntersectionObserver>> = const { RefCell::new(None) };
     }
 
     OBSERVER.with(|obs| {
@@ -210,3 +323,651 @@ fn init_scroll_spy() -> Result<(), JsValue> {
 
     Ok(())
 }
+
+/// Colors assigned to categories, in order of first appearance
+const GRAPH_PALETTE: [&str; 7] = ["#7aa2f7", "#9ece6a", "#f7768e", "#e0af68", "#bb9af7", "#7dcfff", "#ff9e64"];
+
+struct GraphNode {
+    title: String,
+    url: String,
+    color: usize,
+    x: f64,
+    y: f64,
+    vx: f64,
+    vy: f64,
+}
+
+struct GraphView {
+    canvas: HtmlCanvasElement,
+    ctx: CanvasRenderingContext2d,
+    nodes: Vec<GraphNode>,
+    edges: Vec<(usize, usize)>,
+    categories: Vec<String>,
+    focus: Option<usize>,
+    neighbors: Vec<bool>,
+    zoom: f64,
+    pan: (f64, f64),
+    alpha: f64,
+    drag: Option<(f64, f64)>,
+    moved: bool,
+    hover: Option<usize>,
+}
+
+/// A requestAnimationFrame callback that reschedules itself
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+thread_local! {
+    static GRAPH: RefCell<Option<GraphView>> = const { RefCell::new(None) };
+    static GRAPH_GENERATION: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Read a string property off a parsed JSON object
+fn js_string(obj: &JsValue, key: &str) -> String {
+    js_sys::Reflect::get(obj, &JsValue::from_str(key)).ok().and_then(|v| v.as_string()).unwrap_or_default()
+}
+
+fn js_number(obj: &JsValue, key: &str) -> Option<f64> {
+    js_sys::Reflect::get(obj, &JsValue::from_str(key)).ok().and_then(|v| v.as_f64())
+}
+
+fn init_graph() -> Result<(), JsValue> {
+    let (window, document) = get_window_and_doc();
+    let Some(canvas) = document.get_element_by_id("graph-canvas") else {
+        GRAPH.with(|g| *g.borrow_mut() = None);
+        return Ok(());
+    };
+    let Some(data) = document.get_element_by_id("graph-data") else { return Ok(()) };
+
+    let canvas = canvas.dyn_into::<HtmlCanvasElement>()?;
+    let ctx = canvas
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("no 2d context"))?
+        .dyn_into::<CanvasRenderingContext2d>()?;
+
+    let json = js_sys::JSON::parse(&data.text_content().unwrap_or_default())?;
+    let raw_nodes = js_sys::Array::from(&js_sys::Reflect::get(&json, &"nodes".into())?);
+    let raw_edges = js_sys::Array::from(&js_sys::Reflect::get(&json, &"edges".into())?);
+    let focus_path = canvas.get_attribute("data-focus").unwrap_or_default();
+
+    let mut categories: Vec<String> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut focus = None;
+    let count = raw_nodes.length().max(1) as f64;
+    for (i, raw) in raw_nodes.iter().enumerate() {
+        let category = js_string(&raw, "category");
+        let color = match categories.iter().position(|c| *c == category) {
+            Some(pos) => pos,
+            None => {
+                categories.push(category);
+                categories.len() - 1
+            }
+        };
+        if js_string(&raw, "id") == focus_path {
+            focus = Some(i);
+        }
+        // Start on a circle so the simulation has something to untangle
+        let angle = i as f64 / count * std::f64::consts::TAU;
+        nodes.push(GraphNode {
+            title: js_string(&raw, "title"),
+            url: js_string(&raw, "url"),
+            color,
+            x: 200.0 * angle.cos(),
+            y: 200.0 * angle.sin(),
+            vx: 0.0,
+            vy: 0.0,
+        });
+    }
+
+    let mut edges = Vec::new();
+    for raw in raw_edges.iter() {
+        if let (Some(s), Some(t)) = (js_number(&raw, "source"), js_number(&raw, "target")) {
+            let (s, t) = (s as usize, t as usize);
+            if s < nodes.len() && t < nodes.len() {
+                edges.push((s, t));
+            }
+        }
+    }
+
+    let mut neighbors = vec![false; nodes.len()];
+    if let Some(f) = focus {
+        neighbors[f] = true;
+        for &(s, t) in &edges {
+            if s == f { neighbors[t] = true; }
+            if t == f { neighbors[s] = true; }
+        }
+    }
+
+    attach_graph_listeners(&canvas)?;
+
+    GRAPH.with(|g| {
+        *g.borrow_mut() = Some(GraphView {
+            canvas,
+            ctx,
+            nodes,
+            edges,
+            categories,
+            focus,
+            neighbors,
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+            alpha: 1.0,
+            drag: None,
+            moved: false,
+            hover: None,
+        });
+    });
+
+    // Each init starts a fresh animation loop; older loops notice the generation changed and stop
+    let generation = GRAPH_GENERATION.with(|g| {
+        g.set(g.get() + 1);
+        g.get()
+    });
+
+    let frame: FrameCallback = Rc::new(RefCell::new(None));
+    let next = frame.clone();
+    *frame.borrow_mut() = Some(Closure::wrap(Box::new(move || {
+        if GRAPH_GENERATION.with(|g| g.get()) != generation {
+            next.borrow_mut().take();
+            return;
+        }
+        let running = GRAPH.with(|g| {
+            let mut g = g.borrow_mut();
+            let Some(view) = g.as_mut() else { return false };
+            if !view.canvas.is_connected() {
+                return false;
+            }
+            view.step();
+            view.draw();
+            true
+        });
+        if running {
+            if let Some(cb) = next.borrow().as_ref() {
+                let _ = get_window_and_doc().0.request_animation_frame(cb.as_ref().unchecked_ref());
+            }
+        } else {
+            next.borrow_mut().take();
+        }
+    }) as Box<dyn FnMut()>));
+
+    if let Some(cb) = frame.borrow().as_ref() {
+        window.request_animation_frame(cb.as_ref().unchecked_ref())?;
+    }
+    Ok(())
+}
+
+fn attach_graph_listeners(canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+    let on_down = Closure::wrap(Box::new(move |e: MouseEvent| {
+        with_graph(|view| {
+            view.drag = Some((e.offset_x() as f64, e.offset_y() as f64));
+            view.moved = false;
+            let _ = view.canvas.class_list().add_1("dragging");
+        });
+    }) as Box<dyn FnMut(MouseEvent)>);
+    canvas.add_event_listener_with_callback("mousedown", on_down.as_ref().unchecked_ref())?;
+    on_down.forget();
+
+    let on_move = Closure::wrap(Box::new(move |e: MouseEvent| {
+        with_graph(|view| {
+            let (x, y) = (e.offset_x() as f64, e.offset_y() as f64);
+            if let Some((last_x, last_y)) = view.drag {
+                let (dx, dy) = (x - last_x, y - last_y);
+                if dx.abs() + dy.abs() > 3.0 {
+                    view.moved = true;
+                }
+                if view.moved {
+                    view.pan.0 += dx;
+                    view.pan.1 += dy;
+                    view.drag = Some((x, y));
+                }
+            } else {
+                view.hover = view.node_at(x, y);
+                let cursor = if view.hover.is_some() { "pointer" } else { "" };
+                let _ = view.canvas.style().set_property("cursor", cursor);
+            }
+        });
+    }) as Box<dyn FnMut(MouseEvent)>);
+    canvas.add_event_listener_with_callback("mousemove", on_move.as_ref().unchecked_ref())?;
+    on_move.forget();
+
+    let on_up = Closure::wrap(Box::new(move |e: MouseEvent| {
+        let target = with_graph(|view| {
+            let _ = view.canvas.class_list().remove_1("dragging");
+            let clicked = view.drag.take().is_some() && !view.moved;
+            if clicked {
+                view.node_at(e.offset_x() as f64, e.offset_y() as f64).map(|i| view.nodes[i].url.clone())
+            } else {
+                None
+            }
+        });
+        if let Some(url) = target.flatten() {
+            let _ = get_window_and_doc().0.location().set_href(&url);
+        }
+    }) as Box<dyn FnMut(MouseEvent)>);
+    canvas.add_event_listener_with_callback("mouseup", on_up.as_ref().unchecked_ref())?;
+    canvas.add_event_listener_with_callback("mouseleave", on_up.as_ref().unchecked_ref())?;
+    on_up.forget();
+
+    let on_wheel = Closure::wrap(Box::new(move |e: WheelEvent| {
+        e.prevent_default();
+        with_graph(|view| {
+            // Zoom around the cursor so the point under it stays put
+            let (x, y) = (e.offset_x() as f64, e.offset_y() as f64);
+            let (wx, wy) = view.to_world(x, y);
+            let factor = if e.delta_y() < 0.0 { 1.1 } else { 1.0 / 1.1 };
+            view.zoom = (view.zoom * factor).clamp(0.2, 5.0);
+            let (cx, cy) = view.center();
+            view.pan = (x - cx - wx * view.zoom, y - cy - wy * view.zoom);
+        });
+    }) as Box<dyn FnMut(WheelEvent)>);
+    canvas.add_event_listener_with_callback("wheel", on_wheel.as_ref().unchecked_ref())?;
+    on_wheel.forget();
+
+    Ok(())
+}
+
+fn with_graph<R>(f: impl FnOnce(&mut GraphView) -> R) -> Option<R> {
+    GRAPH.with(|g| g.borrow_mut().as_mut().map(f))
+}
+
+impl GraphView {
+    fn center(&self) -> (f64, f64) {
+        (self.canvas.client_width() as f64 / 2.0, self.canvas.client_height() as f64 / 2.0)
+    }
+
+    fn to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        let (cx, cy) = self.center();
+        (cx + self.pan.0 + x * self.zoom, cy + self.pan.1 + y * self.zoom)
+    }
+
+    fn to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        let (cx, cy) = self.center();
+        ((x - cx - self.pan.0) / self.zoom, (y - cy - self.pan.1) / self.zoom)
+    }
+
+    fn radius(&self, i: usize) -> f64 {
+        let degree = self.edges.iter().filter(|&&(s, t)| s == i || t == i).count() as f64;
+        let base = 5.0 + degree.sqrt() * 2.0;
+        if self.focus == Some(i) { base + 3.0 } else { base }
+    }
+
+    fn node_at(&self, x: f64, y: f64) -> Option<usize> {
+        (0..self.nodes.len()).rev().find(|&i| {
+            let (nx, ny) = self.to_screen(self.nodes[i].x, self.nodes[i].y);
+            let r = self.radius(i) * self.zoom + 3.0;
+            (nx - x).powi(2) + (ny - y).powi(2) <= r * r
+        })
+    }
+
+    /// One tick of the force simulation: pairwise repulsion, springs along edges and a pull to the center
+    fn step(&mut self) {
+        if self.alpha < 0.005 {
+            return;
+        }
+        let n = self.nodes.len();
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let dx = self.nodes[j].x - self.nodes[i].x;
+                let dy = self.nodes[j].y - self.nodes[i].y;
+                let dist2 = (dx * dx + dy * dy).max(1.0);
+                let force = 2000.0 / dist2 * self.alpha;
+                let dist = dist2.sqrt();
+                let (fx, fy) = (dx / dist * force, dy / dist * force);
+                self.nodes[i].vx -= fx;
+                self.nodes[i].vy -= fy;
+                self.nodes[j].vx += fx;
+                self.nodes[j].vy += fy;
+            }
+        }
+        for &(s, t) in &self.edges {
+            let dx = self.nodes[t].x - self.nodes[s].x;
+            let dy = self.nodes[t].y - self.nodes[s].y;
+            let dist = (dx * dx + dy * dy).sqrt().max(1.0);
+            let force = (dist - 80.0) * 0.02 * self.alpha;
+            let (fx, fy) = (dx / dist * force, dy / dist * force);
+            self.nodes[s].vx += fx;
+            self.nodes[s].vy += fy;
+            self.nodes[t].vx -= fx;
+            self.nodes[t].vy -= fy;
+        }
+        for node in &mut self.nodes {
+            node.vx -= node.x * 0.01 * self.alpha;
+            node.vy -= node.y * 0.01 * self.alpha;
+            node.vx *= 0.6;
+            node.vy *= 0.6;
+            node.x += node.vx;
+            node.y += node.vy;
+        }
+        self.alpha *= 0.99;
+    }
+
+    fn draw(&self) {
+        let ratio = get_window_and_doc().0.device_pixel_ratio();
+        let (w, h) = (self.canvas.client_width() as f64, self.canvas.client_height() as f64);
+        if self.canvas.width() != (w * ratio) as u32 || self.canvas.height() != (h * ratio) as u32 {
+            self.canvas.set_width((w * ratio) as u32);
+            self.canvas.set_height((h * ratio) as u32);
+        }
+        let ctx = &self.ctx;
+        let _ = ctx.set_transform(ratio, 0.0, 0.0, ratio, 0.0, 0.0);
+        ctx.clear_rect(0.0, 0.0, w, h);
+
+        let text_color = theme_color("--text-color").unwrap_or_else(|| "#1a1a1a".into());
+        let dimmed = |i: usize| self.focus.is_some() && !self.neighbors[i];
+
+        ctx.set_line_width(1.0);
+        for &(s, t) in &self.edges {
+            let highlighted = self.focus.is_some_and(|f| s == f || t == f);
+            ctx.set_global_alpha(if self.focus.is_some() && !highlighted { 0.08 } else { 0.35 });
+            ctx.set_stroke_style_str(&text_color);
+            let (x1, y1) = self.to_screen(self.nodes[s].x, self.nodes[s].y);
+            let (x2, y2) = self.to_screen(self.nodes[t].x, self.nodes[t].y);
+            ctx.begin_path();
+            ctx.move_to(x1, y1);
+            ctx.line_to(x2, y2);
+            ctx.stroke();
+        }
+
+        ctx.set_font("12px Inter, sans-serif");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let (x, y) = self.to_screen(node.x, node.y);
+            let r = self.radius(i) * self.zoom.sqrt();
+            ctx.set_global_alpha(if dimmed(i) { 0.15 } else { 1.0 });
+            ctx.set_fill_style_str(GRAPH_PALETTE[node.color % GRAPH_PALETTE.len()]);
+            ctx.begin_path();
+            let _ = ctx.arc(x, y, r, 0.0, std::f64::consts::TAU);
+            ctx.fill();
+            if self.focus == Some(i) {
+                ctx.set_stroke_style_str(&text_color);
+                ctx.set_line_width(2.0);
+                ctx.stroke();
+                ctx.set_line_width(1.0);
+            }
+
+            let labelled = self.hover == Some(i) || (self.focus.is_some() && !dimmed(i)) || self.zoom >= 1.5;
+            if labelled {
+                ctx.set_fill_style_str(&text_color);
+                let _ = ctx.fill_text(&node.title, x + r + 4.0, y + 4.0);
+            }
+        }
+
+        // Legend
+        ctx.set_global_alpha(1.0);
+        for (i, category) in self.categories.iter().enumerate() {
+            let y = 20.0 + i as f64 * 18.0;
+            ctx.set_fill_style_str(GRAPH_PALETTE[i % GRAPH_PALETTE.len()]);
+            ctx.begin_path();
+            let _ = ctx.arc(16.0, y, 5.0, 0.0, std::f64::consts::TAU);
+            ctx.fill();
+            ctx.set_fill_style_str(&text_color);
+            let label = if category.is_empty() { "Home" } else { category };
+            let _ = ctx.fill_text(label, 28.0, y + 4.0);
+        }
+    }
+}
+
+/// Resolve a CSS custom property on the root element, so the canvas follows the theme
+fn theme_color(var: &str) -> Option<String> {
+    let (window, document) = get_window_and_doc();
+    let root = document.document_element()?;
+    let style = window.get_computed_style(&root).ok()??;
+    let value = style.get_property_value(var).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+struct ReviewCard {
+    id: String,
+    front: String,
+    back: String,
+    title: String,
+    url: String,
+    category: String,
+    tags: Vec<String>,
+}
+
+/// SM-2 scheduling state of one card
+#[derive(Clone, Copy)]
+struct Schedule {
+    ease: f64,
+    interval: f64,
+    reps: u32,
+    due: f64,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule { ease: 2.5, interval: 0.0, reps: 0, due: 0.0 }
+    }
+}
+
+impl Schedule {
+    /// Apply an SM-2 review with quality `q` (0-5) at time `now` (ms since epoch)
+    fn review(mut self, q: f64, now: f64) -> Self {
+        const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+        if q < 3.0 {
+            self.reps = 0;
+            self.interval = 1.0;
+        } else {
+            self.reps += 1;
+            self.interval = match self.reps {
+                1 => 1.0,
+                2 => 6.0,
+                _ => (self.interval * self.ease).round(),
+            };
+        }
+        self.ease = (self.ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(1.3);
+        // "Again" comes back within the session rather than tomorrow
+        self.due = if q < 3.0 { now + 60.0 * 1000.0 } else { now + self.interval * DAY_MS };
+        self
+    }
+}
+
+struct Review {
+    cards: Vec<ReviewCard>,
+    current: Option<usize>,
+}
+
+thread_local! {
+    static REVIEW: RefCell<Option<Review>> = const { RefCell::new(None) };
+}
+
+const REVIEW_STORAGE_KEY: &str = "cards";
+
+fn load_schedules() -> js_sys::Object {
+    let (window, _) = get_window_and_doc();
+    window
+        .local_storage().ok().flatten()
+        .and_then(|s| s.get_item(REVIEW_STORAGE_KEY).ok().flatten())
+        .and_then(|raw| js_sys::JSON::parse(&raw).ok())
+        .and_then(|v| v.dyn_into::<js_sys::Object>().ok())
+        .unwrap_or_default()
+}
+
+fn schedule_for(schedules: &js_sys::Object, id: &str) -> Schedule {
+    let Ok(entry) = js_sys::Reflect::get(schedules, &JsValue::from_str(id)) else { return Schedule::default() };
+    if entry.is_undefined() {
+        return Schedule::default();
+    }
+    let defaults = Schedule::default();
+    Schedule {
+        ease: js_number(&entry, "ease").unwrap_or(defaults.ease),
+        interval: js_number(&entry, "interval").unwrap_or(defaults.interval),
+        reps: js_number(&entry, "reps").unwrap_or(0.0) as u32,
+        due: js_number(&entry, "due").unwrap_or(defaults.due),
+    }
+}
+
+fn save_schedule(id: &str, schedule: Schedule) {
+    let (window, _) = get_window_and_doc();
+    let schedules = load_schedules();
+    let entry = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&entry, &"ease".into(), &schedule.ease.into());
+    let _ = js_sys::Reflect::set(&entry, &"interval".into(), &schedule.interval.into());
+    let _ = js_sys::Reflect::set(&entry, &"reps".into(), &(schedule.reps as f64).into());
+    let _ = js_sys::Reflect::set(&entry, &"due".into(), &schedule.due.into());
+    let _ = js_sys::Reflect::set(&schedules, &JsValue::from_str(id), &entry);
+    if let (Ok(Some(storage)), Ok(raw)) = (window.local_storage(), js_sys::JSON::stringify(&schedules)) {
+        let _ = storage.set_item(REVIEW_STORAGE_KEY, &String::from(raw));
+    }
+}
+
+/// Current value of a `<select>` on the review page
+fn select_value(document: &Document, id: &str) -> String {
+    document
+        .get_element_by_id(id)
+        .and_then(|el| js_sys::Reflect::get(&el, &"value".into()).ok())
+        .and_then(|v| v.as_string())
+        .unwrap_or_default()
+}
+
+fn init_review() -> Result<(), JsValue> {
+    let (_, document) = get_window_and_doc();
+    let (Some(root), Some(data)) = (document.get_element_by_id("review"), document.get_element_by_id("cards-data")) else {
+        REVIEW.with(|r| *r.borrow_mut() = None);
+        return Ok(());
+    };
+
+    let json = js_sys::JSON::parse(&data.text_content().unwrap_or_default())?;
+    let cards = js_sys::Array::from(&json)
+        .iter()
+        .map(|raw| {
+            let tags = js_sys::Reflect::get(&raw, &"tags".into())
+                .map(|t| js_sys::Array::from(&t).iter().filter_map(|v| v.as_string()).collect())
+                .unwrap_or_default();
+            ReviewCard {
+                id: js_string(&raw, "id"),
+                front: js_string(&raw, "front"),
+                back: js_string(&raw, "back"),
+                title: js_string(&raw, "title"),
+                url: js_string(&raw, "url"),
+                category: js_string(&raw, "category"),
+                tags,
+            }
+        })
+        .collect();
+    REVIEW.with(|r| *r.borrow_mut() = Some(Review { cards, current: None }));
+
+    for id in ["review-category", "review-tag"] {
+        if let Some(select) = document.get_element_by_id(id) {
+            let closure = Closure::wrap(Box::new(move || {
+                let _ = next_card();
+            }) as Box<dyn FnMut()>);
+            select.add_event_listener_with_callback("change", closure.as_ref().unchecked_ref())?;
+            closure.forget();
+        }
+    }
+
+    if let Some(btn) = root.query_selector(".review-show")? {
+        let closure = Closure::wrap(Box::new(reveal_card) as Box<dyn FnMut()>);
+        btn.dyn_into::<HtmlElement>()?.set_onclick(Some(closure.as_ref().unchecked_ref()));
+        closure.forget();
+    }
+
+    let grades = root.query_selector_all(".review-grades button")?;
+    for i in 0..grades.length() {
+        let Some(btn) = grades.get(i) else { continue };
+        let btn = btn.dyn_into::<HtmlElement>()?;
+        let quality: f64 = btn.get_attribute("data-grade").and_then(|g| g.parse().ok()).unwrap_or(0.0);
+        let closure = Closure::wrap(Box::new(move || {
+            grade_card(quality);
+        }) as Box<dyn FnMut()>);
+        btn.set_onclick(Some(closure.as_ref().unchecked_ref()));
+        closure.forget();
+    }
+
+    next_card()
+}
+
+/// Show the most overdue card matching the filters, or the "nothing due" message
+fn next_card() -> Result<(), JsValue> {
+    let (_, document) = get_window_and_doc();
+    let category = select_value(&document, "review-category");
+    let tag = select_value(&document, "review-tag");
+    let now = js_sys::Date::now();
+    let schedules = load_schedules();
+
+    let picked = REVIEW.with(|r| {
+        let mut r = r.borrow_mut();
+        let review = r.as_mut()?;
+        let matching: Vec<usize> = (0..review.cards.len())
+            .filter(|&i| category.is_empty() || review.cards[i].category == category)
+            .filter(|&i| tag.is_empty() || review.cards[i].tags.contains(&tag))
+            .collect();
+        let due: Vec<(usize, f64)> = matching
+            .iter()
+            .map(|&i| (i, schedule_for(&schedules, &review.cards[i].id).due))
+            .filter(|&(_, due)| due <= now)
+            .collect();
+        let next = due.iter().min_by(|a, b| a.1.total_cmp(&b.1)).map(|&(i, _)| i);
+        review.current = next;
+        let card = next.map(|i| {
+            let c = &review.cards[i];
+            (c.front.clone(), c.back.clone(), c.title.clone(), c.url.clone())
+        });
+        Some((card, due.len(), matching.len()))
+    });
+    let Some((card, due, total)) = picked else { return Ok(()) };
+
+    if let Some(status) = document.query_selector(".review-status")? {
+        status.set_text_content(Some(&format!("{} due · {} cards", due, total)));
+    }
+
+    let card_el = document.query_selector(".review-card")?;
+    let done_el = document.query_selector(".review-done")?;
+    let show_el = document.query_selector(".review-show")?;
+    let grades_el = document.query_selector(".review-grades")?;
+    let (Some(card_el), Some(done_el), Some(show_el), Some(grades_el)) = (card_el, done_el, show_el, grades_el) else {
+        return Ok(());
+    };
+    grades_el.set_attribute("hidden", "")?;
+
+    match card {
+        Some((front, back, title, url)) => {
+            card_el.remove_attribute("hidden")?;
+            done_el.set_attribute("hidden", "")?;
+            show_el.remove_attribute("hidden")?;
+            if let Some(el) = card_el.query_selector(".flashcard-front")? {
+                el.set_inner_html(&front);
+            }
+            if let Some(el) = card_el.query_selector(".flashcard-back")? {
+                el.set_inner_html(&back);
+                el.set_attribute("hidden", "")?;
+            }
+            if let Some(el) = card_el.query_selector(".review-source")? {
+                el.set_inner_html(&format!(r#"From <a href="{}">{}</a>"#, url, title));
+            }
+        }
+        None => {
+            card_el.set_attribute("hidden", "")?;
+            done_el.remove_attribute("hidden")?;
+            show_el.set_attribute("hidden", "")?;
+        }
+    }
+    Ok(())
+}
+
+fn reveal_card() {
+    let (_, document) = get_window_and_doc();
+    if let Ok(Some(back)) = document.query_selector(".review-card .flashcard-back") {
+        let _ = back.remove_attribute("hidden");
+    }
+    if let Ok(Some(show)) = document.query_selector(".review-show") {
+        let _ = show.set_attribute("hidden", "");
+    }
+    if let Ok(Some(grades)) = document.query_selector(".review-grades") {
+        let _ = grades.remove_attribute("hidden");
+    }
+}
+
+fn grade_card(quality: f64) {
+    let id = REVIEW.with(|r| {
+        let r = r.borrow();
+        let review = r.as_ref()?;
+        review.current.map(|i| review.cards[i].id.clone())
+    });
+    if let Some(id) = id {
+        let schedule = schedule_for(&load_schedules(), &id).review(quality, js_sys::Date::now());
+        save_schedule(&id, schedule);
+    }
+    let _ = next_card();
+}
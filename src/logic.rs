@@ -0,0 +1,758 @@
+use std::fmt::Write;
+
+use crate::chart::{self, SERIES_COLORS, TEXT_COLOR};
+use crate::site::escape_html;
+
+const MAX_VARIABLES: usize = 8;
+const MAX_KMAP_VARIABLES: usize = 6;
+
+/// Boolean expression over variables indexed into the block's variable list
+#[derive(Clone, Debug)]
+enum Bool {
+    Const(bool),
+    Var(String),
+    Not(Box<Bool>),
+    And(Box<Bool>, Box<Bool>),
+    Or(Box<Bool>, Box<Bool>),
+    Xor(Box<Bool>, Box<Bool>),
+}
+
+impl Bool {
+    fn eval(&self, vars: &[String], row: usize) -> bool {
+        match self {
+            Bool::Const(b) => *b,
+            Bool::Var(name) => {
+                let i = vars.iter().position(|v| v == name).unwrap_or(0);
+                row >> (vars.len() - 1 - i) & 1 == 1
+            }
+            Bool::Not(a) => !a.eval(vars, row),
+            Bool::And(a, b) => a.eval(vars, row) && b.eval(vars, row),
+            Bool::Or(a, b) => a.eval(vars, row) || b.eval(vars, row),
+            Bool::Xor(a, b) => a.eval(vars, row) != b.eval(vars, row),
+        }
+    }
+
+    fn collect_vars(&self, out: &mut Vec<String>) {
+        match self {
+            Bool::Const(_) => {}
+            Bool::Var(name) => {
+                if !out.contains(name) {
+                    out.push(name.clone());
+                }
+            }
+            Bool::Not(a) => a.collect_vars(out),
+            Bool::And(a, b) | Bool::Or(a, b) | Bool::Xor(a, b) => {
+                a.collect_vars(out);
+                b.collect_vars(out);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Var(String),
+    Const(bool),
+    Not,
+    Prime,
+    And,
+    Or,
+    Xor,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '\'' | '’' => Token::Prime,
+            '!' | '~' | '¬' => Token::Not,
+            '*' | '·' | '&' | '∧' | '.' => Token::And,
+            '+' | '|' | '∨' => Token::Or,
+            '^' | '⊕' => Token::Xor,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '0' => Token::Const(false),
+            '1' => Token::Const(true),
+            c if c.is_ascii_lowercase() => {
+                // Lowercase words are operators: and, or, xor, not
+                let start = i - 1;
+                while i < chars.len() && chars[i].is_ascii_lowercase() {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "xor" => Token::Xor,
+                    "not" => Token::Not,
+                    _ => return Err(format!("unknown word `{}` (variables are capital letters like A or X1)", word)),
+                }
+            }
+            // A variable is one capital letter, optionally numbered, so AB means A·B
+            c if c.is_ascii_uppercase() => {
+                let start = i - 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                Token::Var(chars[start..i].iter().collect())
+            }
+            other => return Err(format!("unexpected `{}`", other)),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Precedence, loosest first: OR, XOR, AND (explicit or by juxtaposition), NOT
+    fn or(&mut self) -> Result<Bool, String> {
+        let mut lhs = self.xor()?;
+        while self.eat(&Token::Or) {
+            lhs = Bool::Or(Box::new(lhs), Box::new(self.xor()?));
+        }
+        Ok(lhs)
+    }
+
+    fn xor(&mut self) -> Result<Bool, String> {
+        let mut lhs = self.and()?;
+        while self.eat(&Token::Xor) {
+            lhs = Bool::Xor(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Bool, String> {
+        let mut lhs = self.not()?;
+        loop {
+            if self.eat(&Token::And) || matches!(self.peek(), Some(Token::Var(_) | Token::Const(_) | Token::Not | Token::Open)) {
+                lhs = Bool::And(Box::new(lhs), Box::new(self.not()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn not(&mut self) -> Result<Bool, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Bool::Not(Box::new(self.not()?)));
+        }
+        let mut atom = match self.tokens.get(self.pos).cloned() {
+            Some(Token::Var(name)) => {
+                self.pos += 1;
+                Bool::Var(name)
+            }
+            Some(Token::Const(b)) => {
+                self.pos += 1;
+                Bool::Const(b)
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing `)`".to_string());
+                }
+                inner
+            }
+            Some(_) => return Err("expected a variable, constant or `(`".to_string()),
+            None => return Err("unexpected end of expression".to_string()),
+        };
+        // Postfix complement, A'' cancels
+        while self.eat(&Token::Prime) {
+            atom = Bool::Not(Box::new(atom));
+        }
+        Ok(atom)
+    }
+}
+
+fn parse_bool(source: &str) -> Result<Bool, String> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let expr = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("unexpected input in `{}`", source.trim()));
+    }
+    Ok(expr)
+}
+
+/// How an output is defined: an expression, or minterms with don't-cares (`m(1,3) + d(7)`)
+enum Definition {
+    Expr(Bool),
+    Minterms(Vec<usize>, Vec<usize>),
+}
+
+struct Output {
+    name: String,
+    definition: Definition,
+}
+
+fn parse_indices(list: &str) -> Result<Vec<usize>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|_| format!("`{}` is not a minterm number", s)))
+        .collect()
+}
+
+/// `m(1, 3, 5) + d(7)` (also `Σm(...)`), or None if the right-hand side is an expression
+fn parse_minterms(rhs: &str) -> Result<Option<Definition>, String> {
+    let rhs = rhs.trim().trim_start_matches(['Σ', '∑']).trim_start();
+    if !rhs.starts_with("m(") {
+        return Ok(None);
+    }
+    let mut ones = Vec::new();
+    let mut dont_care = Vec::new();
+    for part in rhs.split('+') {
+        let part = part.trim();
+        let (target, inner) = if let Some(inner) = part.strip_prefix("m(") {
+            (&mut ones, inner)
+        } else if let Some(inner) = part.strip_prefix("d(") {
+            (&mut dont_care, inner)
+        } else {
+            return Err(format!("expected `m(...)` or `d(...)`, got `{}`", part));
+        };
+        target.extend(parse_indices(inner.trim_end_matches(')'))?);
+    }
+    Ok(Some(Definition::Minterms(ones, dont_care)))
+}
+
+/// An implicant: `value` on the bits outside `mask`, anything on the bits in it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Implicant {
+    value: usize,
+    mask: usize,
+}
+
+impl Implicant {
+    fn covers(&self, minterm: usize) -> bool {
+        minterm & !self.mask == self.value
+    }
+
+    /// Whether the implicant agrees with `value` on the bits selected by `bits`
+    fn covers_bits(&self, value: usize, bits: usize) -> bool {
+        let fixed = bits & !self.mask;
+        value & fixed == self.value & fixed
+    }
+
+    fn literals(&self, n: usize) -> usize {
+        n - (self.mask.count_ones() as usize)
+    }
+}
+
+/// Quine–McCluskey: all prime implicants of the function with these minterms and don't-cares
+fn prime_implicants(ones: &[usize], dont_care: &[usize]) -> Vec<Implicant> {
+    let mut current: Vec<Implicant> = ones.iter().chain(dont_care).map(|&m| Implicant { value: m, mask: 0 }).collect();
+    current.sort_by_key(|i| i.value);
+    current.dedup();
+    let mut primes = Vec::new();
+
+    while !current.is_empty() {
+        let mut combined = vec![false; current.len()];
+        let mut next: Vec<Implicant> = Vec::new();
+        for i in 0..current.len() {
+            for j in i + 1..current.len() {
+                let (a, b) = (current[i], current[j]);
+                let diff = a.value ^ b.value;
+                if a.mask == b.mask && diff.count_ones() == 1 {
+                    let merged = Implicant { value: a.value & !diff, mask: a.mask | diff };
+                    if !next.contains(&merged) {
+                        next.push(merged);
+                    }
+                    combined[i] = true;
+                    combined[j] = true;
+                }
+            }
+        }
+        primes.extend(current.iter().zip(&combined).filter(|(_, &c)| !c).map(|(i, _)| *i));
+        current = next;
+    }
+    primes
+}
+
+/// Search nodes `minimal_cover` may visit before settling for the best cover found so far
+const SEARCH_BUDGET: usize = 20_000;
+
+/// Number of implicants, then number of literals: what a minimal cover minimises
+fn cost(primes: &[Implicant], set: &[usize], n: usize) -> (usize, usize) {
+    (set.len(), set.iter().map(|&p| primes[p].literals(n)).sum())
+}
+
+/// Branch and bound over the minterms essentials leave uncovered
+struct Search<'a> {
+    primes: &'a [Implicant],
+    n: usize,
+    best: Vec<usize>,
+    best_cost: (usize, usize),
+    nodes: usize,
+}
+
+impl Search<'_> {
+    fn branch(&mut self, chosen: &mut Vec<usize>, remaining: &[usize]) {
+        if self.nodes >= SEARCH_BUDGET {
+            return;
+        }
+        self.nodes += 1;
+        let primes = self.primes;
+        let covering = |m: usize| (0..primes.len()).filter(move |&p| primes[p].covers(m));
+        // Branch on the minterm with the fewest ways to cover it
+        let Some(&m) = remaining.iter().min_by_key(|&&m| covering(m).count()) else {
+            let cost = cost(primes, chosen, self.n);
+            if cost < self.best_cost {
+                self.best = chosen.clone();
+                self.best_cost = cost;
+            }
+            return;
+        };
+        let gain = |p: usize| remaining.iter().filter(|&&r| primes[p].covers(r)).count();
+        // Each further implicant covers at most as many minterms as the widest one
+        let widest = (0..primes.len()).map(gain).max().unwrap_or(1).max(1);
+        if chosen.len() + remaining.len().div_ceil(widest) > self.best_cost.0 {
+            return;
+        }
+        let mut options: Vec<usize> = covering(m).collect();
+        options.sort_by_key(|&p| std::cmp::Reverse(gain(p)));
+        for p in options {
+            chosen.push(p);
+            let rest: Vec<usize> = remaining.iter().copied().filter(|&r| !primes[p].covers(r)).collect();
+            self.branch(chosen, &rest);
+            chosen.pop();
+        }
+    }
+}
+
+/// Smallest set of prime implicants covering every minterm: essentials first, then a branch and
+/// bound search that starts from the greedy cover and keeps the best found within `SEARCH_BUDGET`
+fn minimal_cover(ones: &[usize], primes: &[Implicant], n: usize) -> Vec<Implicant> {
+    let mut chosen: Vec<usize> = Vec::new();
+    let mut remaining: Vec<usize> = ones.to_vec();
+
+    for &m in ones {
+        let covering: Vec<usize> = (0..primes.len()).filter(|&p| primes[p].covers(m)).collect();
+        if covering.len() == 1 && !chosen.contains(&covering[0]) {
+            chosen.push(covering[0]);
+        }
+    }
+    remaining.retain(|&m| !chosen.iter().any(|&p| primes[p].covers(m)));
+
+    if !remaining.is_empty() {
+        // Greedily take whatever covers the most, as the bound to beat
+        let mut greedy = Vec::new();
+        let mut left = remaining.clone();
+        while !left.is_empty() {
+            let best = (0..primes.len()).max_by_key(|&p| left.iter().filter(|&&m| primes[p].covers(m)).count()).unwrap();
+            greedy.push(best);
+            left.retain(|&m| !primes[best].covers(m));
+        }
+        let best_cost = cost(primes, &greedy, n);
+        let mut search = Search { primes, n, best: greedy, best_cost, nodes: 0 };
+        search.branch(&mut Vec::new(), &remaining);
+        chosen.extend(search.best);
+    }
+
+    let mut cover: Vec<Implicant> = chosen.into_iter().map(|p| primes[p]).collect();
+    cover.sort_by_key(|i| (std::cmp::Reverse(i.mask.count_ones()), i.value));
+    cover
+}
+
+fn minimize(ones: &[usize], dont_care: &[usize], n: usize) -> Vec<Implicant> {
+    minimal_cover(ones, &prime_implicants(ones, dont_care), n)
+}
+
+fn latex_var(name: &str) -> String {
+    match name.find(|c: char| c.is_ascii_digit()) {
+        Some(i) => format!("{}_{{{}}}", &name[..i], &name[i..]),
+        None => name.to_string(),
+    }
+}
+
+/// `\overline{A}B + AC`
+fn sop_latex(cover: &[Implicant], vars: &[String]) -> String {
+    let n = vars.len();
+    if cover.is_empty() {
+        return "0".to_string();
+    }
+    if cover.iter().any(|i| i.mask == (1 << n) - 1) {
+        return "1".to_string();
+    }
+    let terms: Vec<String> = cover
+        .iter()
+        .map(|imp| {
+            (0..n)
+                .filter(|&i| imp.mask >> (n - 1 - i) & 1 == 0)
+                .map(|i| {
+                    let var = latex_var(&vars[i]);
+                    if imp.value >> (n - 1 - i) & 1 == 1 { var } else { format!("\\overline{{{}}}", var) }
+                })
+                .collect()
+        })
+        .collect();
+    terms.join(" + ")
+}
+
+/// `(A + B)(\overline{A} + C)`, from a cover of the zeros
+fn pos_latex(cover: &[Implicant], vars: &[String]) -> String {
+    let n = vars.len();
+    if cover.is_empty() {
+        return "1".to_string();
+    }
+    if cover.iter().any(|i| i.mask == (1 << n) - 1) {
+        return "0".to_string();
+    }
+    let terms: Vec<String> = cover
+        .iter()
+        .map(|imp| {
+            let literals: Vec<String> = (0..n)
+                .filter(|&i| imp.mask >> (n - 1 - i) & 1 == 0)
+                .map(|i| {
+                    let var = latex_var(&vars[i]);
+                    if imp.value >> (n - 1 - i) & 1 == 1 { format!("\\overline{{{}}}", var) } else { var }
+                })
+                .collect();
+            if literals.len() == 1 && cover.len() == 1 { literals[0].clone() } else { format!("({})", literals.join(" + ")) }
+        })
+        .collect();
+    terms.join("")
+}
+
+fn math(latex: &str) -> String {
+    katex::render(latex).unwrap_or_else(|_| escape_html(latex))
+}
+
+/// Reflected Gray code of length 2^bits: 00, 01, 11, 10
+fn gray(bits: usize) -> Vec<usize> {
+    (0..1usize << bits).map(|i| i ^ (i >> 1)).collect()
+}
+
+/// Contiguous runs of positions along a K-map edge; a group wrapping around the edge comes out as two runs
+fn runs(positions: &[usize]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &p in positions {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == p => *end = p,
+            _ => runs.push((p, p)),
+        }
+    }
+    runs
+}
+
+/// Karnaugh map with the chosen implicants circled. Five and six variables are drawn as
+/// two or four 4×4 maps side by side, one per value of the leading variables.
+fn kmap(name: &str, vars: &[String], values: &[Option<bool>], cover: &[Implicant]) -> String {
+    let n = vars.len();
+    let outer = n.saturating_sub(4);
+    let inner = n - outer;
+    let row_bits = inner / 2;
+    let col_bits = inner - row_bits;
+    let (rows, cols) = (gray(row_bits), gray(col_bits));
+    let cell = 36.0;
+    let (map_w, map_h) = (cols.len() as f64 * cell, rows.len() as f64 * cell);
+    let (pad_left, pad_top, gap) = (56.0, 44.0, 28.0);
+    let maps = 1usize << outer;
+    let width = pad_left + maps as f64 * (map_w + gap) - gap + 12.0;
+    let height = pad_top + map_h + 12.0;
+
+    let bits = |v: usize, count: usize| -> String { (0..count).rev().map(|b| if v >> b & 1 == 1 { '1' } else { '0' }).collect() };
+    let row_vars: String = vars[outer..outer + row_bits].concat();
+    let col_vars: String = vars[outer + row_bits..].concat();
+
+    let mut svg = String::new();
+    // Cell contents go on top of the group outlines
+    let mut cells = String::new();
+    for map in 0..maps {
+        let x0 = pad_left + map as f64 * (map_w + gap);
+        if outer > 0 {
+            let label = format!("{} = {}", vars[..outer].concat(), bits(map, outer));
+            let _ = write!(svg, r#"<text class="chart-label" x="{:.1}" y="12" text-anchor="middle" fill="{}">{}</text>"#, x0 + map_w / 2.0, TEXT_COLOR, escape_html(&label));
+        }
+        // Corner label "AB \ CD" and the Gray-coded headers
+        let _ = write!(svg, r#"<line class="chart-axis" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, x0 - 30.0, pad_top - 24.0, x0, pad_top, chart::AXIS_COLOR);
+        let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="end" fill="{}">{}</text>"#, x0 - 16.0, pad_top - 2.0, TEXT_COLOR, escape_html(&row_vars));
+        let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" fill="{}">{}</text>"#, x0 - 12.0, pad_top - 16.0, TEXT_COLOR, escape_html(&col_vars));
+        for (c, &code) in cols.iter().enumerate() {
+            let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="middle" fill="{}">{}</text>"#, x0 + (c as f64 + 0.5) * cell, pad_top - 6.0, TEXT_COLOR, bits(code, col_bits));
+        }
+        for (r, &code) in rows.iter().enumerate() {
+            let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="end" dominant-baseline="middle" fill="{}">{}</text>"#, x0 - 6.0, pad_top + (r as f64 + 0.5) * cell, TEXT_COLOR, bits(code, row_bits));
+        }
+
+        for (r, &row_code) in rows.iter().enumerate() {
+            for (c, &col_code) in cols.iter().enumerate() {
+                let minterm = map << inner | row_code << col_bits | col_code;
+                let (x, y) = (x0 + c as f64 * cell, pad_top + r as f64 * cell);
+                let _ = write!(svg, r#"<rect class="chart-grid" x="{:.1}" y="{:.1}" width="{}" height="{}" fill="none" stroke="{}"/>"#, x, y, cell, cell, chart::AXIS_COLOR);
+                let text = match values[minterm] {
+                    Some(true) => "1",
+                    Some(false) => "0",
+                    None => "X",
+                };
+                let _ = write!(cells, r#"<text class="kmap-value" x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="middle" font-size="14" fill="{}">{}</text>"#, x + cell / 2.0, y + cell / 2.0 + 2.0, TEXT_COLOR, text);
+                let _ = write!(cells, r#"<text class="kmap-index" x="{:.1}" y="{:.1}" text-anchor="end" font-size="8" fill="{}" fill-opacity="0.6">{}</text>"#, x + cell - 3.0, y + cell - 3.0, TEXT_COLOR, minterm);
+            }
+        }
+
+        // Groups: each implicant covers a block of rows × columns, possibly wrapping around
+        for (g, imp) in cover.iter().enumerate() {
+            let in_map = (0..outer).all(|b| {
+                let bit = inner + b;
+                imp.mask >> bit & 1 == 1 || (imp.value >> bit & 1) == (map >> b & 1)
+            });
+            if !in_map || imp.mask == (1 << n) - 1 {
+                continue;
+            }
+            let row_hits: Vec<usize> = (0..rows.len()).filter(|&r| imp.covers_bits(rows[r] << col_bits, ((1 << row_bits) - 1) << col_bits)).collect();
+            let col_hits: Vec<usize> = (0..cols.len()).filter(|&c| imp.covers_bits(cols[c], (1 << col_bits) - 1)).collect();
+            let color = SERIES_COLORS[g % SERIES_COLORS.len()];
+            let inset = 3.0 + (g % 3) as f64 * 2.0;
+            for &(r0, r1) in &runs(&row_hits) {
+                for &(c0, c1) in &runs(&col_hits) {
+                    let _ = write!(
+                        svg,
+                        r#"<rect class="chart-series-{}" x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="10" fill="{}" fill-opacity="0.12" stroke="{}" stroke-width="2"/>"#,
+                        g % SERIES_COLORS.len(),
+                        x0 + c0 as f64 * cell + inset,
+                        pad_top + r0 as f64 * cell + inset,
+                        (c1 - c0 + 1) as f64 * cell - 2.0 * inset,
+                        (r1 - r0 + 1) as f64 * cell - 2.0 * inset,
+                        color,
+                        color
+                    );
+                }
+            }
+        }
+    }
+
+    svg.push_str(&cells);
+    chart::figure("kmap", width, height, &svg, &format!("Karnaugh map of {}", name))
+}
+
+/// A ```logic block: one output per line, as a boolean expression or a minterm list.
+///
+/// ```text
+/// F = A'B + AC
+/// G(A, B, C) = m(1, 3, 5) + d(7)
+/// ```
+///
+/// Renders the truth table, a Karnaugh map per output (up to six variables) and the
+/// minimal sum-of-products and product-of-sums forms found with Quine–McCluskey.
+pub fn render(source: &str) -> Result<String, String> {
+    let mut outputs = Vec::new();
+    let mut vars: Vec<String> = Vec::new();
+
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (lhs, rhs) = line.split_once('=').ok_or_else(|| format!("expected `F = expression`, got `{}`", line))?;
+        let (name, declared) = match lhs.trim().split_once('(') {
+            Some((name, list)) => (name.trim(), Some(list.trim_end_matches(')'))),
+            None => (lhs.trim(), None),
+        };
+        if let Some(list) = declared {
+            for var in list.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                if !vars.iter().any(|v| v == var) {
+                    vars.push(var.to_string());
+                }
+            }
+        }
+        let definition = match parse_minterms(rhs)? {
+            Some(minterms) => {
+                if declared.is_none() {
+                    return Err(format!("`{}` is given as minterms, so declare its variables: `{}(A, B, C) = …`", name, name));
+                }
+                minterms
+            }
+            None => {
+                let expr = parse_bool(rhs)?;
+                if declared.is_none() {
+                    let mut found = Vec::new();
+                    expr.collect_vars(&mut found);
+                    found.sort();
+                    for var in found {
+                        if !vars.contains(&var) {
+                            vars.push(var);
+                        }
+                    }
+                }
+                Definition::Expr(expr)
+            }
+        };
+        outputs.push(Output { name: name.to_string(), definition });
+    }
+
+    if outputs.is_empty() {
+        return Err("no outputs: add a line like `F = A'B + AC`".to_string());
+    }
+    // An output is not a variable, so it cannot feed itself or another output
+    if let Some(output) = outputs.iter().find(|o| vars.contains(&o.name)) {
+        return Err(format!("`{}` is both an output and an input; give the output another name", output.name));
+    }
+    if vars.len() > MAX_VARIABLES {
+        return Err(format!("{} variables is too many; at most {} are supported", vars.len(), MAX_VARIABLES));
+    }
+    let n = vars.len();
+    let size = 1usize << n;
+
+    // Some(bit) per row, None for don't-care
+    let mut columns: Vec<Vec<Option<bool>>> = Vec::new();
+    for output in &outputs {
+        let column = match &output.definition {
+            Definition::Expr(expr) => (0..size).map(|row| Some(expr.eval(&vars, row))).collect(),
+            Definition::Minterms(ones, dont_care) => {
+                if let Some(&bad) = ones.iter().chain(dont_care).find(|&&m| m >= size) {
+                    return Err(format!("minterm {} is out of range for {} variables", bad, n));
+                }
+                (0..size)
+                    .map(|row| if dont_care.contains(&row) { None } else { Some(ones.contains(&row)) })
+                    .collect()
+            }
+        };
+        columns.push(column);
+    }
+
+    let mut html = String::from("<div class=\"logic\">\n");
+
+    // Truth table, folded away when it gets long
+    let mut table = String::from("<table class=\"logic-table\">\n<thead><tr>");
+    for var in &vars {
+        let _ = write!(table, "<th>{}</th>", escape_html(var));
+    }
+    for output in &outputs {
+        let _ = write!(table, "<th class=\"logic-output\">{}</th>", escape_html(&output.name));
+    }
+    table.push_str("</tr></thead>\n<tbody>\n");
+    for row in 0..size {
+        table.push_str("<tr>");
+        for i in 0..n {
+            let _ = write!(table, "<td>{}</td>", row >> (n - 1 - i) & 1);
+        }
+        for column in &columns {
+            let cell = match column[row] {
+                Some(true) => "1",
+                Some(false) => "0",
+                None => "X",
+            };
+            let _ = write!(table, "<td class=\"logic-output\">{}</td>", cell);
+        }
+        table.push_str("</tr>\n");
+    }
+    table.push_str("</tbody>\n</table>\n");
+    if size > 16 {
+        let _ = write!(html, "<details class=\"logic-truth-table\">\n<summary>Truth table ({} rows)</summary>\n{}</details>\n", size, table);
+    } else {
+        html.push_str(&table);
+    }
+
+    for (output, column) in outputs.iter().zip(&columns) {
+        let ones: Vec<usize> = (0..size).filter(|&r| column[r] == Some(true)).collect();
+        let zeros: Vec<usize> = (0..size).filter(|&r| column[r] == Some(false)).collect();
+        let dont_care: Vec<usize> = (0..size).filter(|&r| column[r].is_none()).collect();
+        let sop = minimize(&ones, &dont_care, n);
+        let pos = minimize(&zeros, &dont_care, n);
+
+        if (2..=MAX_KMAP_VARIABLES).contains(&n) {
+            html.push_str(&kmap(&output.name, &vars, column, &sop));
+            html.push('\n');
+        }
+
+        let name = latex_var(&output.name);
+        let list = |items: &[usize]| items.iter().map(usize::to_string).collect::<Vec<_>>().join(", ");
+        let canonical = if dont_care.is_empty() {
+            format!("{} = \\textstyle\\sum m({})", name, list(&ones))
+        } else {
+            format!("{} = \\textstyle\\sum m({}) + d({})", name, list(&ones), list(&dont_care))
+        };
+        let _ = write!(
+            html,
+            "<dl class=\"logic-forms\">\n<dt>Minterms</dt><dd>{}</dd>\n<dt>Minimal SOP</dt><dd>{}</dd>\n<dt>Minimal POS</dt><dd>{}</dd>\n</dl>\n",
+            math(&canonical),
+            math(&format!("{} = {}", name, sop_latex(&sop, &vars))),
+            math(&format!("{} = {}", name, pos_latex(&pos, &vars)))
+        );
+    }
+
+    html.push_str("</div>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_covers(cover: &[Implicant], ones: &[usize], zeros: &[usize]) {
+        for &m in ones {
+            assert!(cover.iter().any(|i| i.covers(m)), "minterm {} is not covered", m);
+        }
+        for &m in zeros {
+            assert!(!cover.iter().any(|i| i.covers(m)), "zero {} is covered", m);
+        }
+    }
+
+    #[test]
+    fn prime_implicants_of_cyclic_function() {
+        // F(A, B, C) = m(0, 1, 2, 5, 6, 7): six primes, none essential
+        let mut primes = prime_implicants(&[0, 1, 2, 5, 6, 7], &[]);
+        primes.sort_by_key(|i| (i.value, i.mask));
+        let expected = [(0, 1), (0, 2), (1, 4), (2, 4), (5, 2), (6, 1)];
+        assert_eq!(primes, expected.map(|(value, mask)| Implicant { value, mask }));
+    }
+
+    #[test]
+    fn minimal_cover_of_cyclic_function() {
+        let ones = [0, 1, 2, 5, 6, 7];
+        let cover = minimize(&ones, &[], 3);
+        assert_eq!(cover.len(), 3);
+        assert_eq!(cover.iter().map(|i| i.literals(3)).sum::<usize>(), 6);
+        assert_covers(&cover, &ones, &[3, 4]);
+    }
+
+    #[test]
+    fn minimal_cover_uses_dont_cares() {
+        // F(A, B, C, D) = m(1, 3, 7, 11, 15) + d(0, 2, 5) = CD + A'B', or CD + A'D
+        let ones = [1, 3, 7, 11, 15];
+        let cover = minimize(&ones, &[0, 2, 5], 4);
+        assert_eq!(cover.len(), 2);
+        assert_eq!(cover.iter().map(|i| i.literals(4)).sum::<usize>(), 4);
+        assert_covers(&cover, &ones, &[4, 6, 8, 9, 10, 12, 13, 14]);
+    }
+
+    #[test]
+    fn minimal_cover_of_six_variable_worst_case_is_bounded() {
+        // The zeros of ABCDEF + A'B'C'D'E'F': thirty primes, none essential
+        let ones: Vec<usize> = (1..63).collect();
+        let start = std::time::Instant::now();
+        let cover = minimize(&ones, &[], 6);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5), "took {:?}", start.elapsed());
+        assert_eq!(cover.len(), 6);
+        assert_covers(&cover, &ones, &[0, 63]);
+    }
+
+    #[test]
+    fn output_named_like_an_input_is_an_error() {
+        assert!(render("F = A B C D E F G").unwrap_err().contains("`F` is both an output and an input"));
+        assert!(render("F = A'B\nG = F + C").is_err());
+        assert!(render("F(A, B, F) = m(1)").is_err());
+        assert!(render("F = A'B\nG = A + C").is_ok());
+    }
+}
@@ -1,29 +1,59 @@
 use axum::{
-    extract::Path,
-    http::HeaderMap,
-    response::{Html, IntoResponse, Response},
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
-use std::net::SocketAddr;
+use std::collections::HashMap;
 use tower_http::services::ServeDir;
 use askama::Template;
-use pulldown_cmark::{Parser, Options, html};
-use tokio::fs;
-use chrono::{DateTime, Local};
-use regex::Regex;
-use walkdir::WalkDir;
-use std::sync::LazyLock;
-
-// Compiled regexes for image tag processing
-static IMG_TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<img\s+([^>]+)/?>"#).unwrap());
-static SRC_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"src="([^"]+)""#).unwrap());
-static ALT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"alt="([^"]*)""#).unwrap());
-static TITLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"title="([^"]*)""#).unwrap());
-
-/// Get the SITE_URL environment variable or empty string
+use chrono::Local;
+
+mod blocks;
+mod bode;
+mod book;
+mod callouts;
+mod cards;
+mod chart;
+mod check;
+mod config;
+mod environments;
+mod epub;
+mod expr;
+mod feed;
+mod frontmatter;
+mod gmid;
+mod graph;
+mod html;
+mod latex;
+mod logic;
+mod mathml;
+mod noise;
+mod og;
+mod pdf;
+mod plot;
+mod print;
+mod quiz;
+mod redirects;
+mod render;
+mod renumber;
+mod scaffold;
+mod seo;
+mod site;
+mod spice;
+mod static_site;
+mod stats;
+mod units;
+mod watch;
+mod wavedrom;
+mod wikilinks;
+
+use site::SiteIndex;
+
+/// The path prefix from `site.base_url` (or SITE_URL), empty when the site is served at the root
 fn get_site_url() -> String {
-    std::env::var("SITE_URL").unwrap_or_default()
+    config::get().base_path().to_string()
 }
 
 /// Parse a numbered name like "1) MosFETs" into (sort_key, clean_name)
@@ -41,37 +71,354 @@ fn build_link(path: &str) -> String {
     format!("{}{}", get_site_url(), path)
 }
 
+/// Subcommands, then the flags that override notes.toml
+fn usage() -> String {
+    let mut usage = String::from(
+        "usage: documentation [options] [command]
+
+commands:
+  serve                        run the development server (the default)
+  build [dir]                  write the static site, to _site by default
+  check                        report broken links, math errors and bad frontmatter
+  new <path>                   create a page numbered after its last sibling
+  stats                        words, pages, equations and figures per category
+  renumber <dir> [move <name> <position> | insert <position>]
+                               renumber a directory's pages and chapters, fixing links
+  export-cards [out.tsv]       flashcards for Anki
+  pdf <chapter or category> [out.pdf]
+  latex <path> [out.tex]
+  epub <category> [out.epub]
+  og-images [dir]              link preview images, to _site/og by default
+
+options:
+  --config <file>              settings file, notes.toml by default",
+    );
+    for (flag, setting) in config::FLAGS {
+        usage.push_str(&format!("\n  {:<28} overrides {}", format!("{} <value>", flag), setting));
+    }
+    for (switch, setting) in config::SWITCHES {
+        usage.push_str(&format!("\n  {:<28} turns on {}", switch, setting));
+    }
+    usage
+}
+
 #[tokio::main]
 async fn main() {
+    let (flags, args) = match config::split_args(std::env::args().skip(1).collect()) {
+        Ok(split) => split,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", usage());
+            std::process::exit(2);
+        }
+    };
+    let mut config = match config::from_flags(&flags) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    config.serving = args.first().is_none_or(|command| command == "serve");
+    let problems = config.validate();
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    if !problems.is_empty() {
+        std::process::exit(1);
+    }
+    config::init(config);
+
+    let Some(command) = args.first() else {
+        return serve().await;
+    };
+    match command.as_str() {
+        "serve" => serve().await,
+        "build" => {
+            let dir = args.get(1).map(String::as_str).unwrap_or("_site");
+            match static_site::build(std::path::Path::new(dir)) {
+                Ok(count) => println!("Wrote {} pages to {}", count, dir),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "check" => {
+            let index = SiteIndex::build();
+            let report = check::run(&index, &generate_sidebar());
+            for warning in &report.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            for error in &report.errors {
+                eprintln!("error: {}", error);
+            }
+            println!("Checked {} pages: {} errors, {} warnings", index.pages.len(), report.errors.len(), report.warnings.len());
+            if !report.errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        "new" => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: documentation new <category/chapter/Page Title>");
+                std::process::exit(2);
+            };
+            match scaffold::new_page(path) {
+                Ok(file) => println!("Created {}", file.display()),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "stats" => print!("{}", stats::table(&stats::collect(&SiteIndex::build()))),
+        "renumber" => {
+            let operation = match (args.get(2).map(String::as_str), args.get(3), args.get(4)) {
+                (None, _, _) => Some(renumber::Operation::Renumber),
+                (Some("move"), Some(name), Some(position)) => position.parse().ok().map(|p| renumber::Operation::Move(name.clone(), p)),
+                (Some("insert"), Some(position), None) => position.parse().ok().map(renumber::Operation::Insert),
+                _ => None,
+            };
+            let (Some(dir), Some(operation)) = (args.get(1), operation) else {
+                eprintln!("usage: documentation renumber <dir> [move <name> <position> | insert <position>]");
+                std::process::exit(2);
+            };
+            match renumber::run(dir, &operation) {
+                Ok(summary) => {
+                    for warning in &summary.warnings {
+                        eprintln!("warning: {}", warning);
+                    }
+                    for (old, new) in &summary.renamed {
+                        println!("{} -> {}", old, new);
+                    }
+                    if summary.renamed.is_empty() {
+                        println!("Already numbered that way, nothing to do");
+                    } else {
+                        println!(
+                            "Rewrote {} links in {} files, recorded {} redirects in {}",
+                            summary.links,
+                            summary.files,
+                            summary.redirects,
+                            redirects::file().display()
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "export-cards" => {
+            let out = args.get(1).map(String::as_str).unwrap_or("cards.tsv");
+            let tsv = cards::export_tsv(&SiteIndex::build());
+            if let Err(e) = std::fs::write(out, tsv) {
+                eprintln!("error: could not write {}: {}", out, e);
+                std::process::exit(1);
+            }
+            println!("Wrote {}", out);
+        }
+        "og-images" if !config::get().features.og_images => {
+            println!("Preview images are turned off in {}", config::DEFAULT_FILE);
+        }
+        "og-images" => {
+            let dir = args.get(1).map(String::as_str).unwrap_or("_site");
+            match og::export(&SiteIndex::build(), std::path::Path::new(dir)) {
+                Ok(count) => println!("Wrote {} preview images to {}/og", count, dir),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "pdf" => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: documentation pdf <chapter or category> [out.pdf|out.html]");
+                std::process::exit(2);
+            };
+            let book = match book::Book::collect(&generate_sidebar(), path) {
+                Ok(book) => book,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let out = args.get(2).cloned().unwrap_or_else(|| format!("{}.pdf", render::slugify(&book.title)));
+            let html = book.render_html(&SiteIndex::build());
+            // An .html target keeps the print-styled document for a browser's print dialog
+            let bytes = if out.ends_with(".html") {
+                html.into_bytes()
+            } else {
+                match pdf::render(&html) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            };
+            if let Err(e) = std::fs::write(&out, bytes) {
+                eprintln!("error: could not write {}: {}", out, e);
+                std::process::exit(1);
+            }
+            println!("Wrote {} ({} sections)", out, book.sections.len());
+            if book.skipped > 0 {
+                println!("Skipped {} empty page{}", book.skipped, if book.skipped == 1 { "" } else { "s" });
+            }
+        }
+        "latex" => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: documentation latex <page, chapter or category> [out.tex]");
+                std::process::exit(2);
+            };
+            let book = match book::Book::collect(&generate_sidebar(), path) {
+                Ok(book) => book,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let out = args.get(2).cloned().unwrap_or_else(|| format!("{}.tex", render::slugify(&book.title)));
+            match latex::export(&book, &SiteIndex::build(), std::path::Path::new(&out)) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        eprintln!("warning: {}", warning);
+                    }
+                    println!("Wrote {} ({} sections)", out, book.sections.len());
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "epub" => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: documentation epub <category or chapter> [out.epub]");
+                std::process::exit(2);
+            };
+            let book = match book::Book::collect(&generate_sidebar(), path) {
+                Ok(book) => book,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let out = args.get(2).cloned().unwrap_or_else(|| format!("{}.epub", render::slugify(&book.title)));
+            let bytes = match epub::export(&book, &SiteIndex::build()) {
+                Ok((bytes, warnings)) => {
+                    for warning in warnings {
+                        eprintln!("warning: {}", warning);
+                    }
+                    bytes
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let problems = epub::check(&bytes);
+            for problem in &problems {
+                eprintln!("error: {}", problem);
+            }
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+            if let Err(e) = std::fs::write(&out, bytes) {
+                eprintln!("error: could not write {}: {}", out, e);
+                std::process::exit(1);
+            }
+            println!("Wrote {} ({} sections)", out, book.sections.len());
+        }
+        _ => {
+            eprintln!("{}", usage());
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Serve the site until stopped. Pages are rendered per request from an index that the watcher
+/// rebuilds whenever the content changes.
+async fn serve() {
+    report_problems(&SiteIndex::refresh());
+    watch::spawn();
+
+    let config = config::get();
     let mut app = Router::new()
         .route("/", get(index_handler))
+        .route("/sitemap.xml", get(sitemap_handler))
+        .route("/robots.txt", get(robots_handler));
+    if config.features.graph {
+        app = app.route("/graph", get(graph_handler)).route("/graph.json", get(graph_json_handler));
+    }
+    if config.features.review {
+        app = app.route("/review", get(review_handler));
+    }
+    if config.features.og_images {
+        app = app.route("/og/:file", get(og_image_handler));
+    }
+    if config.features.feeds {
+        app = app
+            .route("/feed.xml", get(atom_handler))
+            .route("/feed.json", get(json_feed_handler))
+            .route("/feeds/:file", get(category_feed_handler));
+    }
+    if config.server.watch {
+        app = app.route(watch::ROUTE, get(|| async { watch::version().to_string() }));
+    }
+    app = app
         .route("/*path", get(dynamic_handler))
         .nest_service("/assets", ServeDir::new("assets"))
-        .nest_service("/content", ServeDir::new("assets/content"))
+        .nest_service("/content", ServeDir::new(site::content_dir()))
         .nest_service("/dist", ServeDir::new("dist"))
         .nest_service("/style", ServeDir::new("style"));
 
-    if let Ok(site_url) = std::env::var("SITE_URL") {
-        if !site_url.is_empty() {
-            app = Router::new().nest(&site_url, app);
-        }
+    let site_url = get_site_url();
+    if !site_url.is_empty() {
+        app = Router::new().nest(&site_url, app);
     }
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("Listening on http://{}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let addr = config.bind_address();
+    println!("Listening on http://{}{}", addr, site_url);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("error: could not listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Warn about unresolved wiki links and pages nothing leads to
+fn report_problems(index: &SiteIndex) {
+    for problem in &index.problems {
+        eprintln!("warning: {}", problem);
+    }
+    for orphan in index.orphans(&generate_sidebar()) {
+        eprintln!("warning: orphan page {} ({}) has no inbound links and is not in the sidebar", orphan.path, orphan.file.display());
+    }
+}
+
 #[derive(Template)]
 #[template(path = "layout.html")]
 struct LayoutTemplate<'a> {
     title: &'a str,
     page_title: String,
+    meta: &'a seo::PageMeta,
     sidebar: &'a str,
     content: &'a str,
     theme: &'a str,
     site_url: &'a str,
+    features: &'a config::Features,
+    graph_url: String,
+    /// Feed path of the page's category without the extension, empty outside a category
+    category_feed: String,
+    category_title: String,
+    /// Paper view: no topbar, sidebar or scripts, and always the light theme
+    print: bool,
+    /// Where to poll for changes when serving with `--watch`, empty otherwise
+    watch: String,
 }
 
 #[derive(Clone, Debug)]
@@ -98,45 +445,16 @@ struct RecentlyAddedItem {
 }
 
 fn generate_recently_added() -> Vec<RecentlyAddedItem> {
-    let content_dir = "assets/content";
-    let mut items: Vec<(std::time::SystemTime, RecentlyAddedItem)> = Vec::new();
-
-    for entry in WalkDir::new(content_dir).min_depth(1).sort_by_file_name() {
-        let Ok(entry) = entry else { continue };
-        let path = entry.path();
-
-        if path.extension().and_then(|e| e.to_str()) != Some("md") {
-            continue;
-        }
-
-        let Ok(relative_path) = path.strip_prefix(content_dir) else { continue };
-        let components: Vec<_> = relative_path.components()
-            .map(|c| c.as_os_str().to_string_lossy().to_string())
-            .collect();
-
-        if components.iter().any(|c| c == "images") {
-            continue;
-        }
-
-        let Ok(metadata) = std::fs::metadata(path) else { continue };
-        let Ok(modified) = metadata.modified() else { continue };
-
-        let file_stem = path.file_stem().unwrap().to_string_lossy().to_string();
-        let title = format_title(&file_stem);
-        let link = build_link(&format!("/{}", relative_path.with_extension("").to_string_lossy()));
-        let category = format_title(&components[0]);
-        let datetime: DateTime<Local> = modified.into();
-
-        items.push((modified, RecentlyAddedItem {
-            title,
-            path: link,
-            category,
-            date: datetime.format("%b %d, %Y %H:%M").to_string(),
-        }));
-    }
-
-    items.sort_by(|a, b| b.0.cmp(&a.0));
-    items.into_iter().take(10).map(|(_, item)| item).collect()
+    feed::entries()
+        .into_iter()
+        .take(10)
+        .map(|entry| RecentlyAddedItem {
+            title: entry.title,
+            path: build_link(&entry.path),
+            category: entry.category,
+            date: entry.updated.with_timezone(&Local).format("%b %d, %Y %H:%M").to_string(),
+        })
+        .collect()
 }
 
 fn generate_sidebar() -> Vec<SidebarItem> {
@@ -182,7 +500,7 @@ fn generate_sidebar() -> Vec<SidebarItem> {
         items
     }
 
-    let content_dir = std::path::Path::new("assets/content");
+    let content_dir = std::path::Path::new(site::content_dir());
     let mut categories = Vec::new();
 
     if let Ok(entries) = std::fs::read_dir(content_dir) {
@@ -214,13 +532,13 @@ fn generate_sidebar() -> Vec<SidebarItem> {
     categories
 }
 
-async fn index_handler(headers: HeaderMap) -> impl IntoResponse {
-    render_page(&["index"], headers).await
+async fn index_handler(Query(params): Query<HashMap<String, String>>, headers: HeaderMap) -> impl IntoResponse {
+    render_page(&["index"], headers, params.get("print").map(String::as_str).filter(|p| *p != "0")).await
 }
 
-async fn dynamic_handler(Path(path): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+async fn dynamic_handler(Path(path): Path<String>, Query(params): Query<HashMap<String, String>>, headers: HeaderMap) -> impl IntoResponse {
     let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-    render_page(&segments, headers).await
+    render_page(&segments, headers, params.get("print").map(String::as_str).filter(|p| *p != "0")).await
 }
 
 fn format_title(s: &str) -> String {
@@ -240,100 +558,53 @@ fn format_title(s: &str) -> String {
         .join(" ")
 }
 
-async fn render_page(segments: &[&str], _headers: HeaderMap) -> Response {
-    // Build file path from segments
-    let file_path = if segments.len() == 1 && segments[0] == "index" {
-        "assets/content/index.md".to_string()
-    } else {
-        format!("assets/content/{}.md", segments.join("/"))
-    };
+/// `print` is the `?print=` query: "section" prints a sidebar node with all its children as
+/// one document, any other value prints the page alone
+async fn render_page(segments: &[&str], headers: HeaderMap, print: Option<&str>) -> Response {
+    match page_html(segments, &feed::origin(&headers), print) {
+        Some(html) => Html(html).into_response(),
+        None => match redirects::lookup(&format!("/{}", segments.join("/"))) {
+            Some(path) => Redirect::permanent(&graph::encode_query(&build_link(&path))).into_response(),
+            None => Html("<h1>404 Not Found</h1>".to_string()).into_response(),
+        },
+    }
+}
 
-    let markdown_input = match fs::read_to_string(&file_path).await {
-        Ok(content) => content,
-        Err(_) => {
-            return Html("<h1>404 Not Found</h1>".to_string()).into_response();
-        }
+/// The page at `segments` in the site layout, None when there is no such page
+fn page_html(segments: &[&str], origin: &str, print: Option<&str>) -> Option<String> {
+    let active_path = format!("/{}", segments.join("/"));
+    let markdown_input = match site::read_page(&active_path) {
+        Some(_) if print == Some("section") => return print_section_html(segments, origin),
+        Some(content) => content,
+        // A chapter directory has no page of its own, but it can still be printed whole
+        None if print.is_some() => return print_section_html(segments, origin),
+        None => return None,
     };
 
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    options.insert(Options::ENABLE_MATH);
-    
-    let parser = Parser::new_ext(&markdown_input, options);
-    
-    // Transform events to handle math
-    let parser = parser.map(|event| {
-        match event {
-            pulldown_cmark::Event::InlineMath(cow) => {
-                // Try render with defaults, which is displayMode: false usually? 
-                // Actually katex-rs 'render' might be display mode. 
-                // Let's assume 'render' works and returns HTML.
-                // We'll trust the error message about 'render_inline' and just use 'render' for now.
-                let html = katex::render(&cow).unwrap_or_else(|_| cow.to_string());
-                pulldown_cmark::Event::Html(html.into())
-            }
-            pulldown_cmark::Event::DisplayMath(cow) => {
-                let html = katex::render(&cow).unwrap_or_else(|_| cow.to_string());
-                pulldown_cmark::Event::Html(html.into())
-            }
-            _ => event,
-        }
-    });
-
-    let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
-
-    let mut current_fig = 1;
-    let html_string = html_output;
-    let html_output = IMG_TAG_RE.replace_all(&html_string, |caps: &regex::Captures| {
-        let attrs = &caps[1];
-        let src = SRC_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
-        let alt = ALT_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
-        let title = TITLE_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
-        let style = if !title.is_empty() { format!("width: {};", title) } else { String::new() };
-        let img_html = format!(r#"<img src="{}" alt="{}" style="{}">"#, src, alt, style);
-
-        if !alt.is_empty() {
-            let num = current_fig;
-            current_fig += 1;
-            format!(
-                r#"<figure class="image-container">\n{}\n<figcaption><strong>Fig. {}:</strong> {}</figcaption>\n</figure>"#,
-                img_html, num, alt
-            )
-        } else {
-            img_html
-        }
-    }).to_string();
+    let index = SiteIndex::current();
+    let html_output = render::render_markdown(&markdown_input, &index, &active_path);
 
-    let active_path = format!("/{}", segments.join("/"));
     let category = segments.first().copied().unwrap_or("index");
 
-    let current_category = if category == "index" {
-        String::new()
-    } else {
-        category.to_string()
-    };
-
-    let sidebar = SidebarTemplate {
-        active_path,
-        current_category,
-        items: generate_sidebar(),
-    };
-    
     let page_title = if let Some(last) = segments.last() {
         if *last == "index" {
-            "Sawy's Notes".to_string()
+            config::get().site.title.clone()
         } else {
             format_title(last)
         }
     } else {
-        "Sawy's Notes".to_string()
+        config::get().site.title.clone()
     };
 
+    let description = index.pages.iter().find(|p| p.path == active_path).map(|p| p.description.as_str()).unwrap_or_default();
+    let mut meta = seo::PageMeta::new(segments, &page_title, description, origin);
+    if config::get().features.og_images {
+        meta.image = Some(format!("{}{}", origin, build_link(&og::image_path(&active_path))));
+    }
+    if print.is_some() {
+        return Some(layout_html(segments, &meta, &print::prepare(&html_output, ""), true));
+    }
+
     // Inject recently added section for index page
     let final_content = if category == "index" {
         let recent_items = generate_recently_added();
@@ -356,18 +627,147 @@ async fn render_page(segments: &[&str], _headers: HeaderMap) -> Response {
         
         format!("{}\n{}", html_output, recently_added_html)
     } else {
-        html_output
+        let backlinks = site::render_backlinks(index.backlinks(&active_path));
+        format!("{}\n{}", html_output, backlinks)
+    };
+
+    Some(layout_html(segments, &meta, &final_content, false))
+}
+
+/// Every page under the sidebar node at `segments` in one print view, one section per page
+fn print_section_html(segments: &[&str], origin: &str) -> Option<String> {
+    let book = book::Book::collect(&generate_sidebar(), &segments.join("/")).ok()?;
+    let index = SiteIndex::current();
+    let mut content = String::new();
+    for (section, html) in book.sections.iter().zip(book.render_sections(&index)) {
+        let tag = if section.depth == 0 { "h1" } else { "h2" };
+        content.push_str(&format!(
+            "<section class=\"print-section\" id=\"{id}\">\n<{tag} class=\"print-section-title\">{}</{tag}>\n{}\n</section>\n",
+            site::escape_html(&section.heading()),
+            print::prepare(&html, &format!("{}-", section.id())),
+            id = section.id(),
+            tag = tag,
+        ));
+    }
+    let description = index.pages.iter().find(|p| p.path == book.sections[0].path).map(|p| p.description.as_str()).unwrap_or_default();
+    let meta = seo::PageMeta::new(segments, &book.title, description, origin);
+    Some(layout_html(segments, &meta, &book.link_sections(&content), true))
+}
+
+/// Wrap rendered page content in the site layout with the sidebar for `segments`, or in the
+/// bare print layout when `print` is set
+fn layout_html(segments: &[&str], meta: &seo::PageMeta, content: &str, print: bool) -> String {
+    let active_path = format!("/{}", segments.join("/"));
+    let category = segments.first().copied().unwrap_or("index");
+
+    let current_category = if category == "index" {
+        String::new()
+    } else {
+        category.to_string()
     };
 
+    let sidebar = SidebarTemplate {
+        active_path: active_path.clone(),
+        current_category,
+        items: generate_sidebar(),
+    };
+
+    let config = config::get();
     let site_url = get_site_url();
+    let category_feed = if config.features.feeds && std::path::Path::new(site::content_dir()).join(category).is_dir() {
+        format!("{}/feeds/{}", site_url, render::slugify(category))
+    } else {
+        String::new()
+    };
     let layout = LayoutTemplate {
-        title: "Sawy's Notes",
-        page_title,
+        title: &config.site.title,
+        page_title: meta.name.clone(),
+        meta,
         sidebar: &sidebar.render().unwrap(),
-        content: &final_content,
-        theme: "light",
+        content,
+        theme: &config.theme.default,
         site_url: &site_url,
+        features: &config.features,
+        watch: if config.server.watch { format!("{}{}", site_url, watch::ROUTE) } else { String::new() },
+        graph_url: format!("{}/graph?focus={}", site_url, graph::encode_query(&active_path)),
+        category_feed,
+        category_title: format_title(category),
+        print,
     };
 
-    Html(layout.render().unwrap()).into_response()
+    layout.render().unwrap()
+}
+
+async fn graph_handler(Query(params): Query<HashMap<String, String>>, headers: HeaderMap) -> impl IntoResponse {
+    let focus = params.get("focus").map(String::as_str).unwrap_or_default();
+    Html(graph_html(&feed::origin(&headers), focus))
+}
+
+fn graph_html(origin: &str, focus: &str) -> String {
+    let meta = seo::PageMeta::new(&["graph"], "Knowledge Graph", "How the notes link to each other.", origin);
+    layout_html(&["graph"], &meta, &graph::render_graph_page(&SiteIndex::current(), focus), false)
+}
+
+async fn graph_json_handler() -> impl IntoResponse {
+    Json(graph::graph_json(&SiteIndex::current()))
+}
+
+async fn review_handler(headers: HeaderMap) -> impl IntoResponse {
+    Html(review_html(&feed::origin(&headers)))
+}
+
+fn review_html(origin: &str) -> String {
+    let meta = seo::PageMeta::new(&["review"], "Review", "Spaced-repetition review of the flashcards in the notes.", origin);
+    layout_html(&["review"], &meta, &cards::render_review_page(&SiteIndex::current()), false)
+}
+
+async fn atom_handler(headers: HeaderMap) -> Response {
+    feed_response(None, "xml", &headers)
+}
+
+async fn json_feed_handler(headers: HeaderMap) -> Response {
+    feed_response(None, "json", &headers)
+}
+
+/// `/feeds/analog.xml` or `/feeds/analog.json`
+async fn category_feed_handler(Path(file): Path<String>, headers: HeaderMap) -> Response {
+    match file.rsplit_once('.') {
+        Some((category, format @ ("xml" | "json"))) => feed_response(Some(category), format, &headers),
+        _ => (StatusCode::NOT_FOUND, "no such feed").into_response(),
+    }
+}
+
+fn feed_response(category: Option<&str>, format: &str, headers: &HeaderMap) -> Response {
+    let Some(feed) = feed::Feed::new(category) else {
+        return (StatusCode::NOT_FOUND, "no such feed").into_response();
+    };
+    let index = SiteIndex::current();
+    let origin = feed::origin(headers);
+    if format == "json" {
+        ([(header::CONTENT_TYPE, "application/feed+json")], feed.json(&index, &origin).to_string()).into_response()
+    } else {
+        ([(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")], feed.atom(&index, &origin)).into_response()
+    }
+}
+
+async fn sitemap_handler(headers: HeaderMap) -> Response {
+    let sitemap = seo::sitemap(&SiteIndex::current(), &feed::origin(&headers));
+    ([(header::CONTENT_TYPE, "application/xml; charset=utf-8")], sitemap).into_response()
+}
+
+async fn robots_handler(headers: HeaderMap) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], seo::robots(&feed::origin(&headers))).into_response()
+}
+
+/// `/og/<page slug>.png`, the page's social preview
+async fn og_image_handler(Path(file): Path<String>) -> Response {
+    let index = SiteIndex::current();
+    let Some(page) = index.pages.iter().find(|p| og::image_path(&p.path) == format!("/og/{}", file)) else {
+        return (StatusCode::NOT_FOUND, "no such page").into_response();
+    };
+    let png = pdf::Fonts::new().and_then(|mut fonts| og::render(page, &mut fonts));
+    match png {
+        Ok(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
 }
@@ -0,0 +1,509 @@
+use crate::html::{Element, Node};
+use crate::pdf::{Family, FontKey, Fonts, Item, Rgb};
+
+/// Laid-out formula; items are placed with the baseline at y = 0 and y growing downwards
+#[derive(Default)]
+pub struct MathBox {
+    pub width: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub items: Vec<Item>,
+}
+
+impl MathBox {
+    fn empty(size: f32) -> MathBox {
+        MathBox { ascent: size * 0.7, descent: size * 0.2, ..MathBox::default() }
+    }
+
+    /// Append `other` at the current width, its baseline `rise` above this one's
+    fn append(&mut self, other: MathBox, rise: f32) {
+        let x = self.width;
+        self.items.extend(other.items.into_iter().map(|i| i.shifted(x, -rise)));
+        self.width += other.width;
+        self.ascent = self.ascent.max(other.ascent + rise);
+        self.descent = self.descent.max(other.descent - rise);
+    }
+
+    /// Place `other` at (`x`, `rise`) without advancing
+    fn overlay(&mut self, other: MathBox, x: f32, rise: f32) {
+        self.items.extend(other.items.into_iter().map(|i| i.shifted(x, -rise)));
+        self.width = self.width.max(x + other.width);
+        self.ascent = self.ascent.max(other.ascent + rise);
+        self.descent = self.descent.max(other.descent - rise);
+    }
+
+    fn space(&mut self, width: f32) {
+        self.width += width;
+    }
+}
+
+const RELATIONS: &str = "=<>≤≥≈≠≡∼≃≅∝→←↔⇒⇐⇔↦∈∉⊂⊃⊆⊇≪≫:≜≐";
+const BINARIES: &str = "+−×·±∓∗÷∘⊕⊗∧∨∩∪";
+const PUNCTUATION: &str = ",;";
+const LARGE: &str = "∑∏∫∬∭∮⋃⋂∐";
+const FENCES: &str = "()[]{}|‖⟨⟩⌊⌋⌈⌉";
+
+/// Italic by default: single Latin letters and lowercase Greek, as TeX sets them
+fn italic_by_default(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_alphabetic() || ('α'..='ω').contains(&c) || "ϕϵϑϱϖ".contains(c))
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        'A'..='Z' => char::from_u32(0x1D538 + (c as u32 - 'A' as u32)).unwrap_or(c),
+        _ => c,
+    }
+}
+
+struct Layout<'a> {
+    fonts: &'a mut Fonts,
+    color: Rgb,
+}
+
+/// Lay out a KaTeX `<math>` element at `size` points. `display` sets limits above and below
+/// large operators and full-size fractions.
+pub fn layout(math: &Element, fonts: &mut Fonts, size: f32, color: Rgb, display: bool) -> MathBox {
+    Layout { fonts, color }.row(&math.children, size, display)
+}
+
+impl Layout<'_> {
+    fn text(&mut self, text: &str, font: FontKey, size: f32) -> MathBox {
+        let (items, width) = self.fonts.line(font, text, size, self.color);
+        MathBox { width, ascent: size * 0.72, descent: size * 0.22, items }
+    }
+
+    fn rule(&self, x1: f32, y1: f32, x2: f32, y2: f32, size: f32) -> Item {
+        Item::Line { x1, y1, x2, y2, width: (size * 0.05).max(0.4), color: self.color }
+    }
+
+    fn row(&mut self, nodes: &[Node], size: f32, display: bool) -> MathBox {
+        let elements: Vec<&Element> = nodes.iter().filter_map(|n| if let Node::Element(e) = n { Some(e) } else { None }).collect();
+        // Fences stretch to what they enclose, so lay out everything else first
+        let mut boxes: Vec<Option<MathBox>> = elements
+            .iter()
+            .map(|e| if is_fence(e) { None } else { Some(self.node(e, size, display)) })
+            .collect();
+        let inner_ascent = boxes.iter().flatten().map(|b| b.ascent).fold(size * 0.72, f32::max);
+        let inner_descent = boxes.iter().flatten().map(|b| b.descent).fold(size * 0.22, f32::max);
+
+        let mut out = MathBox::empty(size);
+        let mut previous: Option<&str> = None;
+        for (i, e) in elements.iter().enumerate() {
+            let b = boxes[i].take().unwrap_or_else(|| self.fence(e, size, inner_ascent, inner_descent));
+            let op = (e.tag == "mo").then(|| e.text());
+            let class = op.as_deref().map(|o| operator_class(o, e));
+            let unary = previous.is_none() || previous == Some("mo");
+            // Function names such as sin and ln are set apart like operators
+            let function = e.tag == "mi" && e.text().chars().count() > 1;
+            let pad = match class {
+                _ if function => size * 0.1667,
+                Some(Class::Relation) => size * 0.2778,
+                Some(Class::Binary) if !unary && i + 1 < elements.len() => size * 0.2222,
+                Some(Class::Large) => size * 0.1667,
+                _ => 0.0,
+            };
+            if elements.len() > 1 {
+                out.space(pad);
+            }
+            out.append(b, 0.0);
+            if elements.len() > 1 {
+                out.space(pad);
+            }
+            if class == Some(Class::Punctuation) {
+                out.space(size * 0.1667);
+            }
+            previous = Some(if class.is_some_and(|c| c != Class::Fence) { "mo" } else { "other" });
+        }
+        out
+    }
+
+    /// A fence drawn large enough to span `ascent` and `descent`, centred on the math axis
+    fn fence(&mut self, e: &Element, size: f32, ascent: f32, descent: f32) -> MathBox {
+        let axis = size * 0.25;
+        let half = (ascent - axis).max(descent + axis);
+        let scaled = (size * half / (size * 0.5)).max(size).min(size * 4.0);
+        let mut b = self.text(&e.text(), FontKey::new(Family::Serif), scaled);
+        // Centre the glyph (nominally 0.75 above and 0.25 below its baseline) on the axis
+        let drop = scaled * 0.25 - axis;
+        b.items = b.items.into_iter().map(|i| i.shifted(0.0, drop)).collect();
+        b.ascent = scaled * 0.75 - drop;
+        b.descent = scaled * 0.25 + drop;
+        b
+    }
+
+    fn node(&mut self, e: &Element, size: f32, display: bool) -> MathBox {
+        let serif = FontKey::new(Family::Serif);
+        let children: Vec<&Element> = e.elements().collect();
+        let child = |i: usize| children.get(i).copied();
+        match e.tag.as_str() {
+            "mi" => {
+                let text = e.text();
+                let variant = e.attr("mathvariant").unwrap_or(if italic_by_default(&text) { "italic" } else { "normal" });
+                let (text, font) = match variant {
+                    "double-struck" => (text.chars().map(double_struck).collect(), serif),
+                    "bold" => (text, FontKey { bold: true, ..serif }),
+                    "bold-italic" => (text, FontKey { bold: true, italic: true, ..serif }),
+                    "italic" => (text, FontKey { italic: true, ..serif }),
+                    "sans-serif" => (text, FontKey::new(Family::Sans)),
+                    "monospace" => (text, FontKey::new(Family::Mono)),
+                    _ => (text, serif),
+                };
+                let mut b = self.text(&text, font, size);
+                // A little room after an italic letter so it does not touch what follows
+                if font.italic {
+                    b.width += size * 0.04;
+                }
+                b
+            }
+            "mn" => {
+                let font = FontKey { bold: e.attr("mathvariant") == Some("bold"), ..serif };
+                self.text(&e.text(), font, size)
+            }
+            "mo" => {
+                let text = e.text().trim().replace('-', "−");
+                let large = text.chars().count() == 1 && LARGE.contains(text.as_str());
+                if large && display {
+                    // The text-style integral glyph is much smaller than a summation sign
+                    let scaled = size * if "∫∬∭∮".contains(text.as_str()) { 2.0 } else { 1.5 };
+                    let mut b = self.text(&text, serif, scaled);
+                    let drop = scaled * 0.25 - size * 0.25;
+                    b.items = b.items.into_iter().map(|i| i.shifted(0.0, drop)).collect();
+                    b.ascent = scaled * 0.8 - drop;
+                    b.descent = scaled * 0.3 + drop;
+                    b
+                } else {
+                    self.text(&text, serif, size)
+                }
+            }
+            "mtext" | "ms" => {
+                let text = e.text().replace(' ', "\u{a0}");
+                let font = FontKey { bold: e.attr("mathvariant") == Some("bold"), ..serif };
+                self.text(&text, font, size)
+            }
+            "mspace" => MathBox { width: length(e.attr("width"), size), ..MathBox::default() },
+            "mphantom" => {
+                let b = self.row(&e.children, size, display);
+                MathBox { items: Vec::new(), ..b }
+            }
+            "semantics" => match child(0) {
+                Some(first) => self.node(first, size, display),
+                None => MathBox::empty(size),
+            },
+            "mstyle" => {
+                let display = e.attr("displaystyle").map_or(display, |d| d == "true");
+                let size = match e.attr("scriptlevel") {
+                    Some("1") => size * 0.7,
+                    Some("2") => size * 0.5,
+                    _ => size,
+                };
+                self.row(&e.children, size, display)
+            }
+            "msub" | "msup" | "msubsup" => {
+                let base = child(0).map_or_else(|| MathBox::empty(size), |b| self.node(b, size, display));
+                let script = (size * 0.7).max(5.0);
+                let (sub, sup) = match e.tag.as_str() {
+                    "msub" => (child(1), None),
+                    "msup" => (None, child(1)),
+                    _ => (child(1), child(2)),
+                };
+                let sub = sub.map(|s| self.node(s, script, false));
+                let sup = sup.map(|s| self.node(s, script, false));
+                self.scripts(base, sub, sup, size)
+            }
+            "munder" | "mover" | "munderover" => {
+                let base_el = child(0);
+                let base = base_el.map_or_else(|| MathBox::empty(size), |b| self.node(b, size, display));
+                let (under, over) = match e.tag.as_str() {
+                    "munder" => (child(1), None),
+                    "mover" => (None, child(1)),
+                    _ => (child(1), child(2)),
+                };
+                // Inline, limits of large operators go beside them like scripts
+                let large = base_el.is_some_and(|b| b.tag == "mo" && LARGE.contains(b.text().trim()));
+                let script = (size * 0.7).max(5.0);
+                if large && !display {
+                    let sub = under.map(|s| self.node(s, script, false));
+                    let sup = over.map(|s| self.node(s, script, false));
+                    return self.scripts(base, sub, sup, size);
+                }
+                let accent = e.attr("accent") == Some("true") || over.is_some_and(|o| o.tag == "mo" && o.text().chars().count() == 1);
+                if let Some(over) = over.filter(|_| accent && e.tag == "mover") {
+                    return self.accent(base, &over.text(), size);
+                }
+                let under = under.map(|s| self.node(s, script, false));
+                let over = over.map(|s| self.node(s, script, false));
+                self.limits(base, under, over, size)
+            }
+            "mfrac" => {
+                let inner = if display { size } else { (size * 0.8).max(5.0) };
+                let num = child(0).map_or_else(|| MathBox::empty(inner), |n| self.node(n, inner, false));
+                let den = child(1).map_or_else(|| MathBox::empty(inner), |d| self.node(d, inner, false));
+                let bar = e.attr("linethickness").is_none_or(|t| length(Some(t), size) > 0.0);
+                self.fraction(num, den, size, bar)
+            }
+            "msqrt" => {
+                let content = self.row(&e.children, size, display);
+                self.radical(content, None, size)
+            }
+            "mroot" => {
+                let content = child(0).map_or_else(|| MathBox::empty(size), |c| self.node(c, size, display));
+                let index = child(1).map(|i| self.node(i, (size * 0.5).max(5.0), false));
+                self.radical(content, index, size)
+            }
+            "mtable" => self.table(e, size, display),
+            "menclose" => {
+                let mut b = self.row(&e.children, size, display);
+                let pad = size * 0.15;
+                let (w, top, bottom) = (b.width + 2.0 * pad, -b.ascent - pad, b.descent + pad);
+                b.items = b.items.into_iter().map(|i| i.shifted(pad, 0.0)).collect();
+                b.items.extend([
+                    self.rule(0.0, top, w, top, size),
+                    self.rule(0.0, bottom, w, bottom, size),
+                    self.rule(0.0, top, 0.0, bottom, size),
+                    self.rule(w, top, w, bottom, size),
+                ]);
+                MathBox { width: w, ascent: b.ascent + pad, descent: b.descent + pad, items: b.items }
+            }
+            "merror" => {
+                let saved = self.color;
+                self.color = [0.86, 0.15, 0.15];
+                let b = self.row(&e.children, size, display);
+                self.color = saved;
+                b
+            }
+            "annotation" | "annotation-xml" => MathBox::default(),
+            // mrow, mpadded, math and anything unknown: lay out the children in a row
+            _ => self.row(&e.children, size, display),
+        }
+    }
+
+    fn scripts(&mut self, base: MathBox, sub: Option<MathBox>, sup: Option<MathBox>, size: f32) -> MathBox {
+        let mut out = MathBox { ascent: base.ascent, descent: base.descent, ..MathBox::default() };
+        out.append(base, 0.0);
+        let x = out.width;
+        let mut width: f32 = 0.0;
+        if let Some(sup) = sup {
+            let rise = (out.ascent - sup.ascent * 0.55).max(size * 0.38);
+            width = width.max(sup.width);
+            out.overlay(sup, x, rise);
+        }
+        if let Some(sub) = sub {
+            let drop = (out.descent + sub.ascent * 0.3).max(size * 0.2).min(size * 0.5);
+            width = width.max(sub.width);
+            out.overlay(sub, x, -drop);
+        }
+        out.width = x + width + size * 0.05;
+        out
+    }
+
+    /// Limits centred above and below a base
+    fn limits(&mut self, base: MathBox, under: Option<MathBox>, over: Option<MathBox>, size: f32) -> MathBox {
+        let width = [Some(&base), under.as_ref(), over.as_ref()].into_iter().flatten().map(|b| b.width).fold(0.0, f32::max);
+        let gap = size * 0.12;
+        let mut out = MathBox::default();
+        let (base_ascent, base_descent) = (base.ascent, base.descent);
+        out.overlay(base.centered(width), 0.0, 0.0);
+        if let Some(over) = over {
+            let rise = base_ascent + gap + over.descent;
+            out.overlay(over.centered(width), 0.0, rise);
+        }
+        if let Some(under) = under {
+            let drop = base_descent + gap + under.ascent;
+            out.overlay(under.centered(width), 0.0, -drop);
+        }
+        out
+    }
+
+    /// An accent over a base: a rule for bars, otherwise the accent glyph centred above
+    fn accent(&mut self, base: MathBox, accent: &str, size: f32) -> MathBox {
+        let mut out = MathBox::default();
+        let width = base.width;
+        let top = base.ascent;
+        out.append(base, 0.0);
+        match accent.trim() {
+            "‾" | "¯" | "ˉ" | "_" | "―" => {
+                let y = -(top + size * 0.08);
+                out.items.push(self.rule(0.0, y, width, y, size));
+                out.ascent = top + size * 0.12;
+            }
+            text => {
+                let text = match text {
+                    "→" | "⃗" => "→",
+                    "^" | "ˆ" => "ˆ",
+                    "~" | "˜" => "˜",
+                    "˙" | "." => "˙",
+                    other => other,
+                };
+                let glyph = self.text(text, FontKey::new(Family::Serif), size * 0.8);
+                // Accent glyphs sit high in their em box already
+                let rise = top - size * 0.45;
+                let glyph = glyph.centered(width);
+                out.overlay(glyph, 0.0, rise.max(0.0));
+            }
+        }
+        out
+    }
+
+    fn fraction(&mut self, num: MathBox, den: MathBox, size: f32, bar: bool) -> MathBox {
+        let axis = size * 0.25;
+        let gap = size * 0.16;
+        let pad = size * 0.1;
+        let width = num.width.max(den.width) + 2.0 * pad;
+        let mut out = MathBox::default();
+        let num_rise = axis + gap + num.descent;
+        let den_drop = den.ascent + gap - axis;
+        out.overlay(num.centered(width), 0.0, num_rise);
+        out.overlay(den.centered(width), 0.0, -den_drop);
+        if bar {
+            out.items.push(self.rule(pad * 0.5, -axis, width - pad * 0.5, -axis, size));
+        }
+        out.width = width + size * 0.05;
+        out
+    }
+
+    fn radical(&mut self, content: MathBox, index: Option<MathBox>, size: f32) -> MathBox {
+        let gap = size * 0.12;
+        let top = content.ascent + gap;
+        let bottom = content.descent;
+        let sign = size * 0.55;
+        let mut out = MathBox::default();
+        let lead = index.as_ref().map_or(0.0, |i| (i.width - sign * 0.4).max(0.0));
+        let (x0, x1, x2) = (lead, lead + sign * 0.3, lead + sign * 0.55);
+        let mid = (bottom - top) * 0.35;
+        out.items.extend([
+            self.rule(x0, -mid + size * 0.05, x0 + sign * 0.12, -mid, size),
+            self.rule(x0 + sign * 0.12, -mid, x1, bottom, size),
+            self.rule(x1, bottom, x2 + sign * 0.2, -top, size),
+            self.rule(x2 + sign * 0.2, -top, x2 + sign * 0.3 + content.width + size * 0.05, -top, size),
+        ]);
+        if let Some(index) = index {
+            let rise = -mid + size * 0.15 + index.descent;
+            out.overlay(index, 0.0, rise);
+        }
+        out.overlay(content, x2 + sign * 0.3, 0.0);
+        out.ascent = out.ascent.max(top + size * 0.05);
+        out.descent = out.descent.max(bottom);
+        out.width += size * 0.1;
+        out
+    }
+
+    /// Rows and columns aligned per `columnalign`, centred on the math axis
+    fn table(&mut self, e: &Element, size: f32, display: bool) -> MathBox {
+        let aligns: Vec<&str> = e.attr("columnalign").unwrap_or("center").split_whitespace().collect();
+        let rows: Vec<Vec<MathBox>> = e
+            .elements()
+            .filter(|r| r.tag == "mtr" || r.tag == "mlabeledtr")
+            .map(|r| r.elements().filter(|c| c.tag == "mtd").map(|c| self.row(&c.children, size, display)).collect())
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0.0f32; columns];
+        for row in &rows {
+            for (c, cell) in row.iter().enumerate() {
+                widths[c] = widths[c].max(cell.width);
+            }
+        }
+        let column_gap = size * if aligns.iter().any(|a| *a != "center") { 0.0 } else { 1.0 };
+        let row_gap = size * 0.35;
+
+        let mut out = MathBox::default();
+        let mut y = 0.0;
+        let mut placed = Vec::new();
+        for row in rows {
+            let ascent = row.iter().map(|c| c.ascent).fold(size * 0.72, f32::max);
+            let descent = row.iter().map(|c| c.descent).fold(size * 0.22, f32::max);
+            let baseline = y + ascent;
+            let mut x = 0.0;
+            for (c, cell) in row.into_iter().enumerate() {
+                let slack = widths[c] - cell.width;
+                let offset = match aligns.get(c).or(aligns.last()).copied().unwrap_or("center") {
+                    "left" => 0.0,
+                    "right" => slack,
+                    _ => slack / 2.0,
+                };
+                placed.push((cell, x + offset, baseline));
+                x += widths[c] + column_gap;
+            }
+            y = baseline + descent + row_gap;
+        }
+        let height = (y - row_gap).max(0.0);
+        // Middle of the table on the axis
+        let shift = height / 2.0 + size * 0.25;
+        for (cell, x, baseline) in placed {
+            out.overlay(cell, x, shift - baseline);
+        }
+        out.width = widths.iter().sum::<f32>() + column_gap * columns.saturating_sub(1) as f32;
+        out.ascent = out.ascent.max(shift);
+        out.descent = out.descent.max(height - shift);
+        out
+    }
+}
+
+impl MathBox {
+    /// Shift right to sit centred in `width`
+    fn centered(mut self, width: f32) -> MathBox {
+        let dx = (width - self.width) / 2.0;
+        self.items = self.items.into_iter().map(|i| i.shifted(dx, 0.0)).collect();
+        self.width = width;
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Relation,
+    Binary,
+    Punctuation,
+    Large,
+    Fence,
+    Other,
+}
+
+fn operator_class(text: &str, e: &Element) -> Class {
+    let text = text.trim();
+    if e.attr("fence") == Some("true") || (text.chars().count() == 1 && FENCES.contains(text)) {
+        return Class::Fence;
+    }
+    if text.chars().count() != 1 {
+        return Class::Other;
+    }
+    match text {
+        t if RELATIONS.contains(t) => Class::Relation,
+        t if BINARIES.contains(t) || t == "-" => Class::Binary,
+        t if PUNCTUATION.contains(t) => Class::Punctuation,
+        t if LARGE.contains(t) => Class::Large,
+        _ => Class::Other,
+    }
+}
+
+/// Stretchy fences such as `\left(` are drawn to fit; `stretchy="false"` ones keep their size
+fn is_fence(e: &Element) -> bool {
+    e.tag == "mo" && e.attr("fence") == Some("true") && e.attr("stretchy") != Some("false")
+}
+
+/// A MathML length ("0.2778em", "3pt", "0px") in points at font `size`
+fn length(value: Option<&str>, size: f32) -> f32 {
+    let value = value.unwrap_or("0").trim();
+    let number = |suffix: &str| value.strip_suffix(suffix).and_then(|v| v.trim().parse::<f32>().ok());
+    if let Some(em) = number("em") {
+        em * size
+    } else if let Some(pt) = number("pt") {
+        pt
+    } else if let Some(px) = number("px") {
+        px * 0.75
+    } else {
+        match value {
+            "thinmathspace" => size / 6.0,
+            "mediummathspace" => size * 2.0 / 9.0,
+            "thickmathspace" => size * 5.0 / 18.0,
+            _ => value.parse().unwrap_or(0.0),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::Write;
+
+use crate::bode;
+use crate::chart::{self, Axis, Panel};
+use crate::expr::{Complex, Env};
+use crate::site::escape_html;
+use crate::units::{self, format_si};
+
+const BOLTZMANN: f64 = 1.380649e-23;
+const POINTS: usize = 400;
+
+/// The shape of one source's input-referred PSD
+enum Kind {
+    /// Flat, in V²/Hz
+    White(f64),
+    /// `k / f`, in V²/Hz at 1 Hz
+    Flicker(f64),
+}
+
+struct Source {
+    name: String,
+    description: &'static str,
+    kind: Kind,
+}
+
+impl Source {
+    fn psd(&self, f: f64) -> f64 {
+        match self.kind {
+            Kind::White(s) => s,
+            Kind::Flicker(k) => k / f,
+        }
+    }
+
+    /// Noise power between `lo` and `hi`, in V²
+    fn power(&self, (lo, hi): (f64, f64)) -> f64 {
+        match self.kind {
+            Kind::White(s) => s * (hi - lo),
+            Kind::Flicker(k) => k * (hi / lo).ln(),
+        }
+    }
+}
+
+/// A ```noise block: noise sources referred to the input, summed and integrated over a band.
+///
+/// ```text
+/// T: 300
+/// gm = 2m
+/// Rs: thermal R = 1k
+/// M1: mosfet gm = {gm}, gamma = 2/3
+/// M1: flicker K = 1e-25, Cox = 8.5m, W = 20u, L = 0.5u
+/// RD: thermal R = 10k, gain = {gm*10k}
+/// band: 10 .. 1meg
+/// ```
+///
+/// Sources are `thermal` (4kTR), `mosfet` (4kTγ/gm), `flicker` (K/(Cox·W·L·f)) or `white`
+/// (a density `v` in V/√Hz); `gain` divides a source down to the input. `name = value` lines
+/// define constants for `{expressions}`. Options: `T:` (kelvin), `band:`, `f:` and `caption:`.
+struct Noise {
+    sources: Vec<Source>,
+    band: (f64, f64),
+    range: Option<(f64, f64)>,
+    caption: String,
+}
+
+/// `lo .. hi` with SI suffixes, e.g. `10 .. 1meg`
+fn parse_band(value: &str, env: &Env) -> Result<(f64, f64), String> {
+    let (lo, hi) = value.split_once("..").ok_or_else(|| format!("expected a range like `1 .. 1meg`, got `{}`", value.trim()))?;
+    let band = (units::parse_value(lo, env, Some("Hz"))?, units::parse_value(hi, env, Some("Hz"))?);
+    if !(band.0 > 0.0 && band.1 > band.0) {
+        return Err(format!("a frequency range must be positive and increasing, got `{}`", value.trim()));
+    }
+    Ok(band)
+}
+
+fn parse(source: &str) -> Result<Noise, String> {
+    let mut noise = Noise { sources: Vec::new(), band: (1.0, 1e6), range: None, caption: String::new() };
+    let mut env = Env::new();
+    let mut temperature = 300.0;
+
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            match key.trim() {
+                "T" | "temp" => temperature = units::parse_value(value, &env, Some("K"))?,
+                "band" => noise.band = parse_band(value, &env)?,
+                "f" | "range" => noise.range = Some(parse_band(value, &env)?),
+                "caption" => noise.caption = value.to_string(),
+                name => noise.sources.push(parse_source(name, value, &env, temperature)?),
+            }
+            continue;
+        }
+        let (name, value) = line.split_once('=').ok_or_else(|| format!("expected `name: kind …` or `name = value` in `{}`", line))?;
+        env.insert(name.trim().to_string(), Complex::real(units::parse_value(value, &env, None)?));
+    }
+
+    if noise.sources.is_empty() {
+        return Err("no noise sources: add a line like `R1: thermal R = 10k`".to_string());
+    }
+    Ok(noise)
+}
+
+/// `thermal R = 10k, gain = 5` into a source, with its PSD already divided by gain²
+fn parse_source(name: &str, spec: &str, env: &Env, temperature: f64) -> Result<Source, String> {
+    let (kind, params) = spec.split_once(char::is_whitespace).unwrap_or((spec, ""));
+    let mut values = Env::new();
+    for part in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = part.split_once('=').ok_or_else(|| format!("expected `key = value` in `{}`", part))?;
+        let key = key.trim().to_lowercase();
+        let unit = match key.as_str() {
+            "r" => Some("Ω"),
+            "gm" => Some("S"),
+            "w" | "l" => Some("m"),
+            "v" => Some("V/√Hz"),
+            "gain" => Some("V/V"),
+            _ => None,
+        };
+        values.insert(key, Complex::real(units::parse_value(value, env, unit)?));
+    }
+    let get = |key: &str| -> Result<f64, String> {
+        values.get(key).map(|v| v.re).ok_or_else(|| format!("`{}: {}` needs `{} = …`", name, kind, key))
+    };
+    let kt4 = 4.0 * BOLTZMANN * temperature;
+
+    let (description, kind) = match kind {
+        "thermal" => ("4kTR", Kind::White(kt4 * get("r")?)),
+        "mosfet" => {
+            let gamma = values.get("gamma").map_or(2.0 / 3.0, |v| v.re);
+            ("4kTγ/gm", Kind::White(kt4 * gamma / get("gm")?))
+        }
+        "flicker" => ("K/(Cox·W·L·f)", Kind::Flicker(get("k")? / (get("cox")? * get("w")? * get("l")?))),
+        "white" => ("white", Kind::White(get("v")?.powi(2))),
+        other => return Err(format!("unknown noise source `{}`: use thermal, mosfet, flicker or white", other)),
+    };
+    let gain = values.get("gain").map_or(1.0, |v| v.re);
+    let kind = match kind {
+        Kind::White(s) => Kind::White(s / (gain * gain)),
+        Kind::Flicker(k) => Kind::Flicker(k / (gain * gain)),
+    };
+    Ok(Source { name: name.to_string(), description, kind })
+}
+
+pub fn render(source: &str) -> Result<String, String> {
+    let noise = parse(source)?;
+    let (lo, hi) = noise.range.unwrap_or((noise.band.0 / 10.0, noise.band.1 * 10.0));
+    let freqs = bode::log_sweep(lo, hi, POINTS);
+
+    let white: f64 = noise.sources.iter().map(|s| if let Kind::White(v) = s.kind { v } else { 0.0 }).sum();
+    let flicker: f64 = noise.sources.iter().map(|s| if let Kind::Flicker(k) = s.kind { k } else { 0.0 }).sum();
+    // Where the summed 1/f noise falls to the white floor
+    let corner = (white > 0.0 && flicker > 0.0).then(|| flicker / white);
+    // Nothing to draw on a log axis, nor a total to take shares of
+    if white <= 0.0 && flicker <= 0.0 {
+        return Err("every source is noiseless, so there is nothing to plot".to_string());
+    }
+
+    let density = |f: f64| (white + flicker / f).sqrt();
+    let mut curves = vec![freqs.iter().map(|&f| (f, density(f))).collect::<Vec<_>>()];
+    if noise.sources.len() > 1 {
+        curves.extend(noise.sources.iter().map(|s| freqs.iter().map(|&f| (f, s.psd(f).sqrt())).collect()));
+    }
+    let top = curves.iter().flatten().map(|p| p.1).fold(0.0, f64::max);
+    let bottom = curves.iter().flatten().map(|p| p.1).filter(|&v| v > 0.0).fold(f64::INFINITY, f64::min).max(top * 1e-4);
+
+    let panel = Panel {
+        x: Axis::log(lo, hi, "Frequency").unit("Hz"),
+        y: Axis::log(bottom, top, "Input-referred noise (V/√Hz)").nice(),
+        left: 64.0,
+        top: 14.0,
+        width: 546.0,
+        height: 300.0,
+    };
+    let (width, height) = (640.0, panel.top + panel.height + 44.0);
+
+    let mut svg = String::new();
+    panel.frame(&mut svg, true);
+    for edge in [noise.band.0, noise.band.1] {
+        if edge > lo && edge < hi {
+            panel.guide(&mut svg, (edge, panel.y.min), (edge, panel.y.max));
+        }
+    }
+    // Individual sources dashed underneath the total
+    for (series, points) in curves.iter().enumerate().skip(1).rev() {
+        panel.curve(&mut svg, points, series, true);
+    }
+    panel.curve(&mut svg, &curves[0], 0, false);
+    if let Some(fc) = corner.filter(|&fc| fc > lo && fc < hi) {
+        panel.dot(&mut svg, (fc, density(fc)), 0);
+        panel.note(&mut svg, (fc, density(fc)), -6.0, &format!("1/f corner {}", format_si(fc, "Hz")));
+    }
+    let mut names = vec!["Total".to_string()];
+    if noise.sources.len() > 1 {
+        names.extend(noise.sources.iter().map(|s| format!("{} ({})", s.name, s.description)));
+    }
+    panel.legend(&mut svg, &names);
+
+    let caption = if noise.caption.is_empty() {
+        "Input-referred noise density".to_string()
+    } else {
+        noise.caption.clone()
+    };
+    let mut html = String::from("<div class=\"noise\">\n");
+    html.push_str(&chart::figure("noise", width, height, &svg, &caption));
+
+    // Summary: each source's share of the noise power in the band
+    let powers: Vec<f64> = noise.sources.iter().map(|s| s.power(noise.band)).collect();
+    let total: f64 = powers.iter().sum();
+    let band = format!("{} – {}", format_si(noise.band.0, "Hz"), format_si(noise.band.1, "Hz"));
+    let _ = writeln!(
+        html,
+        "\n<table class=\"noise-summary\">\n<thead><tr><th>Source</th><th>Model</th><th>Density</th><th>RMS over {}</th><th>Share</th></tr></thead>\n<tbody>",
+        escape_html(&band)
+    );
+    for (source, power) in noise.sources.iter().zip(&powers) {
+        let density = match source.kind {
+            Kind::White(s) => format_si(s.sqrt(), "V/√Hz"),
+            Kind::Flicker(k) => format!("{} at 1 Hz", format_si(k.sqrt(), "V/√Hz")),
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1} %</td></tr>",
+            escape_html(&source.name),
+            source.description,
+            escape_html(&density),
+            escape_html(&format_si(power.sqrt(), "V")),
+            100.0 * power / total
+        );
+    }
+    let _ = writeln!(
+        html,
+        "</tbody>\n<tfoot><tr><th colspan=\"3\">Total</th><th>{}</th><th>100 %</th></tr></tfoot>\n</table>",
+        escape_html(&format_si(total.sqrt(), "V"))
+    );
+
+    html.push_str("<dl class=\"noise-results\">\n");
+    if white > 0.0 {
+        let _ = writeln!(html, "<dt>White noise floor</dt><dd>{}</dd>", escape_html(&format_si(white.sqrt(), "V/√Hz")));
+    }
+    if let Some(fc) = corner {
+        let _ = writeln!(html, "<dt>1/f corner</dt><dd>{}</dd>", escape_html(&format_si(fc, "Hz")));
+    }
+    let _ = writeln!(html, "<dt>Integrated noise ({})</dt><dd>{} rms</dd>", escape_html(&band), escape_html(&format_si(total.sqrt(), "V")));
+    html.push_str("</dl>\n</div>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noiseless_sources_are_an_error() {
+        assert!(render("R: thermal R = 0\nband: 10 .. 1meg").is_err());
+        let html = render("R: thermal R = 0\nR2: thermal R = 1k\nband: 10 .. 1meg").unwrap();
+        assert!(!html.contains("NaN"), "{}", html);
+        assert!(html.contains("<td>0.0 %</td>"));
+    }
+}
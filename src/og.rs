@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use base64::Engine;
+use resvg::tiny_skia::{Pixmap, Transform};
+
+use crate::pdf::{Family, FontKey, Fonts};
+use crate::render::slugify;
+use crate::site::{content_dir, escape_html, Page, SiteIndex};
+use crate::{config, format_title, parse_numbered_name};
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 630;
+
+const TITLE_SIZE: f32 = 68.0;
+const TITLE_LINES: usize = 3;
+
+/// Where a page's preview image is served, without the site_url prefix
+pub fn image_path(page_path: &str) -> String {
+    let slug = slugify(page_path);
+    format!("/og/{}.png", if slug.is_empty() { "index" } else { &slug })
+}
+
+/// `text` broken into lines no wider than `width`, the last one cut short with an ellipsis
+/// when it does not fit in `max_lines`
+fn wrap(fonts: &mut Fonts, key: FontKey, text: &str, size: f32, width: f32, max_lines: usize) -> Vec<String> {
+    let mut measure = |s: &str| s.chars().map(|c| fonts.advance(key, c)).sum::<f32>() * size;
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+        if current.is_empty() || measure(&candidate) <= width {
+            current = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut current, word.to_string()));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = &mut lines[max_lines - 1];
+        while !last.is_empty() && measure(&format!("{}…", last)) > width {
+            last.pop();
+        }
+        *last = format!("{}…", last.trim_end());
+    }
+    lines
+}
+
+/// The category icon, e.g. `assets/content/images/analog.svg`, as a data URL
+fn category_icon(category_dir: &str) -> Option<String> {
+    let file = Path::new(content_dir()).join("images").join(format!("{}.svg", category_dir));
+    let data = std::fs::read(file).ok()?;
+    Some(format!("data:image/svg+xml;base64,{}", base64::engine::general_purpose::STANDARD.encode(data)))
+}
+
+/// The card as SVG: category and title on the left, the category icon on the right and the
+/// site name at the bottom
+fn card(page: &Page, fonts: &mut Fonts) -> String {
+    let segments: Vec<&str> = page.path.split('/').filter(|s| !s.is_empty()).collect();
+    let is_index = page.path == "/index";
+    let icon = segments.first().filter(|_| !is_index).and_then(|dir| category_icon(dir));
+    let title = if is_index { config::get().site.title.clone() } else { page.title.clone() };
+    // Chapter the page sits in, when it is deeper than a category's own pages
+    let chapter = (segments.len() > 2).then(|| format_title(segments[segments.len() - 2]));
+    let chapter = chapter.map(|c| match parse_numbered_name(segments[segments.len() - 2]).0 {
+        i32::MAX => c,
+        n => format!("{}. {}", n, c),
+    });
+
+    let text_width = if icon.is_some() { 600.0 } else { 1040.0 };
+    let bold = FontKey { family: Family::Sans, bold: true, italic: false };
+    let lines = wrap(fonts, bold, &title, TITLE_SIZE, text_width, TITLE_LINES);
+
+    let mut svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">
+<defs><clipPath id="icon"><rect x="700" y="171" width="420" height="252" rx="16"/></clipPath></defs>
+<rect width="{w}" height="{h}" fill="#111827"/>
+<rect width="16" height="{h}" fill="#7aa2f7"/>
+"##,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    if !page.category.is_empty() && !is_index {
+        svg.push_str(&format!(
+            r##"<text x="80" y="120" font-family="sans-serif" font-size="30" font-weight="bold" letter-spacing="3" fill="#7aa2f7">{}</text>
+"##,
+            escape_html(&page.category.to_uppercase())
+        ));
+    }
+    let top = 315.0 - (lines.len() as f32 - 1.0) * 40.0 + if chapter.is_some() { -20.0 } else { 0.0 };
+    for (i, line) in lines.iter().enumerate() {
+        svg.push_str(&format!(
+            r##"<text x="80" y="{:.0}" font-family="sans-serif" font-size="{}" font-weight="bold" fill="#f9fafb">{}</text>
+"##,
+            top + i as f32 * 80.0,
+            TITLE_SIZE,
+            escape_html(line)
+        ));
+    }
+    if let Some(chapter) = chapter {
+        svg.push_str(&format!(
+            r##"<text x="80" y="{:.0}" font-family="sans-serif" font-size="32" fill="#9ca3af">{}</text>
+"##,
+            top + (lines.len() as f32 - 1.0) * 80.0 + 64.0,
+            escape_html(&chapter)
+        ));
+    }
+    if let Some(icon) = icon {
+        svg.push_str(&format!(
+            r##"<image x="700" y="171" width="420" height="252" preserveAspectRatio="xMidYMid slice" clip-path="url(#icon)" href="{}"/>
+"##,
+            icon
+        ));
+    }
+    svg.push_str(&format!(
+        r##"<text x="80" y="560" font-family="sans-serif" font-size="32" font-weight="bold" fill="#e5e7eb">{}</text>
+</svg>
+"##,
+        escape_html(&config::get().site.title)
+    ));
+    svg
+}
+
+/// A 1200×630 OpenGraph preview of `page` as PNG
+pub fn render(page: &Page, fonts: &mut Fonts) -> Result<Vec<u8>, String> {
+    let svg = card(page, fonts);
+    let tree = fonts.parse_svg(svg.as_bytes(), None)?;
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT).ok_or("could not allocate the image")?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Write a preview for every page to `<dir>/og/`, returning how many were written
+pub fn export(index: &SiteIndex, dir: &Path) -> Result<usize, String> {
+    let mut fonts = Fonts::new()?;
+    for page in &index.pages {
+        let file = dir.join(image_path(&page.path).trim_start_matches('/'));
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("could not create {}: {}", parent.display(), e))?;
+        }
+        let png = render(page, &mut fonts).map_err(|e| format!("{}: {}", page.path, e))?;
+        std::fs::write(&file, png).map_err(|e| format!("could not write {}: {}", file.display(), e))?;
+    }
+    Ok(index.pages.len())
+}
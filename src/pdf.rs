@@ -0,0 +1,1621 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use pdf_writer::types::{ActionType, AnnotationType, CidFontType, FontFlags, SystemInfo, UnicodeCmap};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use svg2pdf::usvg::{self, fontdb, PostProcessingSteps, TreeParsing, TreePostProc};
+
+use crate::html::{self, Element, Node};
+use crate::mathml;
+use crate::site::content_dir;
+use crate::{config, get_site_url};
+
+// A4 in points
+const PAGE_WIDTH: f32 = 595.28;
+const PAGE_HEIGHT: f32 = 841.89;
+const MARGIN_X: f32 = 62.0;
+const MARGIN_TOP: f32 = 64.0;
+const MARGIN_BOTTOM: f32 = 70.0;
+const BODY_HEIGHT: f32 = PAGE_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+const BODY_SIZE: f32 = 10.5;
+/// SVG user units are CSS pixels
+const PX: f32 = 0.75;
+
+pub type Rgb = [f32; 3];
+const TEXT: Rgb = [0.1, 0.1, 0.1];
+const MUTED: Rgb = [0.42, 0.45, 0.5];
+const LINK: Rgb = [0.15, 0.39, 0.92];
+const RULE: Rgb = [0.82, 0.84, 0.86];
+const CODE: Rgb = [0.55, 0.16, 0.35];
+const CODE_BG: Rgb = [0.95, 0.96, 0.96];
+
+const FAMILIES: [&[&str]; 3] = [
+    &["DejaVu Serif", "Liberation Serif", "Noto Serif", "Times New Roman", "Georgia"],
+    &["DejaVu Sans", "Liberation Sans", "Noto Sans", "Arial", "Helvetica"],
+    &["DejaVu Sans Mono", "Liberation Mono", "Noto Sans Mono", "Courier New", "Menlo"],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Family {
+    Serif,
+    Sans,
+    Mono,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FontKey {
+    pub family: Family,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+impl FontKey {
+    pub const fn new(family: Family) -> Self {
+        FontKey { family, bold: false, italic: false }
+    }
+}
+
+/// Something drawn on a page. Positions are in points from the left edge and down from the
+/// top of whatever contains the item (a line's baseline for text and math).
+#[derive(Clone, Debug)]
+pub enum Item {
+    Text { x: f32, y: f32, face: usize, size: f32, color: Rgb, glyphs: Vec<u16> },
+    Rect { x: f32, y: f32, w: f32, h: f32, color: Rgb },
+    Line { x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: Rgb },
+    Graphic { x: f32, y: f32, w: f32, h: f32, index: usize },
+    Link { x: f32, y: f32, w: f32, h: f32, target: Target },
+}
+
+impl Item {
+    pub fn shifted(mut self, dx: f32, dy: f32) -> Item {
+        match &mut self {
+            Item::Text { x, y, .. } | Item::Rect { x, y, .. } | Item::Graphic { x, y, .. } | Item::Link { x, y, .. } => {
+                *x += dx;
+                *y += dy;
+            }
+            Item::Line { x1, y1, x2, y2, .. } => {
+                *x1 += dx;
+                *x2 += dx;
+                *y1 += dy;
+                *y2 += dy;
+            }
+        }
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Uri(String),
+    /// An element id within the document
+    Anchor(String),
+}
+
+struct Face {
+    post_script_name: String,
+    data: Vec<u8>,
+    index: u32,
+    key: FontKey,
+    units_per_em: f32,
+    glyphs: HashMap<char, Option<(u16, f32)>>,
+    /// Every glyph drawn so far, with the text it stands for
+    used: BTreeMap<u16, char>,
+}
+
+impl Face {
+    fn parse(&self) -> Option<ttf_parser::Face<'_>> {
+        ttf_parser::Face::parse(&self.data, self.index).ok()
+    }
+
+    /// Glyph id and advance in ems
+    fn glyph(&mut self, c: char) -> Option<(u16, f32)> {
+        if let Some(&known) = self.glyphs.get(&c) {
+            return known;
+        }
+        let found = self.parse().and_then(|face| {
+            let id = face.glyph_index(c)?;
+            Some((id.0, face.glyph_hor_advance(id).unwrap_or(0) as f32 / self.units_per_em))
+        });
+        self.glyphs.insert(c, found);
+        found
+    }
+}
+
+/// System fonts for the three families, loaded on first use and embedded as subsets
+pub struct Fonts {
+    db: fontdb::Database,
+    names: [String; 3],
+    faces: Vec<Face>,
+    by_key: HashMap<FontKey, usize>,
+}
+
+impl Fonts {
+    pub fn new() -> Result<Fonts, String> {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        let available: Vec<String> = db.faces().flat_map(|f| f.families.iter().map(|(name, _)| name.clone())).collect();
+        let Some(any) = available.first().cloned() else {
+            return Err("no system fonts found; install DejaVu or Liberation fonts".to_string());
+        };
+        let pick = |candidates: &[&str]| {
+            candidates.iter().find(|c| available.iter().any(|a| a == *c)).map_or_else(|| any.clone(), |c| c.to_string())
+        };
+        let names = [pick(FAMILIES[0]), pick(FAMILIES[1]), pick(FAMILIES[2])];
+        db.set_serif_family(names[0].clone());
+        db.set_sans_serif_family(names[1].clone());
+        db.set_monospace_family(names[2].clone());
+        Ok(Fonts { db, names, faces: Vec::new(), by_key: HashMap::new() })
+    }
+
+    /// Parse an SVG with its text turned into outlines, so it needs no fonts once embedded
+    pub fn parse_svg(&self, data: &[u8], resources: Option<PathBuf>) -> Result<usvg::Tree, String> {
+        let options = usvg::Options {
+            font_family: self.names[1].clone(),
+            resources_dir: resources,
+            ..usvg::Options::default()
+        };
+        let mut tree = usvg::Tree::from_data(data, &options).map_err(|e| e.to_string())?;
+        tree.postprocess(PostProcessingSteps { convert_text_into_paths: true }, &self.db);
+        Ok(tree)
+    }
+
+    fn face(&mut self, key: FontKey) -> usize {
+        if let Some(&face) = self.by_key.get(&key) {
+            return face;
+        }
+        let name = &self.names[key.family as usize];
+        let query = fontdb::Query {
+            families: &[fontdb::Family::Name(name)],
+            weight: if key.bold { fontdb::Weight::BOLD } else { fontdb::Weight::NORMAL },
+            style: if key.italic { fontdb::Style::Italic } else { fontdb::Style::Normal },
+            ..fontdb::Query::default()
+        };
+        let id = self.db.query(&query).or_else(|| self.db.faces().next().map(|f| f.id)).expect("fonts were checked in Fonts::new");
+        let post_script_name = self.db.face(id).map(|f| f.post_script_name.clone()).unwrap_or_default();
+        let (data, index) = self.db.with_face_data(id, |data, index| (data.to_vec(), index)).unwrap_or_default();
+        let units_per_em = ttf_parser::Face::parse(&data, index).map_or(1000.0, |f| f.units_per_em() as f32);
+        self.faces.push(Face {
+            post_script_name,
+            data,
+            index,
+            key,
+            units_per_em,
+            glyphs: HashMap::new(),
+            used: BTreeMap::new(),
+        });
+        self.by_key.insert(key, self.faces.len() - 1);
+        self.faces.len() - 1
+    }
+
+    /// Advance of `c` in ems, or 0.5 for a missing glyph
+    pub fn advance(&mut self, key: FontKey, c: char) -> f32 {
+        let face = self.face(key);
+        self.faces[face].glyph(c).map_or(0.5, |(_, advance)| advance)
+    }
+
+    /// `text` set on one line from x = 0 along the baseline at y = 0, switching to another
+    /// family for characters the font lacks. Returns the glyph runs and their total width.
+    pub fn line(&mut self, key: FontKey, text: &str, size: f32, color: Rgb) -> (Vec<Item>, f32) {
+        let mut items: Vec<Item> = Vec::new();
+        let mut x = 0.0;
+        for c in text.chars() {
+            let primary = self.face(key);
+            let mut found = self.faces[primary].glyph(c).map(|g| (primary, g));
+            if found.is_none() {
+                for family in [Family::Sans, Family::Serif, Family::Mono] {
+                    let face = self.face(FontKey { family, ..key });
+                    if let Some(g) = self.faces[face].glyph(c) {
+                        found = Some((face, g));
+                        break;
+                    }
+                }
+            }
+            let (face, (glyph, advance)) = found.unwrap_or((primary, (0, 0.5)));
+            self.faces[face].used.entry(glyph).or_insert(c);
+            match items.last_mut() {
+                Some(Item::Text { face: f, glyphs, .. }) if *f == face => glyphs.push(glyph),
+                _ => items.push(Item::Text { x, y: 0.0, face, size, color, glyphs: vec![glyph] }),
+            }
+            x += advance * size;
+        }
+        (items, x)
+    }
+}
+
+/// An SVG or raster image placed in the document
+enum Graphic {
+    Svg(Box<usvg::Tree>),
+    Raster { width: u32, height: u32, rgb: Vec<u8>, alpha: Option<Vec<u8>> },
+    Jpeg { width: u32, height: u32, components: u8, data: Vec<u8> },
+}
+
+impl Graphic {
+    /// Natural size in points
+    fn size(&self) -> (f32, f32) {
+        match self {
+            Graphic::Svg(tree) => (tree.size.width() * PX, tree.size.height() * PX),
+            Graphic::Raster { width, height, .. } | Graphic::Jpeg { width, height, .. } => (*width as f32 * PX, *height as f32 * PX),
+        }
+    }
+}
+
+/// A piece of inline content: a run of text, a formula, a space or a forced line break
+struct Fragment {
+    kind: Piece,
+    items: Vec<Item>,
+    width: f32,
+    ascent: f32,
+    descent: f32,
+    link: Option<Target>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Piece {
+    Word,
+    Space,
+    Break,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+/// Inherited text style
+#[derive(Clone)]
+struct Style {
+    font: FontKey,
+    size: f32,
+    color: Rgb,
+    rise: f32,
+    link: Option<Target>,
+    align: Align,
+    leading: f32,
+}
+
+impl Style {
+    fn body() -> Style {
+        Style {
+            font: FontKey::new(Family::Serif),
+            size: BODY_SIZE,
+            color: TEXT,
+            rise: 0.0,
+            link: None,
+            align: Align::Justify,
+            leading: 1.42,
+        }
+    }
+
+    fn sans(&self, size: f32, bold: bool) -> Style {
+        Style {
+            font: FontKey { family: Family::Sans, bold, italic: false },
+            size,
+            ..self.clone()
+        }
+    }
+}
+
+/// A horizontal band of the document that pagination never splits
+#[derive(Default)]
+struct VBox {
+    height: f32,
+    items: Vec<Item>,
+    /// Baseline of the first line, for list markers
+    baseline: Option<f32>,
+    keep_with_next: bool,
+    break_before: bool,
+    /// Vertical space, dropped at the top of a page
+    gap: bool,
+    anchors: Vec<String>,
+    /// Section title shown in the running header from here on
+    mark: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+struct Area {
+    left: f32,
+    right: f32,
+}
+
+impl Area {
+    fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    fn inset(&self, left: f32, right: f32) -> Area {
+        Area { left: self.left + left, right: self.right - right }
+    }
+}
+
+/// Turns the document tree into boxes stacked down the page
+struct Flow<'a> {
+    fonts: &'a mut Fonts,
+    graphics: &'a mut Vec<Graphic>,
+    /// Page numbers (from 1) of element ids, from the previous layout pass
+    pages: &'a HashMap<String, usize>,
+    boxes: Vec<VBox>,
+    gap: f32,
+    break_next: bool,
+    anchors: Vec<String>,
+    mark: Option<String>,
+}
+
+fn is_skipped(e: &Element) -> bool {
+    matches!(e.tag.as_str(), "button" | "script" | "style" | "head" | "template" | "annotation")
+        || e.attr("hidden").is_some()
+        || e.attr("aria-hidden") == Some("true")
+        || e.has_class("quiz-feedback")
+        || e.has_class("katex-html")
+}
+
+fn is_block(node: &Node) -> bool {
+    let Node::Element(e) = node else { return false };
+    matches!(
+        e.tag.as_str(),
+        "p" | "div" | "section" | "article" | "nav" | "main" | "aside" | "header" | "footer" | "h1" | "h2" | "h3" | "h4" | "h5"
+            | "h6" | "ul" | "ol" | "li" | "dl" | "dt" | "dd" | "table" | "pre" | "blockquote" | "figure" | "figcaption" | "hr"
+            | "details" | "summary" | "svg" | "img" | "form" | "fieldset" | "caption" | "body" | "html"
+    ) || e.has_class("equation")
+        || e.has_class("katex-display")
+}
+
+/// The first `<math>` inside KaTeX output
+fn find_math(e: &Element) -> Option<&Element> {
+    if e.tag == "math" {
+        return Some(e);
+    }
+    e.elements().find_map(find_math)
+}
+
+fn text_align(e: &Element) -> Option<Align> {
+    let style = e.attr("style").unwrap_or_default();
+    let align = style.split(';').find_map(|d| d.split_once(':').filter(|(k, _)| k.trim() == "text-align").map(|(_, v)| v.trim()))?;
+    Some(match align {
+        "center" => Align::Center,
+        "right" => Align::Right,
+        _ => Align::Left,
+    })
+}
+
+impl Flow<'_> {
+    fn space(&mut self, amount: f32) {
+        self.gap = self.gap.max(amount);
+    }
+
+    fn page_break(&mut self) {
+        self.break_next = true;
+        self.gap = 0.0;
+    }
+
+    fn flush_gap(&mut self) {
+        if self.gap > 0.0 && !self.boxes.is_empty() && !self.break_next {
+            self.boxes.push(VBox { height: self.gap, gap: true, ..VBox::default() });
+        }
+        self.gap = 0.0;
+    }
+
+    fn push(&mut self, mut vbox: VBox) {
+        self.flush_gap();
+        vbox.break_before |= std::mem::take(&mut self.break_next);
+        vbox.anchors.append(&mut self.anchors);
+        if vbox.mark.is_none() {
+            vbox.mark = self.mark.take();
+        }
+        self.boxes.push(vbox);
+    }
+
+    fn children(&mut self, nodes: &[Node], area: Area, style: &Style) {
+        let mut run: Vec<&Node> = Vec::new();
+        for node in nodes {
+            if is_block(node) {
+                self.paragraph(&run, area, style);
+                run.clear();
+                if let Node::Element(e) = node {
+                    self.block(e, area, style);
+                }
+            } else {
+                run.push(node);
+            }
+        }
+        self.paragraph(&run, area, style);
+    }
+
+    fn block(&mut self, e: &Element, area: Area, style: &Style) {
+        if is_skipped(e) {
+            return;
+        }
+        if let Some(id) = e.attr("id") {
+            self.anchors.push(id.to_string());
+        }
+        let style = &Style { align: text_align(e).unwrap_or(style.align), ..style.clone() };
+        match e.tag.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.heading(e, area, style),
+            "p" => {
+                self.space(5.0);
+                self.children(&e.children, area, style);
+                self.space(5.0);
+            }
+            "ul" | "ol" => self.list(e, area, style),
+            "pre" => self.code(e, area),
+            "table" => self.table(e, area, style),
+            "figure" => {
+                self.space(10.0);
+                let start = self.boxes.len();
+                let centered = Style { align: Align::Center, ..style.clone() };
+                self.children(&e.children, area, &centered);
+                // Keep a figure with its caption
+                let end = self.boxes.len();
+                if end > start + 1 {
+                    for b in &mut self.boxes[start..end - 1] {
+                        b.keep_with_next = true;
+                    }
+                }
+                self.space(10.0);
+            }
+            "figcaption" | "caption" => {
+                self.space(4.0);
+                let caption = Style { align: Align::Center, ..style.sans(8.8, false) };
+                self.children(&e.children, area.inset(20.0, 20.0), &caption);
+                self.space(4.0);
+            }
+            "svg" => {
+                let source = e.source.as_deref().unwrap_or_default();
+                match self.fonts.parse_svg(source.as_bytes(), None) {
+                    Ok(tree) => self.graphic(Graphic::Svg(Box::new(tree)), area),
+                    Err(err) => self.note(&format!("[figure could not be drawn: {}]", err), area, style),
+                }
+            }
+            "img" => self.image(e, area, style),
+            "hr" => {
+                self.space(8.0);
+                let mid = area.left + area.width() / 2.0;
+                self.push(VBox {
+                    height: 1.0,
+                    items: vec![Item::Line { x1: mid - 60.0, y1: 0.5, x2: mid + 60.0, y2: 0.5, width: 0.6, color: RULE }],
+                    ..VBox::default()
+                });
+                self.space(8.0);
+            }
+            "blockquote" => self.boxed(e, area, style, [0.62, 0.64, 0.68], None),
+            "aside" | "details" if e.has_class("callout") => {
+                let color = callout_color(e);
+                self.boxed(e, area, style, color, Some(mix(color, 0.08)))
+            }
+            "div" if e.has_class("quiz") || e.has_class("flashcard") => self.boxed(e, area, style, [0.39, 0.45, 0.55], Some(CODE_BG)),
+            "summary" | "dt" => {
+                self.space(4.0);
+                let bold = Style { font: FontKey { bold: true, ..style.font }, ..style.clone() };
+                let start = self.boxes.len();
+                self.children(&e.children, area, &bold);
+                if let Some(last) = self.boxes[start..].last_mut() {
+                    last.keep_with_next = true;
+                }
+                self.space(2.0);
+            }
+            "dd" => {
+                self.children(&e.children, area.inset(18.0, 0.0), style);
+                self.space(4.0);
+            }
+            "span" if e.has_class("equation") => self.equation(e, area, style),
+            "span" if e.has_class("katex-display") => self.equation(e, area, style),
+            "nav" if e.has_class("book-toc") => self.toc(e, area, style),
+            "section" if e.has_class("book-cover") => self.cover(e, area, style),
+            "section" if e.has_class("book-part") => {
+                self.page_break();
+                self.children(&e.children, area, style);
+            }
+            "div" if e.has_class("question") => {
+                let bold = Style { font: FontKey { bold: true, ..style.font }, ..style.clone() };
+                self.children(&e.children, area, &bold);
+            }
+            _ => self.children(&e.children, area, style),
+        }
+    }
+
+    fn heading(&mut self, e: &Element, area: Area, style: &Style) {
+        let title = e.has_class("book-section-title");
+        let (size, above, below) = match e.tag.as_str() {
+            _ if title => (19.0, 6.0, 12.0),
+            "h1" => (16.0, 16.0, 6.0),
+            "h2" => (14.0, 14.0, 5.0),
+            "h3" => (12.0, 12.0, 4.0),
+            _ => (BODY_SIZE, 10.0, 3.0),
+        };
+        self.space(above);
+        if title {
+            self.mark = Some(e.text().trim().to_string());
+        }
+        let heading = Style { align: Align::Left, leading: 1.25, ..style.sans(size, true) };
+        let start = self.boxes.len();
+        self.children(&e.children, area, &heading);
+        let end = self.boxes.len();
+        for b in &mut self.boxes[start..end] {
+            b.keep_with_next = true;
+        }
+        if title && end > start {
+            let last = &mut self.boxes[end - 1];
+            last.height += 6.0;
+            let y = last.height - 1.0;
+            last.items.push(Item::Line { x1: area.left, y1: y, x2: area.right, y2: y, width: 0.8, color: RULE });
+        }
+        self.space(below);
+    }
+
+    /// Inline nodes into justified lines, one box per line
+    fn paragraph(&mut self, nodes: &[&Node], area: Area, style: &Style) {
+        if nodes.iter().all(|n| matches!(n, Node::Text(t) if t.trim().is_empty())) {
+            return;
+        }
+        // A paragraph that is only a formula is display math
+        let elements: Vec<&Element> = nodes.iter().filter_map(|n| if let Node::Element(e) = n { Some(e) } else { None }).collect();
+        let texts_blank = nodes.iter().all(|n| !matches!(n, Node::Text(t) if !t.trim().is_empty()));
+        if texts_blank && elements.len() == 1 && elements[0].has_class("katex") {
+            if let Some(math) = find_math(elements[0]) {
+                self.display_math(math, None, area, style);
+                return;
+            }
+        }
+
+        let mut fragments = Vec::new();
+        for node in nodes {
+            self.inline(node, style, &mut fragments);
+        }
+        let boxes = self.lines(&fragments, area, style);
+        let count = boxes.len();
+        for (i, mut b) in boxes.into_iter().enumerate() {
+            // No single first or last line of a paragraph alone on a page
+            b.keep_with_next = count > 1 && (i == 0 || i + 2 == count);
+            self.push(b);
+        }
+    }
+
+    fn inline(&mut self, node: &Node, style: &Style, out: &mut Vec<Fragment>) {
+        let e = match node {
+            Node::Text(text) => return self.words(text, style, out),
+            Node::Element(e) => e,
+        };
+        if is_skipped(e) {
+            return;
+        }
+        if let Some(id) = e.attr("id") {
+            self.anchors.push(id.to_string());
+        }
+        let mut inner = style.clone();
+        match e.tag.as_str() {
+            "br" => {
+                out.push(Fragment { kind: Piece::Break, items: Vec::new(), width: 0.0, ascent: 0.0, descent: 0.0, link: None });
+                return;
+            }
+            "span" if e.has_class("katex") => {
+                if let Some(math) = find_math(e) {
+                    let b = mathml::layout(math, self.fonts, style.size, style.color, false);
+                    out.push(Fragment { kind: Piece::Word, items: b.items, width: b.width, ascent: b.ascent, descent: b.descent, link: style.link.clone() });
+                }
+                return;
+            }
+            "math" => {
+                let b = mathml::layout(e, self.fonts, style.size, style.color, false);
+                out.push(Fragment { kind: Piece::Word, items: b.items, width: b.width, ascent: b.ascent, descent: b.descent, link: style.link.clone() });
+                return;
+            }
+            "input" => {
+                let checked = e.attr("checked").is_some();
+                let mark = match (e.attr("type"), checked) {
+                    (Some("radio"), false) => "○",
+                    (Some("radio"), true) => "◉",
+                    (_, false) => "☐",
+                    (_, true) => "☑",
+                };
+                return self.words(&format!("{} ", mark), style, out);
+            }
+            "img" => {
+                let alt = e.attr("alt").unwrap_or("image");
+                return self.words(&format!("[{}]", alt), style, out);
+            }
+            "svg" => return,
+            "strong" | "b" | "th" => inner.font.bold = true,
+            "em" | "i" | "cite" | "dfn" | "var" => inner.font.italic = !style.font.italic,
+            "code" | "kbd" | "samp" | "tt" => {
+                inner.font = FontKey { family: Family::Mono, ..style.font };
+                inner.size = style.size * 0.88;
+                inner.color = CODE;
+            }
+            "sub" => {
+                inner.size = style.size * 0.72;
+                inner.rise = style.rise - style.size * 0.18;
+            }
+            "sup" => {
+                inner.size = style.size * 0.72;
+                inner.rise = style.rise + style.size * 0.36;
+            }
+            "a" => {
+                let href = e.attr("href").unwrap_or_default();
+                inner.link = if let Some(id) = href.strip_prefix('#').filter(|id| !id.is_empty()) {
+                    Some(Target::Anchor(id.to_string()))
+                } else if href.starts_with("http://") || href.starts_with("https://") || href.starts_with("mailto:") {
+                    Some(Target::Uri(href.to_string()))
+                } else {
+                    None
+                };
+                if inner.link.is_some() {
+                    inner.color = LINK;
+                }
+            }
+            _ => {}
+        }
+        for child in &e.children {
+            self.inline(child, &inner, out);
+        }
+    }
+
+    /// Text into words and collapsible spaces
+    fn words(&mut self, text: &str, style: &Style, out: &mut Vec<Fragment>) {
+        let ascent = style.size * 0.76 + style.rise.max(0.0);
+        let descent = style.size * 0.24 - style.rise.min(0.0);
+        let mut rest = text;
+        while !rest.is_empty() {
+            let end = rest.find(|c: char| c.is_whitespace() && c != '\u{a0}').unwrap_or(rest.len());
+            if end == 0 {
+                let spaces = rest.len() - rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\u{a0}').len();
+                rest = &rest[spaces..];
+                if out.last().is_some_and(|f| f.kind == Piece::Space) {
+                    continue;
+                }
+                let width = self.fonts.advance(style.font, ' ') * style.size;
+                out.push(Fragment { kind: Piece::Space, items: Vec::new(), width, ascent, descent, link: style.link.clone() });
+                continue;
+            }
+            let (items, width) = self.fonts.line(style.font, &rest[..end], style.size, style.color);
+            let items = items.into_iter().map(|i| i.shifted(0.0, -style.rise)).collect();
+            out.push(Fragment { kind: Piece::Word, items, width, ascent, descent, link: style.link.clone() });
+            rest = &rest[end..];
+        }
+    }
+
+    /// Break fragments into lines filling `area`, each line a box
+    fn lines(&mut self, fragments: &[Fragment], area: Area, style: &Style) -> Vec<VBox> {
+        // Greedy breaking at spaces; a word wider than the line gets a line of its own
+        let mut breaks: Vec<(usize, usize, bool)> = Vec::new();
+        let (mut start, mut width, mut i) = (0, 0.0, 0);
+        while i < fragments.len() {
+            match fragments[i].kind {
+                Piece::Break => {
+                    breaks.push((start, i, true));
+                    start = i + 1;
+                    width = 0.0;
+                    i += 1;
+                }
+                Piece::Space => {
+                    width += fragments[i].width;
+                    i += 1;
+                }
+                Piece::Word => {
+                    let mut j = i;
+                    let mut word = 0.0;
+                    while j < fragments.len() && fragments[j].kind == Piece::Word {
+                        word += fragments[j].width;
+                        j += 1;
+                    }
+                    let has_content = fragments[start..i].iter().any(|f| f.kind == Piece::Word);
+                    if has_content && width + word > area.width() + 0.01 {
+                        breaks.push((start, i, false));
+                        start = i;
+                        width = 0.0;
+                    }
+                    width += word;
+                    i = j;
+                }
+            }
+        }
+        breaks.push((start, fragments.len(), true));
+
+        let mut boxes = Vec::new();
+        for (start, end, last) in breaks {
+            let mut line = &fragments[start..end];
+            while line.first().is_some_and(|f| f.kind == Piece::Space) {
+                line = &line[1..];
+            }
+            while line.last().is_some_and(|f| f.kind == Piece::Space) {
+                line = &line[..line.len() - 1];
+            }
+            let natural: f32 = line.iter().map(|f| f.width).sum();
+            let spaces = line.iter().filter(|f| f.kind == Piece::Space).count();
+            let slack = area.width() - natural;
+            let (mut x, stretch) = match style.align {
+                Align::Center => (area.left + slack / 2.0, 0.0),
+                Align::Right => (area.left + slack, 0.0),
+                Align::Justify if !last && spaces > 0 && slack < natural * 0.25 => (area.left, slack / spaces as f32),
+                _ => (area.left, 0.0),
+            };
+            let ascent = line.iter().map(|f| f.ascent).fold(style.size * 0.76, f32::max);
+            let descent = line.iter().map(|f| f.descent).fold(style.size * 0.24, f32::max);
+            let height = (style.size * style.leading).max(ascent + descent + 2.0);
+            let baseline = (height - ascent - descent) / 2.0 + ascent;
+
+            let mut b = VBox { height, baseline: Some(baseline), ..VBox::default() };
+            let mut link: Option<(Target, f32, f32)> = None;
+            for f in line {
+                let advance = f.width + if f.kind == Piece::Space { stretch } else { 0.0 };
+                b.items.extend(f.items.iter().map(|i| i.clone().shifted(x, baseline)));
+                // Merge a link's fragments into one clickable area
+                match (&mut link, &f.link) {
+                    (Some((target, _, end)), Some(t)) if target == t => *end = x + advance,
+                    (_, t) => {
+                        if let Some((target, from, to)) = link.take() {
+                            b.items.push(Item::Link { x: from, y: baseline - ascent, w: to - from, h: ascent + descent, target });
+                        }
+                        link = t.clone().map(|t| (t, x, x + advance));
+                    }
+                }
+                x += advance;
+            }
+            if let Some((target, from, to)) = link {
+                b.items.push(Item::Link { x: from, y: baseline - ascent, w: to - from, h: ascent + descent, target });
+            }
+            boxes.push(b);
+        }
+        boxes
+    }
+
+    fn display_math(&mut self, math: &Element, number: Option<&str>, area: Area, style: &Style) {
+        let b = mathml::layout(math, self.fonts, style.size * 1.05, style.color, true);
+        let height = b.ascent + b.descent + 4.0;
+        let baseline = b.ascent + 2.0;
+        let x = area.left + ((area.width() - b.width) / 2.0).max(0.0);
+        let mut items: Vec<Item> = b.items.into_iter().map(|i| i.shifted(x, baseline)).collect();
+        if let Some(number) = number {
+            let (number_items, width) = self.fonts.line(style.font, number, style.size, style.color);
+            items.extend(number_items.into_iter().map(|i| i.shifted(area.right - width, baseline)));
+        }
+        self.space(6.0);
+        self.push(VBox { height, items, baseline: Some(baseline), ..VBox::default() });
+        self.space(6.0);
+    }
+
+    fn equation(&mut self, e: &Element, area: Area, style: &Style) {
+        let number = e.elements().find(|c| c.has_class("equation-number")).map(|n| n.text());
+        match find_math(e) {
+            Some(math) => self.display_math(math, number.as_deref(), area, style),
+            None => self.children(&e.children, area, style),
+        }
+    }
+
+    fn list(&mut self, e: &Element, area: Area, style: &Style) {
+        self.space(4.0);
+        let ordered = e.tag == "ol";
+        let first_number: i64 = e.attr("start").and_then(|s| s.parse().ok()).unwrap_or(1);
+        let indent = 18.0;
+        for (n, item) in (first_number..).zip(e.elements().filter(|c| c.tag == "li")) {
+            let start = self.boxes.len();
+            let tight = Style { align: Align::Left, ..style.clone() };
+            self.children(&item.children, area.inset(indent, 0.0), &tight);
+            // Task list items show their checkbox instead of a bullet
+            let task = item.elements().any(|c| c.tag == "input") || item.elements().any(|c| c.elements().any(|g| g.tag == "input"));
+            if let Some(first) = self.boxes[start..].iter_mut().find(|b| b.baseline.is_some()).filter(|_| !task) {
+                let marker = if ordered { format!("{}.", n) } else { "•".to_string() };
+                let (items, width) = self.fonts.line(style.font, &marker, style.size, style.color);
+                let baseline = first.baseline.unwrap_or(style.size);
+                first.items.extend(items.into_iter().map(|i| i.shifted(area.left + indent - width - 5.0, baseline)));
+            }
+            self.space(2.0);
+        }
+        self.space(4.0);
+    }
+
+    fn code(&mut self, e: &Element, area: Area) {
+        let text = e.text();
+        let style = Style { font: FontKey::new(Family::Mono), size: 8.4, color: TEXT, ..Style::body() };
+        let inner = area.inset(8.0, 8.0);
+        let per_line = ((inner.width() / (self.fonts.advance(style.font, 'm') * style.size)).floor() as usize).max(10);
+        let line_height = style.size * 1.38;
+
+        self.space(6.0);
+        self.flush_gap();
+        let start = self.boxes.len();
+        self.push(VBox { height: 5.0, keep_with_next: true, ..VBox::default() });
+        for line in text.trim_end_matches('\n').split('\n') {
+            let chars: Vec<char> = line.replace('\t', "    ").chars().collect();
+            for chunk in chars.chunks(per_line).map(|c| c.iter().collect::<String>()).chain(chars.is_empty().then(String::new)) {
+                let (items, _) = self.fonts.line(style.font, &chunk, style.size, style.color);
+                let baseline = line_height * 0.74;
+                self.push(VBox {
+                    height: line_height,
+                    items: items.into_iter().map(|i| i.shifted(inner.left, baseline)).collect(),
+                    baseline: Some(baseline),
+                    ..VBox::default()
+                });
+            }
+        }
+        self.push(VBox { height: 5.0, ..VBox::default() });
+        self.decorate(start, area, None, Some(CODE_BG));
+        self.space(6.0);
+    }
+
+    /// Lay out a container with a coloured bar down its left side and an optional background
+    fn boxed(&mut self, e: &Element, area: Area, style: &Style, bar: Rgb, background: Option<Rgb>) {
+        self.space(8.0);
+        self.flush_gap();
+        let start = self.boxes.len();
+        self.push(VBox { height: 5.0, keep_with_next: true, ..VBox::default() });
+        for child in &e.children {
+            match child {
+                Node::Element(title) if title.has_class("callout-title") => {
+                    let heading = Style { color: bar, align: Align::Left, ..style.sans(style.size * 0.95, true) };
+                    self.gap = 0.0;
+                    let at = self.boxes.len();
+                    self.children(&title.children, area.inset(12.0, 8.0), &heading);
+                    if let Some(last) = self.boxes[at..].last_mut() {
+                        last.keep_with_next = true;
+                    }
+                    self.space(3.0);
+                }
+                _ => self.children(std::slice::from_ref(child), area.inset(12.0, 8.0), style),
+            }
+        }
+        self.gap = 0.0;
+        self.push(VBox { height: 5.0, ..VBox::default() });
+        self.decorate(start, area, Some(bar), background);
+        self.space(8.0);
+    }
+
+    /// Paint a background and left bar behind every box from `start` on
+    fn decorate(&mut self, start: usize, area: Area, bar: Option<Rgb>, background: Option<Rgb>) {
+        for b in &mut self.boxes[start..] {
+            let mut under = Vec::new();
+            if let Some(color) = background {
+                under.push(Item::Rect { x: area.left, y: 0.0, w: area.width(), h: b.height, color });
+            }
+            if let Some(color) = bar {
+                under.push(Item::Rect { x: area.left, y: 0.0, w: 2.5, h: b.height, color });
+            }
+            b.gap = false;
+            under.append(&mut b.items);
+            b.items = under;
+        }
+    }
+
+    fn note(&mut self, text: &str, area: Area, style: &Style) {
+        let muted = Style { color: MUTED, font: FontKey { italic: true, ..style.font }, ..style.clone() };
+        let node = Node::Text(text.to_string());
+        self.paragraph(&[&node], area, &muted);
+    }
+
+    /// A centred figure, shrunk to fit the column and at most half a page tall
+    fn graphic(&mut self, graphic: Graphic, area: Area) {
+        let (w, h) = graphic.size();
+        if w <= 0.0 || h <= 0.0 {
+            return;
+        }
+        let scale = (area.width() / w).min(BODY_HEIGHT * 0.55 / h).min(1.0);
+        let (w, h) = (w * scale, h * scale);
+        self.graphics.push(graphic);
+        let x = area.left + (area.width() - w) / 2.0;
+        self.space(4.0);
+        self.push(VBox {
+            height: h,
+            items: vec![Item::Graphic { x, y: 0.0, w, h, index: self.graphics.len() - 1 }],
+            ..VBox::default()
+        });
+        self.space(4.0);
+    }
+
+    fn image(&mut self, e: &Element, area: Area, style: &Style) {
+        let src = e.attr("src").unwrap_or_default();
+        let path = src.strip_prefix(get_site_url().as_str()).filter(|_| !get_site_url().is_empty()).unwrap_or(src);
+        let path = match path.strip_prefix("/content/") {
+            Some(rest) => PathBuf::from(content_dir()).join(html::decode_entities(rest)),
+            None => PathBuf::from(path.trim_start_matches('/')),
+        };
+        let loaded = std::fs::read(&path).map_err(|err| err.to_string()).and_then(|data| {
+            match path.extension().and_then(|x| x.to_str()).map(str::to_ascii_lowercase).as_deref() {
+                Some("svg") => {
+                    let tree = self.fonts.parse_svg(&data, path.parent().map(PathBuf::from))?;
+                    Ok(Graphic::Svg(Box::new(tree)))
+                }
+                Some("png") => decode_png(&data),
+                Some("jpg" | "jpeg") => decode_jpeg(data),
+                _ => Err("unsupported image format".to_string()),
+            }
+        });
+        match loaded {
+            Ok(graphic) => self.graphic(graphic, area),
+            Err(err) => self.note(&format!("[{}: {}]", e.attr("alt").filter(|a| !a.is_empty()).unwrap_or(src), err), area, style),
+        }
+    }
+
+    fn table(&mut self, e: &Element, area: Area, style: &Style) {
+        // Rows in order from thead, tbody, tfoot or directly under the table
+        let mut rows: Vec<(&Element, bool)> = Vec::new();
+        let mut caption = None;
+        for child in e.elements() {
+            match child.tag.as_str() {
+                "caption" => caption = Some(child),
+                "tr" => rows.push((child, false)),
+                "thead" | "tbody" | "tfoot" => rows.extend(child.elements().filter(|r| r.tag == "tr").map(|r| (r, child.tag == "thead"))),
+                _ => {}
+            }
+        }
+        if rows.is_empty() {
+            return;
+        }
+        let cell_style = |cell: &Element, header: bool| -> Style {
+            let base = if header || cell.tag == "th" { style.sans(8.8, true) } else { Style { size: 9.4, ..style.clone() } };
+            Style { align: text_align(cell).unwrap_or(Align::Left), leading: 1.3, ..base }
+        };
+
+        // Column widths from each cell's widest word and its width on one line
+        let columns = rows.iter().map(|(r, _)| r.elements().filter(|c| c.tag == "td" || c.tag == "th").count()).max().unwrap_or(0);
+        let pad = 5.0;
+        let mut min = vec![0.0f32; columns];
+        let mut max = vec![0.0f32; columns];
+        for (row, header) in &rows {
+            let mut col = 0;
+            for cell in row.elements().filter(|c| c.tag == "td" || c.tag == "th") {
+                let span: usize = cell.attr("colspan").and_then(|s| s.parse().ok()).unwrap_or(1);
+                if span == 1 && col < columns {
+                    let mut fragments = Vec::new();
+                    for child in &cell.children {
+                        self.inline(child, &cell_style(cell, *header), &mut fragments);
+                    }
+                    let (narrow, wide) = measure(&fragments);
+                    min[col] = min[col].max(narrow + 2.0 * pad);
+                    max[col] = max[col].max(wide + 2.0 * pad);
+                }
+                col += span;
+            }
+        }
+        self.anchors.clear();
+        let (total_min, total_max): (f32, f32) = (min.iter().sum(), max.iter().sum());
+        let widths: Vec<f32> = if total_max <= area.width() {
+            max.clone()
+        } else if total_min < area.width() {
+            let t = (area.width() - total_min) / (total_max - total_min);
+            min.iter().zip(&max).map(|(a, b)| a + (b - a) * t).collect()
+        } else {
+            min.iter().map(|m| m * area.width() / total_min).collect()
+        };
+        let table_width: f32 = widths.iter().sum();
+        let left = area.left + ((area.width() - table_width) / 2.0).max(0.0);
+
+        self.space(8.0);
+        if let Some(caption) = caption {
+            self.block(caption, area, style);
+            if let Some(last) = self.boxes.last_mut() {
+                last.keep_with_next = true;
+            }
+        }
+        let count = rows.len();
+        for (i, (row, header)) in rows.iter().enumerate() {
+            let saved_boxes = std::mem::take(&mut self.boxes);
+            let saved_gap = std::mem::take(&mut self.gap);
+            let saved_break = std::mem::take(&mut self.break_next);
+            let mut cells = Vec::new();
+            let mut x = left;
+            let mut col = 0;
+            for cell in row.elements().filter(|c| c.tag == "td" || c.tag == "th") {
+                let span: usize = cell.attr("colspan").and_then(|s| s.parse().ok()).unwrap_or(1);
+                let w: f32 = widths[col.min(columns)..(col + span).min(columns)].iter().sum();
+                let cell_area = Area { left: x + pad, right: x + w - pad };
+                self.children(&cell.children, cell_area, &cell_style(cell, *header));
+                cells.push(std::mem::take(&mut self.boxes));
+                self.gap = 0.0;
+                x += w;
+                col += span;
+            }
+            self.boxes = saved_boxes;
+            self.gap = saved_gap;
+            self.break_next = saved_break;
+
+            let mut b = VBox { keep_with_next: *header, ..VBox::default() };
+            let mut height: f32 = 0.0;
+            for cell in cells {
+                let mut y = 3.0;
+                for cell_box in cell {
+                    if cell_box.gap && y <= 3.0 {
+                        continue;
+                    }
+                    b.items.extend(cell_box.items.into_iter().map(|i| i.shifted(0.0, y)));
+                    b.anchors.extend(cell_box.anchors);
+                    y += cell_box.height;
+                }
+                height = height.max(y + 3.0);
+            }
+            b.height = height;
+            let right = left + table_width;
+            if i == 0 {
+                b.items.push(Item::Line { x1: left, y1: 0.0, x2: right, y2: 0.0, width: 0.8, color: TEXT });
+            }
+            let (weight, color) = if i + 1 == count || *header { (0.8, TEXT) } else { (0.3, RULE) };
+            b.items.push(Item::Line { x1: left, y1: height, x2: right, y2: height, width: weight, color });
+            self.push(b);
+        }
+        self.space(8.0);
+    }
+
+    /// Title page: vertically placed title block, then a page break
+    fn cover(&mut self, e: &Element, area: Area, style: &Style) {
+        self.push(VBox { height: BODY_HEIGHT * 0.3, ..VBox::default() });
+        for child in e.elements() {
+            let (size, bold, color) = match child.tag.as_str() {
+                "h1" => (28.0, true, TEXT),
+                _ if child.has_class("book-site") => (12.0, false, MUTED),
+                _ if child.has_class("book-date") => (10.0, false, MUTED),
+                _ => (15.0, false, TEXT),
+            };
+            let centered = Style { align: Align::Center, color, leading: 1.3, ..style.sans(size, bold) };
+            self.space(if child.tag == "h1" { 18.0 } else { 8.0 });
+            self.children(&child.children, area, &centered);
+        }
+        self.page_break();
+    }
+
+    /// Contents with dot leaders and the page numbers found by the previous pass
+    fn toc(&mut self, e: &Element, area: Area, style: &Style) {
+        self.page_break();
+        for child in e.elements() {
+            match child.tag.as_str() {
+                "ol" | "ul" => self.toc_list(child, 0, area, style),
+                _ => {
+                    self.block(child, area, style);
+                    self.space(10.0);
+                }
+            }
+        }
+        self.page_break();
+    }
+
+    fn toc_list(&mut self, list: &Element, depth: usize, area: Area, style: &Style) {
+        for item in list.elements().filter(|c| c.tag == "li") {
+            if let Some(link) = item.elements().find(|c| c.tag == "a") {
+                let id = link.attr("href").unwrap_or_default().trim_start_matches('#').to_string();
+                let entry = if depth == 0 { style.sans(10.5, true) } else { Style { size: 10.0, ..style.clone() } };
+                let entry = Style { align: Align::Left, leading: 1.35, link: Some(Target::Anchor(id.clone())), ..entry };
+                let number = self.pages.get(&id).map_or_else(|| "0".to_string(), |p| p.to_string());
+                let (number_items, number_width) = self.fonts.line(entry.font, &number, entry.size, entry.color);
+                let text_area = Area { left: area.left + 16.0 * depth as f32, right: area.right - 36.0 };
+
+                self.space(if depth == 0 { 5.0 } else { 1.0 });
+                let mut fragments = Vec::new();
+                self.words(&link.text(), &entry, &mut fragments);
+                let mut boxes = self.lines(&fragments, text_area, &entry);
+                if let Some(last) = boxes.last_mut() {
+                    let baseline = last.baseline.unwrap_or(entry.size);
+                    let end = last.items.iter().filter_map(|i| match i {
+                        Item::Link { x, w, .. } => Some(x + w),
+                        _ => None,
+                    }).fold(text_area.left, f32::max);
+                    let dot = self.fonts.advance(entry.font, '.') * entry.size;
+                    let step = dot * 2.2;
+                    // Dots on a fixed grid so the leaders of all entries line up
+                    let mut x = (end / step).ceil() * step + step;
+                    while x + step < area.right - number_width - 4.0 {
+                        let (items, _) = self.fonts.line(FontKey::new(Family::Serif), ".", entry.size, MUTED);
+                        last.items.extend(items.into_iter().map(|i| i.shifted(x, baseline)));
+                        x += step;
+                    }
+                    last.items.extend(number_items.into_iter().map(|i| i.shifted(area.right - number_width, baseline)));
+                    last.items.push(Item::Link {
+                        x: text_area.left,
+                        y: 0.0,
+                        w: area.right - text_area.left,
+                        h: last.height,
+                        target: Target::Anchor(id.clone()),
+                    });
+                }
+                for b in boxes {
+                    self.push(b);
+                }
+            }
+            for nested in item.elements().filter(|c| c.tag == "ol" || c.tag == "ul") {
+                self.toc_list(nested, depth + 1, area, style);
+            }
+        }
+    }
+}
+
+/// Narrowest and widest a run of fragments can be set: its longest word and its single-line width
+fn measure(fragments: &[Fragment]) -> (f32, f32) {
+    let (mut narrow, mut wide, mut word, mut line) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for f in fragments {
+        match f.kind {
+            Piece::Word => word += f.width,
+            Piece::Space => {
+                narrow = narrow.max(word);
+                word = 0.0;
+            }
+            Piece::Break => {
+                narrow = narrow.max(word);
+                wide = wide.max(line);
+                word = 0.0;
+                line = 0.0;
+                continue;
+            }
+        }
+        line += f.width;
+    }
+    (narrow.max(word), wide.max(line))
+}
+
+fn callout_color(e: &Element) -> Rgb {
+    let kind = e.attr("class").unwrap_or_default().split_whitespace().find_map(|c| c.strip_prefix("callout-")).unwrap_or("note");
+    match kind {
+        "tip" => [0.09, 0.64, 0.29],
+        "important" => [0.55, 0.36, 0.96],
+        "warning" => [0.85, 0.47, 0.02],
+        "caution" | "danger" => [0.86, 0.15, 0.15],
+        "derivation" | "proof" => [0.39, 0.45, 0.55],
+        "example" => [0.03, 0.57, 0.7],
+        _ => [0.23, 0.51, 0.96],
+    }
+}
+
+/// `color` at `amount` over white
+fn mix(color: Rgb, amount: f32) -> Rgb {
+    color.map(|c| 1.0 - (1.0 - c) * amount)
+}
+
+fn decode_png(data: &[u8]) -> Result<Graphic, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let pixels = &buf[..info.buffer_size()];
+    let (rgb, alpha): (Vec<u8>, Option<Vec<u8>>) = match info.color_type {
+        png::ColorType::Rgb => (pixels.to_vec(), None),
+        png::ColorType::Rgba => (
+            pixels.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            Some(pixels.chunks(4).map(|p| p[3]).collect()),
+        ),
+        png::ColorType::Grayscale => (pixels.iter().flat_map(|&g| [g, g, g]).collect(), None),
+        png::ColorType::GrayscaleAlpha => (
+            pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+            Some(pixels.chunks(2).map(|p| p[1]).collect()),
+        ),
+        png::ColorType::Indexed => return Err("indexed PNG was not expanded".to_string()),
+    };
+    Ok(Graphic::Raster { width: info.width, height: info.height, rgb, alpha })
+}
+
+/// JPEGs are embedded as they are; only the frame header is read for the size
+fn decode_jpeg(data: Vec<u8>) -> Result<Graphic, String> {
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            i += 1;
+            continue;
+        }
+        let marker = data[i + 1];
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        if matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+            let components = data[i + 9];
+            return Ok(Graphic::Jpeg { width, height, components, data });
+        }
+        i += 2 + length;
+    }
+    Err("no JPEG frame header".to_string())
+}
+
+/// Laid-out pages plus where every element id ended up
+struct Layout {
+    pages: Vec<Vec<Item>>,
+    anchors: HashMap<String, (usize, f32)>,
+}
+
+/// Split boxes into pages, moving chains of keep-with-next boxes to the next page together,
+/// then add the running header and page number
+fn paginate(boxes: Vec<VBox>, fonts: &mut Fonts) -> Layout {
+    // Where each page starts
+    let mut starts = vec![0];
+    let (mut i, mut y, mut placed) = (0, 0.0, 0);
+    while i < boxes.len() {
+        let b = &boxes[i];
+        if b.break_before && placed > 0 {
+            starts.push(i);
+            (y, placed) = (0.0, 0);
+        }
+        if placed == 0 && b.gap {
+            i += 1;
+            continue;
+        }
+        if placed > 0 && y + b.height > BODY_HEIGHT {
+            let page_start = *starts.last().unwrap_or(&0);
+            let mut j = i;
+            while j > page_start + 1 && boxes[j - 1].keep_with_next {
+                j -= 1;
+            }
+            // A chain filling the whole page cannot move
+            if j <= page_start + 1 && boxes[page_start].keep_with_next {
+                j = i;
+            }
+            starts.push(j);
+            (i, y, placed) = (j, 0.0, 0);
+            continue;
+        }
+        y += b.height;
+        placed += 1;
+        i += 1;
+    }
+    starts.dedup();
+
+    let mut layout = Layout { pages: Vec::new(), anchors: HashMap::new() };
+    let mut mark: Option<String> = None;
+    let mut boxes = boxes.into_iter().enumerate().peekable();
+    for (n, &start) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).copied().unwrap_or(usize::MAX);
+        let mut items = Vec::new();
+        let mut y = 0.0;
+        let mut page_mark = None;
+        while let Some((_, b)) = boxes.next_if(|(k, _)| *k >= start && *k < end) {
+            if y == 0.0 && b.gap {
+                continue;
+            }
+            for anchor in b.anchors {
+                layout.anchors.entry(anchor).or_insert((layout.pages.len(), MARGIN_TOP + y));
+            }
+            if let Some(m) = b.mark {
+                page_mark.get_or_insert(m.clone());
+                mark = Some(m);
+            }
+            items.extend(b.items.into_iter().map(|i| i.shifted(0.0, MARGIN_TOP + y)));
+            y += b.height;
+        }
+        let page = layout.pages.len();
+        if page > 0 {
+            let footer = Style::body().sans(8.5, false);
+            let (number, width) = fonts.line(footer.font, &(page + 1).to_string(), footer.size, MUTED);
+            items.extend(number.into_iter().map(|i| i.shifted((PAGE_WIDTH - width) / 2.0, PAGE_HEIGHT - MARGIN_BOTTOM / 2.0)));
+            if let Some(title) = page_mark.clone().or_else(|| mark.clone()).filter(|_| page_mark.is_none() || y > 0.0) {
+                let (header, width) = fonts.line(footer.font, &title, 8.0, MUTED);
+                let baseline = MARGIN_TOP / 2.0;
+                items.extend(header.into_iter().map(|i| i.shifted(PAGE_WIDTH - MARGIN_X - width, baseline)));
+                items.push(Item::Line { x1: MARGIN_X, y1: baseline + 5.0, x2: PAGE_WIDTH - MARGIN_X, y2: baseline + 5.0, width: 0.4, color: RULE });
+            }
+        }
+        layout.pages.push(items);
+    }
+    layout
+}
+
+/// An entry of the PDF outline, taken from the document's table of contents
+struct Bookmark {
+    title: String,
+    anchor: String,
+    children: Vec<Bookmark>,
+}
+
+fn bookmarks(list: &Element) -> Vec<Bookmark> {
+    list.elements()
+        .filter(|li| li.tag == "li")
+        .filter_map(|li| {
+            let link = li.elements().find(|c| c.tag == "a")?;
+            Some(Bookmark {
+                title: link.text().trim().to_string(),
+                anchor: link.attr("href").unwrap_or_default().trim_start_matches('#').to_string(),
+                children: li.elements().filter(|c| c.tag == "ol" || c.tag == "ul").flat_map(bookmarks).collect(),
+            })
+        })
+        .collect()
+}
+
+fn find_element<'a>(nodes: &'a [Node], matches: &dyn Fn(&Element) -> bool) -> Option<&'a Element> {
+    nodes.iter().find_map(|n| match n {
+        Node::Element(e) if matches(e) => Some(e),
+        Node::Element(e) => find_element(&e.children, matches),
+        Node::Text(_) => None,
+    })
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
+
+/// Typeset an HTML document into a PDF: A4 pages, embedded system fonts, vector figures,
+/// a contents page with page numbers, clickable cross-references and an outline
+pub fn render(document: &str) -> Result<Vec<u8>, String> {
+    let nodes = html::parse(document);
+    let body = find_element(&nodes, &|e| e.tag == "body").map_or(nodes.as_slice(), |b| b.children.as_slice());
+    let title = find_element(&nodes, &|e| e.tag == "title").map(|t| t.text()).unwrap_or_default();
+    let mut fonts = Fonts::new()?;
+
+    // Lay out until the page numbers quoted in the contents stop changing
+    let mut pages: HashMap<String, usize> = HashMap::new();
+    let mut result = None;
+    for _ in 0..4 {
+        let mut graphics = Vec::new();
+        let mut flow = Flow {
+            fonts: &mut fonts,
+            graphics: &mut graphics,
+            pages: &pages,
+            boxes: Vec::new(),
+            gap: 0.0,
+            break_next: false,
+            anchors: Vec::new(),
+            mark: None,
+        };
+        let area = Area { left: MARGIN_X, right: PAGE_WIDTH - MARGIN_X };
+        flow.children(body, area, &Style::body());
+        let boxes = std::mem::take(&mut flow.boxes);
+        let layout = paginate(boxes, &mut fonts);
+        let found: HashMap<String, usize> = layout.anchors.iter().map(|(id, (page, _))| (id.clone(), page + 1)).collect();
+        let settled = found == pages;
+        pages = found;
+        result = Some((layout, graphics));
+        if settled {
+            break;
+        }
+    }
+    let (layout, graphics) = result.expect("at least one layout pass");
+    let outline = find_element(body, &|e| e.has_class("book-toc"))
+        .and_then(|toc| toc.elements().find(|c| c.tag == "ol" || c.tag == "ul"))
+        .map(bookmarks)
+        .unwrap_or_default();
+    Ok(write(&title, &layout, &graphics, &mut fonts, &outline))
+}
+
+fn write(title: &str, layout: &Layout, graphics: &[Graphic], fonts: &mut Fonts, outline: &[Bookmark]) -> Vec<u8> {
+    let mut pdf = Pdf::new();
+    let next = Cell::new(1);
+    let mut alloc = || {
+        next.set(next.get() + 1);
+        Ref::new(next.get() - 1)
+    };
+    let catalog_id = alloc();
+    let tree_id = alloc();
+    let outline_id = alloc();
+    let info_id = alloc();
+    let page_ids: Vec<Ref> = layout.pages.iter().map(|_| alloc()).collect();
+    let content_ids: Vec<Ref> = layout.pages.iter().map(|_| alloc()).collect();
+
+    // Fonts as CID-keyed TrueType subsets
+    let mut font_refs = Vec::new();
+    for (n, face) in fonts.faces.iter().enumerate() {
+        let (type0, cid, descriptor, file, cmap) = (alloc(), alloc(), alloc(), alloc(), alloc());
+        font_refs.push(type0);
+        let Some(parsed) = face.parse() else { continue };
+        let scale = 1000.0 / face.units_per_em;
+        let tag: String = (0..6).map(|k| (b'A' + ((n / 26usize.pow(k)) % 26) as u8) as char).collect();
+        let base_font = format!("{}+{}", tag, face.post_script_name);
+        let system_info = SystemInfo { registry: Str(b"Adobe"), ordering: Str(b"Identity"), supplement: 0 };
+        let cff = face.data.starts_with(b"OTTO");
+
+        pdf.type0_font(type0)
+            .base_font(Name(base_font.as_bytes()))
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid)
+            .to_unicode(cmap);
+        let mut cid_font = pdf.cid_font(cid);
+        cid_font
+            .subtype(if cff { CidFontType::Type0 } else { CidFontType::Type2 })
+            .base_font(Name(base_font.as_bytes()))
+            .system_info(system_info)
+            .font_descriptor(descriptor)
+            .default_width(0.0);
+        if !cff {
+            cid_font.cid_to_gid_map_predefined(Name(b"Identity"));
+        }
+        let mut widths = cid_font.widths();
+        for &glyph in face.used.keys() {
+            let advance = parsed.glyph_hor_advance(ttf_parser::GlyphId(glyph)).unwrap_or(0) as f32 * scale;
+            widths.consecutive(glyph, [advance]);
+        }
+        widths.finish();
+        cid_font.finish();
+
+        let bbox = parsed.global_bounding_box();
+        let mut flags = FontFlags::NON_SYMBOLIC;
+        flags.set(FontFlags::SERIF, face.key.family == Family::Serif);
+        flags.set(FontFlags::FIXED_PITCH, face.key.family == Family::Mono);
+        flags.set(FontFlags::ITALIC, face.key.italic);
+        let mut desc = pdf.font_descriptor(descriptor);
+        desc.name(Name(base_font.as_bytes()))
+            .flags(flags)
+            .bbox(Rect::new(bbox.x_min as f32 * scale, bbox.y_min as f32 * scale, bbox.x_max as f32 * scale, bbox.y_max as f32 * scale))
+            .italic_angle(parsed.italic_angle().unwrap_or(0.0))
+            .ascent(parsed.ascender() as f32 * scale)
+            .descent(parsed.descender() as f32 * scale)
+            .cap_height(parsed.capital_height().unwrap_or(parsed.ascender()) as f32 * scale)
+            .stem_v(if face.key.bold { 120.0 } else { 80.0 });
+        if cff {
+            desc.font_file3(file);
+        } else {
+            desc.font_file2(file);
+        }
+        desc.finish();
+
+        let glyphs: Vec<u16> = std::iter::once(0).chain(face.used.keys().copied()).collect();
+        let data = if cff {
+            face.data.clone()
+        } else {
+            subsetter::subset(&face.data, face.index, subsetter::Profile::pdf(&glyphs)).unwrap_or_else(|_| face.data.clone())
+        };
+        let compressed = deflate(&data);
+        let mut stream = pdf.stream(file, &compressed);
+        stream.filter(Filter::FlateDecode);
+        if cff {
+            stream.pair(Name(b"Subtype"), Name(b"OpenType"));
+        }
+        stream.finish();
+
+        let mut unicode = UnicodeCmap::new(Name(b"Custom"), system_info);
+        for (&glyph, &c) in &face.used {
+            unicode.pair(glyph, c);
+        }
+        pdf.cmap(cmap, &unicode.finish()).system_info(system_info).name(Name(b"Custom"));
+    }
+
+    // Figures as form XObjects (SVG) or image XObjects
+    let mut graphic_refs = Vec::new();
+    for graphic in graphics {
+        let id = alloc();
+        graphic_refs.push(id);
+        match graphic {
+            Graphic::Svg(tree) => {
+                let after = svg2pdf::convert_tree_into(tree, svg2pdf::Options::default(), &mut pdf, id);
+                // svg2pdf numbered its own objects from `id` on
+                next.set(next.get().max(after.get()));
+            }
+            Graphic::Raster { width, height, rgb, alpha } => {
+                let mask = alpha.as_ref().map(|a| (alloc(), deflate(a)));
+                let data = deflate(rgb);
+                let mut image = pdf.image_xobject(id, &data);
+                image.filter(Filter::FlateDecode);
+                image.width(*width as i32).height(*height as i32).color_space().device_rgb();
+                image.bits_per_component(8);
+                if let Some((mask_id, _)) = &mask {
+                    image.s_mask(*mask_id);
+                }
+                image.finish();
+                if let Some((mask_id, data)) = &mask {
+                    let mut smask = pdf.image_xobject(*mask_id, data);
+                    smask.filter(Filter::FlateDecode);
+                    smask.width(*width as i32).height(*height as i32).color_space().device_gray();
+                    smask.bits_per_component(8);
+                }
+            }
+            Graphic::Jpeg { width, height, components, data } => {
+                let mut image = pdf.image_xobject(id, data);
+                image.filter(Filter::DctDecode);
+                image.width(*width as i32).height(*height as i32);
+                match components {
+                    1 => image.color_space().device_gray(),
+                    4 => image.color_space().device_cmyk(),
+                    _ => image.color_space().device_rgb(),
+                };
+                image.bits_per_component(8);
+            }
+        }
+    }
+
+    let font_names: Vec<String> = (0..font_refs.len()).map(|i| format!("F{}", i)).collect();
+    let graphic_names: Vec<String> = (0..graphic_refs.len()).map(|i| format!("G{}", i)).collect();
+    let destination = |id: &str| layout.anchors.get(id).map(|&(page, y)| (page_ids[page], PAGE_HEIGHT - y + 4.0));
+
+    for (n, items) in layout.pages.iter().enumerate() {
+        let mut content = Content::new();
+        let mut links = Vec::new();
+        for item in items {
+            match item {
+                Item::Text { x, y, face, size, color, glyphs } => {
+                    let bytes: Vec<u8> = glyphs.iter().flat_map(|g| g.to_be_bytes()).collect();
+                    content.set_fill_rgb(color[0], color[1], color[2]);
+                    content.begin_text();
+                    content.set_font(Name(font_names[*face].as_bytes()), *size);
+                    content.next_line(*x, PAGE_HEIGHT - y);
+                    content.show(Str(&bytes));
+                    content.end_text();
+                }
+                Item::Rect { x, y, w, h, color } => {
+                    content.set_fill_rgb(color[0], color[1], color[2]);
+                    content.rect(*x, PAGE_HEIGHT - y - h, *w, *h);
+                    content.fill_nonzero();
+                }
+                Item::Line { x1, y1, x2, y2, width, color } => {
+                    content.set_stroke_rgb(color[0], color[1], color[2]);
+                    content.set_line_width(*width);
+                    content.move_to(*x1, PAGE_HEIGHT - y1);
+                    content.line_to(*x2, PAGE_HEIGHT - y2);
+                    content.stroke();
+                }
+                Item::Graphic { x, y, w, h, index } => {
+                    content.save_state();
+                    content.transform([*w, 0.0, 0.0, *h, *x, PAGE_HEIGHT - y - h]);
+                    content.x_object(Name(graphic_names[*index].as_bytes()));
+                    content.restore_state();
+                }
+                Item::Link { x, y, w, h, target } => links.push((Rect::new(*x, PAGE_HEIGHT - y - h, x + w, PAGE_HEIGHT - y), target)),
+            }
+        }
+        let data = deflate(&content.finish());
+        pdf.stream(content_ids[n], &data).filter(Filter::FlateDecode);
+
+        let mut page = pdf.page(page_ids[n]);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT)).parent(tree_id).contents(content_ids[n]);
+        let mut resources = page.resources();
+        let mut font_dict = resources.fonts();
+        for (name, id) in font_names.iter().zip(&font_refs) {
+            font_dict.pair(Name(name.as_bytes()), *id);
+        }
+        font_dict.finish();
+        let mut x_objects = resources.x_objects();
+        for (name, id) in graphic_names.iter().zip(&graphic_refs) {
+            x_objects.pair(Name(name.as_bytes()), *id);
+        }
+        x_objects.finish();
+        resources.finish();
+
+        let mut annotations = page.annotations();
+        for (rect, target) in links {
+            let dest = match target {
+                Target::Anchor(id) => match destination(id) {
+                    Some(dest) => Some(dest),
+                    None => continue,
+                },
+                Target::Uri(_) => None,
+            };
+            let mut annotation = annotations.push();
+            annotation.subtype(AnnotationType::Link).rect(rect).border(0.0, 0.0, 0.0, None);
+            match (target, dest) {
+                (Target::Uri(uri), _) => {
+                    annotation.action().action_type(ActionType::Uri).uri(Str(uri.as_bytes()));
+                }
+                (_, Some((page_ref, top))) => {
+                    annotation.action().action_type(ActionType::GoTo).destination().page(page_ref).xyz(0.0, top, None);
+                }
+                _ => {}
+            }
+        }
+        annotations.finish();
+    }
+
+    pdf.pages(tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+
+    // Outline mirroring the contents
+    fn write_items(pdf: &mut Pdf, items: &[(Ref, &Bookmark)], parent: Ref, alloc: &mut dyn FnMut() -> Ref, destination: &dyn Fn(&str) -> Option<(Ref, f32)>) -> i32 {
+        let mut total = 0;
+        for (k, (id, bookmark)) in items.iter().enumerate() {
+            let children: Vec<(Ref, &Bookmark)> = bookmark.children.iter().map(|c| (alloc(), c)).collect();
+            let count = write_items(pdf, &children, *id, alloc, destination);
+            let mut item = pdf.outline_item(*id);
+            item.title(TextStr(&bookmark.title)).parent(parent);
+            if k > 0 {
+                item.prev(items[k - 1].0);
+            }
+            if let Some((next, _)) = items.get(k + 1) {
+                item.next(*next);
+            }
+            if let (Some((first, _)), Some((last, _))) = (children.first(), children.last()) {
+                item.first(*first).last(*last).count(-count);
+            }
+            if let Some((page, top)) = destination(&bookmark.anchor) {
+                item.dest().page(page).xyz(0.0, top, None);
+            }
+            total += 1 + count;
+        }
+        total
+    }
+    let top: Vec<(Ref, &Bookmark)> = outline.iter().map(|b| (alloc(), b)).collect();
+    let count = write_items(&mut pdf, &top, outline_id, &mut alloc, &destination);
+    let mut root = pdf.outline(outline_id);
+    if let (Some((first, _)), Some((last, _))) = (top.first(), top.last()) {
+        root.first(*first).last(*last);
+    }
+    root.count(count);
+    root.finish();
+
+    pdf.document_info(info_id).title(TextStr(title)).creator(TextStr(&config::get().site.title));
+    let mut catalog = pdf.catalog(catalog_id);
+    catalog.pages(tree_id);
+    if !outline.is_empty() {
+        catalog.outlines(outline_id);
+    }
+    catalog.finish();
+    pdf.finish()
+}
+
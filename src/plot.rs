@@ -0,0 +1,495 @@
+use std::f64::consts::{PI, TAU};
+use std::fmt::Write;
+
+use base64::Engine;
+
+use crate::chart::{self, Axis, Panel, SERIES_COLORS};
+use crate::expr::{self, Complex, Env, Expr};
+use crate::site::escape_html;
+
+const SAMPLES: usize = 800;
+const GRID: usize = 80;
+const IMAGE_SIZE: u32 = 160;
+
+/// An interval `[lo, hi]` of x, y, t or the complex plane
+type Range = (f64, f64);
+
+/// One thing drawn in a ```plot block
+enum Item {
+    /// `y = f(x)`
+    Function { expr: Expr, range: Option<Range>, name: String },
+    /// `x = f(t), y = g(t)`
+    Parametric { x: Expr, y: Expr, range: Range, name: String },
+    /// `z = f(x, y)`, drawn as contour lines
+    Contour { expr: Expr, name: String },
+    /// `w = f(z)` over the complex plane, drawn by domain colouring
+    Domain { expr: Expr },
+    /// `v = (f(x, y), g(x, y))`
+    Vectors { x: Expr, y: Expr, name: String },
+}
+
+/// A ```plot block:
+///
+/// ```text
+/// y = sin(x)/x, x in [-10, 10]
+/// x = cos(t), y = sin(2t), t in [0, 2pi]
+/// z = x^2 - y^2
+/// w = (z^2 - 1)/(z^2 + 1)
+/// v = (-y, x)
+/// y in [-1.5, 1.5]
+/// caption: The sinc function
+/// ```
+///
+/// A line with only ranges sets the view; `name = value` lines define constants.
+/// Options: `caption:`, `xlabel:`, `ylabel:`, `levels:` (contour count) and `legend: off`.
+struct Plot {
+    items: Vec<Item>,
+    env: Env,
+    x_range: Option<Range>,
+    y_range: Option<Range>,
+    caption: String,
+    xlabel: Option<String>,
+    ylabel: Option<String>,
+    levels: usize,
+    legend: bool,
+}
+
+/// Split on commas that are not inside brackets, so `x in [-1, 1]` stays whole
+fn clauses(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(line[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(line[start..].trim());
+    parts.into_iter().filter(|p| !p.is_empty()).collect()
+}
+
+/// `x in [a, b]` into ("x", (a, b)), or None if the clause is not a range
+fn parse_range<'a>(clause: &'a str, env: &Env) -> Result<Option<(&'a str, Range)>, String> {
+    let Some((var, range)) = clause.split_once(" in ") else {
+        return Ok(None);
+    };
+    let var = var.trim();
+    let inner = range.trim().strip_prefix('[').and_then(|r| r.strip_suffix(']'));
+    let (lo, hi) = inner
+        .and_then(|r| r.split_once(','))
+        .ok_or_else(|| format!("expected a range like `{} in [-1, 1]`", var))?;
+    let (lo, hi) = (expr::eval_real(lo, env)?, expr::eval_real(hi, env)?);
+    if hi <= lo {
+        return Err(format!("empty range for `{}`", var));
+    }
+    Ok(Some((var, (lo, hi))))
+}
+
+fn parse(source: &str) -> Result<Plot, String> {
+    let mut plot = Plot {
+        items: Vec::new(),
+        env: Env::new(),
+        x_range: None,
+        y_range: None,
+        caption: String::new(),
+        xlabel: None,
+        ylabel: None,
+        levels: 10,
+        legend: true,
+    };
+
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        if let Some((key, value)) = line.split_once(':').filter(|(k, _)| k.trim().chars().all(|c| c.is_ascii_alphabetic())) {
+            let value = value.trim();
+            match key.trim() {
+                "caption" => plot.caption = value.to_string(),
+                "xlabel" => plot.xlabel = Some(value.to_string()),
+                "ylabel" => plot.ylabel = Some(value.to_string()),
+                "levels" => plot.levels = value.parse().map_err(|_| format!("`levels` must be a number, got `{}`", value))?,
+                "legend" => plot.legend = !matches!(value, "off" | "no" | "false"),
+                other => return Err(format!("unknown option `{}`", other)),
+            }
+            continue;
+        }
+
+        let mut equations: Vec<(&str, &str)> = Vec::new();
+        let mut ranges: Vec<(&str, Range)> = Vec::new();
+        for clause in clauses(line) {
+            if let Some(range) = parse_range(clause, &plot.env)? {
+                ranges.push(range);
+            } else if let Some((lhs, rhs)) = clause.split_once('=') {
+                equations.push((lhs.trim(), rhs.trim()));
+            } else {
+                return Err(format!("expected `y = …` or `x in [a, b]`, got `{}`", clause));
+            }
+        }
+        let range_of = |var: &str| ranges.iter().find(|(v, _)| *v == var).map(|(_, r)| *r);
+        let equation = |name: &str| equations.iter().find(|(n, _)| *n == name).map(|(_, e)| *e);
+
+        if equations.is_empty() {
+            plot.x_range = range_of("x").or(range_of("re")).or(plot.x_range);
+            plot.y_range = range_of("y").or(range_of("im")).or(plot.y_range);
+            continue;
+        }
+
+        let item = match (equation("x"), equation("y"), equation("z"), equation("w"), equation("v")) {
+            (Some(x), Some(y), _, _, _) => Item::Parametric {
+                x: expr::parse(x)?,
+                y: expr::parse(y)?,
+                range: range_of("t").unwrap_or((0.0, TAU)),
+                name: format!("({}, {})", x, y),
+            },
+            (None, Some(y), _, _, _) => {
+                if let Some(range) = range_of("x") {
+                    plot.x_range.get_or_insert(range);
+                }
+                Item::Function { expr: expr::parse(y)?, range: range_of("x"), name: format!("y = {}", y) }
+            }
+            (_, _, Some(z), _, _) => {
+                plot.x_range = range_of("x").or(plot.x_range);
+                plot.y_range = range_of("y").or(plot.y_range);
+                Item::Contour { expr: expr::parse(z)?, name: format!("z = {}", z) }
+            }
+            (_, _, _, Some(w), _) => {
+                plot.x_range = range_of("re").or(plot.x_range);
+                plot.y_range = range_of("im").or(plot.y_range);
+                Item::Domain { expr: expr::parse(w)? }
+            }
+            (_, _, _, _, Some(v)) => {
+                let inner = v.strip_prefix('(').and_then(|v| v.strip_suffix(')')).unwrap_or(v);
+                let [x, y] = clauses(inner)[..] else {
+                    return Err(format!("a vector field needs two components, like `v = (-y, x)`, got `{}`", v));
+                };
+                plot.x_range = range_of("x").or(plot.x_range);
+                plot.y_range = range_of("y").or(plot.y_range);
+                Item::Vectors { x: expr::parse(x)?, y: expr::parse(y)?, name: format!("v = ({}, {})", x, y) }
+            }
+            _ => {
+                // Anything else is a constant for the lines that follow
+                for (name, value) in &equations {
+                    let value = expr::parse(value)?.eval(&plot.env)?;
+                    plot.env.insert(name.to_string(), value);
+                }
+                continue;
+            }
+        };
+        plot.items.push(item);
+    }
+
+    if plot.items.is_empty() {
+        return Err("nothing to plot: add a line like `y = sin(x)`".to_string());
+    }
+    Ok(plot)
+}
+
+/// A sampled function or parametric curve, kept with the index of its item
+struct Curve<'a> {
+    item: usize,
+    name: &'a str,
+    points: Vec<(f64, f64)>,
+}
+
+/// A y range that ignores the few samples shooting off towards a pole
+fn robust_range(values: &mut [f64]) -> Option<Range> {
+    values.sort_by(f64::total_cmp);
+    if values.is_empty() {
+        return None;
+    }
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    let (lo, hi) = (at(0.01), at(0.99));
+    let pad = if hi > lo { (hi - lo) * 0.08 } else { lo.abs().max(1.0) * 0.5 };
+    Some((lo - pad, hi + pad))
+}
+
+fn linspace((lo, hi): Range, n: usize) -> impl Iterator<Item = f64> {
+    (0..n).map(move |i| lo + (hi - lo) * i as f64 / (n - 1) as f64)
+}
+
+/// Sample `expr` over the grid of the panel, row by row from the bottom
+fn grid_values(expr: &Expr, env: &Env, x: Range, y: Range, n: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut env = env.clone();
+    linspace(y, n)
+        .map(|yv| {
+            env.insert("y".to_string(), Complex::real(yv));
+            linspace(x, n).map(|xv| Ok(expr.eval_at(&mut env, "x", Complex::real(xv))?.as_real())).collect()
+        })
+        .collect()
+}
+
+/// Marching squares: line segments where the sampled surface crosses `level`
+fn contour_segments(values: &[Vec<f64>], level: f64) -> Vec<((f64, f64), (f64, f64))> {
+    let n = values.len();
+    let mut segments = Vec::new();
+    for j in 0..n - 1 {
+        for i in 0..n - 1 {
+            let corners = [
+                (i as f64, j as f64, values[j][i]),
+                (i as f64 + 1.0, j as f64, values[j][i + 1]),
+                (i as f64 + 1.0, j as f64 + 1.0, values[j + 1][i + 1]),
+                (i as f64, j as f64 + 1.0, values[j + 1][i]),
+            ];
+            if corners.iter().any(|c| !c.2.is_finite()) {
+                continue;
+            }
+            let mut crossings = Vec::new();
+            for k in 0..4 {
+                let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                if (a.2 < level) != (b.2 < level) {
+                    let t = (level - a.2) / (b.2 - a.2);
+                    crossings.push((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)));
+                }
+            }
+            // Saddle cells cross four times; pairing neighbours keeps the lines apart
+            for pair in crossings.chunks(2) {
+                if let [a, b] = pair {
+                    segments.push((*a, *b));
+                }
+            }
+        }
+    }
+    segments
+}
+
+/// Hue from the argument, brightness from the modulus: zeros go dark, poles go light
+fn domain_color(w: Complex) -> [u8; 3] {
+    if !w.re.is_finite() || !w.im.is_finite() {
+        return [255, 255, 255];
+    }
+    let hue = (w.arg() / TAU).rem_euclid(1.0) * 6.0;
+    let modulus = w.abs();
+    // Faint bands at every doubling of |w| make the modulus readable
+    let band = modulus.log2().rem_euclid(1.0);
+    let lightness = (2.0 / PI * modulus.atan()) * 0.85 + 0.05 + band * 0.1;
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()).max(0.0);
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r, g, b].map(|c| ((c + m).clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+fn domain_image(expr: &Expr, env: &Env, re: Range, im: Range) -> Result<String, String> {
+    let mut env = env.clone();
+    let mut pixels = Vec::with_capacity((IMAGE_SIZE * IMAGE_SIZE * 3) as usize);
+    for row in 0..IMAGE_SIZE {
+        // Image rows run top to bottom, the imaginary axis bottom to top
+        let y = im.1 - (im.1 - im.0) * (row as f64 + 0.5) / IMAGE_SIZE as f64;
+        for col in 0..IMAGE_SIZE {
+            let x = re.0 + (re.1 - re.0) * (col as f64 + 0.5) / IMAGE_SIZE as f64;
+            pixels.extend(domain_color(expr.eval_at(&mut env, "z", Complex::new(x, y))?));
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, IMAGE_SIZE, IMAGE_SIZE);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| format!("could not encode the domain colouring: {}", e))?;
+    Ok(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png)))
+}
+
+/// Blend from the first series colour to the second, for contour levels
+fn ramp(t: f64) -> String {
+    let parse = |hex: &str| -> [f64; 3] {
+        [1, 3, 5].map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f64)
+    };
+    let (a, b) = (parse(SERIES_COLORS[0]), parse(SERIES_COLORS[1]));
+    let c: Vec<String> = (0..3).map(|i| format!("{:02x}", (a[i] + (b[i] - a[i]) * t).round() as u8)).collect();
+    format!("#{}", c.concat())
+}
+
+pub fn render(source: &str) -> Result<String, String> {
+    let plot = parse(source)?;
+    let surface = plot.items.iter().any(|i| matches!(i, Item::Contour { .. } | Item::Domain { .. } | Item::Vectors { .. }));
+    let x_range = plot.x_range.unwrap_or(if surface { (-2.0, 2.0) } else { (-10.0, 10.0) });
+
+    // Sample curves first so the y range can follow them
+    let mut env = plot.env.clone();
+    let mut curves: Vec<Curve> = Vec::new();
+    for (i, item) in plot.items.iter().enumerate() {
+        match item {
+            Item::Function { expr, range, name } => {
+                let points = linspace(range.unwrap_or(x_range), SAMPLES)
+                    .map(|x| Ok((x, expr.eval_at(&mut env, "x", Complex::real(x))?.as_real())))
+                    .collect::<Result<Vec<_>, String>>()?;
+                curves.push(Curve { item: i, name, points });
+            }
+            Item::Parametric { x, y, range, name } => {
+                let points = linspace(*range, SAMPLES)
+                    .map(|t| {
+                        let px = x.eval_at(&mut env, "t", Complex::real(t))?.as_real();
+                        Ok((px, y.eval(&env)?.as_real()))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                curves.push(Curve { item: i, name, points });
+            }
+            _ => {}
+        }
+    }
+
+    let y_range = match plot.y_range {
+        Some(range) => range,
+        None if surface => (-2.0, 2.0),
+        None => {
+            let mut ys: Vec<f64> = curves.iter().flat_map(|c| c.points.iter().map(|p| p.1)).filter(|y| y.is_finite()).collect();
+            robust_range(&mut ys).ok_or("the plotted functions are undefined everywhere in range")?
+        }
+    };
+    // Parametric curves may leave the x range of the functions; widen it to fit them
+    let x_range = if plot.x_range.is_some() {
+        x_range
+    } else {
+        let is_parametric = |i: usize| matches!(plot.items[i], Item::Parametric { .. });
+        let xs: Vec<f64> = curves
+            .iter()
+            .filter(|c| is_parametric(c.item))
+            .flat_map(|c| c.points.iter().map(|p| p.0))
+            .filter(|x| x.is_finite())
+            .collect();
+        let (lo, hi) = xs.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+        if xs.is_empty() {
+            x_range
+        } else if plot.items.iter().enumerate().all(|(i, _)| is_parametric(i)) {
+            let pad = ((hi - lo) * 0.05).max(1e-9);
+            (lo - pad, hi + pad)
+        } else {
+            (x_range.0.min(lo), x_range.1.max(hi))
+        }
+    };
+
+    let domain = plot.items.iter().any(|i| matches!(i, Item::Domain { .. }));
+    let (xlabel, ylabel) = if domain { ("Re z", "Im z") } else { ("x", "y") };
+    let panel = Panel {
+        x: Axis::linear(x_range.0, x_range.1, plot.xlabel.as_deref().unwrap_or(xlabel)),
+        y: Axis::linear(y_range.0, y_range.1, plot.ylabel.as_deref().unwrap_or(ylabel)),
+        left: 64.0,
+        top: 14.0,
+        width: 556.0,
+        height: 360.0,
+    };
+    let (width, height) = (640.0, panel.top + panel.height + 44.0);
+
+    let mut svg = String::new();
+    // Domain colouring sits underneath the grid
+    for item in &plot.items {
+        if let Item::Domain { expr } = item {
+            let href = domain_image(expr, &plot.env, x_range, y_range)?;
+            let _ = write!(svg, r#"<image x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" preserveAspectRatio="none" href="{}"/>"#, panel.left, panel.top, panel.width, panel.height, href);
+        }
+    }
+    if !domain {
+        panel.frame(&mut svg, true);
+    }
+
+    // The x and y axes themselves, when they're in view
+    if !domain {
+        if panel.contains(0.0, y_range.0) {
+            panel.guide(&mut svg, (0.0, y_range.0), (0.0, y_range.1));
+        }
+        if panel.contains(x_range.0, 0.0) {
+            panel.guide(&mut svg, (x_range.0, 0.0), (x_range.1, 0.0));
+        }
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    for (i, item) in plot.items.iter().enumerate() {
+        match item {
+            Item::Contour { expr, name } => {
+                let values = grid_values(expr, &plot.env, x_range, y_range, GRID)?;
+                let mut finite: Vec<f64> = values.iter().flatten().copied().filter(|v| v.is_finite()).collect();
+                let Some((lo, hi)) = robust_range(&mut finite) else { continue };
+                let to_data = |(gx, gy): (f64, f64)| {
+                    let step = |(a, b): Range| (b - a) / (GRID - 1) as f64;
+                    (x_range.0 + gx * step(x_range), y_range.0 + gy * step(y_range))
+                };
+                for k in 1..=plot.levels {
+                    let t = k as f64 / (plot.levels + 1) as f64;
+                    let level = lo + (hi - lo) * t;
+                    let mut d = String::new();
+                    for (a, b) in contour_segments(&values, level) {
+                        let (a, b) = (to_data(a), to_data(b));
+                        let _ = write!(d, "M{:.1},{:.1}L{:.1},{:.1}", panel.px(a.0), panel.py(a.1), panel.px(b.0), panel.py(b.1));
+                    }
+                    if !d.is_empty() {
+                        let _ = write!(svg, r#"<path class="plot-contour" d="{}" fill="none" stroke="{}" stroke-width="1.5"><title>{} = {}</title></path>"#, d, ramp(t), escape_html(name.split('=').next().unwrap_or("z").trim()), chart::format_number(level));
+                    }
+                }
+                names.push(name.clone());
+            }
+            Item::Vectors { x, y, name } => {
+                let n = 15;
+                let mut env = plot.env.clone();
+                let mut arrows = Vec::new();
+                for gy in linspace(y_range, n + 2).skip(1).take(n) {
+                    env.insert("y".to_string(), Complex::real(gy));
+                    for gx in linspace(x_range, n + 2).skip(1).take(n) {
+                        let vx = x.eval_at(&mut env, "x", Complex::real(gx))?.as_real();
+                        let vy = y.eval(&env)?.as_real();
+                        if vx.is_finite() && vy.is_finite() {
+                            arrows.push((gx, gy, vx, vy));
+                        }
+                    }
+                }
+                let longest = arrows.iter().map(|a| a.2.hypot(a.3)).fold(0.0, f64::max);
+                let cell = panel.width.min(panel.height) / (n + 1) as f64 * 0.9;
+                let color = SERIES_COLORS[i % SERIES_COLORS.len()];
+                for (gx, gy, vx, vy) in arrows {
+                    // Screen-space direction, scaled so the longest arrow fills one cell
+                    let (sx, sy) = (vx * panel.width / (x_range.1 - x_range.0), -vy * panel.height / (y_range.1 - y_range.0));
+                    let length = sx.hypot(sy);
+                    if length == 0.0 || longest == 0.0 {
+                        continue;
+                    }
+                    let scale = cell * (vx.hypot(vy) / longest).sqrt() / length;
+                    let (x0, y0) = (panel.px(gx) - sx * scale / 2.0, panel.py(gy) - sy * scale / 2.0);
+                    let (x1, y1) = (x0 + sx * scale, y0 + sy * scale);
+                    let angle = sy.atan2(sx);
+                    let head = |da: f64| (x1 - 5.0 * (angle + da).cos(), y1 - 5.0 * (angle + da).sin());
+                    let (h1, h2) = (head(0.45), head(-0.45));
+                    let _ = write!(
+                        svg,
+                        r#"<path class="chart-series-{}" d="M{:.1},{:.1}L{:.1},{:.1}M{:.1},{:.1}L{:.1},{:.1}L{:.1},{:.1}" fill="none" stroke="{}" stroke-width="1.3"/>"#,
+                        i % SERIES_COLORS.len(), x0, y0, x1, y1, h1.0, h1.1, x1, y1, h2.0, h2.1, color
+                    );
+                }
+                names.push(name.clone());
+            }
+            Item::Domain { .. } => {}
+            Item::Function { .. } | Item::Parametric { .. } => {
+                if let Some(curve) = curves.iter().find(|c| c.item == i) {
+                    panel.curve(&mut svg, &curve.points, i, false);
+                    names.push(curve.name.to_string());
+                }
+            }
+        }
+    }
+
+    if domain {
+        // Grid and labels drawn over the image so they stay readable
+        panel.frame(&mut svg, true);
+    }
+    if plot.legend {
+        panel.legend(&mut svg, &names);
+    }
+
+    let caption = if plot.caption.is_empty() && names.len() == 1 {
+        names[0].clone()
+    } else {
+        plot.caption.clone()
+    };
+    Ok(chart::figure("plot", width, height, &svg, &caption))
+}
@@ -0,0 +1,66 @@
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+static DETAILS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<details(\s|>)").unwrap());
+static QUIZ_OPTIONS_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)data-answer="(\d+)">\n<ul class="quiz-options">\n(.*?)</ul>"#).unwrap());
+static FOOTNOTE_REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r##"<sup class="footnote-reference"><a href="#([^"]*)">"##).unwrap());
+static FOOTNOTE_DEF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?s)<div class="footnote-definition" id="([^"]*)">.*?</div>\n?"#).unwrap());
+static EXTERNAL_LINK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?s)<a href="(https?://[^"]+)"[^>]*>.*?</a>"#).unwrap());
+
+/// Rendered page HTML made ready for paper: collapsed callouts and quiz answers are opened,
+/// correct quiz options are marked, footnotes are gathered at the end and external links get
+/// numbered references to a list of their URLs. `prefix` keeps footnote ids apart when several
+/// pages share one document.
+pub fn prepare(html: &str, prefix: &str) -> String {
+    let html = DETAILS_RE.replace_all(html, "<details open$1");
+
+    let html = QUIZ_OPTIONS_RE.replace_all(&html, |caps: &Captures| {
+        let answer: usize = caps[1].parse().unwrap_or(0);
+        let options = caps[2].split_inclusive('\n').enumerate().map(|(i, line)| {
+            if i == answer {
+                line.replacen("<li>", "<li class=\"quiz-correct\">", 1)
+            } else {
+                line.to_string()
+            }
+        });
+        format!("data-answer=\"{}\">\n<ul class=\"quiz-options\">\n{}</ul>", &caps[1], options.collect::<String>())
+    });
+
+    let html = FOOTNOTE_REF_RE.replace_all(&html, |caps: &Captures| {
+        format!("<sup class=\"footnote-reference\"><a href=\"#{}fn-{}\">", prefix, &caps[1])
+    });
+    let mut notes = String::new();
+    let html = FOOTNOTE_DEF_RE.replace_all(&html, |caps: &Captures| {
+        notes.push_str(&caps[0].replacen(&format!("id=\"{}\"", &caps[1]), &format!("id=\"{}fn-{}\"", prefix, &caps[1]), 1));
+        String::new()
+    });
+
+    let mut urls: Vec<String> = Vec::new();
+    let html = EXTERNAL_LINK_RE.replace_all(&html, |caps: &Captures| {
+        let url = caps[1].to_string();
+        let n = match urls.iter().position(|u| *u == url) {
+            Some(i) => i + 1,
+            None => {
+                urls.push(url);
+                urls.len()
+            }
+        };
+        format!("{}<sup class=\"print-link-ref\">[{}]</sup>", &caps[0], n)
+    });
+
+    let mut out = html.into_owned();
+    if !notes.is_empty() {
+        out.push_str(&format!("\n<section class=\"print-notes\">\n<h2>Notes</h2>\n{}</section>\n", notes));
+    }
+    if !urls.is_empty() {
+        out.push_str("\n<section class=\"print-links\">\n<h2>Links</h2>\n<ol>\n");
+        // Still escaped from the href they were taken from
+        for url in &urls {
+            out.push_str(&format!("<li>{}</li>\n", url));
+        }
+        out.push_str("</ol>\n</section>\n");
+    }
+    out
+}
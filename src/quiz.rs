@@ -0,0 +1,183 @@
+use crate::render::{render_fragment, stable_id, strip_paragraph};
+use crate::site::{escape_html, SiteIndex};
+
+/// A ```quiz block:
+///
+/// ```text
+/// topic: Slew Rate
+/// Q: What limits the large-signal step response?
+/// - [ ] The unity-gain bandwidth
+/// - [x] The slew rate
+/// A: Explanation shown once the answer is revealed.
+/// ```
+///
+/// `topic:` and the options are optional; without options the answer is just revealed and self-graded.
+#[derive(Default)]
+struct Quiz {
+    topic: String,
+    question: String,
+    options: Vec<(bool, String)>,
+    answer: String,
+}
+
+enum Section {
+    Header,
+    Question,
+    Options,
+    Answer,
+}
+
+fn parse(source: &str) -> Quiz {
+    let mut quiz = Quiz::default();
+    let mut section = Section::Header;
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix("Q:") {
+            section = Section::Question;
+            quiz.question.push_str(rest.trim());
+            quiz.question.push('\n');
+        } else if let Some(rest) = trimmed.strip_prefix("A:") {
+            section = Section::Answer;
+            quiz.answer.push_str(rest.trim());
+            quiz.answer.push('\n');
+        } else if let (false, Some((correct, text))) = (matches!(section, Section::Answer), parse_option(trimmed)) {
+            section = Section::Options;
+            quiz.options.push((correct, text.to_string()));
+        } else {
+            match section {
+                Section::Header => {
+                    if let Some(topic) = trimmed.strip_prefix("topic:") {
+                        quiz.topic = topic.trim().to_string();
+                    }
+                }
+                Section::Question => {
+                    quiz.question.push_str(line);
+                    quiz.question.push('\n');
+                }
+                Section::Options => {
+                    // Continuation of the previous option
+                    if let Some((_, text)) = quiz.options.last_mut() {
+                        text.push(' ');
+                        text.push_str(trimmed);
+                    }
+                }
+                Section::Answer => {
+                    quiz.answer.push_str(line);
+                    quiz.answer.push('\n');
+                }
+            }
+        }
+    }
+
+    quiz
+}
+
+/// `- [x] text` / `- [ ] text` into (is_correct, text)
+fn parse_option(line: &str) -> Option<(bool, &str)> {
+    let rest = line.strip_prefix("- [")?;
+    let (mark, text) = rest.split_once(']')?;
+    match mark {
+        "x" | "X" => Some((true, text.trim())),
+        " " | "" => Some((false, text.trim())),
+        _ => None,
+    }
+}
+
+/// `key` names the block's place on its page, so identical questions keep separate scores
+pub fn render(source: &str, index: &SiteIndex, key: &str) -> Result<String, String> {
+    let quiz = parse(source);
+    if quiz.question.trim().is_empty() {
+        return Err("no question: start it with `Q:`".to_string());
+    }
+    let id = stable_id(&format!("{}\n{}", key, quiz.question));
+    let question = render_fragment(&quiz.question, index);
+    let topic = if quiz.topic.is_empty() {
+        String::new()
+    } else {
+        format!("[{}] ", escape_html(&quiz.topic))
+    };
+
+    let mut html = format!(
+        r#"<div class="example-box quiz" data-quiz-id="{}">
+<div class="question">Concept Check: {}{}</div>
+"#,
+        id, topic, strip_paragraph(&question)
+    );
+
+    if !quiz.options.is_empty() {
+        let answer = quiz
+            .options
+            .iter()
+            .position(|(correct, _)| *correct)
+            .ok_or("no option is marked correct: mark one with `- [x]`")?;
+        html.push_str(&format!("<div class=\"question-detail\" data-answer=\"{}\">\n<ul class=\"quiz-options\">\n", answer));
+        for (i, (_, text)) in quiz.options.iter().enumerate() {
+            html.push_str(&format!(
+                "<li><label><input type=\"radio\" name=\"quiz-{}\" value=\"{}\"> {}</label></li>\n",
+                id, i, strip_paragraph(&render_fragment(text, index))
+            ));
+        }
+        html.push_str("</ul>\n<button class=\"quiz-check\">Check answer</button> <span class=\"quiz-feedback\"></span>\n</div>\n");
+    }
+
+    if !quiz.answer.trim().is_empty() {
+        html.push_str(&format!(
+            "<details class=\"quiz-answer\">\n<summary>Show answer</summary>\n{}</details>\n",
+            render_fragment(&quiz.answer, index)
+        ));
+    }
+
+    if quiz.options.is_empty() {
+        // Revealing the answer shows the grading buttons; with no answer to reveal they show at once
+        let hidden = if quiz.answer.trim().is_empty() { "" } else { " hidden" };
+        html.push_str(&format!(r#"<div class="question-detail quiz-self-grade"{}>
+<span>Did you get it?</span> <button class="quiz-grade" data-correct="true">Yes</button> <button class="quiz-grade" data-correct="false">No</button> <span class="quiz-feedback"></span>
+</div>
+"#, hidden));
+    }
+
+    html.push_str("</div>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiz_id(html: &str) -> &str {
+        let start = html.find("data-quiz-id=\"").unwrap() + "data-quiz-id=\"".len();
+        &html[start..start + 8]
+    }
+
+    #[test]
+    fn identical_questions_get_separate_ids() {
+        let index = SiteIndex::build();
+        let source = "Q: Same question?\nA: Same answer.";
+        let first = render(source, &index, "/page#1").unwrap();
+        let second = render(source, &index, "/page#2").unwrap();
+        let elsewhere = render(source, &index, "/other#1").unwrap();
+        assert_ne!(quiz_id(&first), quiz_id(&second));
+        assert_ne!(quiz_id(&first), quiz_id(&elsewhere));
+    }
+
+    #[test]
+    fn missing_question_or_correct_option_is_an_error() {
+        let index = SiteIndex::build();
+        assert!(render("", &index, "/page#1").is_err());
+        assert!(render("topic: Slew Rate", &index, "/page#1").is_err());
+        assert!(render("Q:\n- [ ] a\n- [ ] b", &index, "/page#1").is_err());
+        assert!(render("Q: Which?\n- [ ] a\n- [ ] b", &index, "/page#1").is_err());
+        assert!(render("Q: Which?\n- [ ] a\n- [x] b", &index, "/page#1").unwrap().contains("data-answer=\"1\""));
+    }
+
+    #[test]
+    fn self_grading_shows_at_once_without_an_answer() {
+        let index = SiteIndex::build();
+        let unanswered = render("Q: Why?\nA:", &index, "/page#1").unwrap();
+        assert!(unanswered.contains("class=\"question-detail quiz-self-grade\">"));
+        assert!(!unanswered.contains("quiz-answer"));
+        let answered = render("Q: Why?\nA: Because.", &index, "/page#1").unwrap();
+        assert!(answered.contains("class=\"question-detail quiz-self-grade\" hidden>"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::graph::encode_query;
+use crate::site::{content_dir, escape_html};
+
+/// Kept with the content it describes so it moves along with it
+const FILE: &str = "redirects.toml";
+
+/// Page paths that moved, old to new, as `"/old/path" = "/new/path"` lines
+pub type Redirects = BTreeMap<String, String>;
+
+pub fn file() -> PathBuf {
+    file_in(Path::new(content_dir()))
+}
+
+/// Where the redirects of the content directory `root` are kept
+pub fn file_in(root: &Path) -> PathBuf {
+    root.join(FILE)
+}
+
+/// Every recorded redirect; none when the file does not exist or cannot be parsed
+pub fn load() -> Redirects {
+    read(Path::new(content_dir())).unwrap_or_default()
+}
+
+/// The redirects recorded under `root`, none when there is no file yet
+pub fn read(root: &Path) -> Result<Redirects, String> {
+    let file = file_in(root);
+    match std::fs::read_to_string(&file) {
+        Ok(text) => toml::from_str(&text).map_err(|e| format!("cannot parse {}: {}", file.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Redirects::new()),
+        Err(e) => Err(format!("cannot read {}: {}", file.display(), e)),
+    }
+}
+
+/// `redirects` with `moves` added. Earlier redirects that pointed at a path that moved now point
+/// at its new place, and ones that would lead back to where they start are dropped.
+pub fn merge(mut redirects: Redirects, moves: &Redirects) -> Redirects {
+    for target in redirects.values_mut() {
+        if let Some(moved) = moves.get(target.as_str()) {
+            *target = moved.clone();
+        }
+    }
+    redirects.extend(moves.iter().map(|(old, new)| (old.clone(), new.clone())));
+    redirects.retain(|old, new| old != new);
+    redirects
+}
+
+/// The file contents recording `redirects`
+pub fn to_text(redirects: &Redirects) -> Result<String, String> {
+    let text = toml::to_string(redirects).map_err(|e| e.to_string())?;
+    Ok(format!("# Old page paths and where they moved, written by `documentation renumber`\n{}", text))
+}
+
+/// Where `path` moved to, when it moved
+pub fn lookup(path: &str) -> Option<String> {
+    load().remove(path)
+}
+
+/// A page sending browsers on to `url` where the host cannot answer with a redirect
+pub fn stub(url: &str) -> String {
+    let url = escape_html(&encode_query(url));
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\" />\n<title>Moved</title>\n<link rel=\"canonical\" href=\"{0}\" />\n<meta http-equiv=\"refresh\" content=\"0; url={0}\" />\n</head>\n<body>\n<p>This page has moved to <a href=\"{0}\">{0}</a>.</p>\n</body>\n</html>\n",
+        url
+    )
+}
@@ -0,0 +1,182 @@
+use std::sync::LazyLock;
+
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd, TextMergeStream};
+use regex::Regex;
+
+use crate::site::SiteIndex;
+use crate::{blocks, callouts, cards, config, environments, units, wikilinks};
+
+// Compiled regexes for image tag processing
+static IMG_TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<img\s+([^>]+)/?>|<figcaption class="numbered">"#).unwrap());
+static SRC_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"src="([^"]+)""#).unwrap());
+static ALT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"alt="([^"]*)""#).unwrap());
+static TITLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"title="([^"]*)""#).unwrap());
+
+/// Markdown extensions enabled for every page, from `[markdown] extensions` in notes.toml
+pub fn markdown_options() -> Options {
+    config::get().markdown_options()
+}
+
+/// Short id derived from content, so saved client state survives edits elsewhere on the page
+pub fn stable_id(s: &str) -> String {
+    // FNV-1a
+    let mut hash: u32 = 0x811c9dc5;
+    for b in s.trim().bytes() {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("{:08x}", hash)
+}
+
+/// Turn heading text into the id used for `#anchor` links
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::new();
+    for c in s.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Give every heading without an explicit id one derived from its text
+pub fn add_heading_ids(mut events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut i = 0;
+    while i < events.len() {
+        if let Event::Start(Tag::Heading { id: None, .. }) = &events[i] {
+            let mut text = String::new();
+            for event in &events[i + 1..] {
+                match event {
+                    Event::End(TagEnd::Heading(_)) => break,
+                    Event::Text(t) | Event::Code(t) | Event::InlineMath(t) => text.push_str(t),
+                    _ => {}
+                }
+            }
+            if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+                *id = Some(slugify(&text).into());
+            }
+        }
+        i += 1;
+    }
+    events
+}
+
+/// Figure and equation counters, which an exported chapter carries from one page to the next
+#[derive(Clone, Debug, Default)]
+pub struct Numbering {
+    /// Prefix for every number, e.g. "8" for "Fig. 8.3"; empty to number per page
+    pub chapter: String,
+    pub figures: usize,
+    /// Display equations numbered so far, or None to leave them unnumbered as the site does
+    pub equations: Option<usize>,
+}
+
+impl Numbering {
+    fn label(&self, n: usize) -> String {
+        if self.chapter.is_empty() {
+            n.to_string()
+        } else {
+            format!("{}.{}", self.chapter, n)
+        }
+    }
+}
+
+/// Render a whole page: markdown, math, wiki links and fenced blocks, then figure numbering
+pub fn render_markdown(source: &str, index: &SiteIndex, page_path: &str) -> String {
+    render_numbered(source, index, page_path, &mut Numbering::default())
+}
+
+/// [`render_markdown`] continuing the figure (and equation) numbers in `numbering`
+pub fn render_numbered(source: &str, index: &SiteIndex, page_path: &str, numbering: &mut Numbering) -> String {
+    let source = environments::preprocess(source, index, page_path);
+    let html = render_events(&source, index, page_path, Some(&mut *numbering));
+    number_figures(&html, numbering)
+}
+
+/// Render markdown nested inside another block, without any page-level post-processing
+pub fn render_fragment(source: &str, index: &SiteIndex) -> String {
+    render_events(source, index, "", None)
+}
+
+fn render_events(source: &str, index: &SiteIndex, page_path: &str, mut numbering: Option<&mut Numbering>) -> String {
+    let events = add_heading_ids(TextMergeStream::new(Parser::new_ext(source, markdown_options())).collect());
+    let events = wikilinks::resolve(events, index);
+    let events = cards::render(events);
+    let events = callouts::render(events);
+    let events = units::render(events);
+    let events = blocks::render(events, index, page_path);
+
+    // Transform events to handle math
+    let parser = events.into_iter().map(|event| {
+        match event {
+            Event::InlineMath(cow) => {
+                // Try render with defaults, which is displayMode: false usually? 
+                // Actually katex-rs 'render' might be display mode. 
+                // Let's assume 'render' works and returns HTML.
+                // We'll trust the error message about 'render_inline' and just use 'render' for now.
+                let html = katex::render(&cow).unwrap_or_else(|_| cow.to_string());
+                Event::Html(html.into())
+            }
+            Event::DisplayMath(cow) => match numbering.as_deref_mut() {
+                Some(numbering) if numbering.equations.is_some() => {
+                    let n = numbering.equations.unwrap_or_default() + 1;
+                    numbering.equations = Some(n);
+                    let label = numbering.label(n);
+                    let opts = katex::Opts::builder().display_mode(true).build().unwrap();
+                    let html = katex::render_with_opts(&cow, &opts).unwrap_or_else(|_| cow.to_string());
+                    Event::InlineHtml(format!(
+                        r#"<span class="equation" id="eq-{}">{}<span class="equation-number">({})</span></span>"#,
+                        label.replace('.', "-"),
+                        html,
+                        label
+                    ).into())
+                }
+                _ => {
+                    let html = katex::render(&cow).unwrap_or_else(|_| cow.to_string());
+                    Event::Html(html.into())
+                }
+            },
+            _ => event,
+        }
+    });
+
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, parser);
+    html_output
+}
+
+/// Strip the paragraph wrapper pulldown-cmark puts around single-line content
+pub fn strip_paragraph(html: &str) -> &str {
+    let trimmed = html.trim();
+    match trimmed.strip_prefix("<p>").and_then(|s| s.strip_suffix("</p>")) {
+        Some(inner) if !inner.contains("<p>") => inner,
+        _ => trimmed,
+    }
+}
+
+/// Wrap captioned images in numbered figures, and number the figures generated by blocks
+fn number_figures(html_string: &str, numbering: &mut Numbering) -> String {
+    IMG_TAG_RE.replace_all(html_string, |caps: &regex::Captures| {
+        let Some(attrs) = caps.get(1).map(|m| m.as_str()) else {
+            numbering.figures += 1;
+            return format!(r#"<figcaption><strong>Fig. {}:</strong> "#, numbering.label(numbering.figures));
+        };
+        let src = SRC_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
+        let alt = ALT_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
+        let title = TITLE_RE.captures(attrs).map(|c| c[1].to_string()).unwrap_or_default();
+        let style = if !title.is_empty() { format!("width: {};", title) } else { String::new() };
+        let img_html = format!(r#"<img src="{}" alt="{}" style="{}">"#, src, alt, style);
+
+        if !alt.is_empty() {
+            numbering.figures += 1;
+            format!(
+                r#"<figure class="image-container">\n{}\n<figcaption><strong>Fig. {}:</strong> {}</figcaption>\n</figure>"#,
+                img_html, numbering.label(numbering.figures), alt
+            )
+        } else {
+            img_html
+        }
+    }).to_string()
+}
@@ -0,0 +1,495 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, LinkType, Parser, Tag};
+use walkdir::WalkDir;
+
+use crate::parse_numbered_name;
+use crate::redirects::{self, Redirects};
+use crate::render::markdown_options;
+use crate::site::{content_dir, percent_decode, resolve_link};
+
+/// What to do with the numbered entries of a directory
+pub enum Operation {
+    /// Number them consecutively, closing gaps
+    Renumber,
+    /// Put the entry with this name at this number, shifting the others
+    Move(String, i32),
+    /// Leave this number free for a new entry, shifting the ones from it on
+    Insert(i32),
+}
+
+/// A numbered page or chapter directory, or both when a chapter has a page of the same name
+struct Entry {
+    number: i32,
+    name: String,
+    /// "3) Limitations" and "3) Limitations.md"
+    files: Vec<String>,
+}
+
+/// What `run` changed
+#[derive(Debug)]
+pub struct Summary {
+    /// Old and new names of each renamed entry
+    pub renamed: Vec<(String, String)>,
+    pub links: usize,
+    pub files: usize,
+    pub redirects: usize,
+    /// Links that point at a moved page but could not be rewritten
+    pub warnings: Vec<String>,
+}
+
+fn entries(dir: &Path) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for file in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let file_name = file.file_name().to_string_lossy().to_string();
+        let stem = file_name.strip_suffix(".md").unwrap_or(&file_name);
+        let (number, name) = parse_numbered_name(stem);
+        if number == i32::MAX {
+            continue;
+        }
+        match entries.iter_mut().find(|e| e.number == number && e.name == name) {
+            Some(entry) => entry.files.push(file_name),
+            None => entries.push(Entry { number, name, files: vec![file_name] }),
+        }
+    }
+    entries.sort_by(|a, b| (a.number, &a.name).cmp(&(b.number, &b.name)));
+    entries
+}
+
+/// The new number of each entry, in the order of `entries`
+fn plan(entries: &[Entry], operation: &Operation) -> Result<Vec<i32>, String> {
+    // Chapters start at 0 where they already do, at 1 otherwise
+    let start = entries.first().map_or(1, |e| e.number.min(1));
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    let mut gap = None;
+    match operation {
+        Operation::Renumber => {}
+        Operation::Move(name, position) => {
+            let wanted = name.trim();
+            let Some(from) = entries.iter().position(|e| {
+                e.name.eq_ignore_ascii_case(wanted) || format!("{}) {}", e.number, e.name).eq_ignore_ascii_case(wanted)
+            }) else {
+                return Err(format!("no numbered page or chapter named {:?}", name));
+            };
+            if *position < start || *position >= start + entries.len() as i32 {
+                return Err(format!("position must be between {} and {}", start, start + entries.len() as i32 - 1));
+            }
+            order.remove(from);
+            order.insert((position - start) as usize, from);
+        }
+        Operation::Insert(position) => {
+            if *position < start || *position > start + entries.len() as i32 {
+                return Err(format!("position must be between {} and {}", start, start + entries.len() as i32));
+            }
+            gap = Some(*position);
+        }
+    }
+
+    let mut numbers = vec![0; entries.len()];
+    for (i, &entry) in order.iter().enumerate() {
+        let number = start + i as i32;
+        numbers[entry] = if gap.is_some_and(|gap| number >= gap) { number + 1 } else { number };
+    }
+    Ok(numbers)
+}
+
+/// Where `path` ends up after `moves`, when it is a moved entry or lies below one
+fn moved(path: &str, moves: &[(String, String)]) -> Option<String> {
+    moves.iter().find_map(|(old, new)| {
+        if path == old {
+            Some(new.clone())
+        } else {
+            path.strip_prefix(old.as_str()).filter(|rest| rest.starts_with('/')).map(|rest| format!("{}{}", new, rest))
+        }
+    })
+}
+
+/// `dest` rewritten so that from `page` it reaches `target`: each segment naming a moved entry
+/// is tried in turn with its new name, keeping the link's own spelling otherwise
+fn rewrite_dest(dest: &str, page: &str, target: &str, names: &BTreeMap<String, String>) -> Option<String> {
+    let split = dest.find(['#', '?']).unwrap_or(dest.len());
+    let (link, suffix) = dest.split_at(split);
+    let segments: Vec<&str> = link.split('/').collect();
+    for (i, segment) in segments.iter().enumerate() {
+        let encoded = segment.contains('%');
+        let decoded = percent_decode(segment);
+        let (stem, extension) = match decoded.strip_suffix(".md") {
+            Some(stem) => (stem.to_string(), ".md"),
+            None => (decoded.clone(), ""),
+        };
+        let Some(new_stem) = names.get(&stem) else { continue };
+        let new_segment = format!("{}{}", new_stem, extension);
+        // Encode what an inline link cannot hold bare, as the original did
+        let new_segment =
+            if encoded { new_segment.replace(' ', "%20").replace('(', "%28").replace(')', "%29") } else { new_segment };
+        let mut candidate = segments.clone();
+        candidate[i] = &new_segment;
+        let candidate = format!("{}{}", candidate.join("/"), suffix);
+        if resolve_link(page, &candidate).as_deref() == Some(target) {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+/// The byte range of `dest` inside `source[span]`, searching from the end since the destination
+/// follows the link text
+fn dest_range(source: &str, span: Range<usize>, dest: &str) -> Option<Range<usize>> {
+    let start = span.start + source.get(span)?.rfind(dest)?;
+    Some(start..start + dest.len())
+}
+
+/// Rewrite every internal link in `source`, the page at `old_page` that is moving to `new_page`,
+/// whose target moved. Returns the new source and how many links changed.
+fn rewrite_links(
+    source: &str,
+    old_page: &str,
+    new_page: &str,
+    moves: &[(String, String)],
+    names: &BTreeMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> (String, usize) {
+    let mut links: Vec<(Range<usize>, String)> = Vec::new();
+    let mut parser = Parser::new_ext(source, markdown_options()).into_offset_iter();
+    for (event, span) in parser.by_ref() {
+        if let Event::Start(Tag::Link { link_type: LinkType::Inline | LinkType::Autolink, dest_url, .. }) = event {
+            links.push((span, dest_url.to_string()));
+        }
+    }
+    // Reference-style links keep their destination in the definition
+    for (_, definition) in parser.reference_definitions().iter() {
+        links.push((definition.span.clone(), definition.dest.to_string()));
+    }
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for (span, dest) in links {
+        let Some(old_target) = resolve_link(old_page, &dest) else { continue };
+        let new_target = moved(&old_target, moves).unwrap_or(old_target);
+        if resolve_link(new_page, &dest).as_deref() == Some(new_target.as_str()) {
+            continue;
+        }
+        let rewritten = rewrite_dest(&dest, new_page, &new_target, names);
+        match (rewritten, dest_range(source, span, &dest)) {
+            (Some(rewritten), Some(range)) => edits.push((range, rewritten)),
+            _ => warnings.push(format!("{}: could not rewrite the link to {}", old_page, dest)),
+        }
+    }
+
+    let count = edits.len();
+    let mut out = source.to_string();
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    for (range, replacement) in edits {
+        out.replace_range(range, &replacement);
+    }
+    (out, count)
+}
+
+/// Every page under the content directory `root`, as URL path and file, the way the site index
+/// finds them
+fn pages(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut pages = Vec::new();
+    for entry in WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter().flatten() {
+        let file = entry.path();
+        if file.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Ok(relative) = file.strip_prefix(root) else { continue };
+        if relative.components().any(|c| c.as_os_str() == "images") {
+            continue;
+        }
+        pages.push((format!("/{}", relative.with_extension("").to_string_lossy()), file.to_path_buf()));
+    }
+    pages
+}
+
+/// A step already taken, and how to take it back
+enum Undo {
+    /// Rename the first path back to the second
+    Rename(PathBuf, PathBuf),
+    /// Put back a file's old contents, or remove it when it did not exist
+    Restore(PathBuf, Option<String>),
+}
+
+/// Take back `undo` newest first, returning what could not be
+fn rollback(undo: Vec<Undo>) -> Vec<String> {
+    let mut failed = Vec::new();
+    for step in undo.into_iter().rev() {
+        let (file, result) = match step {
+            Undo::Rename(from, to) => {
+                let result = std::fs::rename(&from, &to);
+                (from, result)
+            }
+            Undo::Restore(file, Some(text)) => {
+                let result = std::fs::write(&file, text);
+                (file, result)
+            }
+            Undo::Restore(file, None) => {
+                let result = std::fs::remove_file(&file);
+                (file, result)
+            }
+        };
+        if let Err(e) = result {
+            failed.push(format!("{}: {}", file.display(), e));
+        }
+    }
+    failed
+}
+
+fn writable(file: &Path) -> Result<(), String> {
+    match std::fs::metadata(file) {
+        Ok(metadata) if metadata.permissions().readonly() => Err(format!("{} is read-only", file.display())),
+        _ => Ok(()),
+    }
+}
+
+/// Apply `operation` to the numbered entries of `dir`, a directory under the content directory
+/// such as "analog/8) Op-Amp Design": rename them, rewrite links to them across every page and
+/// record redirects from their old URLs
+pub fn run(dir: &str, operation: &Operation) -> Result<Summary, String> {
+    let dir = dir.trim_matches('/');
+    run_in(Path::new(content_dir()), dir.strip_prefix(content_dir()).unwrap_or(dir), operation)
+}
+
+/// [`run`] over the content directory `root`. Everything is worked out and checked before the
+/// first file changes, and the changes made so far are taken back if one of them fails.
+fn run_in(root: &Path, dir: &str, operation: &Operation) -> Result<Summary, String> {
+    let dir = dir.trim_matches('/');
+    let path = root.join(dir);
+    if !path.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+
+    let entries = entries(&path);
+    let numbers = plan(&entries, operation)?;
+    let url_dir = if dir.is_empty() { String::new() } else { format!("/{}", dir) };
+
+    // Old and new URL path of each renamed entry, and its old and new name for link rewriting
+    let mut moves: Vec<(String, String)> = Vec::new();
+    let mut names: BTreeMap<String, String> = BTreeMap::new();
+    let mut renames: Vec<(String, String)> = Vec::new();
+    let mut renamed = Vec::new();
+    for (entry, &number) in entries.iter().zip(&numbers) {
+        if number == entry.number {
+            continue;
+        }
+        let old_stem = format!("{}) {}", entry.number, entry.name);
+        let new_stem = format!("{}) {}", number, entry.name);
+        for file in &entry.files {
+            let stem = file.strip_suffix(".md").unwrap_or(file);
+            let new_file = format!("{}{}", new_stem, &file[stem.len()..]);
+            renames.push((file.clone(), new_file));
+            names.insert(stem.to_string(), new_stem.clone());
+        }
+        moves.push((format!("{}/{}", url_dir, old_stem), format!("{}/{}", url_dir, new_stem)));
+        renamed.push((old_stem, new_stem));
+    }
+
+    let mut summary = Summary { renamed, links: 0, files: 0, redirects: 0, warnings: Vec::new() };
+    if moves.is_empty() {
+        return Ok(summary);
+    }
+
+    // Rewritten pages, at the file each one ends up in, with their old contents
+    let new_file = |file: &Path| -> PathBuf {
+        let Ok(relative) = file.strip_prefix(&path) else { return file.to_path_buf() };
+        let mut components = relative.components();
+        let first = components.next().map(|c| c.as_os_str().to_string_lossy().to_string()).unwrap_or_default();
+        let rest = components.as_path();
+        match renames.iter().find(|(old, _)| *old == first) {
+            Some((_, new)) if rest.as_os_str().is_empty() => path.join(new),
+            Some((_, new)) => path.join(new).join(rest),
+            None => file.to_path_buf(),
+        }
+    };
+    let mut moved_redirects = Redirects::new();
+    for (old, new) in &moves {
+        moved_redirects.insert(old.clone(), new.clone());
+    }
+    let mut rewrites: Vec<(PathBuf, String, String)> = Vec::new();
+    for (page, file) in pages(root) {
+        let new_page = moved(&page, &moves);
+        if let Some(new_page) = &new_page {
+            moved_redirects.insert(page.clone(), new_page.clone());
+        }
+        let source = std::fs::read_to_string(&file).map_err(|e| format!("could not read {}: {}", file.display(), e))?;
+        let new_page = new_page.unwrap_or_else(|| page.clone());
+        let (rewritten, count) = rewrite_links(&source, &page, &new_page, &moves, &names, &mut summary.warnings);
+        if count > 0 {
+            writable(&file)?;
+            rewrites.push((new_file(&file), source, rewritten));
+            summary.links += count;
+            summary.files += 1;
+        }
+    }
+    let redirects_file = redirects::file_in(root);
+    let redirects_text = redirects::to_text(&redirects::merge(redirects::read(root)?, &moved_redirects))?;
+    writable(&redirects_file)?;
+    summary.redirects = moved_redirects.len();
+
+    // Through temporary names, so "2)" can become "3)" while "3)" becomes "4)"
+    let temporary = |i: usize| path.join(format!(".renumber-{}", i));
+    for (i, (old, new)) in renames.iter().enumerate() {
+        if temporary(i).exists() {
+            return Err(format!("{} is in the way, left over from an earlier run", temporary(i).display()));
+        }
+        if let Some((other, _)) = renames.iter().find(|(other, target)| target == new && other != old) {
+            return Err(format!("{} and {} would both become {}", old, other, new));
+        }
+        if path.join(new).exists() && !renames.iter().any(|(other, _)| other == new) {
+            return Err(format!("cannot rename {} to {}: {} already exists", old, new, path.join(new).display()));
+        }
+    }
+    writable(&path)?;
+
+    let mut undo = Vec::new();
+    let result = (|| -> Result<(), String> {
+        for (i, (old, _)) in renames.iter().enumerate() {
+            std::fs::rename(path.join(old), temporary(i)).map_err(|e| format!("could not rename {}: {}", old, e))?;
+            undo.push(Undo::Rename(temporary(i), path.join(old)));
+        }
+        for (i, (_, new)) in renames.iter().enumerate() {
+            let target = path.join(new);
+            std::fs::rename(temporary(i), &target).map_err(|e| format!("could not rename to {}: {}", target.display(), e))?;
+            undo.push(Undo::Rename(target, temporary(i)));
+        }
+        for (file, source, rewritten) in &rewrites {
+            std::fs::write(file, rewritten).map_err(|e| format!("could not write {}: {}", file.display(), e))?;
+            undo.push(Undo::Restore(file.clone(), Some(source.clone())));
+        }
+        let previous = std::fs::read_to_string(&redirects_file).ok();
+        std::fs::write(&redirects_file, &redirects_text)
+            .map_err(|e| format!("could not write {}: {}", redirects_file.display(), e))?;
+        undo.push(Undo::Restore(redirects_file.clone(), previous));
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let failed = rollback(undo);
+        if failed.is_empty() {
+            return Err(format!("{}; nothing was changed", e));
+        }
+        return Err(format!("{}; could not undo every change: {}", e, failed.join(", ")));
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh content directory holding `files`
+    fn scratch(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("renumber-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (file, text) in files {
+            let file = root.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, text).unwrap();
+        }
+        root
+    }
+
+    /// Every file under `root` with its contents
+    fn snapshot(root: &Path) -> BTreeMap<PathBuf, String> {
+        WalkDir::new(root)
+            .into_iter()
+            .flatten()
+            .filter(|e| e.file_type().is_file())
+            .map(|e| (e.path().strip_prefix(root).unwrap().to_path_buf(), std::fs::read_to_string(e.path()).unwrap()))
+            .collect()
+    }
+
+    fn read(root: &Path, file: &str) -> String {
+        std::fs::read_to_string(root.join(file)).unwrap()
+    }
+
+    const FILES: &[(&str, &str)] = &[
+        ("index.md", "See [the sub page](/a/2%29%20Two/1%29%20Sub) and [two](</a/2) Two>).\n"),
+        ("a/1) One.md", "Next: [two](<2) Two.md#top>), [ref][r].\n\n[r]: </a/2) Two/1) Sub>\n"),
+        ("a/2) Two.md", "Back to [one](<1) One.md>).\n"),
+        ("a/2) Two/1) Sub.md", "Up: [two](</a/2) Two>).\n"),
+    ];
+
+    #[test]
+    fn insert_renames_rewrites_links_and_records_redirects() {
+        let root = scratch("insert", FILES);
+        let summary = run_in(&root, "a", &Operation::Insert(2)).unwrap();
+        assert_eq!(summary.renamed, vec![("2) Two".to_string(), "3) Two".to_string())]);
+        assert!(summary.warnings.is_empty(), "{:?}", summary.warnings);
+        assert!(root.join("a/3) Two.md").is_file() && root.join("a/3) Two/1) Sub.md").is_file());
+        assert!(!root.join("a/2) Two").exists());
+
+        assert_eq!(read(&root, "index.md"), "See [the sub page](/a/3%29%20Two/1%29%20Sub) and [two](</a/3) Two>).\n");
+        assert_eq!(read(&root, "a/1) One.md"), "Next: [two](<3) Two.md#top>), [ref][r].\n\n[r]: </a/3) Two/1) Sub>\n");
+        assert_eq!(read(&root, "a/3) Two.md"), "Back to [one](<1) One.md>).\n");
+        assert_eq!(read(&root, "a/3) Two/1) Sub.md"), "Up: [two](</a/3) Two>).\n");
+        assert_eq!((summary.links, summary.files), (5, 3));
+
+        let recorded = redirects::read(&root).unwrap();
+        assert_eq!(recorded.get("/a/2) Two").map(String::as_str), Some("/a/3) Two"));
+        assert_eq!(recorded.get("/a/2) Two/1) Sub").map(String::as_str), Some("/a/3) Two/1) Sub"));
+        assert_eq!(summary.redirects, recorded.len());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn moving_back_chains_and_drops_redirects() {
+        let root = scratch("chain", FILES);
+        run_in(&root, "a", &Operation::Insert(2)).unwrap();
+        run_in(&root, "a", &Operation::Renumber).unwrap();
+        assert!(root.join("a/2) Two.md").is_file());
+        let recorded = redirects::read(&root).unwrap();
+        assert_eq!(recorded.get("/a/3) Two").map(String::as_str), Some("/a/2) Two"));
+        assert!(!recorded.contains_key("/a/2) Two"), "{:?}", recorded);
+        assert_eq!(read(&root, "index.md"), FILES[0].1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn move_swaps_entries() {
+        let root = scratch("move", FILES);
+        let summary = run_in(&root, "a", &Operation::Move("Two".to_string(), 1)).unwrap();
+        assert_eq!(summary.renamed.len(), 2);
+        assert!(root.join("a/1) Two.md").is_file() && root.join("a/2) One.md").is_file());
+        assert_eq!(read(&root, "a/1) Two.md"), "Back to [one](<2) One.md>).\n");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn errors_leave_everything_unchanged() {
+        let root = scratch("errors", FILES);
+        let before = snapshot(&root);
+        assert!(run_in(&root, "a", &Operation::Move("Three".to_string(), 1)).is_err());
+        assert!(run_in(&root, "a", &Operation::Insert(9)).is_err());
+        assert!(run_in(&root, "missing", &Operation::Renumber).is_err());
+        assert_eq!(snapshot(&root), before);
+
+        // A leftover temporary name is in the way of the second rename
+        std::fs::write(root.join("a/.renumber-0"), "").unwrap();
+        let before = snapshot(&root);
+        let error = run_in(&root, "a", &Operation::Insert(1)).unwrap_err();
+        assert!(error.contains("left over"), "{}", error);
+        assert_eq!(snapshot(&root), before);
+        std::fs::remove_file(root.join("a/.renumber-0")).unwrap();
+
+        std::fs::write(root.join("redirects.toml"), "not = [valid").unwrap();
+        let before = snapshot(&root);
+        let error = run_in(&root, "a", &Operation::Insert(1)).unwrap_err();
+        assert!(error.contains("cannot parse"), "{}", error);
+        assert_eq!(snapshot(&root), before);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn names_that_would_collide_are_refused() {
+        // "02) Two" and "2) Two" are the same entry, and cannot both be renamed to "3) Two"
+        let root = scratch("collision", &[("b/1) One.md", ""), ("b/2) Two.md", ""), ("b/02) Two.md", "")]);
+        let before = snapshot(&root);
+        let error = run_in(&root, "b", &Operation::Insert(1)).unwrap_err();
+        assert!(error.contains("would both become 3) Two.md"), "{}", error);
+        assert_eq!(snapshot(&root), before);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use crate::parse_numbered_name;
+use crate::site::content_dir;
+
+/// What a new page starts with
+const TEMPLATE: &str = "---\ntags: []\ndescription:\n---\n\n";
+
+/// The next free number among the pages and chapters in `dir`: one past the highest numeric
+/// prefix, or 1 when none is numbered yet
+fn next_number(dir: &Path) -> i32 {
+    let highest = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| parse_numbered_name(&e.file_name().to_string_lossy()).0)
+        .filter(|&n| n != i32::MAX)
+        .max();
+    highest.map_or(1, |n| n + 1)
+}
+
+/// Create the page `path`, e.g. "analog/8) Op-Amp Design/Compensation", under the content
+/// directory. Pages inside a category are numbered after their highest sibling unless they
+/// already have a number; missing chapter directories are created as named.
+pub fn new_page(path: &str) -> Result<PathBuf, String> {
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".md").unwrap_or(path);
+    let (parent, name) = match path.rsplit_once('/') {
+        Some((parent, name)) => (Path::new(content_dir()).join(parent), name.trim()),
+        None => (PathBuf::from(content_dir()), path.trim()),
+    };
+    if name.is_empty() {
+        return Err("the page needs a name".to_string());
+    }
+    if path.split('/').any(|c| c == ".." || c == ".") {
+        return Err("the path must stay inside the content directory".to_string());
+    }
+    if name == "images" || path.split('/').next() == Some("images") {
+        return Err("images/ holds category icons, not pages".to_string());
+    }
+
+    // Categories at the top are not numbered, only what goes inside them
+    let file_name = if parse_numbered_name(name).0 != i32::MAX || !path.contains('/') {
+        format!("{}.md", name)
+    } else {
+        format!("{}) {}.md", next_number(&parent), name)
+    };
+    let taken = std::fs::read_dir(&parent).into_iter().flatten().flatten().find(|e| {
+        let stem = Path::new(&e.file_name()).with_extension("").to_string_lossy().to_string();
+        parse_numbered_name(&stem).1.eq_ignore_ascii_case(parse_numbered_name(name).1.as_str())
+    });
+    if let Some(existing) = taken {
+        return Err(format!("{} already exists", existing.path().display()));
+    }
+    let file = parent.join(file_name);
+    if file.exists() {
+        return Err(format!("{} already exists", file.display()));
+    }
+    std::fs::create_dir_all(&parent).map_err(|e| format!("could not create {}: {}", parent.display(), e))?;
+    std::fs::write(&file, TEMPLATE).map_err(|e| format!("could not write {}: {}", file.display(), e))?;
+    Ok(file)
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::SecondsFormat;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use regex::Regex;
+
+use crate::graph::encode_query;
+use crate::render::markdown_options;
+use crate::site::{escape_html, SiteIndex};
+use crate::{build_link, config, feed, format_title};
+
+static TEXT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\(?:text|mathrm|operatorname)\{([^}]*)\}").unwrap());
+static COMMAND_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\([A-Za-z]+|.)").unwrap());
+
+/// Longest meta description, about what search results show
+const DESCRIPTION_LENGTH: usize = 160;
+
+/// The start of the first paragraph of a page, as plain text cut at a word boundary
+pub fn first_paragraph(source: &str) -> String {
+    let mut text = String::new();
+    let mut in_paragraph = false;
+    for event in Parser::new_ext(source, markdown_options()) {
+        match event {
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(TagEnd::Paragraph) => {
+                in_paragraph = false;
+                // Directive lines such as `:::theorem` and callout markers are markup, not prose
+                let trimmed = text.trim_start();
+                if !trimmed.is_empty() && !trimmed.starts_with(":::") && !trimmed.starts_with("[!") {
+                    break;
+                }
+                text.clear();
+            }
+            Event::Text(t) | Event::Code(t) if in_paragraph => text.push_str(&t),
+            Event::InlineMath(t) if in_paragraph => text.push_str(&plain_math(&t)),
+            Event::SoftBreak | Event::HardBreak if in_paragraph => text.push(' '),
+            _ => {}
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= DESCRIPTION_LENGTH {
+        return text;
+    }
+    let cut: String = text.chars().take(DESCRIPTION_LENGTH - 1).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end_matches([',', ';', ':']))
+}
+
+/// Inline TeX as readable text for places that cannot typeset it: `g_m = 2\ \text{mS}` into "g_m = 2 mS"
+fn plain_math(tex: &str) -> String {
+    const SYMBOLS: &[(&str, &str)] = &[
+        ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("Delta", "Δ"), ("epsilon", "ε"), ("theta", "θ"),
+        ("lambda", "λ"), ("mu", "μ"), ("pi", "π"), ("sigma", "σ"), ("tau", "τ"), ("phi", "φ"), ("omega", "ω"),
+        ("Omega", "Ω"), ("approx", "≈"), ("le", "≤"), ("leq", "≤"), ("ge", "≥"), ("geq", "≥"), ("neq", "≠"),
+        ("times", "×"), ("cdot", "·"), ("pm", "±"), ("infty", "∞"), ("to", "→"),
+    ];
+    let tex = TEXT_RE.replace_all(tex, "$1");
+    let tex = COMMAND_RE.replace_all(&tex, |caps: &regex::Captures| {
+        let name = &caps[1];
+        match SYMBOLS.iter().find(|(command, _)| *command == name) {
+            Some((_, symbol)) => symbol.to_string(),
+            // Spacing commands such as `\ ` and `\,`
+            None if !name.chars().all(char::is_alphabetic) => " ".to_string(),
+            None => String::new(),
+        }
+    });
+    tex.replace(['{', '}'], "")
+}
+
+/// What goes in a page's `<head>` for browsers, search engines and link previews
+pub struct PageMeta {
+    /// "Slew Rate · Op-Amp Design · Sawy's Notes"
+    pub title: String,
+    /// Title of the page alone, for link previews
+    pub name: String,
+    pub description: String,
+    pub canonical: String,
+    /// OpenGraph type: "website" for the home page, "article" for notes
+    pub kind: &'static str,
+    /// Absolute URL of the page's preview image, for pages that have one
+    pub image: Option<String>,
+}
+
+impl PageMeta {
+    pub fn new(segments: &[&str], page_title: &str, description: &str, origin: &str) -> PageMeta {
+        let is_index = segments.is_empty() || segments == ["index"];
+        let title = if is_index {
+            config::get().site.title.clone()
+        } else if segments.len() > 1 {
+            // The chapter below the category, or the category itself for pages directly in it
+            let section = if segments.len() > 2 { segments[1] } else { segments[0] };
+            format!("{} · {} · {}", page_title, format_title(section), config::get().site.title)
+        } else {
+            format!("{} · {}", page_title, config::get().site.title)
+        };
+        let path = if is_index { "/".to_string() } else { format!("/{}", segments.join("/")) };
+        PageMeta {
+            title,
+            name: if is_index { config::get().site.title.clone() } else { page_title.to_string() },
+            description: description.to_string(),
+            canonical: format!("{}{}", origin, encode_query(&build_link(&path))),
+            kind: if is_index { "website" } else { "article" },
+            image: None,
+        }
+    }
+}
+
+/// Every page of the site index with the date it last changed
+pub fn sitemap(index: &SiteIndex, origin: &str) -> String {
+    let updated: HashMap<String, String> =
+        feed::entries().into_iter().map(|e| (e.path, e.updated.to_rfc3339_opts(SecondsFormat::Secs, true))).collect();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for page in &index.pages {
+        let path = if page.path == "/index" { "/" } else { page.path.as_str() };
+        xml.push_str(&format!("<url>\n<loc>{}{}</loc>\n", origin, escape_html(&encode_query(&build_link(path)))));
+        if let Some(lastmod) = updated.get(&page.path) {
+            xml.push_str(&format!("<lastmod>{}</lastmod>\n", lastmod));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+pub fn robots(origin: &str) -> String {
+    format!("User-agent: *\nAllow: /\n\nSitemap: {}{}\n", origin, build_link("/sitemap.xml"))
+}
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use walkdir::WalkDir;
+
+use crate::cards::{self, Card};
+use crate::environments::{self, Environment};
+use crate::frontmatter;
+use crate::render::markdown_options;
+use crate::{build_link, config, format_title, seo, SidebarItem};
+
+/// The index set by `SiteIndex::refresh`, so a server scans the content once per change instead
+/// of once per request
+static CURRENT: RwLock<Option<Arc<SiteIndex>>> = RwLock::new(None);
+
+/// Root directory holding every markdown page, `site.content_dir` in notes.toml
+pub fn content_dir() -> &'static str {
+    &config::get().site.content_dir
+}
+
+/// The markdown file behind a page path such as "/analog/6) Noise/1) Intro" or "/index"
+pub fn page_file(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}.md", content_dir(), path))
+}
+
+/// The source of the page at `path`, None when there is no such page
+pub fn read_page(path: &str) -> Option<String> {
+    std::fs::read_to_string(page_file(path)).ok()
+}
+
+/// A single markdown page known to the site
+#[derive(Clone, Debug)]
+pub struct Page {
+    /// URL path without the site_url prefix, e.g. "/analog/6) Noise/2) Types of Noise"
+    pub path: String,
+    pub file: PathBuf,
+    pub title: String,
+    /// Title of the top-level directory, or of the page itself for top-level pages; empty for the index
+    pub category: String,
+    pub tags: Vec<String>,
+    pub links: Vec<OutgoingLink>,
+    pub cards: Vec<Card>,
+    /// Theorem-like environments in document order, numbered once the whole site is known
+    pub environments: Vec<Environment>,
+    /// Frontmatter `numbering:` setting for those environments
+    pub numbering: String,
+    /// Frontmatter `description:`, or the start of the first paragraph
+    pub description: String,
+}
+
+/// An internal link found in a page, with the sentence it appears in
+#[derive(Clone, Debug)]
+pub struct OutgoingLink {
+    pub target: String,
+    pub context: String,
+}
+
+/// Where a link found in the source points, before `[[wiki links]]` are matched to pages
+enum RawTarget {
+    Path(String),
+    Title(String),
+}
+
+struct RawLink {
+    target: RawTarget,
+    context: String,
+}
+
+/// Result of looking up a `[[wiki link]]` by page title
+pub enum WikiTarget<'a> {
+    Found(&'a Page),
+    Ambiguous(Vec<&'a Page>),
+    Missing,
+}
+
+/// A page linking to the current one, as shown in the "Linked from" section
+#[derive(Clone, Debug)]
+pub struct Backlink {
+    pub title: String,
+    pub path: String,
+    pub context: String,
+}
+
+/// Every page under the content directory plus the link graph between them
+pub struct SiteIndex {
+    pub pages: Vec<Page>,
+    /// Unresolved or ambiguous `[[wiki links]]`, one message per occurrence
+    pub problems: Vec<String>,
+    backlinks: BTreeMap<String, Vec<Backlink>>,
+}
+
+impl SiteIndex {
+    /// The index from the last `refresh`, or a fresh build when nothing has refreshed it
+    pub fn current() -> Arc<SiteIndex> {
+        if let Some(index) = CURRENT.read().unwrap().as_ref() {
+            return index.clone();
+        }
+        Arc::new(SiteIndex::build())
+    }
+
+    /// Build the index again and make it the one `current` returns
+    pub fn refresh() -> Arc<SiteIndex> {
+        let index = Arc::new(SiteIndex::build());
+        *CURRENT.write().unwrap() = Some(index.clone());
+        index
+    }
+
+    /// Scan the content directory and build the link graph
+    pub fn build() -> Self {
+        let mut pages = Vec::new();
+        let mut raw_links = Vec::new();
+
+        for entry in WalkDir::new(content_dir()).min_depth(1).sort_by_file_name() {
+            let Ok(entry) = entry else { continue };
+            let file = entry.path();
+
+            if file.extension().and_then(|e| e.to_str()) != Some("md") {
+                continue;
+            }
+
+            let Ok(relative_path) = file.strip_prefix(content_dir()) else { continue };
+            let components: Vec<_> = relative_path.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+
+            if components.iter().any(|c| c == "images") {
+                continue;
+            }
+
+            let Ok(source) = std::fs::read_to_string(file) else { continue };
+            let path = format!("/{}", relative_path.with_extension("").to_string_lossy());
+            let file_stem = file.file_stem().unwrap().to_string_lossy().to_string();
+            let category = match components.len() {
+                1 if file_stem == "index" => String::new(),
+                1 => format_title(&file_stem),
+                _ => format_title(&components[0]),
+            };
+            raw_links.push(extract_links(&path, &source));
+            let frontmatter = frontmatter::parse(&source);
+
+            pages.push(Page {
+                path,
+                file: file.to_path_buf(),
+                title: format_title(&file_stem),
+                category,
+                tags: frontmatter.tags,
+                links: Vec::new(),
+                cards: cards::extract(&source),
+                environments: environments::extract(&source),
+                numbering: frontmatter.numbering,
+                description: if frontmatter.description.is_empty() {
+                    seo::first_paragraph(&source)
+                } else {
+                    frontmatter.description
+                },
+            });
+        }
+
+        environments::number(&mut pages);
+
+        // Wiki links can only be matched once every page title is known
+        let mut problems = Vec::new();
+        for (i, raw) in raw_links.into_iter().enumerate() {
+            let mut links = Vec::new();
+            for link in raw {
+                let target = match link.target {
+                    RawTarget::Path(path) => path,
+                    RawTarget::Title(title) => match match_title(&pages, &title).as_slice() {
+                        [page] => page.path.clone(),
+                        [] => {
+                            problems.push(format!("{}: unresolved link [[{}]]", pages[i].path, title));
+                            continue;
+                        }
+                        candidates => {
+                            let paths: Vec<_> = candidates.iter().map(|p| p.path.as_str()).collect();
+                            problems.push(format!("{}: ambiguous link [[{}]] matches {}", pages[i].path, title, paths.join(", ")));
+                            candidates[0].path.clone()
+                        }
+                    },
+                };
+                links.push(OutgoingLink { target, context: link.context });
+            }
+            pages[i].links = links;
+        }
+
+        let mut backlinks: BTreeMap<String, Vec<Backlink>> = BTreeMap::new();
+        for page in &pages {
+            let mut seen = HashSet::new();
+            for link in &page.links {
+                // Self links and repeated links to the same page add nothing
+                if link.target == page.path || !seen.insert(&link.target) {
+                    continue;
+                }
+                backlinks.entry(link.target.clone()).or_default().push(Backlink {
+                    title: page.title.clone(),
+                    path: build_link(&page.path),
+                    context: link.context.clone(),
+                });
+            }
+        }
+
+        SiteIndex { pages, problems, backlinks }
+    }
+
+    /// Match a `[[wiki link]]` target (without any `#heading` part) against page titles
+    pub fn resolve_title(&self, title: &str) -> WikiTarget<'_> {
+        let mut candidates = match_title(&self.pages, title);
+        match candidates.len() {
+            0 => WikiTarget::Missing,
+            1 => WikiTarget::Found(candidates.remove(0)),
+            _ => WikiTarget::Ambiguous(candidates),
+        }
+    }
+
+    /// Pages that link to `path`
+    pub fn backlinks(&self, path: &str) -> &[Backlink] {
+        self.backlinks.get(path).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Pages nobody links to and that the sidebar does not list either
+    pub fn orphans(&self, sidebar: &[SidebarItem]) -> Vec<&Page> {
+        fn collect(items: &[SidebarItem], out: &mut HashSet<String>) {
+            for item in items {
+                out.insert(item.path.clone());
+                collect(&item.children, out);
+            }
+        }
+
+        let mut listed = HashSet::new();
+        collect(sidebar, &mut listed);
+
+        self.pages
+            .iter()
+            .filter(|p| p.path != "/index")
+            .filter(|p| !listed.contains(&build_link(&p.path)))
+            .filter(|p| self.backlinks(&p.path).is_empty())
+            .collect()
+    }
+}
+
+/// Render the "Linked from" section appended to the bottom of a page
+pub fn render_backlinks(backlinks: &[Backlink]) -> String {
+    if backlinks.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<section class=\"backlinks\">\n<h2>Linked from</h2>\n<ul>\n");
+    for link in backlinks {
+        html.push_str(&format!(r#"<li><a href="{}">{}</a>"#, link.path, escape_html(&link.title)));
+        if !link.context.is_empty() {
+            html.push_str(&format!(r#"<p class="backlink-context">{}</p>"#, escape_html(&link.context)));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n</section>");
+    html
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Pages whose title matches `title`, case-insensitively.
+///
+/// A target such as "Limitations/Slew Rate" also has to match the titles of the
+/// parent directories, which is how ambiguous titles get disambiguated.
+fn match_title<'a>(pages: &'a [Page], title: &str) -> Vec<&'a Page> {
+    let wanted: Vec<String> = title
+        .split('/')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return Vec::new();
+    }
+
+    pages
+        .iter()
+        .filter(|page| {
+            let segments: Vec<String> = page.path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| format_title(s).to_lowercase())
+                .collect();
+            segments.len() >= wanted.len() && segments[segments.len() - wanted.len()..] == wanted[..]
+        })
+        .collect()
+}
+
+/// Collect every internal link in `source`, along with the sentence surrounding it
+fn extract_links(page_path: &str, source: &str) -> Vec<RawLink> {
+    // One text buffer per open block; links remember which buffer they belong to
+    // and the byte range they cover so the sentence can be cut out once the block closes.
+    struct Pending {
+        target: RawTarget,
+        start: usize,
+        end: usize,
+    }
+
+    let mut blocks: Vec<(String, Vec<Pending>)> = Vec::new();
+    let mut open_links: Vec<Option<(RawTarget, usize)>> = Vec::new();
+    let mut links = Vec::new();
+
+    for event in Parser::new_ext(source, markdown_options()) {
+        match event {
+            Event::Start(Tag::Paragraph | Tag::Heading { .. } | Tag::Item | Tag::TableCell) => {
+                blocks.push((String::new(), Vec::new()));
+            }
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::TableCell) => {
+                let Some((text, pending)) = blocks.pop() else { continue };
+                for p in pending {
+                    links.push(RawLink {
+                        target: p.target,
+                        context: surrounding_sentence(&text, p.start, p.end),
+                    });
+                }
+            }
+            Event::Start(Tag::Link { link_type, dest_url, .. }) => {
+                let start = blocks.last().map(|b| b.0.len()).unwrap_or(0);
+                let target = if matches!(link_type, LinkType::WikiLink { .. }) {
+                    // "[[#Heading]]" points back at the same page
+                    let title = dest_url.split('#').next().unwrap_or_default().trim();
+                    (!title.is_empty()).then(|| RawTarget::Title(title.to_string()))
+                } else {
+                    resolve_link(page_path, &dest_url).map(RawTarget::Path)
+                };
+                open_links.push(target.map(|t| (t, start)));
+            }
+            Event::End(TagEnd::Link) => {
+                let Some(Some((target, start))) = open_links.pop() else { continue };
+                match blocks.last_mut() {
+                    Some((text, pending)) => pending.push(Pending { target, start, end: text.len() }),
+                    None => links.push(RawLink { target, context: String::new() }),
+                }
+            }
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) => {
+                if let Some((text, _)) = blocks.last_mut() {
+                    text.push_str(&t);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((text, _)) = blocks.last_mut() {
+                    text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    links
+}
+
+/// Cut the sentence containing `text[start..end]` out of a block of text
+fn surrounding_sentence(text: &str, start: usize, end: usize) -> String {
+    let is_boundary = |c: char| matches!(c, '.' | '?' | '!' | ':');
+
+    let begin = text[..start]
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| is_boundary(c) && text[i + c.len_utf8()..].starts_with(char::is_whitespace))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+
+    let finish = text[end..]
+        .char_indices()
+        .find(|&(i, c)| {
+            let after = end + i + c.len_utf8();
+            is_boundary(c) && (after == text.len() || text[after..].starts_with(char::is_whitespace))
+        })
+        .map(|(i, c)| end + i + c.len_utf8())
+        .unwrap_or(text.len());
+
+    text[begin..finish].trim().to_string()
+}
+
+/// Turn a link destination into a page path (without site_url), if it points at a page
+pub fn resolve_link(page_path: &str, dest: &str) -> Option<String> {
+    if dest.is_empty() || dest.starts_with('#') || dest.contains("://") || dest.starts_with("mailto:") {
+        return None;
+    }
+
+    let dest = dest.split(['#', '?']).next().unwrap_or_default();
+    let dest = percent_decode(dest);
+    let site_url = crate::get_site_url();
+    let dest = match dest.strip_prefix(site_url.as_str()) {
+        Some(rest) if !site_url.is_empty() && rest.starts_with('/') => rest.to_string(),
+        _ => dest,
+    };
+
+    let joined = if dest.starts_with('/') {
+        dest
+    } else {
+        // Relative links resolve against the directory of the linking page
+        let parent = Path::new(page_path).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        format!("{}/{}", parent.trim_end_matches('/'), dest)
+    };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => { parts.pop(); }
+            _ => parts.push(part),
+        }
+    }
+
+    if matches!(parts.first(), Some(&("assets" | "content" | "dist" | "style"))) {
+        return None;
+    }
+
+    let path = format!("/{}", parts.join("/"));
+    let path = path.strip_suffix(".md").map(str::to_string).unwrap_or(path);
+    Some(if path == "/" { "/index".to_string() } else { path })
+}
+
+/// "%20" and friends in an href back into the characters of a page path
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
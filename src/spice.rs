@@ -0,0 +1,734 @@
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::bode::{self, Response};
+use crate::expr::{self, Complex, Env};
+use crate::site::escape_html;
+use crate::units::{self, format_si};
+
+static PARAM_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\w+)\s*=\s*(\{[^}]*\}|\S+)").unwrap());
+static PROBE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b([vi])\(([^()]*)\)").unwrap());
+
+/// Points per decade when there is no `.ac` line
+const DEFAULT_POINTS: usize = 60;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Resistor,
+    Capacitor,
+    Inductor,
+    /// `G`: current `value · (V(c+) − V(c−))` from n+ to n− through the source
+    Vccs,
+    /// `E`: `V(n+) − V(n−) = value · (V(c+) − V(c−))`
+    Vcvs,
+    Voltage,
+    Current,
+}
+
+struct Element {
+    name: String,
+    kind: Kind,
+    nodes: [usize; 2],
+    control: [usize; 2],
+    /// Resistance, capacitance, inductance, gain or DC value
+    value: f64,
+    ac: Complex,
+    /// Row of the branch current for elements that need one (V, E, L)
+    branch: Option<usize>,
+}
+
+/// A quantity to report: `V(out)`, `V(a, b)` or `I(R1)`
+enum Probe {
+    Voltage(usize, usize),
+    Current(usize),
+}
+
+/// A ```spice-ac or ```spice-op block: a linear netlist in SPICE syntax.
+///
+/// ```text
+/// * Common-source stage
+/// .param gm = 1m
+/// V1 in 0 DC 0.6 AC 1
+/// G1 out 0 in 0 {gm}
+/// R1 out 0 20k
+/// C1 out 0 1p
+/// .ac dec 50 1k 10g
+/// .plot V(out)/V(in)
+/// ```
+///
+/// Elements are R, C, L, G (VCCS), E (VCVS), V and I. Values take SPICE suffixes
+/// (`1k`, `2.2meg`, `10p`) or `{expressions}` of `.param` names. `.plot` (or `.print`)
+/// chooses what is shown; `.title` or `caption:` sets the caption.
+struct Netlist {
+    elements: Vec<Element>,
+    nodes: Vec<String>,
+    branches: usize,
+    params: Env,
+    sweep: Option<(usize, f64, f64)>,
+    probes: Vec<String>,
+    caption: String,
+}
+
+impl Netlist {
+    fn node(&mut self, name: &str) -> usize {
+        let name = name.to_lowercase();
+        if name == "0" || name == "gnd" {
+            return 0;
+        }
+        match self.nodes.iter().position(|n| *n == name) {
+            Some(i) => i,
+            None => {
+                self.nodes.push(name);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.nodes.len() - 1 + self.branches
+    }
+}
+
+/// `DC 1.2 AC 1 90`, `AC 1` or a bare DC value after a source's nodes, in `unit` (V or A)
+fn parse_source(tokens: &[&str], params: &Env, unit: &str) -> Result<(f64, Complex), String> {
+    let (mut dc, mut ac) = (0.0, Complex::default());
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = |j: usize| -> Result<f64, String> {
+            let token = tokens.get(j).ok_or_else(|| format!("missing value after `{}`", tokens[i]))?;
+            units::parse_spice_value(token, params, Some(unit))
+        };
+        match tokens[i].to_lowercase().as_str() {
+            "dc" => {
+                dc = value(i + 1)?;
+                i += 2;
+            }
+            "ac" => {
+                let magnitude = value(i + 1)?;
+                let phase = tokens.get(i + 2).and_then(|t| units::parse_spice_value(t, params, Some("°")).ok());
+                ac = Complex::from_polar(magnitude, phase.unwrap_or(0.0).to_radians());
+                i += if phase.is_some() { 3 } else { 2 };
+            }
+            _ => {
+                dc = value(i)?;
+                i += 1;
+            }
+        }
+    }
+    Ok((dc, ac))
+}
+
+/// Split on whitespace outside parentheses, so `.plot V(out, in) I(R1)` gives two probes
+fn words(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, None);
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if let Some(s) = start.take() {
+                parts.push(&line[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        parts.push(&line[s..]);
+    }
+    parts
+}
+
+fn parse(source: &str) -> Result<Netlist, String> {
+    let mut netlist = Netlist {
+        elements: Vec::new(),
+        nodes: vec!["0".to_string()],
+        branches: 0,
+        params: Env::new(),
+        sweep: None,
+        probes: Vec::new(),
+        caption: String::new(),
+    };
+
+    for line in source.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('*') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(caption) = line.strip_prefix("caption:") {
+            netlist.caption = caption.trim().to_string();
+            continue;
+        }
+
+        if let Some(directive) = line.strip_prefix('.') {
+            let (name, rest) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            match name.to_lowercase().as_str() {
+                "param" | "params" => {
+                    for caps in PARAM_RE.captures_iter(rest) {
+                        let value = units::parse_spice_value(&caps[2], &netlist.params, None)?;
+                        netlist.params.insert(caps[1].to_string(), Complex::real(value));
+                    }
+                }
+                "ac" => {
+                    let tokens: Vec<&str> = rest.split_whitespace().collect();
+                    let [kind, points, lo, hi] = tokens[..] else {
+                        return Err(format!("expected `.ac dec <points> <fstart> <fstop>`, got `{}`", line));
+                    };
+                    let points: usize = points.parse().map_err(|_| format!("bad point count `{}`", points))?;
+                    let hz = |v: &str| units::parse_spice_value(v, &netlist.params, Some("Hz"));
+                    let per_decade = match kind.to_lowercase().as_str() {
+                        "dec" => points,
+                        "oct" => (points as f64 / 2f64.log10()).round() as usize,
+                        other => return Err(format!("unsupported sweep `{}`: use dec or oct", other)),
+                    };
+                    netlist.sweep = Some((per_decade.max(1), hz(lo)?, hz(hi)?));
+                }
+                "plot" | "print" | "probe" => netlist.probes.extend(words(rest).into_iter().map(str::to_string)),
+                "title" => netlist.caption = rest.trim().to_string(),
+                "op" | "end" => {}
+                other => return Err(format!("unsupported directive `.{}`", other)),
+            }
+            continue;
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let name = tokens[0].to_string();
+        let kind = match name.chars().next().map(|c| c.to_ascii_uppercase()) {
+            Some('R') => Kind::Resistor,
+            Some('C') => Kind::Capacitor,
+            Some('L') => Kind::Inductor,
+            Some('G') => Kind::Vccs,
+            Some('E') => Kind::Vcvs,
+            Some('V') => Kind::Voltage,
+            Some('I') => Kind::Current,
+            _ => return Err(format!("unknown element `{}`: use R, C, L, G, E, V or I", name)),
+        };
+        let controlled = matches!(kind, Kind::Vccs | Kind::Vcvs);
+        let needed = if controlled { 6 } else if matches!(kind, Kind::Voltage | Kind::Current) { 3 } else { 4 };
+        if tokens.len() < needed {
+            return Err(format!("too few fields for `{}`", line));
+        }
+
+        let nodes = [netlist.node(tokens[1]), netlist.node(tokens[2])];
+        let control = if controlled { [netlist.node(tokens[3]), netlist.node(tokens[4])] } else { [0, 0] };
+        // The unit each kind of value should be in, so `R1 a b 10pF` is caught
+        let unit = match kind {
+            Kind::Resistor => "Ω",
+            Kind::Capacitor => "F",
+            Kind::Inductor => "H",
+            Kind::Vccs => "S",
+            Kind::Vcvs => "V/V",
+            Kind::Voltage => "V",
+            Kind::Current => "A",
+        };
+        let (value, ac) = match kind {
+            Kind::Voltage | Kind::Current => parse_source(&tokens[3..], &netlist.params, unit)?,
+            _ => (units::parse_spice_value(tokens[needed - 1], &netlist.params, Some(unit))?, Complex::default()),
+        };
+        if value == 0.0 && kind == Kind::Resistor {
+            return Err(format!("`{}` has zero resistance", name));
+        }
+        let branch = matches!(kind, Kind::Voltage | Kind::Vcvs | Kind::Inductor).then(|| {
+            netlist.branches += 1;
+            netlist.branches - 1
+        });
+        netlist.elements.push(Element { name, kind, nodes, control, value, ac, branch });
+    }
+
+    if netlist.elements.is_empty() {
+        return Err("the netlist is empty".to_string());
+    }
+    // Branch rows come after the node rows
+    let offset = netlist.nodes.len() - 1;
+    for element in &mut netlist.elements {
+        if let Some(branch) = element.branch.as_mut() {
+            *branch += offset;
+        }
+    }
+    Ok(netlist)
+}
+
+/// The MNA system `(G + sC)·x = b`, with separate DC and AC excitations
+struct System {
+    g: Vec<Vec<f64>>,
+    c: Vec<Vec<f64>>,
+    dc: Vec<f64>,
+    ac: Vec<Complex>,
+}
+
+fn stamp(netlist: &Netlist) -> System {
+    let n = netlist.size();
+    let mut sys = System { g: vec![vec![0.0; n]; n], c: vec![vec![0.0; n]; n], dc: vec![0.0; n], ac: vec![Complex::default(); n] };
+    // Ground is node 0 and has no row
+    let row = |node: usize| node.checked_sub(1);
+
+    fn add(m: &mut [Vec<f64>], r: Option<usize>, c: Option<usize>, v: f64) {
+        if let (Some(r), Some(c)) = (r, c) {
+            m[r][c] += v;
+        }
+    }
+
+    for e in &netlist.elements {
+        let (a, b) = (row(e.nodes[0]), row(e.nodes[1]));
+        let (ca, cb) = (row(e.control[0]), row(e.control[1]));
+        match e.kind {
+            Kind::Resistor | Kind::Capacitor | Kind::Vccs => {
+                let (m, v, (p, q)) = match e.kind {
+                    Kind::Resistor => (&mut sys.g, 1.0 / e.value, (a, b)),
+                    Kind::Capacitor => (&mut sys.c, e.value, (a, b)),
+                    _ => (&mut sys.g, e.value, (ca, cb)),
+                };
+                add(m, a, p, v);
+                add(m, a, q, -v);
+                add(m, b, p, -v);
+                add(m, b, q, v);
+            }
+            Kind::Current => {
+                // SPICE convention: positive current flows from n+ through the source to n−
+                if let Some(a) = a {
+                    sys.dc[a] -= e.value;
+                    sys.ac[a] = sys.ac[a] - e.ac;
+                }
+                if let Some(b) = b {
+                    sys.dc[b] += e.value;
+                    sys.ac[b] = sys.ac[b] + e.ac;
+                }
+            }
+            Kind::Voltage | Kind::Vcvs | Kind::Inductor => {
+                let k = e.branch.expect("branch elements have a branch row");
+                add(&mut sys.g, a, Some(k), 1.0);
+                add(&mut sys.g, b, Some(k), -1.0);
+                add(&mut sys.g, Some(k), a, 1.0);
+                add(&mut sys.g, Some(k), b, -1.0);
+                match e.kind {
+                    Kind::Voltage => {
+                        sys.dc[k] = e.value;
+                        sys.ac[k] = e.ac;
+                    }
+                    Kind::Vcvs => {
+                        add(&mut sys.g, Some(k), ca, -e.value);
+                        add(&mut sys.g, Some(k), cb, e.value);
+                    }
+                    _ => sys.c[k][k] = -e.value,
+                }
+            }
+        }
+    }
+    sys
+}
+
+/// Gaussian elimination with partial pivoting; `None` when the matrix is singular
+fn solve(mut a: Vec<Vec<Complex>>, mut b: Vec<Complex>) -> Option<Vec<Complex>> {
+    let n = b.len();
+    let scale = a.iter().flatten().map(|v| v.abs()).fold(0.0, f64::max);
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() <= scale * 1e-14 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for r in col + 1..n {
+            let factor = a[r][col] / a[col][col];
+            if factor == Complex::default() {
+                continue;
+            }
+            let (top, rest) = a.split_at_mut(r);
+            for (value, &p) in rest[0][col..].iter_mut().zip(&top[col][col..]) {
+                *value = *value - factor * p;
+            }
+            b[r] = b[r] - factor * b[col];
+        }
+    }
+    let mut x = vec![Complex::default(); n];
+    for r in (0..n).rev() {
+        let sum = (r + 1..n).fold(b[r], |acc, c| acc - a[r][c] * x[c]);
+        x[r] = sum / a[r][r];
+    }
+    Some(x)
+}
+
+impl System {
+    /// Solve at the complex frequency `s` with the AC (or, when `dc` is set, the DC) excitation
+    fn solve_at(&self, s: Complex, dc: bool) -> Option<Vec<Complex>> {
+        let a = self
+            .g
+            .iter()
+            .zip(&self.c)
+            .map(|(g, c)| g.iter().zip(c).map(|(&g, &c)| Complex::real(g) + s * Complex::real(c)).collect())
+            .collect();
+        let b = if dc { self.dc.iter().map(|&v| Complex::real(v)).collect() } else { self.ac.clone() };
+        solve(a, b)
+    }
+
+    /// Natural frequencies: the roots of det(G + sC), from the eigenvalues λ of G⁻¹C as s = −1/λ
+    fn poles(&self) -> Option<Vec<Complex>> {
+        let n = self.g.len();
+        let g: Vec<Vec<Complex>> = self.g.iter().map(|row| row.iter().map(|&v| Complex::real(v)).collect()).collect();
+        let mut m = vec![vec![Complex::default(); n]; n];
+        for j in 0..n {
+            let column = solve(g.clone(), self.c.iter().map(|row| Complex::real(row[j])).collect())?;
+            for i in 0..n {
+                m[i][j] = column[i];
+            }
+        }
+        let lambdas = eigenvalues(m);
+        let largest = lambdas.iter().map(|l| l.abs()).fold(0.0, f64::max);
+        let mut poles: Vec<Complex> = lambdas
+            .into_iter()
+            .filter(|l| l.abs() > largest * 1e-12)
+            .map(|l| -(Complex::real(1.0) / l))
+            .collect();
+        poles.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+        Some(poles)
+    }
+}
+
+fn conj(z: Complex) -> Complex {
+    Complex::new(z.re, -z.im)
+}
+
+/// Eigenvalues of a small dense matrix: reduction to Hessenberg form, then shifted QR with Givens rotations
+fn eigenvalues(mut a: Vec<Vec<Complex>>) -> Vec<Complex> {
+    let n = a.len();
+    for m in 1..n.saturating_sub(1) {
+        let pivot = (m..n).max_by(|&i, &j| a[i][m - 1].abs().total_cmp(&a[j][m - 1].abs())).unwrap_or(m);
+        if a[pivot][m - 1].abs() == 0.0 {
+            continue;
+        }
+        a.swap(m, pivot);
+        for row in a.iter_mut() {
+            row.swap(m, pivot);
+        }
+        for i in m + 1..n {
+            let y = a[i][m - 1] / a[m][m - 1];
+            if y == Complex::default() {
+                continue;
+            }
+            let (top, rest) = a.split_at_mut(i);
+            for (value, &p) in rest[0][m - 1..].iter_mut().zip(&top[m][m - 1..]) {
+                *value = *value - y * p;
+            }
+            for row in a.iter_mut() {
+                row[m] = row[m] + y * row[i];
+            }
+        }
+    }
+
+    let norm = a.iter().flatten().map(|v| v.abs()).fold(0.0, f64::max);
+    let mut values = Vec::with_capacity(n);
+    let (mut hi, mut iterations) = (n, 0);
+    while hi > 0 {
+        // Deflate once the last subdiagonal entry of the active block is negligible
+        let mut lo = hi - 1;
+        while lo > 0 && a[lo][lo - 1].abs() > f64::EPSILON * (a[lo][lo].abs() + a[lo - 1][lo - 1].abs()).max(norm) {
+            lo -= 1;
+        }
+        if lo == hi - 1 || iterations > 60 * n {
+            values.push(a[hi - 1][hi - 1]);
+            hi -= 1;
+            iterations = 0;
+            continue;
+        }
+        iterations += 1;
+
+        // Wilkinson shift from the trailing 2×2 block, with an occasional exceptional shift
+        let (p, q, r, t) = (a[hi - 2][hi - 2], a[hi - 2][hi - 1], a[hi - 1][hi - 2], a[hi - 1][hi - 1]);
+        let half = (p - t) / Complex::real(2.0);
+        let root = (half * half + q * r).sqrt();
+        let (mu1, mu2) = (t - q * r / (half + root), t - q * r / (half - root));
+        let mut shift = if (mu1 - t).abs() < (mu2 - t).abs() || !mu2.re.is_finite() { mu1 } else { mu2 };
+        if !shift.re.is_finite() || !shift.im.is_finite() {
+            shift = t;
+        }
+        if iterations % 11 == 10 {
+            shift = t + Complex::real(a[hi - 1][hi - 2].abs());
+        }
+
+        for (k, row) in a.iter_mut().enumerate().take(hi).skip(lo) {
+            row[k] = row[k] - shift;
+        }
+        let mut rotations = Vec::with_capacity(hi - lo);
+        for k in lo..hi - 1 {
+            let (x, y) = (a[k][k], a[k + 1][k]);
+            let r = (x.abs().powi(2) + y.abs().powi(2)).sqrt();
+            let (c, s) = if r == 0.0 {
+                (1.0, Complex::default())
+            } else if x.abs() == 0.0 {
+                (0.0, Complex::real(1.0))
+            } else {
+                (x.abs() / r, x / Complex::real(x.abs()) * conj(y) / Complex::real(r))
+            };
+            let (top, rest) = a.split_at_mut(k + 1);
+            for (u, v) in top[k][k..hi].iter_mut().zip(&mut rest[0][k..hi]) {
+                (*u, *v) = (Complex::real(c) * *u + s * *v, Complex::real(c) * *v - conj(s) * *u);
+            }
+            rotations.push((c, s));
+        }
+        for (k, (c, s)) in (lo..).zip(rotations) {
+            for row in a.iter_mut().take((k + 2).min(hi)).skip(lo) {
+                let (u, v) = (row[k], row[k + 1]);
+                row[k] = u * Complex::real(c) + v * conj(s);
+                row[k + 1] = v * Complex::real(c) - u * s;
+            }
+        }
+        for (k, row) in a.iter_mut().enumerate().take(hi).skip(lo) {
+            row[k] = row[k] + shift;
+        }
+    }
+    values
+}
+
+/// Replace each `V(..)`/`I(..)` in a `.plot` expression with a variable bound to that probe
+fn compile(netlist: &Netlist, text: &str) -> Result<(expr::Expr, Vec<(String, Probe)>), String> {
+    let mut probes = Vec::new();
+    let mut error = None;
+    let replaced = PROBE_RE.replace_all(text, |caps: &regex::Captures| {
+        let args: Vec<String> = caps[2].split(',').map(|a| a.trim().to_lowercase()).collect();
+        let find = |name: &str| -> Option<usize> {
+            (name == "0" || name == "gnd").then_some(0).or_else(|| netlist.nodes.iter().position(|n| n == name))
+        };
+        let probe = if caps[1].eq_ignore_ascii_case("v") {
+            match &args[..] {
+                [a] => find(a).map(|a| Probe::Voltage(a, 0)),
+                [a, b] => find(a).zip(find(b)).map(|(a, b)| Probe::Voltage(a, b)),
+                _ => None,
+            }
+        } else {
+            netlist.elements.iter().position(|e| e.name.eq_ignore_ascii_case(&args[0])).map(Probe::Current)
+        };
+        match probe {
+            Some(probe) => {
+                let var = format!("_probe{}", probes.len());
+                probes.push((var.clone(), probe));
+                var
+            }
+            None => {
+                error.get_or_insert_with(|| format!("unknown node or element in `{}`", &caps[0]));
+                String::new()
+            }
+        }
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+    if probes.is_empty() {
+        return Err(format!("`{}` does not probe anything: use V(node) or I(element)", text));
+    }
+    Ok((expr::parse(&replaced)?, probes))
+}
+
+/// The value of a probe given the solution `x` at complex frequency `s`
+fn measure(netlist: &Netlist, probe: &Probe, x: &[Complex], s: Complex, dc: bool) -> Complex {
+    let v = |node: usize| if node == 0 { Complex::default() } else { x[node - 1] };
+    match *probe {
+        Probe::Voltage(a, b) => v(a) - v(b),
+        Probe::Current(i) => {
+            let e = &netlist.elements[i];
+            let across = v(e.nodes[0]) - v(e.nodes[1]);
+            match e.kind {
+                Kind::Resistor => across / Complex::real(e.value),
+                Kind::Capacitor => s * Complex::real(e.value) * across,
+                Kind::Vccs => Complex::real(e.value) * (v(e.control[0]) - v(e.control[1])),
+                Kind::Current if dc => Complex::real(e.value),
+                Kind::Current => e.ac,
+                _ => x[e.branch.expect("branch elements have a branch row")],
+            }
+        }
+    }
+}
+
+fn evaluate(netlist: &Netlist, compiled: &(expr::Expr, Vec<(String, Probe)>), x: &[Complex], s: Complex, dc: bool) -> Result<Complex, String> {
+    let (expr, probes) = compiled;
+    let mut env = netlist.params.clone();
+    env.insert("s".to_string(), s);
+    for (var, probe) in probes {
+        env.insert(var.clone(), measure(netlist, probe, x, s, dc));
+    }
+    expr.eval(&env)
+}
+
+/// A unit for a bare probe, none for an expression of several
+fn unit_of(text: &str) -> &'static str {
+    match PROBE_RE.find(text) {
+        Some(m) if m.as_str() == text.trim() => {
+            if text.trim_start().starts_with(['v', 'V']) { "V" } else { "A" }
+        }
+        _ => "",
+    }
+}
+
+const SINGULAR: &str = "the circuit matrix is singular: check for floating nodes or loops of voltage sources";
+
+fn describe_pole(p: Complex) -> String {
+    let hz = |w: f64| format_si(w / (2.0 * PI), "Hz");
+    let side = if p.re > 0.0 { " (right half-plane)" } else { "" };
+    if p.im.abs() <= 1e-9 * p.abs() {
+        format!("{}{}", hz(p.re.abs()), side)
+    } else {
+        let q = p.abs() / (2.0 * p.re.abs());
+        format!("{} ± j{}, f₀ = {}, Q = {:.2}{}", hz(p.re.abs()), hz(p.im.abs()), hz(p.abs()), q, side)
+    }
+}
+
+/// A ```spice-ac block: an AC sweep of the `.plot` quantities with gain, bandwidth and poles
+pub fn render_ac(source: &str) -> Result<String, String> {
+    let netlist = parse(source)?;
+    if !netlist.elements.iter().any(|e| e.ac != Complex::default()) {
+        return Err("no AC excitation: add `AC 1` to a V or I source".to_string());
+    }
+    let system = stamp(&netlist);
+
+    let (per_decade, lo, hi) = netlist.sweep.unwrap_or((DEFAULT_POINTS, 1.0, 1e9));
+    if !(lo > 0.0 && hi > lo) {
+        return Err(format!("frequency range must be positive and increasing, got {} .. {}", lo, hi));
+    }
+    let points = ((hi / lo).log10() * per_decade as f64).ceil() as usize + 1;
+    let freqs = bode::log_sweep(lo, hi, points.max(2));
+
+    let probes = if netlist.probes.is_empty() {
+        if !netlist.nodes.iter().any(|n| n == "out") {
+            return Err("nothing to plot: add `.plot V(node)`".to_string());
+        }
+        vec!["V(out)".to_string()]
+    } else {
+        netlist.probes.clone()
+    };
+    let compiled = probes.iter().map(|p| compile(&netlist, p)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut values = vec![Vec::with_capacity(freqs.len()); compiled.len()];
+    for &f in &freqs {
+        let s = Complex::new(0.0, 2.0 * PI * f);
+        let x = system.solve_at(s, false).ok_or_else(|| format!("{} (at {})", SINGULAR, format_si(f, "Hz")))?;
+        for (trace, c) in values.iter_mut().zip(&compiled) {
+            trace.push(evaluate(&netlist, c, &x, s, false)?);
+        }
+    }
+    let responses: Vec<Response> = values.into_iter().map(Response::new).collect();
+
+    let caption = if netlist.caption.is_empty() {
+        format!("AC response of {}", probes.join(", "))
+    } else {
+        netlist.caption.clone()
+    };
+    let mut html = String::from("<div class=\"spice\">\n");
+    html.push_str(&bode::chart(&freqs, &probes, &responses, false, &caption)?);
+
+    // Figures of merit for the first trace, when it has any
+    let mut results = String::new();
+    let first = &responses[0];
+    let dc_gain = system
+        .solve_at(Complex::default(), false)
+        .and_then(|x| evaluate(&netlist, &compiled[0], &x, Complex::default(), false).ok())
+        .map(|h| h.abs())
+        .filter(|h| h.is_finite() && *h > 0.0);
+    if let Some(gain) = dc_gain {
+        let db = 20.0 * gain.log10();
+        let _ = writeln!(results, "<dt>DC gain</dt><dd>{:.1} dB ({})</dd>", db, format_si(gain, unit_of(&probes[0])));
+        if let Some((_, f)) = bode::crossing(&freqs, &first.mag, db - 3.0) {
+            let _ = writeln!(results, "<dt>−3 dB bandwidth</dt><dd>{}</dd>", format_si(f, "Hz"));
+        }
+    }
+    if let Some((_, f)) = bode::crossing(&freqs, &first.mag, 0.0) {
+        let _ = writeln!(results, "<dt>Unity-gain frequency</dt><dd>{}</dd>", format_si(f, "Hz"));
+    }
+    if let Some(poles) = system.poles().filter(|p| !p.is_empty()) {
+        let list: Vec<String> = poles.iter().filter(|p| p.im >= 0.0).map(|&p| describe_pole(p)).collect();
+        let _ = writeln!(results, "<dt>Poles</dt><dd>{}</dd>", escape_html(&list.join("; ")));
+    }
+    if !results.is_empty() {
+        let _ = write!(html, "\n<dl class=\"spice-results\">\n{}</dl>", results);
+    }
+    html.push_str("\n</div>");
+    Ok(html)
+}
+
+/// A ```spice-op block: the DC operating point, with capacitors open and inductors shorted
+pub fn render_op(source: &str) -> Result<String, String> {
+    let netlist = parse(source)?;
+    let system = stamp(&netlist);
+    let x = system.solve_at(Complex::default(), true).ok_or_else(|| SINGULAR.to_string())?;
+
+    // Every node voltage and element current unless `.print` narrows it down
+    let probes = if netlist.probes.is_empty() {
+        let nodes = netlist.nodes.iter().skip(1).map(|n| format!("V({})", n));
+        let currents = netlist.elements.iter().filter(|e| e.kind != Kind::Capacitor).map(|e| format!("I({})", e.name));
+        nodes.chain(currents).collect()
+    } else {
+        netlist.probes.clone()
+    };
+
+    let mut html = String::from("<div class=\"spice\">\n<table class=\"spice-op\">\n");
+    if !netlist.caption.is_empty() {
+        let _ = writeln!(html, "<caption>{}</caption>", escape_html(&netlist.caption));
+    }
+    html.push_str("<thead><tr><th>Quantity</th><th>Value</th></tr></thead>\n<tbody>\n");
+    let mut seen = HashSet::new();
+    for probe in &probes {
+        if !seen.insert(probe.as_str()) {
+            continue;
+        }
+        let value = evaluate(&netlist, &compile(&netlist, probe)?, &x, Complex::default(), true)?;
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            escape_html(probe),
+            escape_html(&format_si(value.as_real(), unit_of(probe)))
+        );
+    }
+    html.push_str("</tbody>\n</table>\n</div>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The operating-point value of `probe`
+    fn op(source: &str, probe: &str) -> f64 {
+        let netlist = parse(source).unwrap();
+        let x = stamp(&netlist).solve_at(Complex::default(), true).unwrap();
+        evaluate(&netlist, &compile(&netlist, probe).unwrap(), &x, Complex::default(), true).unwrap().as_real()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn divider() {
+        let source = "V1 in 0 DC 10\nR1 in out 3k\nR2 out 0 1k";
+        assert!(close(op(source, "V(out)"), 2.5));
+        assert!(close(op(source, "I(R2)"), 2.5e-3));
+        assert!(render_op(source).unwrap().contains("<td>V(out)</td><td>2.5 V</td>"));
+    }
+
+    #[test]
+    fn current_source_into_a_resistor() {
+        let source = "I1 0 out DC 1m\nR1 out 0 2k";
+        assert!(close(op(source, "V(out)"), 2.0));
+    }
+
+    #[test]
+    fn rc_pole() {
+        let source = "V1 in 0 AC 1\nR1 in out 1k\nC1 out 0 1u";
+        let poles = stamp(&parse(source).unwrap()).poles().unwrap();
+        assert_eq!(poles.len(), 1);
+        assert!(close(poles[0].re, -1.0 / (1e3 * 1e-6)));
+        let html = render_ac(source).unwrap();
+        assert!(html.contains(&format!("<dt>Poles</dt><dd>{}</dd>", format_si(1.0 / (2.0 * PI * 1e-3), "Hz"))), "{}", html);
+    }
+
+    #[test]
+    fn no_results_leaves_out_the_list() {
+        let html = render_ac("V1 in 0 AC 1\nC1 in out 1p\nC2 out 0 1p").unwrap();
+        assert!(!html.contains("spice-results"), "{}", html);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+use walkdir::WalkDir;
+
+use crate::feed::Feed;
+use crate::render::slugify;
+use crate::site::{self, content_dir, SiteIndex};
+use crate::{config, get_site_url, graph, og, redirects, seo};
+
+/// Root-relative links, which markdown content writes without the site_url prefix
+static ROOT_RELATIVE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(href|src)="(/[^"]*)""#).unwrap());
+
+/// Directories pages load scripts, styles and images from, copied as they are
+const STATIC_DIRS: &[&str] = &["assets", "dist", "style"];
+
+/// Give root-relative links that lack it the site_url prefix, as the server would when nested
+fn prefix_links(html: &str, site_url: &str) -> String {
+    if site_url.is_empty() {
+        return html.to_string();
+    }
+    ROOT_RELATIVE_RE
+        .replace_all(html, |caps: &Captures| {
+            let path = &caps[2];
+            if path == site_url || path.starts_with(&format!("{}/", site_url)) {
+                caps[0].to_string()
+            } else {
+                format!("{}=\"{}{}\"", &caps[1], site_url, path)
+            }
+        })
+        .into_owned()
+}
+
+fn write(dir: &Path, path: &str, data: &[u8]) -> Result<(), String> {
+    let file = dir.join(path.trim_start_matches('/'));
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("could not create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&file, data).map_err(|e| format!("could not write {}: {}", file.display(), e))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    for entry in WalkDir::new(from).into_iter().flatten() {
+        let Ok(relative) = entry.path().strip_prefix(from) else { continue };
+        let target = to.join(relative);
+        let result = if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)
+        } else {
+            std::fs::copy(entry.path(), &target).map(|_| ())
+        };
+        result.map_err(|e| format!("could not copy {} to {}: {}", entry.path().display(), target.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Write the whole site to `dir` as files a static host can serve: `<page path>.html` for every
+/// page, a stub for every redirect, the graph and review pages, sitemap, feeds and preview
+/// images for the enabled features, and the directories pages load assets from. Absolute URLs
+/// use the origin of `site.base_url`, or the server's own address when it is only a path.
+/// Returns how many pages were written.
+pub fn build(dir: &Path) -> Result<usize, String> {
+    let config = config::get();
+    let origin = match config.origin() {
+        Some(origin) => origin.to_string(),
+        None => {
+            let origin = format!("http://{}", config.bind_address());
+            eprintln!(
+                "warning: site.base_url is not an absolute URL, so canonical links, preview images, the sitemap and feeds will point at {}; pass --base-url https://host/path",
+                origin
+            );
+            origin
+        }
+    };
+    let site_url = get_site_url();
+    let index = SiteIndex::refresh();
+    crate::report_problems(&index);
+
+    let mut written = 0;
+    for page in &index.pages {
+        let segments: Vec<&str> = page.path.split('/').filter(|s| !s.is_empty()).collect();
+        let Some(html) = crate::page_html(&segments, &origin, None) else { continue };
+        write(dir, &format!("{}.html", page.path), prefix_links(&html, &site_url).as_bytes())?;
+        written += 1;
+    }
+
+    // Pages that moved, for hosts that cannot answer with a redirect themselves
+    for (old, new) in redirects::load() {
+        if site::read_page(&old).is_none() {
+            write(dir, &format!("{}.html", old), redirects::stub(&crate::build_link(&new)).as_bytes())?;
+        }
+    }
+
+    if config.features.graph {
+        write(dir, "graph.html", prefix_links(&crate::graph_html(&origin, ""), &site_url).as_bytes())?;
+        write(dir, "graph.json", graph::graph_json(&index).to_string().as_bytes())?;
+    }
+    if config.features.review {
+        write(dir, "review.html", prefix_links(&crate::review_html(&origin), &site_url).as_bytes())?;
+    }
+    write(dir, "sitemap.xml", seo::sitemap(&index, &origin).as_bytes())?;
+    write(dir, "robots.txt", seo::robots(&origin).as_bytes())?;
+
+    if config.features.feeds {
+        let categories: BTreeSet<String> = index
+            .pages
+            .iter()
+            .filter_map(|p| p.path.trim_start_matches('/').split_once('/').map(|(category, _)| slugify(category)))
+            .collect();
+        let feeds = std::iter::once(Feed::new(None)).chain(categories.iter().map(|c| Feed::new(Some(c)))).flatten();
+        for feed in feeds {
+            write(dir, &format!("{}.xml", feed.base), feed.atom(&index, &origin).as_bytes())?;
+            write(dir, &format!("{}.json", feed.base), feed.json(&index, &origin).to_string().as_bytes())?;
+        }
+    }
+    if config.features.og_images {
+        og::export(&index, dir)?;
+    }
+
+    for static_dir in STATIC_DIRS {
+        if Path::new(static_dir).is_dir() {
+            copy_dir(Path::new(static_dir), &dir.join(static_dir))?;
+        }
+    }
+    copy_dir(Path::new(content_dir()), &dir.join("content"))?;
+
+    Ok(written)
+}
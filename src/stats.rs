@@ -0,0 +1,87 @@
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+
+use crate::render::markdown_options;
+use crate::site::SiteIndex;
+
+/// Fenced block types that render as numbered figures
+const FIGURE_BLOCKS: &[&str] = &["bode", "gmid", "logic", "noise", "plot", "spice-ac", "wavedrom"];
+
+/// Counts for one category, or for the whole site
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub category: String,
+    pub pages: usize,
+    /// Words of prose, leaving out code and math
+    pub words: usize,
+    /// Display equations; inline math is not counted
+    pub equations: usize,
+    pub figures: usize,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.pages += other.pages;
+        self.words += other.words;
+        self.equations += other.equations;
+        self.figures += other.figures;
+    }
+}
+
+/// Counts for a single page's source
+fn count(source: &str) -> Stats {
+    let mut stats = Stats { pages: 1, ..Stats::default() };
+    let mut in_code = false;
+    for event in Parser::new_ext(source, markdown_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code = true;
+                if let CodeBlockKind::Fenced(lang) = kind {
+                    let lang = lang.split_whitespace().next().unwrap_or_default();
+                    if FIGURE_BLOCKS.contains(&lang) {
+                        stats.figures += 1;
+                    }
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Start(Tag::Image { .. }) => stats.figures += 1,
+            Event::DisplayMath(_) => stats.equations += 1,
+            Event::Text(text) if !in_code => stats.words += text.split_whitespace().count(),
+            _ => {}
+        }
+    }
+    stats
+}
+
+/// One row per category in content directory order, with the home page under "Other"
+pub fn collect(index: &SiteIndex) -> Vec<Stats> {
+    let mut rows: Vec<Stats> = Vec::new();
+    for page in &index.pages {
+        let Ok(source) = std::fs::read_to_string(&page.file) else { continue };
+        let category = if page.category.is_empty() { "Other".to_string() } else { page.category.clone() };
+        let stats = count(&source);
+        match rows.iter_mut().find(|r| r.category == category) {
+            Some(row) => row.add(&stats),
+            None => rows.push(Stats { category, ..stats }),
+        }
+    }
+    rows.sort_by_key(|r| r.category == "Other");
+    rows
+}
+
+/// `rows` as an aligned text table with a total line
+pub fn table(rows: &[Stats]) -> String {
+    let mut total = Stats { category: "Total".to_string(), ..Stats::default() };
+    for row in rows {
+        total.add(row);
+    }
+    let width = rows.iter().chain([&total]).map(|r| r.category.chars().count()).max().unwrap_or(0).max("Category".len());
+    let line = |r: &Stats| format!("{:<width$}  {:>6}  {:>8}  {:>9}  {:>7}\n", r.category, r.pages, r.words, r.equations, r.figures, width = width);
+
+    let mut out = format!("{:<width$}  {:>6}  {:>8}  {:>9}  {:>7}\n", "Category", "Pages", "Words", "Equations", "Figures", width = width);
+    for row in rows {
+        out.push_str(&line(row));
+    }
+    out.push_str(&format!("{}\n", "-".repeat(width + 40)));
+    out.push_str(&line(&total));
+    out
+}
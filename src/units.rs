@@ -0,0 +1,311 @@
+use std::f64::consts::PI;
+use std::sync::LazyLock;
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use regex::Regex;
+
+use crate::chart::format_number;
+use crate::expr::{self, Env};
+use crate::site::escape_html;
+
+static NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*([+-]?(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?)\s*(\S*(?:\s+\S+)?)\s*$").unwrap());
+static SHORTCODE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{si\s+([^}]*?)\s*\}\}|:si\[([^\]]*)\]\{([^}]*)\}").unwrap());
+
+/// SI prefixes, longest first so `meg` wins over `m`
+const PREFIXES: &[(&str, f64)] = &[
+    ("meg", 1e6), ("Meg", 1e6), ("MEG", 1e6),
+    ("T", 1e12), ("G", 1e9), ("M", 1e6), ("k", 1e3),
+    ("m", 1e-3), ("µ", 1e-6), ("u", 1e-6), ("n", 1e-9), ("p", 1e-12), ("f", 1e-15),
+];
+
+/// SPICE prefixes: case-insensitive, so `M` is milli and mega is `meg`
+const SPICE_PREFIXES: &[(&str, f64)] = &[
+    ("meg", 1e6), ("t", 1e12), ("g", 1e9), ("k", 1e3),
+    ("m", 1e-3), ("µ", 1e-6), ("u", 1e-6), ("n", 1e-9), ("p", 1e-12), ("f", 1e-15),
+];
+
+/// Known units as (symbol, other spellings, takes a prefix)
+const UNITS: &[(&str, &[&str], bool)] = &[
+    ("Ω", &["ohm", "ohms", "Ohm", "Ohms", "V/A"], true),
+    ("F", &[], true),
+    ("H", &[], true),
+    ("V", &[], true),
+    ("A", &[], true),
+    ("S", &["mho", "A/V"], true),
+    ("Hz", &["hz"], true),
+    ("s", &["sec"], true),
+    ("W", &[], true),
+    ("m", &[], true),
+    ("rad/s", &[], true),
+    ("V/√Hz", &["V/rtHz", "V/sqrtHz"], true),
+    ("A/√Hz", &["A/rtHz", "A/sqrtHz"], true),
+    ("V²/Hz", &["V^2/Hz"], true),
+    ("K", &[], false),
+    ("°C", &["degC"], false),
+    ("°", &["deg"], false),
+    ("dB", &[], false),
+    ("dBm", &[], false),
+    ("dBV", &[], false),
+    ("V/V", &[], false),
+    ("%", &[], false),
+];
+
+/// A number with its unit symbol, "" when it has none
+#[derive(Clone, Copy, Debug)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+fn unit(text: &str) -> Option<(&'static str, bool)> {
+    UNITS
+        .iter()
+        .find(|(symbol, aliases, _)| *symbol == text || aliases.contains(&text))
+        .map(|&(symbol, _, prefixed)| (symbol, prefixed))
+}
+
+/// Split a suffix like `pF`, `kΩ` or `meg` into a scale and a unit symbol
+fn suffix(text: &str, spice: bool) -> Option<(f64, &'static str)> {
+    if text.is_empty() {
+        return Some((1.0, ""));
+    }
+    if spice {
+        // SPICE ignores whatever follows the prefix, so `10pF` and `10pfarads` are both 10p
+        let lower = text.to_lowercase();
+        let (scale, rest) = match SPICE_PREFIXES.iter().find(|(p, _)| lower.starts_with(p)) {
+            Some(&(p, scale)) => (scale, &text[p.len()..]),
+            None => (1.0, text),
+        };
+        return Some((scale, unit(rest).map_or("", |(symbol, _)| symbol)));
+    }
+    // A bare prefix reads as a prefix, so `2m` is 2 milli rather than 2 metres
+    if let Some(&(_, scale)) = PREFIXES.iter().find(|(p, _)| *p == text) {
+        return Some((scale, ""));
+    }
+    if let Some((symbol, _)) = unit(text) {
+        return Some((1.0, symbol));
+    }
+    PREFIXES.iter().find_map(|&(p, scale)| match unit(text.strip_prefix(p)?) {
+        Some((symbol, true)) => Some((scale, symbol)),
+        _ => None,
+    })
+}
+
+fn parse_with(text: &str, spice: bool) -> Result<Quantity, String> {
+    let caps = NUMBER_RE.captures(text).ok_or_else(|| format!("expected a number, got `{}`", text.trim()))?;
+    let number: f64 = caps[1].parse().map_err(|_| format!("bad number `{}`", &caps[1]))?;
+    // `2.2meg Ω` spaces the prefix away from the unit
+    let (scale, unit) = suffix(&caps[2].replace(char::is_whitespace, ""), spice).ok_or_else(|| format!("unknown unit `{}` in `{}`", &caps[2], text.trim()))?;
+    Ok(Quantity { value: number * scale, unit })
+}
+
+/// An engineering value such as `2.5p`, `10 kΩ`, `1.2mA` or `2.5e-12 F`. Prefixes are case-sensitive (`M` is mega).
+pub fn parse(text: &str) -> Result<Quantity, String> {
+    parse_with(text, false)
+}
+
+/// Convert between related units: dB and V/V, dBm and W, dBV and V, rad/s and Hz, °C and K
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let canonical = |u: &str| -> String {
+        match unit(u) {
+            Some(("V/V", _)) => String::new(),
+            Some((symbol, _)) => symbol.to_string(),
+            None => u.to_string(),
+        }
+    };
+    let (from, to) = (canonical(from), canonical(to));
+    Ok(match (from.as_str(), to.as_str()) {
+        (a, b) if a == b => value,
+        ("dB", "") => 10f64.powf(value / 20.0),
+        ("", "dB") => 20.0 * value.log10(),
+        ("dBm", "W") => 1e-3 * 10f64.powf(value / 10.0),
+        ("W", "dBm") => 10.0 * (value / 1e-3).log10(),
+        ("dBV", "V") => 10f64.powf(value / 20.0),
+        ("V", "dBV") => 20.0 * value.log10(),
+        ("rad/s", "Hz") => value / (2.0 * PI),
+        ("Hz", "rad/s") => value * 2.0 * PI,
+        ("°C", "K") => value + 273.15,
+        ("K", "°C") => value - 273.15,
+        (a, b) => {
+            let name = |u: &str| if u.is_empty() { "a plain number".to_string() } else { u.to_string() };
+            return Err(format!("cannot convert {} to {}", name(a), name(b)));
+        }
+    })
+}
+
+fn value_with(token: &str, env: &Env, expected: Option<&str>, spice: bool) -> Result<f64, String> {
+    let token = token.trim();
+    if let Some(inner) = token.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        return expr::eval_real(inner, env);
+    }
+    match parse_with(token, spice) {
+        // A bare number is taken to be in the expected unit
+        Ok(q) if q.unit.is_empty() => Ok(q.value),
+        Ok(q) => match expected {
+            None => Ok(q.value),
+            Some(to) => convert(q.value, q.unit, to).map_err(|_| format!("`{}` is in {}, expected {}", token, q.unit, to)),
+        },
+        // Not a plain value, so maybe an expression like `2/3` or `gm*10k`
+        Err(e) => expr::eval_real(token, env).map_err(|_| if NUMBER_RE.is_match(token) { e } else { format!("expected a value, got `{}`", token) }),
+    }
+}
+
+/// A parameter of a computation block: an engineering value, checked against (or converted to) the
+/// `expected` unit, or an expression of the block's constants, optionally in `{braces}`
+pub fn parse_value(token: &str, env: &Env, expected: Option<&str>) -> Result<f64, String> {
+    value_with(token, env, expected, false)
+}
+
+/// [`parse_value`] for netlists, where prefixes are case-insensitive, `meg` is mega and trailing letters are ignored
+pub fn parse_spice_value(token: &str, env: &Env, expected: Option<&str>) -> Result<f64, String> {
+    value_with(token, env, expected, true)
+}
+
+/// Three significant figures with trailing zeros dropped: 316, 6.22, 0.5
+fn significant(v: f64) -> String {
+    if v == 0.0 || !v.is_finite() {
+        return format_number(v);
+    }
+    let decimals = (2 - v.abs().log10().floor() as i32).max(0) as usize;
+    let s = format!("{:.*}", decimals, v);
+    if s.contains('.') { s.trim_end_matches('0').trim_end_matches('.').to_string() } else { s }
+}
+
+/// A value with an SI prefix, e.g. `format_si(2.5e6, "Hz")` is "2.5 MHz". Logarithmic and
+/// relative units (dB, °C, %) never take a prefix.
+pub fn format_si(v: f64, unit: &str) -> String {
+    const SCALES: &[(f64, &str)] = &[
+        (1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "k"), (1.0, ""),
+        (1e-3, "m"), (1e-6, "µ"), (1e-9, "n"), (1e-12, "p"), (1e-15, "f"),
+    ];
+    if v == 0.0 || !v.is_finite() || matches!(self::unit(unit), Some((_, false))) {
+        return format!("{} {}", significant(v), unit).trim_end().to_string();
+    }
+    // Round first so 999.99 becomes 1 k rather than 1000
+    let v = format!("{:.2e}", v).parse::<f64>().unwrap_or(v);
+    let (scale, prefix) = SCALES
+        .iter()
+        .find(|(scale, _)| v.abs() >= scale * (1.0 - 1e-9))
+        .unwrap_or(&SCALES[SCALES.len() - 1]);
+    format!("{} {}{}", significant(v / scale), prefix, unit).trim_end().to_string()
+}
+
+/// `2.5e-12 F`, `10kΩ` or `20 dB -> V/V` from a shortcode into its formatted text. A `target` unit
+/// converts the value, except that `:si[2.5p]{F}` names the unit of a bare number (`named`).
+fn shortcode(value: &str, target: Option<&str>, named: bool) -> Result<String, String> {
+    let q = parse(value)?;
+    let (value, unit) = match target.map(str::trim).filter(|t| !t.is_empty()) {
+        None => (q.value, q.unit),
+        Some(t) if named && q.unit.is_empty() => (q.value, unit(t).map_or(t, |(symbol, _)| symbol)),
+        Some(t) => (convert(q.value, q.unit, t)?, unit(t).map_or(t, |(symbol, _)| symbol)),
+    };
+    // Keep the number and its unit on one line
+    Ok(format_si(value, unit).replacen(' ', "\u{a0}", 1))
+}
+
+fn expand(text: &str) -> String {
+    let mut html = String::new();
+    let mut last = 0;
+    for caps in SHORTCODE_RE.captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        html.push_str(&escape_html(&text[last..whole.start()]));
+        let result = match (caps.get(1), caps.get(2), caps.get(3)) {
+            (Some(body), _, _) => {
+                let (value, target) = match body.as_str().split_once("->") {
+                    Some((value, target)) => (value, Some(target)),
+                    None => (body.as_str(), None),
+                };
+                shortcode(value, target, false)
+            }
+            (_, Some(value), Some(unit)) => shortcode(value.as_str(), Some(unit.as_str()), true),
+            _ => unreachable!("the regex has two alternatives"),
+        };
+        match result {
+            Ok(formatted) => html.push_str(&format!(r#"<span class="si">{}</span>"#, escape_html(&formatted))),
+            Err(e) => html.push_str(&format!(r#"<span class="si si-error" title="{}">{}</span>"#, escape_html(&e), escape_html(whole.as_str()))),
+        }
+        last = whole.end();
+    }
+    html.push_str(&escape_html(&text[last..]));
+    html
+}
+
+/// Replace `{{si …}}` and `:si[…]{…}` shortcodes in text (not in code) with formatted values
+pub fn render(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut in_code = false;
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code = true;
+                event
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code = false;
+                event
+            }
+            Event::Text(text) if !in_code && SHORTCODE_RE.is_match(&text) => Event::InlineHtml(CowStr::from(expand(&text))),
+            _ => event,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-12 * b.abs().max(1e-300)
+    }
+
+    #[test]
+    fn prefixes() {
+        let q = parse("2.2mA").unwrap();
+        assert!(close(q.value, 2.2e-3) && q.unit == "A");
+        assert!(close(parse("1M").unwrap().value, 1e6));
+        assert!(close(parse("2.2meg Ω").unwrap().value, 2.2e6));
+        for micro in ["10 µF", "10uF"] {
+            let q = parse(micro).unwrap();
+            assert!(close(q.value, 1e-5) && q.unit == "F", "{}", micro);
+        }
+        // A bare prefix is a prefix, not metres
+        let q = parse("2m").unwrap();
+        assert!(close(q.value, 2e-3) && q.unit.is_empty());
+        assert!(close(parse("5 mm").unwrap().value, 5e-3));
+
+        // SPICE reads `M` as milli
+        let env = Env::new();
+        assert!(close(parse_spice_value("1M", &env, None).unwrap(), 1e-3));
+        assert!(close(parse_spice_value("2.2MEG", &env, None).unwrap(), 2.2e6));
+        assert!(close(parse_spice_value("10pF", &env, Some("F")).unwrap(), 1e-11));
+    }
+
+    #[test]
+    fn conversions() {
+        assert!(close(convert(20.0, "dB", "V/V").unwrap(), 10.0));
+        assert!(close(convert(100.0, "", "dB").unwrap(), 40.0));
+        assert!(close(convert(0.0, "dBm", "W").unwrap(), 1e-3));
+        assert!(close(convert(2.0 * PI * 1e3, "rad/s", "Hz").unwrap(), 1e3));
+        assert!(close(convert(25.0, "degC", "K").unwrap(), 298.15));
+        assert!(close(parse_value("1 kHz", &Env::new(), Some("rad/s")).unwrap(), 2.0 * PI * 1e3));
+        assert!(close(parse_value("27 °C", &Env::new(), Some("K")).unwrap(), 300.15));
+    }
+
+    #[test]
+    fn dimension_mismatch_is_an_error() {
+        assert_eq!(convert(1.0, "V", "A").unwrap_err(), "cannot convert V to A");
+        assert_eq!(convert(1.0, "dB", "Hz").unwrap_err(), "cannot convert dB to Hz");
+        assert_eq!(parse_value("10 pF", &Env::new(), Some("Ω")).unwrap_err(), "`10 pF` is in F, expected Ω");
+        assert!(parse("3 furlongs").is_err());
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_si(2.5e6, "Hz"), "2.5 MHz");
+        assert_eq!(format_si(999.99, "Ω"), "1 kΩ");
+        assert_eq!(format_si(4.7e-6, "F"), "4.7 µF");
+        assert_eq!(format_si(20.0, "dB"), "20 dB");
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use walkdir::WalkDir;
+
+use crate::site::{content_dir, SiteIndex};
+use crate::{config, feed};
+
+/// Polled by open pages; answers with the current version
+pub const ROUTE: &str = "/__watch";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// Bumped every time a watched file changes
+pub fn version() -> u64 {
+    VERSION.load(Ordering::Relaxed)
+}
+
+/// Number of files and the newest modification time under the watched directories, which
+/// changes when a file is edited, added or removed
+fn snapshot() -> (usize, Option<SystemTime>) {
+    let mut count = 0;
+    let mut newest = None;
+    for dir in [content_dir(), "style"] {
+        for entry in WalkDir::new(dir).into_iter().flatten() {
+            count += 1;
+            let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+            newest = newest.max(modified);
+        }
+    }
+    (count, newest)
+}
+
+/// Watch the content directory and `style/` in the background, rebuilding the site index and
+/// reporting link problems again after each change
+pub fn spawn() {
+    tokio::task::spawn_blocking(|| {
+        let mut last = snapshot();
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let current = snapshot();
+            if current == last {
+                continue;
+            }
+            last = current;
+            VERSION.fetch_add(1, Ordering::Relaxed);
+            if config::get().server.watch {
+                println!("Change detected, reloading open pages");
+            }
+            feed::forget_history();
+            crate::report_problems(&SiteIndex::refresh());
+        }
+    });
+}
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use crate::chart::{self, TEXT_COLOR};
+use crate::render::stable_id;
+use crate::site::escape_html;
+
+/// Fallback fills for `=` and `2`–`9` data bricks; main.scss restyles them for the dark theme
+const DATA_FILLS: &[&str] = &["#ffffff", "#ffffff", "#ffffb4", "#ffe0b9", "#b9e0ff", "#ccfdfe", "#cdfdc5", "#f0c1fb", "#f5c2c0"];
+const WAVE_COLOR: &str = "#1a1a1a";
+
+const LANE: f64 = 30.0;
+const AMPLITUDE: f64 = 20.0;
+const SLOPE: f64 = 3.0;
+const GROUP_WIDTH: f64 = 18.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    High,
+    Low,
+    Z,
+    X,
+    Data(usize),
+}
+
+impl Kind {
+    fn is_bus(self) -> bool {
+        matches!(self, Kind::X | Kind::Data(_))
+    }
+}
+
+/// One constant stretch of a wave, in slot units before scaling
+#[derive(Clone, Debug)]
+struct Piece {
+    kind: Kind,
+    x0: f64,
+    x1: f64,
+    /// Clocks and `h`/`l` switch with vertical edges instead of slopes
+    sharp: bool,
+    arrow: bool,
+    label: Option<String>,
+}
+
+/// Expand a wave string like `p..|.` or `x.34.5x` into pieces, plus the slot positions of `|` gaps
+fn parse_wave(wave: &str, period: f64, data: &mut impl Iterator<Item = String>) -> (Vec<Piece>, Vec<f64>) {
+    let mut pieces: Vec<Piece> = Vec::new();
+    let mut gaps = Vec::new();
+    let mut last = None;
+    let mut x = 0.0;
+    let half = period / 2.0;
+
+    for ch in wave.chars() {
+        if ch == '|' {
+            // Gaps sit in the middle of their brick
+            gaps.push(x + half);
+        }
+        let extend = ch == '.' || ch == '|';
+        let Some(c) = (if extend { last } else { Some(ch) }) else {
+            x += period;
+            continue;
+        };
+
+        let mut push = |kind: Kind, width: f64, sharp: bool, arrow: bool, label: Option<String>| {
+            pieces.push(Piece { kind, x0: x, x1: x + width, sharp, arrow, label });
+            x += width;
+        };
+        match c {
+            // A clock repeats its full cycle on every `.`
+            'p' | 'P' => {
+                push(Kind::High, half, true, c == 'P', None);
+                push(Kind::Low, half, true, false, None);
+            }
+            'n' | 'N' => {
+                push(Kind::Low, half, true, c == 'N', None);
+                push(Kind::High, half, true, false, None);
+            }
+            _ if extend => {
+                if let Some(piece) = pieces.last_mut() {
+                    piece.x1 += period;
+                }
+                x += period;
+            }
+            'h' | 'H' => push(Kind::High, period, true, c == 'H', None),
+            'l' | 'L' => push(Kind::Low, period, true, c == 'L', None),
+            '1' | 'u' => push(Kind::High, period, false, false, None),
+            '0' | 'd' => push(Kind::Low, period, false, false, None),
+            'z' => push(Kind::Z, period, false, false, None),
+            '=' => push(Kind::Data(0), period, false, false, data.next()),
+            '2'..='9' => push(Kind::Data(c as usize - '0' as usize), period, false, false, data.next()),
+            // `x` and anything WaveDrom would reject draw as unknown
+            _ => push(Kind::X, period, false, false, None),
+        }
+        last = Some(c);
+    }
+
+    (pieces, gaps)
+}
+
+/// Data labels come either as an array or as one space-separated string
+fn data_labels(signal: &Value) -> Vec<String> {
+    match signal.get("data") {
+        Some(Value::Array(items)) => items.iter().map(value_text).collect(),
+        Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// A lane in the diagram: a signal, a spacer (`{}`) or a group label
+enum Lane<'a> {
+    Signal(&'a Value, usize),
+    Spacer,
+}
+
+struct Group {
+    name: String,
+    depth: usize,
+    first: usize,
+    last: usize,
+}
+
+/// Flatten nested `["Group", {...}, [...]]` arrays into lanes, remembering where groups start and end
+fn flatten<'a>(items: &'a [Value], depth: usize, lanes: &mut Vec<Lane<'a>>, groups: &mut Vec<Group>) {
+    for item in items {
+        match item {
+            Value::Array(inner) => {
+                let (name, rest) = match inner.first() {
+                    Some(Value::String(name)) => (name.clone(), &inner[1..]),
+                    _ => (String::new(), &inner[..]),
+                };
+                let first = lanes.len();
+                flatten(rest, depth + 1, lanes, groups);
+                if lanes.len() > first {
+                    groups.push(Group { name, depth, first, last: lanes.len() - 1 });
+                }
+            }
+            Value::Object(map) if map.is_empty() => lanes.push(Lane::Spacer),
+            Value::Object(_) => lanes.push(Lane::Signal(item, depth)),
+            _ => {}
+        }
+    }
+}
+
+pub fn render(source: &str) -> Result<String, String> {
+    let diagram: Value = json5::from_str(source).map_err(|e| format!("invalid WaveDrom JSON5: {}", e))?;
+    let signals = diagram
+        .get("signal")
+        .and_then(Value::as_array)
+        .ok_or("a WaveDrom diagram needs a `signal` array")?;
+
+    let hscale = diagram.pointer("/config/hscale").and_then(Value::as_f64).unwrap_or(1.0).max(0.1);
+    let slot = 40.0 * hscale;
+    let id = stable_id(source);
+
+    let mut lanes = Vec::new();
+    let mut groups = Vec::new();
+    flatten(signals, 0, &mut lanes, &mut groups);
+    if lanes.is_empty() {
+        return Err("the `signal` array is empty".to_string());
+    }
+
+    // Everything needed to place waves, gaps and nodes
+    struct Parsed {
+        name: String,
+        pieces: Vec<Piece>,
+        gaps: Vec<f64>,
+        nodes: Vec<(char, f64)>,
+        phase: f64,
+    }
+    let parsed: Vec<Option<Parsed>> = lanes
+        .iter()
+        .map(|lane| match lane {
+            Lane::Spacer => None,
+            Lane::Signal(signal, _) => {
+                let period = signal.get("period").and_then(Value::as_f64).unwrap_or(1.0).max(0.1);
+                let wave = signal.get("wave").and_then(Value::as_str).unwrap_or_default();
+                let mut labels = data_labels(signal).into_iter();
+                let (pieces, gaps) = parse_wave(wave, period, &mut labels);
+                let nodes = signal
+                    .get("node")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c != '.')
+                    .map(|(i, c)| (c, i as f64 * period))
+                    .collect();
+                Some(Parsed {
+                    name: signal.get("name").map(value_text).unwrap_or_default(),
+                    pieces,
+                    gaps,
+                    nodes,
+                    phase: signal.get("phase").and_then(Value::as_f64).unwrap_or(0.0),
+                })
+            }
+        })
+        .collect();
+
+    let max_depth = lanes
+        .iter()
+        .map(|lane| match lane {
+            Lane::Signal(_, depth) => *depth,
+            Lane::Spacer => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    let name_width = parsed.iter().flatten().map(|p| p.name.chars().count()).max().unwrap_or(0) as f64 * 7.0 + 16.0;
+    let left = max_depth as f64 * GROUP_WIDTH + name_width;
+    let slots = parsed
+        .iter()
+        .flatten()
+        .map(|p| p.pieces.last().map(|piece| piece.x1).unwrap_or(0.0).max(p.gaps.last().copied().unwrap_or(0.0)))
+        .fold(0.0, f64::max)
+        .ceil();
+    let waves_width = slots * slot;
+
+    let text_of = |key: &str, field: &str| diagram.get(key).and_then(|h| h.get(field)).map(value_text);
+    let head = text_of("head", "text");
+    let foot = text_of("foot", "text");
+    let tick = |key: &str| -> Option<(f64, bool)> {
+        let h = diagram.get(key)?;
+        h.get("tick").and_then(Value::as_f64).map(|t| (t, true)).or_else(|| h.get("tock").and_then(Value::as_f64).map(|t| (t, false)))
+    };
+    let head_height = if head.is_some() { 24.0 } else { 0.0 } + if tick("head").is_some() { 16.0 } else { 0.0 } + 6.0;
+    let top = head_height;
+    let lanes_height = lanes.len() as f64 * LANE;
+    let height = top + lanes_height + if tick("foot").is_some() { 16.0 } else { 0.0 } + if foot.is_some() { 24.0 } else { 0.0 } + 6.0;
+    let width = left + waves_width + 10.0;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r##"<defs><pattern id="wd-hatch-{0}" width="6" height="6" patternUnits="userSpaceOnUse" patternTransform="rotate(45)"><line class="wd-hatch" x1="0" y1="0" x2="0" y2="6" stroke="{1}" stroke-opacity="0.5"/></pattern><clipPath id="wd-clip-{0}"><rect x="{2}" y="0" width="{3}" height="{4}"/></clipPath><marker id="wd-arrow-{0}" viewBox="0 0 10 10" refX="9" refY="5" markerWidth="7" markerHeight="7" orient="auto-start-reverse"><path class="wd-arrowhead" d="M0,0 L10,5 L0,10 z" fill="{1}"/></marker></defs>"##,
+        id, WAVE_COLOR, left, waves_width, height
+    );
+
+    if let Some(text) = &head {
+        let _ = write!(svg, r#"<text class="chart-label" x="{:.1}" y="16" text-anchor="middle" font-weight="bold" fill="{}">{}</text>"#, left + waves_width / 2.0, TEXT_COLOR, escape_html(text));
+    }
+
+    // Cycle grid
+    for i in 0..=slots as usize {
+        let x = left + i as f64 * slot;
+        let _ = write!(svg, r#"<line class="chart-grid" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-dasharray="2 3"/>"#, x, top, x, top + lanes_height, chart::GRID_COLOR);
+    }
+    let mut ticks = |key: &str, y: f64| {
+        if let Some((start, at_edges)) = tick(key) {
+            for i in 0..slots as usize + usize::from(at_edges) {
+                let x = left + i as f64 * slot + if at_edges { 0.0 } else { slot / 2.0 };
+                let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="middle" fill="{}">{}</text>"#, x, y, TEXT_COLOR, start as i64 + i as i64);
+            }
+        }
+    };
+    ticks("head", top - 6.0);
+    ticks("foot", top + lanes_height + 14.0);
+
+    // Group brackets and names, outermost on the left
+    for group in &groups {
+        let x = group.depth as f64 * GROUP_WIDTH + 12.0;
+        let (y0, y1) = (top + group.first as f64 * LANE + 4.0, top + (group.last + 1) as f64 * LANE - 4.0);
+        let _ = write!(svg, r#"<path class="wd-group" d="M{0:.1},{1:.1} h-4 V{2:.1} h4" fill="none" stroke="{3}"/>"#, x + 4.0, y0, y1, WAVE_COLOR);
+        if !group.name.is_empty() {
+            let y = (y0 + y1) / 2.0;
+            let _ = write!(svg, r#"<text class="chart-label" x="{0:.1}" y="{1:.1}" text-anchor="middle" transform="rotate(-90 {0:.1} {1:.1})" fill="{2}">{3}</text>"#, x - 4.0, y, TEXT_COLOR, escape_html(&group.name));
+        }
+    }
+
+    let mut node_positions: HashMap<char, (f64, f64)> = HashMap::new();
+    for (lane, parsed) in parsed.iter().enumerate() {
+        let Some(p) = parsed else { continue };
+        let y_top = top + lane as f64 * LANE + (LANE - AMPLITUDE) / 2.0;
+        let (y_mid, y_bottom) = (y_top + AMPLITUDE / 2.0, y_top + AMPLITUDE);
+        let shift = left - p.phase * slot;
+
+        let _ = write!(svg, r#"<text class="chart-tick" x="{:.1}" y="{:.1}" text-anchor="end" dominant-baseline="middle" fill="{}">{}</text>"#, left - 8.0, y_mid, TEXT_COLOR, escape_html(&p.name));
+        let _ = write!(svg, r#"<g clip-path="url(#wd-clip-{})">"#, id);
+        draw_wave(&mut svg, &p.pieces, |x| shift + x * slot, (y_top, y_mid, y_bottom), &id);
+        for &g in &p.gaps {
+            let x = shift + g * slot;
+            let _ = write!(svg, r#"<path class="wd-gap" d="M{0:.1},{1:.1} l4,{2:.1} h3 l-4,-{2:.1} z" fill="white" stroke="none"/><path class="wd-gap-edge" d="M{0:.1},{1:.1} l4,{2:.1} M{3:.1},{1:.1} l4,{2:.1}" fill="none" stroke="{4}"/>"#, x - 3.5, y_top - 3.0, AMPLITUDE + 6.0, x - 0.5, WAVE_COLOR);
+        }
+        svg.push_str("</g>");
+
+        for &(name, x) in &p.nodes {
+            node_positions.insert(name, (shift + x * slot, y_mid));
+        }
+    }
+
+    if let Some(edges) = diagram.get("edge").and_then(Value::as_array) {
+        for edge in edges.iter().filter_map(Value::as_str) {
+            draw_edge(&mut svg, edge, &node_positions, &id);
+        }
+    }
+
+    if let Some(text) = &foot {
+        let _ = write!(svg, r#"<text class="chart-label" x="{:.1}" y="{:.1}" text-anchor="middle" fill="{}">{}</text>"#, left + waves_width / 2.0, height - 10.0, TEXT_COLOR, escape_html(text));
+    }
+
+    let caption = diagram.get("caption").map(value_text).unwrap_or_default();
+    Ok(chart::figure("wavedrom", width.ceil(), height.ceil(), &svg, &caption))
+}
+
+fn draw_wave(svg: &mut String, pieces: &[Piece], x: impl Fn(f64) -> f64, (top, mid, bottom): (f64, f64, f64), id: &str) {
+    let level = |kind: Kind| match kind {
+        Kind::High => top,
+        Kind::Low => bottom,
+        _ => mid,
+    };
+    let slope = |piece: &Piece| if piece.sharp { 0.0 } else { SLOPE };
+
+    for (i, piece) in pieces.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| &pieces[j]);
+        let next = pieces.get(i + 1);
+        let (x0, x1) = (x(piece.x0), x(piece.x1));
+
+        if piece.kind.is_bus() {
+            let taper_in = if prev.is_some() { SLOPE } else { 0.0 };
+            let taper_out = if next.is_some() { SLOPE } else { 0.0 };
+            let fill = match piece.kind {
+                Kind::Data(n) => DATA_FILLS[n.min(DATA_FILLS.len() - 1)].to_string(),
+                _ => format!("url(#wd-hatch-{})", id),
+            };
+            let class = match piece.kind {
+                Kind::Data(n) => format!("wd-bus wd-data-{}", n),
+                _ => "wd-bus wd-x".to_string(),
+            };
+            let _ = write!(
+                svg,
+                r#"<path class="{}" d="M{:.1},{:.1} L{:.1},{:.1} H{:.1} L{:.1},{:.1} L{:.1},{:.1} H{:.1} z" fill="{}" stroke="{}"/>"#,
+                class, x0, mid, x0 + taper_in, top, x1 - taper_out, x1, mid, x1 - taper_out, bottom, x0 + taper_in, fill, WAVE_COLOR
+            );
+            if let Some(label) = piece.label.as_deref().filter(|l| !l.is_empty()) {
+                let _ = write!(svg, r#"<text class="wd-label" x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="middle" fill="{}">{}</text>"#, (x0 + x1) / 2.0, mid, TEXT_COLOR, escape_html(label));
+            }
+            continue;
+        }
+
+        // Level: the flat part, then the slope into the next piece
+        let y = level(piece.kind);
+        let start = if prev.is_some() { x0 + slope(piece) } else { x0 };
+        let end = match next {
+            Some(n) if !n.kind.is_bus() => x1 - slope(n),
+            Some(_) => x1 - SLOPE,
+            None => x1,
+        };
+        let class = if piece.kind == Kind::Z { "wd-wave wd-z" } else { "wd-wave" };
+        let _ = write!(svg, r#"<line class="{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, class, start, y, end, y, WAVE_COLOR);
+
+        match prev {
+            Some(p) if p.kind.is_bus() => {
+                let _ = write!(svg, r#"<line class="wd-wave" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, x0, mid, start, y, WAVE_COLOR);
+            }
+            _ => {}
+        }
+        if let Some(n) = next {
+            let (to_x, to_y) = if n.kind.is_bus() { (x1, mid) } else { (x1 + slope(n), level(n.kind)) };
+            let _ = write!(svg, r#"<line class="wd-wave" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#, end, y, to_x, to_y, WAVE_COLOR);
+        }
+
+        if piece.arrow && prev.is_some() {
+            // Edge marker on P/N/H/L bricks, pointing the way the edge goes
+            let dir = if piece.kind == Kind::High { -1.0 } else { 1.0 };
+            let _ = write!(svg, r#"<path class="wd-arrowhead" d="M{:.1},{:.1} l-4,{:.1} h8 z" fill="{}"/>"#, x0, mid + dir * 4.0, -dir * 8.0, WAVE_COLOR);
+        }
+    }
+}
+
+/// Draw a WaveDrom edge such as `a~>b label` or `c-|>d`
+fn draw_edge(svg: &mut String, edge: &str, nodes: &HashMap<char, (f64, f64)>, id: &str) {
+    let (spec, label) = edge.split_once(char::is_whitespace).unwrap_or((edge, ""));
+    let chars: Vec<char> = spec.chars().collect();
+    if chars.len() < 2 {
+        return;
+    }
+    let (Some(&from), Some(&to)) = (nodes.get(&chars[0]), nodes.get(&chars[chars.len() - 1])) else {
+        return;
+    };
+    let shape: String = chars[1..chars.len() - 1].iter().collect();
+    let start_arrow = shape.starts_with('<');
+    let end_arrow = shape.ends_with('>');
+    let shape = shape.trim_matches(|c| c == '<' || c == '>');
+
+    let ((x0, y0), (x1, y1)) = (from, to);
+    let (mx, my) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+    let (d, label_at) = match shape {
+        "~" => (format!("M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}", x0, y0, mx, y0, mx, y1, x1, y1), (mx, my)),
+        "-~" => (format!("M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}", x0, y0, x1, y0, x1, y0, x1, y1), (mx, y0)),
+        "~-" => (format!("M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}", x0, y0, x0, y1, x0, y1, x1, y1), (mx, y1)),
+        "-|" => (format!("M{:.1},{:.1} H{:.1} V{:.1}", x0, y0, x1, y1), (x1, my)),
+        "|-" => (format!("M{:.1},{:.1} V{:.1} H{:.1}", x0, y0, y1, x1), (x0, my)),
+        "-|-" => (format!("M{:.1},{:.1} H{:.1} V{:.1} H{:.1}", x0, y0, mx, y1, x1), (mx, my)),
+        _ => (format!("M{:.1},{:.1} L{:.1},{:.1}", x0, y0, x1, y1), (mx, my)),
+    };
+    let marker = |on: bool| if on { format!("url(#wd-arrow-{})", id) } else { "none".to_string() };
+    let _ = write!(
+        svg,
+        r#"<path class="wd-edge" d="{}" fill="none" stroke="{}" marker-start="{}" marker-end="{}"/>"#,
+        d, chart::SERIES_COLORS[0], marker(start_arrow), marker(end_arrow)
+    );
+    if !label.trim().is_empty() {
+        let _ = write!(
+            svg,
+            r#"<text class="wd-edge-label" x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="middle" paint-order="stroke" stroke="white" stroke-width="3" fill="{}">{}</text>"#,
+            label_at.0, label_at.1, TEXT_COLOR, escape_html(label.trim())
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+
+use crate::site::{escape_html, SiteIndex, WikiTarget};
+use crate::{build_link, config};
+use crate::render::slugify;
+
+/// Whether unresolved links are rendered as visibly broken instead of plain text: when serving,
+/// not in a build
+fn dev_mode() -> bool {
+    config::get().serving
+}
+
+/// How the matching `End(Link)` of a rewritten link has to be closed
+enum Close {
+    Link,
+    Html,
+    Nothing,
+}
+
+/// Rewrite `[[Title]]`, `[[Title|text]]` and `[[Title#Heading]]` links into links to the matching page
+pub fn resolve<'a>(events: Vec<Event<'a>>, index: &SiteIndex) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut open: Vec<Close> = Vec::new();
+    let mut relabel: Option<String> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Link { link_type: LinkType::WikiLink { has_pothole }, dest_url, title, id }) => {
+                let (name, heading) = match dest_url.split_once('#') {
+                    Some((name, heading)) => (name.trim(), Some(heading.trim())),
+                    None => (dest_url.trim(), None),
+                };
+                let fragment = heading.map(|h| format!("#{}", slugify(h))).unwrap_or_default();
+
+                if !has_pothole {
+                    if let Some(h) = heading {
+                        relabel = Some(if name.is_empty() { h.to_string() } else { format!("{} › {}", name, h) });
+                    }
+                }
+
+                if name.is_empty() {
+                    out.push(Event::Start(Tag::Link { link_type: LinkType::Inline, dest_url: fragment.into(), title, id }));
+                    open.push(Close::Link);
+                    continue;
+                }
+
+                match index.resolve_title(name) {
+                    WikiTarget::Found(page) => {
+                        let href = format!("{}{}", build_link(&page.path), fragment);
+                        out.push(Event::Start(Tag::Link { link_type: LinkType::Inline, dest_url: href.into(), title, id }));
+                        open.push(Close::Link);
+                    }
+                    WikiTarget::Ambiguous(candidates) => {
+                        let href = format!("{}{}", build_link(&candidates[0].path), fragment);
+                        if dev_mode() {
+                            let paths: Vec<_> = candidates.iter().map(|p| p.path.as_str()).collect();
+                            out.push(Event::Html(format!(
+                                r#"<a class="wikilink ambiguous" href="{}" title="Ambiguous: {}">"#,
+                                escape_html(&href), escape_html(&paths.join(", "))
+                            ).into()));
+                            open.push(Close::Html);
+                        } else {
+                            out.push(Event::Start(Tag::Link { link_type: LinkType::Inline, dest_url: href.into(), title, id }));
+                            open.push(Close::Link);
+                        }
+                    }
+                    WikiTarget::Missing => {
+                        if dev_mode() {
+                            out.push(Event::Html(format!(
+                                r##"<a class="wikilink missing" href="#" title="No page titled {}">"##,
+                                escape_html(name)
+                            ).into()));
+                            open.push(Close::Html);
+                        } else {
+                            open.push(Close::Nothing);
+                        }
+                    }
+                }
+            }
+            Event::Start(Tag::Link { .. }) => {
+                out.push(event);
+                open.push(Close::Link);
+            }
+            Event::End(TagEnd::Link) => match open.pop() {
+                Some(Close::Html) => out.push(Event::Html(CowStr::Borrowed("</a>"))),
+                Some(Close::Nothing) => {}
+                _ => out.push(event),
+            },
+            Event::Text(_) if relabel.is_some() => {
+                out.push(Event::Text(relabel.take().unwrap().into()));
+            }
+            _ => out.push(event),
+        }
+    }
+
+    out
+}